@@ -0,0 +1,64 @@
+// benchmarks for the typing hot path
+//
+//   cargo bench -p azookey-windows --features bench
+//
+// romaji is converted to kana by the server, so it is only measured by the round trip,
+// which runs when the server is running and is skipped otherwise
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::time::Duration;
+
+use azookey_windows::bench;
+use common::{connect, MOCK_SERVER_PORT};
+use criterion::{criterion_group, criterion_main, Criterion};
+use shared::transport::{NamedPipe, Tcp};
+
+const INPUT: &str = "kyouhaiitenkidesune";
+
+fn conversions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("conversions");
+    let hiragana = "きょうはいいてんきですね";
+    let latin = "Hello, World! 1234";
+
+    group.bench_function("to_katakana", |b| b.iter(|| bench::to_katakana(hiragana)));
+    group.bench_function("to_half_katakana", |b| {
+        b.iter(|| bench::to_half_katakana(hiragana))
+    });
+    group.bench_function("to_fullwidth", |b| {
+        b.iter(|| bench::to_fullwidth(latin, true))
+    });
+    group.bench_function("to_full_latin", |b| b.iter(|| bench::to_full_latin(latin)));
+    group.bench_function("to_halfwidth", |b| {
+        b.iter(|| bench::to_halfwidth(&bench::to_fullwidth(latin, true)))
+    });
+    group.finish();
+}
+
+fn handle_action(c: &mut Criterion) {
+    connect(Tcp::loopback(MOCK_SERVER_PORT)).expect("failed to connect to the mocks");
+
+    c.bench_function("handle_action", |b| {
+        b.iter(|| bench::type_text(INPUT).expect("typing failed"))
+    });
+}
+
+fn round_trip(c: &mut Criterion) {
+    // the server is expected to be running already, e.g. with the IME installed
+    let server = NamedPipe::new("azookey_server").with_retries(3, Duration::from_millis(100));
+    if let Err(e) = connect(server) {
+        eprintln!(
+            "skipping the round trip, the server is not running: {:?}",
+            e
+        );
+        return;
+    }
+
+    c.bench_function("round_trip", |b| {
+        b.iter(|| bench::type_text(INPUT).expect("typing failed"))
+    });
+}
+
+criterion_group!(benches, conversions, handle_action, round_trip);
+criterion_main!(benches);
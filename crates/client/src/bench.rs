@@ -0,0 +1,113 @@
+// entry points for the benchmarks in benches/, the IME itself does not use them
+use anyhow::Result;
+use shared::transport::Transport;
+use windows::Win32::Foundation::WPARAM;
+
+use crate::engine::{
+    client_action::ClientAction,
+    composition::{key_transition, run_actions, Composition, CompositionState},
+    input_mode::InputMode,
+    ipc_service::{block_on, IPCService, ReconnectState},
+    state::IMEState,
+    user_action::UserAction,
+};
+
+pub use crate::engine::full_width::{to_full_latin, to_fullwidth, to_halfwidth};
+pub use crate::engine::text_util::{to_half_katakana, to_katakana};
+
+// connect the typing path to the servers behind the transports
+pub fn connect(server: impl Transport, ui: impl Transport) -> Result<()> {
+    let ipc_service = block_on(IPCService::with_transports(server, ui))?;
+    IMEState::get()?.ipc_service = Some(ipc_service);
+    Ok(())
+}
+
+// type the text one key at a time and discard it
+// this is `handle_action` without the edit sessions, which need a TSF context
+pub fn type_text(text: &str) -> Result<()> {
+    let mode = InputMode::Kana;
+    let mut reconnect = ReconnectState::default();
+    let mut composition = Composition::default();
+
+    for (i, c) in text.chars().enumerate() {
+        let mut actions = vec![];
+        if i == 0 {
+            actions.push(ClientAction::StartComposition);
+        }
+        actions.push(ClientAction::AppendText(c.to_string()));
+
+        (composition, _) = run_actions(
+            &composition,
+            &mode,
+            &actions,
+            CompositionState::Composing,
+            &mut reconnect,
+        )?;
+    }
+
+    run_actions(
+        &composition,
+        &mode,
+        &[ClientAction::ClearText, ClientAction::EndComposition],
+        CompositionState::None,
+        &mut reconnect,
+    )?;
+
+    Ok(())
+}
+
+// a client which keeps its composition between the keys, for the replay test in tests/
+#[derive(Default)]
+pub struct Session {
+    composition: Composition,
+    reconnect: ReconnectState,
+}
+
+impl Session {
+    // starts in the kana mode like an activation
+    pub fn new() -> Result<Self> {
+        IMEState::get()?.input_mode = InputMode::Kana;
+        Ok(Self::default())
+    }
+
+    // `process_action` and the ipc worker for the key, without the bindings and the edit sessions
+    // false if the key goes to the application
+    pub fn press_key(&mut self, wparam: usize) -> Result<bool> {
+        let (mode, config) = {
+            let state = IMEState::get()?;
+            (state.input_mode.clone(), state.config.clone())
+        };
+
+        let action = UserAction::try_from(wparam)?;
+        let transition = key_transition(
+            WPARAM(wparam),
+            action,
+            &self.composition,
+            &mode,
+            &config,
+            false,
+            || false,
+        )?;
+        let Some((actions, transition)) = transition else {
+            return Ok(false);
+        };
+
+        (self.composition, _) = run_actions(
+            &self.composition,
+            &mode,
+            &actions,
+            transition,
+            &mut self.reconnect,
+        )?;
+        Ok(true)
+    }
+
+    // the mode and the state as they are recorded, see `recorder::record_key`
+    pub fn mode(&self) -> Result<String> {
+        Ok(format!("{:?}", IMEState::get()?.input_mode))
+    }
+
+    pub fn state(&self) -> String {
+        format!("{:?}", self.composition.state)
+    }
+}
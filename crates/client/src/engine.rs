@@ -1,9 +1,24 @@
+pub(super) mod app_quirks;
+pub(super) mod augment;
+pub(super) mod calculator;
+pub(super) mod caps_lock;
 pub(super) mod client_action;
+pub(super) mod codepoint;
 pub(super) mod composition;
 pub(super) mod full_width;
+pub(super) mod ime_status;
 pub(super) mod input_mode;
 pub(super) mod ipc_service;
+pub(super) mod key_queue;
+pub(super) mod keymap;
+pub(super) mod metrics;
+pub(super) mod privacy;
+pub(super) mod recorder;
+pub(super) mod request_id;
+pub(super) mod server_status;
 pub(super) mod state;
+pub(super) mod sticky_keys;
 pub(super) mod text_util;
 pub(super) mod theme;
+pub(super) mod thumb_shift;
 pub(super) mod user_action;
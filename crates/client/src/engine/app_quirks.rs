@@ -0,0 +1,98 @@
+use std::sync::OnceLock;
+
+use shared::FullscreenBehavior;
+use windows::Win32::{
+    Foundation::{HMODULE, MAX_PATH, RECT},
+    Graphics::Gdi::{GetMonitorInfoW, MonitorFromWindow, MONITORINFO, MONITOR_DEFAULTTONEAREST},
+    System::LibraryLoader::GetModuleFileNameW,
+    UI::WindowsAndMessaging::{
+        GetDesktopWindow, GetForegroundWindow, GetShellWindow, GetWindowRect,
+    },
+};
+
+use super::state::IMEState;
+
+// features which depend on the application
+#[derive(Debug, Clone, Copy)]
+pub struct AppQuirks {
+    // reading the text around the caret needs extra edit sessions
+    pub surrounding_text: bool,
+    // false while a fullscreen application is in front, unless the overlay is enabled for it
+    pub candidate_window: bool,
+}
+
+static HOST_PROCESS: OnceLock<String> = OnceLock::new();
+
+// executable name of the application which loaded the text service
+pub fn host_process() -> &'static str {
+    HOST_PROCESS.get_or_init(|| {
+        let mut buffer: [u16; MAX_PATH as usize] = [0; MAX_PATH as usize];
+        let length = unsafe { GetModuleFileNameW(HMODULE::default(), &mut buffer) };
+        let path = String::from_utf16_lossy(&buffer[..length as usize]);
+
+        path.rsplit(['\\', '/'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase()
+    })
+}
+
+pub fn current() -> AppQuirks {
+    let process = host_process();
+    let (surrounding_text, fullscreen) = IMEState::get()
+        .map(|state| {
+            (
+                state.config.surrounding_text.is_allowed(process),
+                state.config.fullscreen.behavior_for(process),
+            )
+        })
+        .unwrap_or_default();
+    let candidate_window = fullscreen == FullscreenBehavior::Overlay || !is_foreground_fullscreen();
+
+    AppQuirks {
+        surrounding_text,
+        candidate_window,
+    }
+}
+
+// checked on activation and on focus changes, the config may have been changed in between
+pub fn update_excluded() -> anyhow::Result<()> {
+    let mut state = IMEState::get()?;
+    let excluded = state.config.exclusion.is_excluded(host_process());
+    if excluded != state.excluded {
+        tracing::debug!("Excluded application: {}", excluded);
+    }
+    state.excluded = excluded;
+    Ok(())
+}
+
+// whether the foreground window covers its whole monitor
+// both exclusive and borderless fullscreen windows do, maximized windows leave the taskbar
+fn is_foreground_fullscreen() -> bool {
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_invalid() || hwnd == GetDesktopWindow() || hwnd == GetShellWindow() {
+            return false;
+        }
+
+        let mut rect = RECT::default();
+        if GetWindowRect(hwnd, &mut rect).is_err() {
+            return false;
+        }
+
+        let mut info = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
+        if !GetMonitorInfoW(monitor, &mut info).as_bool() {
+            return false;
+        }
+
+        let screen = info.rcMonitor;
+        rect.left <= screen.left
+            && rect.top <= screen.top
+            && rect.right >= screen.right
+            && rect.bottom >= screen.bottom
+    }
+}
@@ -0,0 +1,186 @@
+use shared::proto::CandidateSource;
+
+use super::{
+    calculator::calculate,
+    codepoint::{codepoint, notation},
+    ipc_service::Candidates,
+};
+
+// candidates made by the IME itself from the whole reading, added after the ones of the server
+// (text, annotation)
+fn generated(reading: &str) -> Vec<(String, String)> {
+    let mut generated: Vec<(String, String)> = numerals(reading)
+        .into_iter()
+        .map(|(text, annotation)| (text, annotation.to_string()))
+        .collect();
+    if let Some((result, equation)) = calculate(reading) {
+        generated.push((result, "計算結果".to_string()));
+        generated.push((equation, "計算結果".to_string()));
+    }
+    if let Some(c) = codepoint(reading) {
+        // the character with its name, and the name itself
+        let name = unicode_names2::name(c).map(|name| name.to_string());
+        let annotation = match &name {
+            Some(name) => format!("{} {name}", notation(c)),
+            None => notation(c),
+        };
+        generated.push((c.to_string(), annotation.clone()));
+        if let Some(name) = name {
+            generated.push((name, annotation));
+        }
+    }
+    for face in shared::kaomoji::lookup(reading) {
+        generated.push((face.to_string(), "顔文字".to_string()));
+    }
+    generated
+}
+
+impl Candidates {
+    pub fn augment(&mut self) {
+        let reading = self.hiragana.clone();
+        let count = reading.chars().count() as i32;
+
+        for (text, annotation) in generated(&reading) {
+            if text == reading || self.texts.contains(&text) {
+                continue;
+            }
+            self.texts.push(text);
+            self.sub_texts.push(String::new());
+            self.corresponding_count.push(count);
+            self.annotations.push(annotation);
+            self.readings.push(reading.clone());
+            self.sources.push(CandidateSource::Generated);
+        }
+    }
+}
+
+const KANJI_DIGITS: [&str; 10] = ["〇", "一", "二", "三", "四", "五", "六", "七", "八", "九"];
+// the digits of contracts and money orders, only the ones easy to rewrite are replaced
+const DAIJI_DIGITS: [&str; 10] = ["零", "壱", "弐", "参", "四", "五", "六", "七", "八", "九"];
+
+// a number under 10000, 一 is left out before 十, 百 and 千 unless `one` is set
+fn under_10000(n: u64, digits: &[&str; 10], units: [&str; 3], one: bool) -> String {
+    let mut text = String::new();
+    for (place, unit) in [(1000, units[2]), (100, units[1]), (10, units[0])] {
+        let digit = (n / place % 10) as usize;
+        if digit == 0 {
+            continue;
+        }
+        if digit != 1 || one {
+            text.push_str(digits[digit]);
+        }
+        text.push_str(unit);
+    }
+    if n % 10 != 0 {
+        text.push_str(digits[(n % 10) as usize]);
+    }
+    text
+}
+
+// e.g. 二千二十四 for 2024
+fn positional(n: u64, digits: &[&str; 10], units: [&str; 3], one: bool) -> String {
+    if n == 0 {
+        return digits[0].to_string();
+    }
+
+    let mut text = String::new();
+    for (exponent, unit) in [(12, "兆"), (8, "億"), (4, "万"), (0, "")] {
+        let group = n / 10u64.pow(exponent) % 10000;
+        if group > 0 {
+            text.push_str(&under_10000(group, digits, units, one));
+            text.push_str(unit);
+        }
+    }
+    text
+}
+
+// e.g. 2,024
+fn with_commas(digits: &str) -> String {
+    let mut text = String::new();
+    for (index, c) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index) % 3 == 0 {
+            text.push(',');
+        }
+        text.push(c);
+    }
+    text
+}
+
+// the kanji of a number typed in digits, full-width digits are read as well
+fn numerals(reading: &str) -> Vec<(String, &'static str)> {
+    let digits: String = reading
+        .chars()
+        .map(|c| match c {
+            '０'..='９' => char::from_u32(c as u32 - '０' as u32 + '0' as u32).unwrap_or(c),
+            _ => c,
+        })
+        .collect();
+    // up to 9999兆
+    if digits.is_empty() || digits.len() > 16 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return vec![];
+    }
+
+    let mut numerals = vec![(
+        digits
+            .chars()
+            .map(|c| KANJI_DIGITS[c as usize - '0' as usize])
+            .collect(),
+        "漢数字",
+    )];
+    // the digits after a leading zero are not a number, e.g. 007
+    if digits.starts_with('0') && digits.len() > 1 {
+        return numerals;
+    }
+
+    let n: u64 = digits.parse().unwrap_or_default();
+    numerals.push((
+        positional(n, &KANJI_DIGITS, ["十", "百", "千"], false),
+        "漢数字",
+    ));
+    numerals.push((
+        positional(n, &DAIJI_DIGITS, ["拾", "百", "千"], true),
+        "大字",
+    ));
+    if n >= 1000 {
+        numerals.push((with_commas(&digits), "桁区切り"));
+    }
+    numerals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(reading: &str) -> Vec<String> {
+        numerals(reading)
+            .into_iter()
+            .map(|(text, _)| text)
+            .collect()
+    }
+
+    #[test]
+    fn kanji_numerals() {
+        assert_eq!(
+            texts("2024"),
+            ["二〇二四", "二千二十四", "弐千弐拾四", "2,024"]
+        );
+        assert_eq!(texts("１１"), ["一一", "十一", "壱拾壱"]);
+        assert_eq!(texts("0"), ["〇", "〇", "零"]);
+    }
+
+    #[test]
+    fn large_numbers() {
+        assert_eq!(
+            positional(100010000, &KANJI_DIGITS, ["十", "百", "千"], false),
+            "一億一万"
+        );
+        assert_eq!(with_commas("1234567"), "1,234,567");
+    }
+
+    #[test]
+    fn not_a_number() {
+        assert!(texts("にせん").is_empty());
+        assert!(texts("12a").is_empty());
+        assert_eq!(texts("007"), ["〇〇七"]);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{iter::Peekable, str::Chars};
+
+// the reading has the symbols in full-width, and - typed in kana is ー
+fn normalize(reading: &str) -> String {
+    reading
+        .chars()
+        .map(|c| match c {
+            '！'..='～' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            'ー' | '−' => '-',
+            '×' => '*',
+            '÷' => '/',
+            '　' => ' ',
+            _ => c,
+        })
+        .collect()
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.chars.next();
+            return true;
+        }
+        false
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Option<f64> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    // term := factor (('*' | '/' | '%') factor)*
+    fn term(&mut self) -> Option<f64> {
+        let mut value = self.factor()?;
+        loop {
+            if self.eat('*') {
+                value *= self.factor()?;
+            } else if self.eat('/') {
+                value /= self.factor()?;
+            } else if self.eat('%') {
+                value %= self.factor()?;
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    // factor := ('-' | '+') factor | primary ('^' factor)?
+    fn factor(&mut self) -> Option<f64> {
+        if self.eat('-') {
+            return Some(-self.factor()?);
+        }
+        if self.eat('+') {
+            return self.factor();
+        }
+
+        let base = self.primary()?;
+        if self.eat('^') {
+            return Some(base.powf(self.factor()?));
+        }
+        Some(base)
+    }
+
+    // primary := number | '(' expression ')'
+    fn primary(&mut self) -> Option<f64> {
+        if self.eat('(') {
+            let value = self.expression()?;
+            return self.eat(')').then_some(value);
+        }
+
+        let mut number = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+            number.push(c);
+        }
+        number.parse().ok()
+    }
+}
+
+fn evaluate(expression: &str) -> Option<f64> {
+    let mut parser = Parser {
+        chars: expression.chars().peekable(),
+    };
+    let value = parser.expression()?;
+
+    (parser.peek().is_none() && value.is_finite()).then_some(value)
+}
+
+// the result without the error of the floating point, e.g. 0.3 for 0.1+0.2
+fn display(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        return (value as i64).to_string();
+    }
+    let text = format!("{value:.10}");
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+// (result, expression with the result) of a reading like 12*8=, None if it isn't one
+pub fn calculate(reading: &str) -> Option<(String, String)> {
+    let reading = normalize(reading);
+    let expression = reading.strip_suffix('=')?.trim();
+    // a number alone is not a calculation
+    if expression.parse::<f64>().is_ok() {
+        return None;
+    }
+
+    let result = display(evaluate(expression)?);
+    Some((result.clone(), format!("{expression}={result}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(reading: &str) -> Option<String> {
+        calculate(reading).map(|(result, _)| result)
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(result("12*8="), Some("96".to_string()));
+        assert_eq!(result("1+2*3="), Some("7".to_string()));
+        assert_eq!(result("(1+2)*3="), Some("9".to_string()));
+        assert_eq!(result("2^10="), Some("1024".to_string()));
+        assert_eq!(result("0.1+0.2="), Some("0.3".to_string()));
+        assert_eq!(result("-3+1="), Some("-2".to_string()));
+        assert_eq!(result("10/4="), Some("2.5".to_string()));
+    }
+
+    #[test]
+    fn full_width_reading() {
+        assert_eq!(
+            calculate("１２＊８＝"),
+            Some(("96".to_string(), "12*8=96".to_string()))
+        );
+        assert_eq!(result("10ー3＝"), Some("7".to_string()));
+    }
+
+    #[test]
+    fn not_a_calculation() {
+        assert_eq!(result("12*8"), None);
+        assert_eq!(result("12="), None);
+        assert_eq!(result("1/0="), None);
+        assert_eq!(result("(1+2="), None);
+        assert_eq!(result("かず="), None);
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::Result;
+use windows::Win32::{
+    Foundation::WPARAM,
+    UI::Input::KeyboardAndMouse::{
+        SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYEVENTF_KEYUP, VK_CAPITAL,
+    },
+};
+
+use super::state::IMEState;
+
+// the system turns Caps Lock on and off before the key comes to the IME, and Eisū doesn't at
+// all, so Caps Lock is typed once more when it has to be the other way, see `CapsLockBehavior`
+pub fn toggle() -> Result<()> {
+    let key = |flags| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VK_CAPITAL,
+                dwFlags: flags,
+                ..Default::default()
+            },
+        },
+    };
+    let inputs = [key(Default::default()), key(KEYEVENTF_KEYUP)];
+
+    IMEState::get()?.caps_lock_sent = true;
+    let sent = unsafe { SendInput(&inputs, std::mem::size_of::<INPUT>() as i32) };
+    if sent != inputs.len() as u32 {
+        IMEState::get()?.caps_lock_sent = false;
+        anyhow::bail!("Failed to send Caps Lock");
+    }
+
+    Ok(())
+}
+
+// true for the Caps Lock sent by `toggle`, which goes to the application as it is
+pub fn take_sent(wparam: WPARAM) -> Result<bool> {
+    if wparam.0 != VK_CAPITAL.0 as usize {
+        return Ok(false);
+    }
+
+    Ok(std::mem::take(&mut IMEState::get()?.caps_lock_sent))
+}
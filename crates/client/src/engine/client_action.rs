@@ -1,6 +1,8 @@
+use shared::{SelectionBoundary, TextTransform};
+
 use super::input_mode::InputMode;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ClientAction {
     StartComposition,
     EndComposition,
@@ -8,6 +10,20 @@ pub enum ClientAction {
     AppendText(String),
     RemoveText,
     ShrinkText(String),
+    // discard the text of the composition
+    ClearText,
+    // show the whole reading again instead of the conversion
+    RevertToReading,
+    // grow or shrink the first clause by the number of characters
+    ResizeSegment(i32),
+    // move the conversion to the next (1) or the previous (-1) clause
+    FocusClause(i32),
+    // commit the first of the converted clauses and keep the rest composing
+    CommitPrefix,
+    // compose the text committed last again
+    ReopenLastCommit,
+    // compose the selected text again from the reading the server finds for it
+    Reconvert(String),
 
     SetTextWithType(SetTextType),
 
@@ -15,20 +31,97 @@ pub enum ClientAction {
     SetSelection(SetSelectionType),
 
     SetIMEMode(InputMode),
+    // turn Caps Lock the other way, see `caps_lock::toggle`
+    ToggleCapsLock,
+    // commit the text directly without composition
+    InsertText(String),
+
+    // show or hide the annotations in the candidate window
+    ToggleDetail,
+    // show the kaomoji by category in the candidate window, until the next composition
+    ShowPalette,
+    ClosePalette,
+    // show the next completion of the reading instead of the conversion, see `CandidateConfig`
+    SelectPrediction,
+
+    // text before the caret, used by the server as the context of the conversion
+    SetContext(String),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SetSelectionType {
     Up,
     Down,
     Number(i32),
+    // move by whole pages of the candidate window, keeping the position in the page
+    Page(i32),
 }
 
-#[derive(Debug, PartialEq)]
+impl SetSelectionType {
+    // index of the candidate to select, or None if there is nothing to select
+    pub fn resolve(
+        &self,
+        current: i32,
+        len: usize,
+        page_size: usize,
+        boundary: &SelectionBoundary,
+    ) -> Option<i32> {
+        if len == 0 {
+            return None;
+        }
+
+        let last = len as i32 - 1;
+        let index = match self {
+            SetSelectionType::Up if current <= 0 => match boundary {
+                SelectionBoundary::Stop => 0,
+                SelectionBoundary::Wrap => last,
+            },
+            SetSelectionType::Down if current >= last => match boundary {
+                SelectionBoundary::Stop => last,
+                SelectionBoundary::Wrap => 0,
+            },
+            SetSelectionType::Up => current - 1,
+            SetSelectionType::Down => current + 1,
+            // out of range numbers are ignored instead of being clamped
+            SetSelectionType::Number(number) if (0..=last).contains(number) => *number,
+            SetSelectionType::Number(_) => return None,
+            SetSelectionType::Page(pages) => {
+                let page_size = page_size.max(1) as i32;
+                let current = current.clamp(0, last);
+                let page_count = last / page_size + 1;
+                let page = current / page_size + pages;
+                let page = match boundary {
+                    SelectionBoundary::Stop => page.clamp(0, page_count - 1),
+                    SelectionBoundary::Wrap => page.rem_euclid(page_count),
+                };
+                // the last page may be shorter
+                (page * page_size + current % page_size).min(last)
+            }
+        };
+
+        Some(index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum SetTextType {
-    Hiragana,     // F6
-    Katakana,     // F7
-    HalfKatakana, // F8
-    FullLatin,    // F9
-    HalfLatin,    // F10
+    Hiragana,
+    Katakana,
+    HalfKatakana,
+    FullLatin,
+    HalfLatin,
+    LongVowelKatakana,
+}
+
+impl From<TextTransform> for SetTextType {
+    fn from(transform: TextTransform) -> Self {
+        match transform {
+            TextTransform::Hiragana => SetTextType::Hiragana,
+            TextTransform::Katakana => SetTextType::Katakana,
+            TextTransform::HalfKatakana => SetTextType::HalfKatakana,
+            TextTransform::FullLatin => SetTextType::FullLatin,
+            TextTransform::HalfLatin => SetTextType::HalfLatin,
+            TextTransform::LongVowelKatakana => SetTextType::LongVowelKatakana,
+        }
+    }
 }
@@ -0,0 +1,80 @@
+// the letters of the hex digits as the romaji table turns them into kana, e.g. ふぇ for fe
+// b, c, d and f alone are left as they are
+const KANA_HEX: &[(&str, &str)] = &[
+    ("ふぁ", "fa"),
+    ("ふぇ", "fe"),
+    ("あ", "a"),
+    ("え", "e"),
+    ("ば", "ba"),
+    ("べ", "be"),
+    ("か", "ca"),
+    ("せ", "ce"),
+    ("だ", "da"),
+    ("で", "de"),
+];
+
+// the reading of u+ is う＋ in the kana modes, and the symbols and the digits are full-width
+fn normalize(reading: &str) -> String {
+    let mut text: String = reading
+        .chars()
+        .map(|c| match c {
+            '！'..='～' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            _ => c,
+        })
+        .collect();
+    for (kana, hex) in KANA_HEX {
+        text = text.replace(kana, hex);
+    }
+    text
+}
+
+// the character of a reading like u+3042, None if it isn't one
+pub fn codepoint(reading: &str) -> Option<char> {
+    let reading = normalize(reading);
+    let hex = ["u+", "U+", "う+"]
+        .iter()
+        .find_map(|prefix| reading.strip_prefix(prefix))?;
+    if hex.is_empty() || hex.len() > 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    // surrogates are not characters, and neither are the controls for the text of a document
+    char::from_u32(u32::from_str_radix(hex, 16).ok()?).filter(|c| !c.is_control())
+}
+
+// e.g. U+3042 for あ
+pub fn notation(c: char) -> String {
+    format!("U+{:04X}", c as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_codepoints() {
+        assert_eq!(codepoint("u+3042"), Some('あ'));
+        assert_eq!(codepoint("U+1F600"), Some('😀'));
+        assert_eq!(codepoint("う＋３０４２"), Some('あ'));
+        assert_eq!(codepoint("う＋１ｆ６００"), Some('😀'));
+        assert_eq!(codepoint("う＋１ふぁ７０"), Some('🩰'));
+        assert_eq!(codepoint("う＋００え９"), Some('é'));
+    }
+
+    #[test]
+    fn not_a_codepoint() {
+        assert_eq!(codepoint("u+"), None);
+        assert_eq!(codepoint("u+3042g"), None);
+        assert_eq!(codepoint("u+d800"), None);
+        assert_eq!(codepoint("u+110000"), None);
+        assert_eq!(codepoint("u+000a"), None);
+        assert_eq!(codepoint("うた"), None);
+    }
+
+    #[test]
+    fn notations() {
+        assert_eq!(notation('あ'), "U+3042");
+        assert_eq!(notation('é'), "U+00E9");
+        assert_eq!(notation('😀'), "U+1F600");
+    }
+}
@@ -1,64 +1,43 @@
-use std::cmp::{max, min};
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::time::{SystemTime, UNIX_EPOCH};
-
-// Cooldown for IPC reconnection attempts (10 seconds)
-static LAST_IPC_FAIL_TIME: AtomicU64 = AtomicU64::new(0);
-const IPC_RECONNECT_COOLDOWN_SECS: u64 = 10;
-
-fn should_try_ipc_reconnect() -> bool {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
-    let last_fail = LAST_IPC_FAIL_TIME.load(Ordering::Relaxed);
-    now.saturating_sub(last_fail) >= IPC_RECONNECT_COOLDOWN_SECS
-}
-
-fn mark_ipc_reconnect_failed() {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
-    LAST_IPC_FAIL_TIME.store(now, Ordering::Relaxed);
-}
-
-// Debug helper - DISABLED to prevent slowdown from file I/O on every keystroke
-fn debug_log(_msg: &str) {
-    // Disabled - was causing progressive slowdown due to file writes
-    // if let Ok(mut file) = std::fs::OpenOptions::new()
-    //     .create(true)
-    //     .append(true)
-    //     .open("G:/Projects/azooKey-Windows/logs/debug.log")
-    // {
-    //     let _ = writeln!(file, "[{}] {}", chrono::Local::now().format("%H:%M:%S%.3f"), msg);
-    // }
-}
-
 use crate::{
     engine::user_action::UserAction,
-    extension::VKeyExt as _,
+    globals::{
+        GUID_DISPLAY_ATTRIBUTE_CONVERTED, GUID_DISPLAY_ATTRIBUTE_INPUT,
+        GUID_DISPLAY_ATTRIBUTE_TARGET,
+    },
     tsf::factory::{TextServiceFactory, TextServiceFactory_Impl},
 };
 
 use super::{
+    app_quirks, caps_lock,
     client_action::{ClientAction, SetSelectionType, SetTextType},
-    full_width::{to_fullwidth, to_halfwidth},
+    full_width::{to_full_latin, to_fullwidth, to_halfwidth, with_punctuation},
     input_mode::InputMode,
-    ipc_service::{Candidates, IPCService},
+    ipc_service::{self, Candidates, IPCService, ReconnectState},
+    recorder, request_id,
     state::IMEState,
-    text_util::{to_half_katakana, to_katakana},
-    user_action::{Function, Navigation},
+    sticky_keys,
+    text_util::{
+        is_japanese, katakana_runs, to_half_katakana, to_katakana, to_long_vowel_katakana,
+        with_katakana_runs,
+    },
+    user_action::Navigation,
 };
 use windows::Win32::{
     Foundation::WPARAM,
     UI::{
-        Input::KeyboardAndMouse::{VK_CONTROL, VK_LCONTROL, VK_RCONTROL},
+        Input::KeyboardAndMouse::{
+            VK_CAPITAL, VK_CONTROL, VK_CONVERT, VK_LCONTROL, VK_RCONTROL, VK_SHIFT,
+        },
         TextServices::{ITfComposition, ITfCompositionSink_Impl, ITfContext},
     },
 };
 
 use anyhow::{Context, Result};
+use shared::proto::{CandidateKind, CandidateSource, PaletteCategory, Prediction};
+use shared::{
+    AppConfig, BackspaceBehavior, CandidateWindowTiming, CapsLockBehavior, EscapeBehavior,
+    LearningMode, SpaceWidth, SymbolWidth, TabBehavior,
+};
 
 #[derive(Default, Clone, PartialEq, Debug)]
 pub enum CompositionState {
@@ -82,7 +61,98 @@ pub struct Composition {
     pub candidates: Candidates,
 
     pub state: CompositionState,
-    pub tip_composition: Option<ITfComposition>,
+
+    // converted clauses before the preview, committed together with it
+    pub clauses: Vec<Clause>,
+
+    // completions of the reading while composing, and the one shown with Tab
+    pub predictions: Vec<Prediction>,
+    pub prediction: Option<usize>,
+
+    pub last_commit: Option<LastCommit>,
+
+    // the characters of `raw_hiragana` typed with Shift, see `KeyConfig.shift_katakana`
+    pub katakana: Vec<bool>,
+
+    // the server kept only a part of the composition after a failed request, see `shrink!`
+    // it is composed again from `raw_input` once the server answers
+    pub out_of_sync: bool,
+}
+
+// a clause left of the one being converted, see `ClientAction::FocusClause`
+// the server has already forgotten it, so everything needed to convert it again is kept
+#[derive(Default, Clone, Debug)]
+pub struct Clause {
+    pub text: String,
+    pub reading: String,
+    pub raw_input: String,
+}
+
+// a part of the clauses joined, e.g. the text shown in front of the preview
+fn joined(clauses: &[Clause], part: fn(&Clause) -> &str) -> String {
+    clauses.iter().map(part).collect()
+}
+
+// the clause at the `offset` of the composition, counted like `clauses`
+// the offset comes from TSF, so it is in UTF-16 units and surrogate pairs count twice
+// the preview comes after the clauses, and the reading after it is taken as one more clause
+fn clause_at(composition: &Composition, offset: usize) -> usize {
+    let mut end = 0;
+    for (index, clause) in composition.clauses.iter().enumerate() {
+        end += clause.text.encode_utf16().count();
+        if offset < end {
+            return index;
+        }
+    }
+
+    let preview_end = end + composition.preview.encode_utf16().count();
+    if offset < preview_end || composition.suffix.is_empty() {
+        composition.clauses.len()
+    } else {
+        composition.clauses.len() + 1
+    }
+}
+
+// the text committed last, kept so that it can be converted again with the function keys
+// or taken back into the composition with Ctrl+Backspace
+#[derive(Default, Clone, Debug)]
+pub struct LastCommit {
+    pub text: String,
+    pub raw_input: String,
+    pub raw_hiragana: String,
+}
+
+// index and suffix of the candidate numbered `number` on the page of the current selection
+fn numbered_candidate(composition: &Composition, number: i8) -> Option<(i32, String)> {
+    // same paging as the window, which may show fewer candidates than CANDIDATE_PAGE_SIZE
+    let page_size = ipc_service::page_size() as i32;
+    if !(1..=page_size).contains(&(number as i32)) {
+        return None;
+    }
+
+    let page_start = composition.selection_index - composition.selection_index % page_size;
+    let index = page_start + number as i32 - 1;
+    let candidate = composition.candidates.get(index)?;
+
+    Some((index, candidate.sub_text))
+}
+
+// edit operations which must be performed on the TSF thread
+#[derive(Debug, Clone)]
+pub enum EditOp {
+    StartComposition,
+    EndComposition,
+    // converted clauses, text, suffix
+    SetText(String, String, String),
+    // committed text, new text, new suffix
+    ShiftStart(String, String, String),
+    InsertText(String),
+    UpdatePos,
+    UpdateLangBar,
+    // show the mode next to the caret for a moment, the argument is `InputMode::indicator`
+    FlashModeIndicator(&'static str),
+    // send the text before the composition to the server, the argument is the composing text
+    UpdateContext(String),
 }
 
 impl ITfCompositionSink_Impl for TextServiceFactory_Impl {
@@ -102,6 +172,95 @@ impl ITfCompositionSink_Impl for TextServiceFactory_Impl {
     }
 }
 
+// (current state, setting, next state, actions)
+type EscapeRule = (
+    CompositionState,
+    EscapeBehavior,
+    CompositionState,
+    &'static [ClientAction],
+);
+
+// what Escape does in each state
+// states which are not listed cancel the composition
+const ESCAPE_TABLE: &[EscapeRule] = &[
+    (
+        CompositionState::Previewing,
+        EscapeBehavior::Revert,
+        CompositionState::Composing,
+        &[ClientAction::RevertToReading],
+    ),
+    (
+        CompositionState::Composing,
+        EscapeBehavior::Revert,
+        CompositionState::None,
+        &[ClientAction::ClearText, ClientAction::EndComposition],
+    ),
+];
+
+fn escape_transition(
+    state: &CompositionState,
+    behavior: &EscapeBehavior,
+) -> (CompositionState, Vec<ClientAction>) {
+    ESCAPE_TABLE
+        .iter()
+        .find(|(from, escape, _, _)| from == state && escape == behavior)
+        .map(|(_, _, to, actions)| (to.clone(), actions.to_vec()))
+        .unwrap_or_else(|| {
+            (
+                CompositionState::None,
+                vec![ClientAction::RemoveText, ClientAction::EndComposition],
+            )
+        })
+}
+
+// text shown while composing
+// in the katakana modes, the reading is shown in katakana instead of the conversion
+// without live conversion, the reading is shown until Space converts it
+fn composing_text(
+    mode: &InputMode,
+    live: bool,
+    text: String,
+    sub_text: String,
+    hiragana: &str,
+) -> (String, String) {
+    match mode {
+        InputMode::Katakana => (to_katakana(hiragana), String::new()),
+        InputMode::HalfKatakana => (to_half_katakana(hiragana), String::new()),
+        _ if !live => (hiragana.to_string(), String::new()),
+        _ => (text, sub_text),
+    }
+}
+
+// the typed keys as sent to the server, the symbols become the Japanese ones in the kana modes
+// unless they are set to half-width
+fn typed_text(mode: &InputMode, text: &str) -> Result<String> {
+    let text = match mode {
+        InputMode::Kana | InputMode::Katakana | InputMode::HalfKatakana => {
+            let config = &IMEState::get()?.config;
+            text.chars()
+                .map(|c| match config.symbols.rule(c) {
+                    // the Shift is kept as `Composition.katakana`, the converter gets romaji
+                    _ if is_shifted(mode, config.keys.shift_katakana, c) => {
+                        c.to_ascii_lowercase().to_string()
+                    }
+                    Some(rule) if rule.width == SymbolWidth::Half => c.to_string(),
+                    _ => with_punctuation(
+                        &to_fullwidth(&c.to_string(), false),
+                        config.punctuation_style,
+                    ),
+                })
+                .collect()
+        }
+        InputMode::Latin | InputMode::FullLatin => text.to_string(),
+    };
+    Ok(text)
+}
+
+// a letter typed with Shift which starts or continues a katakana run
+fn is_shifted(mode: &InputMode, shift_katakana: bool, c: char) -> bool {
+    *mode == InputMode::Kana && shift_katakana && c.is_ascii_uppercase()
+}
+
 impl TextServiceFactory {
     #[tracing::instrument]
     pub fn process_key(
@@ -113,6 +272,11 @@ impl TextServiceFactory {
             return Ok(None);
         };
 
+        // the keys go to password fields as they are, see `update_password_field`
+        if IMEState::get()?.password_field.is_some() {
+            return Ok(None);
+        }
+
         // IME ON/OFF switching via special key codes from AutoHotkey
         // 0x97 = IME OFF (English), 0x98 = IME ON (Japanese)
         // These are unassigned VK codes that won't conflict with system keys
@@ -133,549 +297,1613 @@ impl TextServiceFactory {
             )));
         }
 
-        // check shortcut keys
-        if VK_CONTROL.is_pressed() {
+        // Escape closes the kaomoji palette, which is there only without a composition
+        if IMEState::get()?.palette && matches!(UserAction::try_from(wparam.0)?, UserAction::Escape)
+        {
+            return Ok(Some((
+                vec![ClientAction::ClosePalette],
+                CompositionState::None,
+            )));
+        }
+
+        // the keys of the user come before the built-in ones
+        if let Some(processed) = self.process_binding(wparam)? {
+            return Ok(Some(processed));
+        }
+
+        // Henkan over a selection converts it again, the key goes on to the application otherwise
+        if wparam.0 == VK_CONVERT.0 as usize
+            && !sticky_keys::is_held(VK_CONTROL)
+            && IMEState::get()?.input_mode.is_composing()
+        {
+            if let Some(processed) = self.selection_actions()? {
+                return Ok(Some(processed));
+            }
+        }
+
+        // the other Ctrl keys are left to the application, see `KeyConfig.bindings` for the ones
+        // while composing
+        if sticky_keys::is_held(VK_CONTROL) {
+            // Ctrl+Backspace takes the text committed right before back into the composition
+            if matches!(UserAction::try_from(wparam.0)?, UserAction::Backspace) {
+                return self.undo_last_commit();
+            }
             return Ok(None);
         }
 
-        #[allow(clippy::let_and_return)]
-        let (composition, mode) = {
+        self.process_action(wparam, UserAction::try_from(wparam.0)?)
+    }
+
+    // the last commit is composed again from its reading, None if it can't be
+    fn undo_last_commit(&self) -> Result<Option<(Vec<ClientAction>, CompositionState)>> {
+        let text_service = self.borrow()?;
+        let composition = text_service.borrow_composition()?;
+        if composition.state != CompositionState::None
+            || composition.last_commit.is_none()
+            || text_service.last_commit_range.try_borrow()?.is_none()
+        {
+            return Ok(None);
+        }
+
+        Ok(Some((
+            vec![
+                ClientAction::ReopenLastCommit,
+                ClientAction::RevertToReading,
+            ],
+            CompositionState::Composing,
+        )))
+    }
+
+    // the transition for what the key typed, which is not always the key itself with thumb-shift
+    pub fn process_action(
+        &self,
+        wparam: WPARAM,
+        action: UserAction,
+    ) -> Result<Option<(Vec<ClientAction>, CompositionState)>> {
+        let (composition, mode, config, can_reopen) = {
             let text_service = self.borrow()?;
             let composition = text_service.borrow_composition()?.clone();
-            let mode = IMEState::get()?.input_mode.clone();
-            (composition, mode)
+            let can_reopen = composition.last_commit.is_some()
+                && text_service.last_commit_range.try_borrow()?.is_some();
+            let state = IMEState::get()?;
+            (
+                composition,
+                state.input_mode.clone(),
+                state.config.clone(),
+                can_reopen,
+            )
         };
 
         // Debug: log key event info
-        debug_log(&format!("process_key: wparam={}, mode={:?}, state={:?}", wparam.0, mode, composition.state));
+        tracing::debug!(
+            "process_key: wparam={}, mode={:?}, state={:?}",
+            wparam.0,
+            mode,
+            composition.state
+        );
+        recorder::record_key(wparam.0, &mode, &composition.state);
+        tracing::debug!("action: {:?}", action);
 
-        let action = UserAction::try_from(wparam.0)?;
-        debug_log(&format!("action: {:?}", action));
+        let after_japanese = || {
+            self.preceding_text("")
+                .is_ok_and(|text| text.chars().last().is_some_and(is_japanese))
+        };
+        key_transition(
+            wparam,
+            action,
+            &composition,
+            &mode,
+            &config,
+            can_reopen,
+            after_japanese,
+        )
+    }
 
-        let (transition, actions) = match composition.state {
-            CompositionState::None => match action {
-                UserAction::Input(char) if mode == InputMode::Kana => (
-                    CompositionState::Composing,
-                    vec![
-                        ClientAction::StartComposition,
-                        ClientAction::AppendText(char.to_string()),
-                    ],
-                ),
-                UserAction::Number(number) if mode == InputMode::Kana => (
-                    CompositionState::Composing,
-                    vec![
-                        ClientAction::StartComposition,
-                        ClientAction::AppendText(number.to_string()),
-                    ],
-                ),
-                UserAction::ToggleInputMode => (
-                    CompositionState::None,
-                    vec![match mode {
-                        InputMode::Kana => ClientAction::SetIMEMode(InputMode::Latin),
-                        InputMode::Latin => ClientAction::SetIMEMode(InputMode::Kana),
-                    }],
-                ),
-                _ => {
-                    return Ok(None);
-                }
+    #[tracing::instrument]
+    pub fn handle_key(&self, context: Option<&ITfContext>, wparam: WPARAM) -> Result<bool> {
+        if let Some(context) = context {
+            self.borrow_mut()?.context = Some(context.clone());
+        } else {
+            return Ok(false);
+        };
+
+        // follow this keystroke across the ipc worker and the server
+        let request_id = request_id::next();
+        let _request_id = request_id::enter(&request_id);
+        let span = tracing::info_span!("keystroke", request_id = %request_id);
+        let _span = span.enter();
+
+        if caps_lock::take_sent(wparam)? {
+            return Ok(false);
+        }
+
+        // apply results of the previous keys before deciding the next transition
+        // only character input is pipelined, other keys depend on the converted text
+        let thumb_shift_key = self.thumb_shift_key(wparam)?;
+        match UserAction::try_from(wparam.0) {
+            Ok(UserAction::Input(_) | UserAction::Number(_)) => self.drain_key_queue()?,
+            _ if thumb_shift_key.is_some() => self.drain_key_queue()?,
+            _ => self.flush_pending_keys()?,
+        }
+
+        // thumb-shift keys are paired with each other before they become a UserAction
+        let processed = match thumb_shift_key {
+            Some(key) => match self.pair_thumb_shift(wparam, key)? {
+                Some(processed) => Some(processed),
+                // a thumb key waiting for a character or its release
+                None => return Ok(true),
             },
-            CompositionState::Composing => match action {
-                UserAction::Input(char) => (
-                    CompositionState::Composing,
-                    vec![ClientAction::AppendText(char.to_string())],
-                ),
-                UserAction::Number(number) => (
-                    CompositionState::Composing,
-                    vec![ClientAction::AppendText(number.to_string())],
-                ),
-                UserAction::Backspace => {
-                    if composition.preview.chars().count() == 1 {
-                        (
-                            CompositionState::None,
-                            vec![ClientAction::RemoveText, ClientAction::EndComposition],
-                        )
-                    } else {
-                        (CompositionState::Composing, vec![ClientAction::RemoveText])
+            None => {
+                self.borrow_mut()?.thumb_shift.interrupt();
+                self.process_key(context, wparam)?
+            }
+        };
+        let Some((actions, transition)) = processed else {
+            self.forget_last_commit()?;
+            return Ok(false);
+        };
+
+        // the last commit can be converted again only with the next key
+        if actions.first() == Some(&ClientAction::ReopenLastCommit) {
+            if !self.reopen_last_commit()? {
+                return Ok(false);
+            }
+        } else {
+            self.forget_last_commit()?;
+        }
+
+        let actions = self.with_context(actions);
+        self.update_idle_timer(&transition)?;
+        // the conversion is done on the ipc worker, so return to the application quickly
+        self.enqueue_actions(actions, transition)?;
+
+        Ok(true)
+    }
+
+    // the candidates shown are committed when no key comes for a while, see `auto_commit`
+    fn update_idle_timer(&self, next: &CompositionState) -> Result<()> {
+        let ms = IMEState::get()?.config.conversion.auto_commit_ms;
+        let text_service = self.borrow()?;
+        let Some(message_window) = &text_service.message_window else {
+            return Ok(());
+        };
+
+        if ms > 0 && *next == CompositionState::Previewing {
+            message_window.start_idle_timer(ms);
+        } else {
+            message_window.stop_idle_timer();
+        }
+
+        Ok(())
+    }
+
+    // called by the idle timer of the message window
+    pub fn auto_commit(&self) -> Result<()> {
+        self.flush_pending_keys()?;
+        if self.borrow()?.borrow_composition()?.state != CompositionState::Previewing {
+            return Ok(());
+        }
+
+        tracing::debug!("Committing after the idle timeout");
+        self.handle_action(&[ClientAction::EndComposition], CompositionState::None)
+    }
+
+    // commit the candidate clicked in the candidate window
+    // this goes through the same path as Enter, so the candidate is learned as well
+    pub fn commit_candidate(&self, index: i32) -> Result<()> {
+        self.flush_pending_keys()?;
+        if self.borrow()?.borrow_composition()?.state == CompositionState::None {
+            return Ok(());
+        }
+
+        let actions = vec![
+            ClientAction::SetSelection(SetSelectionType::Number(index)),
+            ClientAction::EndComposition,
+        ];
+        self.handle_action(&actions, CompositionState::None)
+    }
+
+    // the mode chosen in the menu of the tray icon, `kana` for ひらがな and Latin otherwise
+    pub fn select_input_mode(&self, kana: bool) -> Result<()> {
+        self.flush_pending_keys()?;
+        let mode = if kana {
+            InputMode::Kana
+        } else {
+            InputMode::Latin
+        };
+        if IMEState::get()?.input_mode == mode {
+            return Ok(());
+        }
+
+        self.handle_action(&[ClientAction::SetIMEMode(mode)], CompositionState::None)
+    }
+
+    // commit the kaomoji clicked in the palette, see `ClientAction::ShowPalette`
+    pub fn commit_palette(&self, index: i32) -> Result<()> {
+        self.flush_pending_keys()?;
+        let Some(face) = usize::try_from(index).ok().and_then(shared::kaomoji::face) else {
+            return Ok(());
+        };
+
+        let actions = vec![
+            ClientAction::ClosePalette,
+            ClientAction::InsertText(face.to_string()),
+        ];
+        self.handle_action(&actions, CompositionState::None)
+    }
+
+    // a click on the composition, see `ITfMouseSink`
+    // the conversion moves to the clause under the mouse, and the right button converts the reading
+    // returns false if the click should end the composition as usual
+    pub fn click_composition(&self, offset: usize, right: bool) -> Result<bool> {
+        self.flush_pending_keys()?;
+        let (state, distance) = {
+            let text_service = self.borrow()?;
+            let composition = text_service.borrow_composition()?;
+            let distance =
+                clause_at(&composition, offset) as i32 - composition.clauses.len() as i32;
+            (composition.state.clone(), distance)
+        };
+
+        let actions = match state {
+            CompositionState::None => return Ok(false),
+            // there are no clauses before the conversion, the same as Space
+            CompositionState::Composing if right => {
+                if IMEState::get()?.config.conversion.live_conversion {
+                    vec![ClientAction::SetSelection(SetSelectionType::Down)]
+                } else {
+                    vec![ClientAction::SetSelection(SetSelectionType::Number(0))]
+                }
+            }
+            CompositionState::Composing => vec![],
+            // the clauses are converted again one by one on the way, like the arrow keys
+            _ => {
+                vec![ClientAction::FocusClause(distance.signum()); distance.unsigned_abs() as usize]
+            }
+        };
+
+        if !actions.is_empty() {
+            self.handle_action(&actions, CompositionState::Previewing)?;
+        }
+
+        Ok(true)
+    }
+
+    // start a composition over the selected text and convert it
+    // useful when the text was typed with the IME off, or to choose another candidate for it
+    pub fn convert_selection(&self, context: Option<&ITfContext>) -> Result<bool> {
+        let Some(context) = context else {
+            return Ok(false);
+        };
+        self.borrow_mut()?.context = Some(context.clone());
+
+        self.flush_pending_keys()?;
+        let Some((actions, transition)) = self.selection_actions()? else {
+            return Ok(false);
+        };
+
+        if !IMEState::get()?.input_mode.is_composing() {
+            self.handle_action(
+                &[ClientAction::SetIMEMode(InputMode::Kana)],
+                CompositionState::None,
+            )?;
+        }
+
+        let actions = self.with_context(actions);
+        self.handle_action(&actions, transition)?;
+
+        Ok(true)
+    }
+
+    // the actions converting the selected text, None if there is nothing to convert
+    fn selection_actions(&self) -> Result<Option<(Vec<ClientAction>, CompositionState)>> {
+        if self.borrow()?.borrow_composition()?.state != CompositionState::None {
+            return Ok(None);
+        }
+
+        let text = self.selected_text()?;
+        if text.is_empty() || text.contains(['\r', '\n']) {
+            tracing::debug!("Nothing to convert in the selection: {:?}", text);
+            return Ok(None);
+        }
+
+        let processed = if text.is_ascii() {
+            // the composition replaces the selection, and romaji is converted in lowercase
+            (
+                vec![
+                    ClientAction::StartComposition,
+                    ClientAction::AppendText(text.to_lowercase()),
+                ],
+                CompositionState::Composing,
+            )
+        } else {
+            // committed text, which the server reads before the composition replaces it
+            (
+                vec![
+                    ClientAction::Reconvert(text),
+                    ClientAction::SetSelection(SetSelectionType::Number(0)),
+                ],
+                CompositionState::Previewing,
+            )
+        };
+
+        Ok(Some(processed))
+    }
+
+    // handle actions synchronously
+    // queued keys are processed first so that the order of the actions is preserved
+    #[tracing::instrument]
+    pub fn handle_action(
+        &self,
+        actions: &[ClientAction],
+        transition: CompositionState,
+    ) -> Result<()> {
+        let request_id = request_id::current().unwrap_or_else(request_id::next);
+        let _request_id = request_id::enter(&request_id);
+        let span = tracing::info_span!("action", request_id = %request_id);
+        let _span = span.enter();
+
+        if let Some(seq) = self.enqueue_actions(actions.to_vec(), transition)? {
+            self.flush_key_queue(seq)?;
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    pub fn apply_edit_ops(&self, ops: &[EditOp], state: &CompositionState) -> Result<()> {
+        // the clause is highlighted only while the candidates are shown
+        // without live conversion, the text while typing is the reading itself
+        let live = IMEState::get()?.config.conversion.live_conversion;
+        let clause = match state {
+            CompositionState::Previewing => GUID_DISPLAY_ATTRIBUTE_TARGET,
+            CompositionState::Composing if !live => GUID_DISPLAY_ATTRIBUTE_INPUT,
+            _ => GUID_DISPLAY_ATTRIBUTE_CONVERTED,
+        };
+
+        for op in ops {
+            match op {
+                EditOp::StartComposition => self.start_composition()?,
+                EditOp::EndComposition => self.end_composition()?,
+                EditOp::SetText(clauses, text, subtext) => {
+                    self.set_text(clauses, text, subtext, &clause)?
+                }
+                EditOp::ShiftStart(committed, text, subtext) => {
+                    self.shift_start(committed, text, subtext, &clause)?
+                }
+                EditOp::InsertText(text) => self.insert_text(text)?,
+                EditOp::UpdatePos => self.update_pos()?,
+                EditOp::UpdateLangBar => {
+                    let _ = self.update_lang_bar();
+                }
+                EditOp::FlashModeIndicator(mode) => {
+                    // the language bar shows the mode anyway, so errors are ignored
+                    if let Err(e) = self.flash_mode_indicator(mode) {
+                        tracing::debug!("Failed to flash the mode indicator: {:?}", e);
                     }
                 }
-                UserAction::Enter => {
-                    if composition.suffix.is_empty() {
-                        (CompositionState::None, vec![ClientAction::EndComposition])
-                    } else {
-                        (
-                            CompositionState::Composing,
-                            vec![ClientAction::ShrinkText("".to_string())],
-                        )
+                EditOp::UpdateContext(composing) => {
+                    // the context only improves the conversion, so errors are ignored
+                    if let Err(e) = self.update_context(composing) {
+                        tracing::debug!("Failed to update the context: {:?}", e);
                     }
                 }
-                UserAction::Escape => (
+            }
+        }
+
+        // the composition may have been started or ended
+        if let Err(e) = self.publish_status(true) {
+            tracing::debug!("Failed to publish the status: {:?}", e);
+        }
+
+        Ok(())
+    }
+}
+
+// the actions for a key and the state after them
+// the TSF state comes in the arguments, so that the replay test can run it without TSF
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn key_transition(
+    wparam: WPARAM,
+    action: UserAction,
+    composition: &Composition,
+    mode: &InputMode,
+    config: &AppConfig,
+    can_reopen: bool,
+    after_japanese: impl FnOnce() -> bool,
+) -> Result<Option<(Vec<ClientAction>, CompositionState)>> {
+    let keys = &config.keys;
+    let live = config.conversion.live_conversion;
+    let symbols = &config.symbols;
+
+    // the function keys go to the application unless they are bound to a form of the reading
+    let action = match action {
+        UserAction::Function(key) => match keys.function_key(key) {
+            Some(transform) => UserAction::Transform(transform.into()),
+            None => UserAction::Unknown,
+        },
+        action => action,
+    };
+
+    // Caps Lock and Eisū switch the mode like Zenkaku/Hankaku unless they are left as they are
+    let caps_lock_key = matches!(action, UserAction::CapsLock);
+    let action = match action {
+        UserAction::CapsLock if keys.caps_lock == CapsLockBehavior::Caps => UserAction::Unknown,
+        UserAction::CapsLock => UserAction::ToggleInputMode,
+        action => action,
+    };
+
+    // Henkan converts while composing like Space
+    let action = match action {
+        UserAction::Henkan | UserAction::Muhenkan if !keys.henkan_muhenkan => UserAction::Unknown,
+        UserAction::Henkan if composition.state != CompositionState::None => UserAction::Space,
+        action => action,
+    };
+
+    // the case tells the Shift apart from then on, Caps Lock alone doesn't start a katakana run
+    let action = match action {
+        UserAction::Input(c) if is_shifted(mode, keys.shift_katakana, c.to_ascii_uppercase()) => {
+            match sticky_keys::is_held(VK_SHIFT) {
+                true => UserAction::Input(c.to_ascii_uppercase()),
+                false => UserAction::Input(c.to_ascii_lowercase()),
+            }
+        }
+        action => action,
+    };
+
+    // a symbol set to commit is typed after the composition, the server never sees it
+    if let UserAction::Input(c) = action {
+        if mode.is_composing()
+            && symbols.commits(c)
+            && composition.state != CompositionState::Selecting
+        {
+            let mut actions = match composition.state {
+                CompositionState::None => vec![],
+                _ => vec![ClientAction::EndComposition],
+            };
+            actions.push(ClientAction::InsertText(typed_text(mode, &c.to_string())?));
+            return Ok(Some((actions, CompositionState::None)));
+        }
+    }
+
+    let (transition, actions) = match composition.state {
+        CompositionState::None => match action {
+            // convert the text committed right before again
+            UserAction::Transform(set_type) if can_reopen => (
+                CompositionState::Previewing,
+                vec![
+                    ClientAction::ReopenLastCommit,
+                    ClientAction::SetTextWithType(set_type),
+                ],
+            ),
+            UserAction::Input(char) if mode.is_composing() => (
+                CompositionState::Composing,
+                vec![
+                    ClientAction::StartComposition,
+                    ClientAction::AppendText(char.to_string()),
+                ],
+            ),
+            UserAction::Number(number) if mode.is_composing() => (
+                CompositionState::Composing,
+                vec![
+                    ClientAction::StartComposition,
+                    ClientAction::AppendText(number.to_string()),
+                ],
+            ),
+            UserAction::Input(char) if *mode == InputMode::FullLatin => (
+                CompositionState::None,
+                vec![ClientAction::InsertText(to_full_latin(&char.to_string()))],
+            ),
+            UserAction::Number(number) if *mode == InputMode::FullLatin => (
+                CompositionState::None,
+                vec![ClientAction::InsertText(to_full_latin(&number.to_string()))],
+            ),
+            UserAction::Space if *mode == InputMode::FullLatin => (
+                CompositionState::None,
+                vec![ClientAction::InsertText(to_full_latin(" "))],
+            ),
+            // the half-width space is left to the application
+            UserAction::Space if mode.is_composing() => {
+                let full = match config.space_width {
+                    SpaceWidth::Half => false,
+                    SpaceWidth::Full => true,
+                    SpaceWidth::AfterJapanese => after_japanese(),
+                };
+                if !full {
+                    return Ok(None);
+                }
+                (
                     CompositionState::None,
-                    vec![ClientAction::RemoveText, ClientAction::EndComposition],
-                ),
-                UserAction::Navigation(direction) => match direction {
-                    Navigation::Right => (
-                        CompositionState::Composing,
-                        vec![ClientAction::MoveCursor(1)],
-                    ),
-                    Navigation::Left => (
+                    vec![ClientAction::InsertText("\u{3000}".to_string())],
+                )
+            }
+            UserAction::ToggleInputMode => (
+                CompositionState::None,
+                vec![match mode {
+                    InputMode::Latin => ClientAction::SetIMEMode(InputMode::Kana),
+                    _ => ClientAction::SetIMEMode(InputMode::Latin),
+                }],
+            ),
+            UserAction::CycleInputMode => (
+                CompositionState::None,
+                vec![ClientAction::SetIMEMode(mode.next(&InputMode::cycle(keys)))],
+            ),
+            UserAction::Henkan => (
+                CompositionState::None,
+                vec![ClientAction::SetIMEMode(InputMode::Kana)],
+            ),
+            UserAction::Muhenkan => (
+                CompositionState::None,
+                vec![ClientAction::SetIMEMode(InputMode::Latin)],
+            ),
+            _ => {
+                return Ok(None);
+            }
+        },
+        CompositionState::Composing => match action {
+            UserAction::Input(char) => (
+                CompositionState::Composing,
+                vec![ClientAction::AppendText(char.to_string())],
+            ),
+            UserAction::Number(number) => (
+                CompositionState::Composing,
+                vec![ClientAction::AppendText(number.to_string())],
+            ),
+            UserAction::Backspace => {
+                if composition.preview.chars().count() == 1 && composition.clauses.is_empty() {
+                    (
+                        CompositionState::None,
+                        vec![ClientAction::RemoveText, ClientAction::EndComposition],
+                    )
+                } else {
+                    (CompositionState::Composing, vec![ClientAction::RemoveText])
+                }
+            }
+            UserAction::Enter => {
+                if composition.suffix.is_empty() {
+                    (CompositionState::None, vec![ClientAction::EndComposition])
+                } else {
+                    (
                         CompositionState::Composing,
-                        vec![ClientAction::MoveCursor(-1)],
-                    ),
-                    Navigation::Up => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetSelection(SetSelectionType::Up)],
-                    ),
-                    Navigation::Down => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetSelection(SetSelectionType::Down)],
-                    ),
-                },
-                UserAction::ToggleInputMode => (
-                    CompositionState::None,
-                    vec![
-                        ClientAction::EndComposition,
-                        ClientAction::SetIMEMode(InputMode::Latin),
-                    ],
+                        vec![ClientAction::ShrinkText("".to_string())],
+                    )
+                }
+            }
+            UserAction::Escape => escape_transition(&composition.state, &keys.escape),
+            UserAction::Navigation(direction) => match direction {
+                Navigation::Right => (
+                    CompositionState::Composing,
+                    vec![ClientAction::MoveCursor(1)],
+                ),
+                Navigation::Left => (
+                    CompositionState::Composing,
+                    vec![ClientAction::MoveCursor(-1)],
                 ),
-                UserAction::Space | UserAction::Tab => (
+                Navigation::Up => (
+                    CompositionState::Previewing,
+                    vec![ClientAction::SetSelection(SetSelectionType::Up)],
+                ),
+                Navigation::Down => (
                     CompositionState::Previewing,
                     vec![ClientAction::SetSelection(SetSelectionType::Down)],
                 ),
-                UserAction::Function(key) => match key {
-                    Function::Six => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetTextWithType(SetTextType::Hiragana)],
-                    ),
-                    Function::Seven => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetTextWithType(SetTextType::Katakana)],
-                    ),
-                    Function::Eight => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetTextWithType(SetTextType::HalfKatakana)],
-                    ),
-                    Function::Nine => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetTextWithType(SetTextType::FullLatin)],
-                    ),
-                    Function::Ten => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetTextWithType(SetTextType::HalfLatin)],
-                    ),
-                },
-                _ => {
-                    return Ok(None);
-                }
             },
-            CompositionState::Previewing => match action {
-                UserAction::Input(char) => (
+            // the window follows the selection, so this also shows the new page
+            UserAction::PageUp => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetSelection(SetSelectionType::Page(-1))],
+            ),
+            UserAction::PageDown => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetSelection(SetSelectionType::Page(1))],
+            ),
+            UserAction::ToggleInputMode | UserAction::Muhenkan => (
+                CompositionState::None,
+                vec![
+                    ClientAction::EndComposition,
+                    ClientAction::SetIMEMode(InputMode::Latin),
+                ],
+            ),
+            UserAction::CycleInputMode => (
+                CompositionState::None,
+                vec![
+                    ClientAction::EndComposition,
+                    ClientAction::SetIMEMode(mode.next(&InputMode::cycle(keys))),
+                ],
+            ),
+            UserAction::Tab if !composition.predictions.is_empty() => (
+                CompositionState::Composing,
+                vec![ClientAction::SelectPrediction],
+            ),
+            // the first candidate is already shown with live conversion
+            UserAction::Space | UserAction::Tab if !live => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetSelection(SetSelectionType::Number(0))],
+            ),
+            UserAction::Space | UserAction::Tab => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetSelection(SetSelectionType::Down)],
+            ),
+            UserAction::Transform(set_type) => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetTextWithType(set_type)],
+            ),
+            _ => {
+                return Ok(None);
+            }
+        },
+        CompositionState::Previewing => match action {
+            UserAction::Input(char) => (
+                CompositionState::Composing,
+                vec![ClientAction::ShrinkText(char.to_string())],
+            ),
+            // commit the candidate with the number shown in the candidate window
+            UserAction::Number(number) => match numbered_candidate(composition, number) {
+                Some((index, sub_text)) if sub_text.is_empty() => (
+                    CompositionState::None,
+                    vec![
+                        ClientAction::SetSelection(SetSelectionType::Number(index)),
+                        ClientAction::EndComposition,
+                    ],
+                ),
+                // only the first clause is committed, like Enter
+                Some((index, _)) => (
                     CompositionState::Composing,
-                    vec![ClientAction::ShrinkText(char.to_string())],
+                    vec![
+                        ClientAction::SetSelection(SetSelectionType::Number(index)),
+                        ClientAction::ShrinkText("".to_string()),
+                    ],
                 ),
-                UserAction::Number(number) => (
+                None => (
                     CompositionState::Composing,
                     vec![ClientAction::ShrinkText(number.to_string())],
                 ),
-                UserAction::Backspace => {
-                    if composition.preview.chars().count() == 1 {
-                        (
-                            CompositionState::None,
-                            vec![ClientAction::RemoveText, ClientAction::EndComposition],
-                        )
-                    } else {
-                        (CompositionState::Composing, vec![ClientAction::RemoveText])
-                    }
+            },
+            UserAction::Backspace if keys.backspace == BackspaceBehavior::Revert => (
+                CompositionState::Composing,
+                vec![ClientAction::RevertToReading],
+            ),
+            UserAction::Backspace => {
+                if composition.preview.chars().count() == 1 && composition.clauses.is_empty() {
+                    (
+                        CompositionState::None,
+                        vec![ClientAction::RemoveText, ClientAction::EndComposition],
+                    )
+                } else {
+                    (CompositionState::Composing, vec![ClientAction::RemoveText])
                 }
-                UserAction::Enter => {
-                    if composition.suffix.is_empty() {
-                        (CompositionState::None, vec![ClientAction::EndComposition])
-                    } else {
-                        (
-                            CompositionState::Composing,
-                            vec![ClientAction::ShrinkText("".to_string())],
-                        )
-                    }
+            }
+            UserAction::Enter => {
+                if composition.suffix.is_empty() {
+                    (CompositionState::None, vec![ClientAction::EndComposition])
+                } else {
+                    (
+                        CompositionState::Composing,
+                        vec![ClientAction::ShrinkText("".to_string())],
+                    )
                 }
-                UserAction::Escape => (
-                    CompositionState::None,
-                    vec![ClientAction::RemoveText, ClientAction::EndComposition],
+            }
+            UserAction::Escape => escape_transition(&composition.state, &keys.escape),
+            UserAction::Navigation(direction) => match direction {
+                // Shift+Left/Right changes the reading of the clause being converted
+                Navigation::Right if sticky_keys::is_held(VK_SHIFT) => (
+                    CompositionState::Previewing,
+                    vec![ClientAction::ResizeSegment(1)],
                 ),
-                UserAction::Navigation(direction) => match direction {
-                    Navigation::Right => (
-                        CompositionState::Composing,
-                        vec![ClientAction::MoveCursor(1)],
-                    ),
-                    Navigation::Left => (
-                        CompositionState::Composing,
-                        vec![ClientAction::MoveCursor(-1)],
-                    ),
-                    Navigation::Up => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetSelection(SetSelectionType::Up)],
-                    ),
-                    Navigation::Down => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetSelection(SetSelectionType::Down)],
-                    ),
-                },
-                UserAction::ToggleInputMode => (
-                    CompositionState::None,
-                    vec![
-                        ClientAction::EndComposition,
-                        ClientAction::SetIMEMode(InputMode::Latin),
-                    ],
+                Navigation::Left if sticky_keys::is_held(VK_SHIFT) => (
+                    CompositionState::Previewing,
+                    vec![ClientAction::ResizeSegment(-1)],
+                ),
+                // move between the clauses while there are any on that side
+                Navigation::Right if !composition.suffix.is_empty() => (
+                    CompositionState::Previewing,
+                    vec![ClientAction::FocusClause(1)],
                 ),
-                UserAction::Space | UserAction::Tab => (
+                Navigation::Left if !composition.clauses.is_empty() => (
+                    CompositionState::Previewing,
+                    vec![ClientAction::FocusClause(-1)],
+                ),
+                Navigation::Right => (
+                    CompositionState::Composing,
+                    vec![ClientAction::MoveCursor(1)],
+                ),
+                Navigation::Left => (
+                    CompositionState::Composing,
+                    vec![ClientAction::MoveCursor(-1)],
+                ),
+                Navigation::Up => (
+                    CompositionState::Previewing,
+                    vec![ClientAction::SetSelection(SetSelectionType::Up)],
+                ),
+                Navigation::Down => (
                     CompositionState::Previewing,
                     vec![ClientAction::SetSelection(SetSelectionType::Down)],
                 ),
-                UserAction::Function(key) => match key {
-                    Function::Six => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetTextWithType(SetTextType::Hiragana)],
-                    ),
-                    Function::Seven => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetTextWithType(SetTextType::Katakana)],
-                    ),
-                    Function::Eight => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetTextWithType(SetTextType::HalfKatakana)],
-                    ),
-                    Function::Nine => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetTextWithType(SetTextType::FullLatin)],
-                    ),
-                    Function::Ten => (
-                        CompositionState::Previewing,
-                        vec![ClientAction::SetTextWithType(SetTextType::HalfLatin)],
-                    ),
-                },
-                _ => {
-                    return Ok(None);
-                }
             },
+            UserAction::PageUp => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetSelection(SetSelectionType::Page(-1))],
+            ),
+            UserAction::PageDown => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetSelection(SetSelectionType::Page(1))],
+            ),
+            UserAction::ToggleInputMode | UserAction::Muhenkan => (
+                CompositionState::None,
+                vec![
+                    ClientAction::EndComposition,
+                    ClientAction::SetIMEMode(InputMode::Latin),
+                ],
+            ),
+            UserAction::CycleInputMode => (
+                CompositionState::None,
+                vec![
+                    ClientAction::EndComposition,
+                    ClientAction::SetIMEMode(mode.next(&InputMode::cycle(keys))),
+                ],
+            ),
+            UserAction::Tab if keys.tab == TabBehavior::Detail => (
+                CompositionState::Previewing,
+                vec![ClientAction::ToggleDetail],
+            ),
+            UserAction::Space | UserAction::Tab => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetSelection(SetSelectionType::Down)],
+            ),
+            UserAction::Transform(set_type) => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetTextWithType(set_type)],
+            ),
             _ => {
                 return Ok(None);
             }
-        };
+        },
+        _ => {
+            return Ok(None);
+        }
+    };
 
-        Ok(Some((actions, transition)))
+    // the system has toggled Caps Lock already for the Caps Lock key, but not for Eisū
+    let mut actions = actions;
+    if caps_lock_key
+        && (keys.caps_lock == CapsLockBehavior::Both) != (wparam.0 == VK_CAPITAL.0 as usize)
+    {
+        actions.push(ClientAction::ToggleCapsLock);
     }
 
-    #[tracing::instrument]
-    pub fn handle_key(&self, context: Option<&ITfContext>, wparam: WPARAM) -> Result<bool> {
-        if let Some(context) = context {
-            self.borrow_mut()?.context = Some(context.clone());
-        } else {
-            return Ok(false);
-        };
+    Ok(Some((actions, transition)))
+}
 
-        if let Some((actions, transition)) = self.process_key(context, wparam)? {
-            self.handle_action(&actions, transition)?;
-        } else {
-            return Ok(false);
-        }
+// run the actions against the conversion server
+// this does not touch TSF, so it can be called from the ipc worker
+// the edits to the document are returned as `EditOp`s
+#[tracing::instrument]
+pub fn run_actions(
+    composition: &Composition,
+    mode: &InputMode,
+    actions: &[ClientAction],
+    transition: CompositionState,
+    reconnect: &mut ReconnectState,
+) -> Result<(Composition, Vec<EditOp>)> {
+    let mut preview = composition.preview.clone();
+    let mut suffix = composition.suffix.clone();
+    let mut raw_input = composition.raw_input.clone();
+    let mut raw_hiragana = composition.raw_hiragana.clone();
+    let mut corresponding_count = composition.corresponding_count.clone();
+    let mut candidates = composition.candidates.clone();
+    let mut selection_index = composition.selection_index;
+    let mut last_commit = composition.last_commit.clone();
+    let mut clauses = composition.clauses.clone();
+    let mut predictions = composition.predictions.clone();
+    let mut prediction = composition.prediction;
+    let mut out_of_sync = composition.out_of_sync;
+    // IPC service is optional - some actions (like SetIMEMode) don't need it
+    let mut ipc_service = IMEState::get()?.ipc_service.clone();
+    let mut transition = transition;
+    let mut ops = vec![];
+    // the mode when the key was pressed, changed by `SetIMEMode` in the batch
+    let mut current_mode = mode.clone();
+    let live = IMEState::get()?.config.conversion.live_conversion;
+    // without live conversion, nothing has been converted before Space
+    // the katakana runs are shown instead of the conversion, see `Composition.katakana`
+    // a candidate picked in the same batch, e.g. clicked in the candidate window, is chosen by the
+    // user even while composing
+    let picked = actions
+        .iter()
+        .any(|action| matches!(action, ClientAction::SetSelection(_)));
+    let converted = picked
+        || (live && !composition.katakana.contains(&true))
+        || composition.state == CompositionState::Previewing;
+    let shift_katakana = IMEState::get()?.config.keys.shift_katakana;
+    let mut shifted = false;
+    // see `CandidateWindowTiming`
+    let inline_first =
+        IMEState::get()?.config.candidate.window == CandidateWindowTiming::SecondSpace;
 
-        Ok(true)
+    // Helper macro to get IPC service, with lazy reconnection if needed
+    // Returns Result<&mut IPCService, anyhow::Error>
+    // Uses cooldown to avoid blocking UI with repeated failed connection attempts
+    macro_rules! require_ipc {
+        () => {{
+            if ipc_service.is_none() && reconnect.should_try() {
+                // Try lazy reconnection (only if cooldown has passed)
+                tracing::debug!("IPC service is None, attempting lazy reconnection...");
+                match IPCService::new() {
+                    Ok(new_ipc) => {
+                        tracing::debug!("Lazy IPC reconnection successful");
+                        ipc_service = Some(new_ipc);
+                        // Also update the global state
+                        if let Ok(mut state) = IMEState::get() {
+                            state.ipc_service = ipc_service.clone();
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("Lazy IPC reconnection failed: {:?}", e);
+                        reconnect.mark_failed();
+                    }
+                }
+            }
+            ipc_service.as_mut().context("IPC service not available")
+        }};
     }
 
-    #[tracing::instrument]
-    pub fn handle_action(
-        &self,
-        actions: &[ClientAction],
-        transition: CompositionState,
-    ) -> Result<()> {
-        #[allow(clippy::let_and_return)]
-        let (composition, mode) = {
-            let text_service = self.borrow()?;
-            let composition = text_service.borrow_composition()?.clone();
-            let mode = IMEState::get()?.input_mode.clone();
-            (composition, mode)
-        };
+    // the context needs an extra edit session, so it is only updated in applications which allow it
+    let quirks = app_quirks::current();
+    let surrounding_text = quirks.surrounding_text;
 
-        let mut preview = composition.preview.clone();
-        let mut suffix = composition.suffix.clone();
-        let mut raw_input = composition.raw_input.clone();
-        let mut raw_hiragana = composition.raw_hiragana.clone();
-        let mut corresponding_count = composition.corresponding_count.clone();
-        let mut candidates = composition.candidates.clone();
-        let mut selection_index = composition.selection_index;
-        // IPC service is optional - some actions (like SetIMEMode) don't need it
-        let mut ipc_service = IMEState::get()?.ipc_service.clone();
-        let mut transition = transition;
-
-        // Helper macro to get IPC service, with lazy reconnection if needed
-        // Returns Result<&mut IPCService, anyhow::Error>
-        // Uses cooldown to avoid blocking UI with repeated failed connection attempts
-        macro_rules! require_ipc {
-            () => {{
-                if ipc_service.is_none() && should_try_ipc_reconnect() {
-                    // Try lazy reconnection (only if cooldown has passed)
-                    tracing::debug!("IPC service is None, attempting lazy reconnection...");
-                    debug_log("Attempting lazy IPC reconnection...");
-                    match IPCService::new() {
-                        Ok(new_ipc) => {
-                            tracing::debug!("Lazy IPC reconnection successful");
-                            debug_log("Lazy IPC reconnection successful");
-                            ipc_service = Some(new_ipc);
-                            // Also update the global state
-                            if let Ok(mut state) = IMEState::get() {
-                                state.ipc_service = ipc_service.clone();
-                            }
-                        }
-                        Err(e) => {
-                            tracing::warn!("Lazy IPC reconnection failed: {:?}", e);
-                            debug_log(&format!("Lazy IPC reconnection failed: {:?}", e));
-                            mark_ipc_reconnect_failed();
-                        }
-                    }
+    tracing::debug!(
+        "run_actions: actions={:?}, ipc_available={}",
+        actions,
+        ipc_service.is_some()
+    );
+
+    // Helper macro to try IPC but continue on failure (for optional IPC calls)
+    macro_rules! try_ipc {
+        ($expr:expr) => {{
+            if let Some(ref mut ipc) = ipc_service {
+                let _ = $expr(ipc);
+            }
+        }};
+    }
+
+    // Helper macro to learn the selected candidate
+    // every commit (Enter, click, partial commit) goes through this
+    macro_rules! learn_selection {
+        () => {{
+            let learning = {
+                let state = IMEState::get()?;
+                state.config.conversion.learning == LearningMode::Learn && !state.privacy
+            };
+            if learning
+                && converted
+                && candidates.get(selection_index).is_some()
+                && !preview.is_empty()
+                // the server doesn't know the candidates made by the client or its own tables
+                && !matches!(
+                    candidates.sources.get(selection_index as usize),
+                    Some(
+                        CandidateSource::Generated
+                            | CandidateSource::Emoji
+                            | CandidateSource::English
+                    )
+                )
+            {
+                try_ipc!(|ipc: &mut IPCService| ipc.learn_candidate(selection_index));
+            }
+        }};
+    }
+
+    // Helper macro to report a commit to the automations, see `AutomationConfig`
+    macro_rules! report_commit {
+        ($text:expr, $reading:expr) => {{
+            let enabled = {
+                let state = IMEState::get()?;
+                state.config.automation.commit_events && !state.privacy
+            };
+            if enabled {
+                try_ipc!(|ipc: &mut IPCService| ipc.report_commit($text, $reading));
+            }
+        }};
+    }
+
+    // Helper macro to show the text after the converted clauses
+    macro_rules! set_text {
+        ($text:expr, $suffix:expr) => {{
+            ops.push(EditOp::SetText(
+                joined(&clauses, |c| &c.text),
+                $text,
+                $suffix,
+            ));
+        }};
+    }
+
+    // Helper macro to record the result of a conversion request
+    // the language bar is updated when the server becomes unavailable or comes back
+    macro_rules! report_ipc {
+        ($result:expr) => {{
+            let result = $result;
+            let changed = match &result {
+                Ok(_) => IMEState::get()?.server_status.record_success(),
+                Err(e) => {
+                    tracing::warn!("Conversion request failed: {:?}", e);
+                    IMEState::get()?.server_status.record_failure()
                 }
-                ipc_service
-                    .as_mut()
-                    .context("IPC service not available")
-            }};
-        }
+            };
+            if changed {
+                ops.push(EditOp::UpdateLangBar);
+            }
+            result
+        }};
+    }
 
-        // Skip update_context to reduce RequestEditSession calls
-        // Qt apps crash when multiple RequestEditSession calls happen in rapid succession
-        // update_context is used for surrounding text context (non-essential)
-        // TODO: Re-enable for non-Qt apps if needed
-        // self.update_context(&preview)?;
+    // Helper macro to show the completions of the reading converted last
+    // this runs on every key, so the window is only updated when they change
+    // they come with the conversion, so no request is made for them
+    macro_rules! update_predictions {
+        () => {{
+            let new_predictions = if IMEState::get()?.config.candidate.predictions {
+                candidates.predictions.clone()
+            } else {
+                vec![]
+            };
+            if new_predictions != predictions || prediction.is_some() {
+                predictions = new_predictions;
+                prediction = None;
+                try_ipc!(|ipc: &mut IPCService| ipc.set_predictions(&predictions, -1));
+            }
+        }};
+    }
 
-        debug_log(&format!("handle_action: actions={:?}, ipc_available={}", actions, ipc_service.is_some()));
+    // Helper macro to commit the first clause and keep the rest composing
+    // `$typed` is appended to the rest, falls back to hiragana when the server is not available
+    macro_rules! shrink {
+        ($typed:expr) => {{
+            let typed: String = $typed;
+            raw_input = raw_input
+                .chars()
+                .skip(corresponding_count as usize)
+                .collect();
 
-        // Helper macro to try IPC but continue on failure (for optional IPC calls)
-        macro_rules! try_ipc {
-            ($expr:expr) => {{
-                if let Some(ref mut ipc) = ipc_service {
-                    let _ = $expr(ipc);
+            // Learn the selected candidate before shrinking
+            learn_selection!();
+            // the suffix is the reading of the rest, which stays composing
+            let reading = raw_hiragana
+                .strip_suffix(suffix.as_str())
+                .unwrap_or(&raw_hiragana)
+                .to_string();
+            // the converted clauses before it are committed too
+            let committed = format!("{}{preview}", joined(&clauses, |c| &c.text));
+            report_commit!(
+                committed.clone(),
+                format!("{}{reading}", joined(&clauses, |c| &c.reading))
+            );
+            // shrinking already converts the remainder, typed text needs one more request
+            let mut shrunk = false;
+            let result = report_ipc!(require_ipc!().and_then(|ipc| {
+                let remainder = ipc.shrink_text(corresponding_count)?;
+                if typed.is_empty() {
+                    return Ok(remainder);
                 }
-            }};
+                shrunk = true;
+                ipc.append_text(typed.clone())
+            }));
+            selection_index = 0;
+
+            let (text, sub_text) = if let Ok(new_candidates) = result {
+                candidates = new_candidates;
+                let candidate = candidates.get_or_hiragana(selection_index);
+
+                corresponding_count = candidate.corresponding_count;
+                raw_hiragana = candidates.hiragana.clone();
+                try_ipc!(|ipc: &mut IPCService| ipc.set_candidates(&candidates));
+                try_ipc!(|ipc: &mut IPCService| ipc.set_selection(selection_index));
+
+                composing_text(
+                    mode,
+                    live,
+                    candidate.text,
+                    candidate.sub_text,
+                    &raw_hiragana,
+                )
+            } else {
+                // Offline mode: commit the preview and keep the rest as hiragana
+                tracing::debug!("Offline mode: committing the preview without conversion");
+                // the server has the rest without the typed text, so it can't continue from there
+                out_of_sync |= shrunk;
+                candidates = Candidates::default();
+                raw_hiragana = format!("{suffix}{typed}");
+                corresponding_count = raw_hiragana.chars().count() as i32;
+
+                composing_text(
+                    mode,
+                    live,
+                    raw_hiragana.clone(),
+                    String::new(),
+                    &raw_hiragana,
+                )
+            };
+
+            // commit the clause and show the remainder in a single edit session
+            ops.push(EditOp::ShiftStart(
+                committed,
+                text.clone(),
+                sub_text.clone(),
+            ));
+            clauses.clear();
+            preview = text;
+            suffix = sub_text;
+            update_predictions!();
+
+            // the committed clause is now part of the context
+            if surrounding_text {
+                ops.push(EditOp::UpdateContext(format!("{preview}{suffix}")));
+            }
+        }};
+    }
+
+    // compose the rest again before the server is asked anything about it, see `shrink!`
+    if out_of_sync {
+        let result = report_ipc!(require_ipc!().and_then(|ipc| {
+            ipc.clear_text()?;
+            if raw_input.is_empty() {
+                Ok(None)
+            } else {
+                ipc.append_text(raw_input.clone()).map(Some)
+            }
+        }));
+        if let Ok(new_candidates) = result {
+            tracing::debug!("Composed the rest again on the server");
+            out_of_sync = false;
+            candidates = new_candidates.unwrap_or_default();
         }
+    }
 
-        for action in actions {
-            match action {
-                ClientAction::StartComposition => {
-                    self.start_composition()?;
-                    self.update_pos()?;
-                    // Show window is optional - works without server
+    // the candidates while typing are shown in a smaller window than the converted ones
+    let kind = match transition {
+        CompositionState::Previewing | CompositionState::Selecting => CandidateKind::Conversion,
+        CompositionState::None | CompositionState::Composing => CandidateKind::Prediction,
+    };
+    try_ipc!(|ipc: &mut IPCService| ipc.set_candidate_kind(kind));
+
+    for action in actions {
+        match action {
+            ClientAction::StartComposition => {
+                last_commit = None;
+                // the candidates take the place of the palette
+                IMEState::get()?.palette = false;
+                ops.push(EditOp::StartComposition);
+                ops.push(EditOp::UpdatePos);
+                // Show window is optional - works without server
+                // not shown over fullscreen applications, see `FullscreenConfig`
+                if quirks.candidate_window && !inline_first {
                     try_ipc!(|ipc: &mut IPCService| ipc.show_window());
                 }
-                ClientAction::EndComposition => {
-                    // Learn the selected candidate if there was a valid selection
-                    if !candidates.texts.is_empty()
-                        && (selection_index as usize) < candidates.texts.len()
-                        && !preview.is_empty() {
-                        try_ipc!(|ipc: &mut IPCService| ipc.learn_candidate(selection_index));
-                    }
+            }
+            ClientAction::EndComposition => {
+                // a prediction is not one of the candidates, and it has a longer reading
+                let chosen = prediction.and_then(|index| predictions.get(index));
+                if chosen.is_none() {
+                    learn_selection!();
+                }
+                let hiragana = chosen.map_or(raw_hiragana.as_str(), |p| p.reading.as_str());
 
-                    self.end_composition()?;
-                    selection_index = 0;
-                    corresponding_count = 0;
-                    preview.clear();
-                    suffix.clear();
-                    raw_input.clear();
-                    raw_hiragana.clear();
-                    // UI calls are optional - works without server
-                    try_ipc!(|ipc: &mut IPCService| ipc.hide_window());
-                    try_ipc!(|ipc: &mut IPCService| ipc.set_candidates(vec![]));
-                    try_ipc!(|ipc: &mut IPCService| ipc.clear_text());
+                // the converted clauses are already in the composition
+                let committed = format!("{}{preview}{suffix}", joined(&clauses, |c| &c.text));
+                let reading = format!("{}{hiragana}", joined(&clauses, |c| &c.reading));
+                if !committed.is_empty() {
+                    report_commit!(committed.clone(), reading.clone());
                 }
-                ClientAction::AppendText(text) => {
-                    raw_input.push_str(&text);
-
-                    let fullwidth_text = match mode {
-                        InputMode::Kana => to_fullwidth(text, false),
-                        InputMode::Latin => text.to_string(),
-                    };
-
-                    // Try to get candidates from server, fall back to showing hiragana
-                    if let Ok(ipc) = require_ipc!() {
-                        candidates = ipc.append_text(fullwidth_text.clone())?;
-                        let conv_text = candidates.texts[selection_index as usize].clone();
-                        let sub_text = candidates.sub_texts[selection_index as usize].clone();
-                        let hiragana = candidates.hiragana.clone();
-
-                        corresponding_count = candidates.corresponding_count[selection_index as usize];
-
-                        preview = conv_text.clone();
-                        suffix = sub_text.clone();
-                        raw_hiragana = hiragana.clone();
-
-                        self.set_text(&conv_text, &sub_text)?;
-                        let _ = ipc.set_candidates(candidates.texts.clone());
-                        let _ = ipc.set_selection(selection_index as i32);
-                    } else {
-                        // Offline mode: just show the hiragana without conversion
-                        debug_log("Offline mode: showing hiragana without conversion");
-                        raw_hiragana.push_str(&fullwidth_text);
-                        preview = raw_hiragana.clone();
-                        suffix.clear();
-                        corresponding_count = raw_hiragana.chars().count() as i32;
-                        self.set_text(&preview, "")?;
+                // nothing is kept in the privacy mode, so it can't be converted again
+                let privacy = IMEState::get()?.privacy;
+                last_commit = (!committed.is_empty() && !privacy).then(|| LastCommit {
+                    text: committed,
+                    raw_input: format!("{}{raw_input}", joined(&clauses, |c| &c.raw_input)),
+                    raw_hiragana: reading,
+                });
+
+                ops.push(EditOp::EndComposition);
+                clauses.clear();
+                selection_index = 0;
+                corresponding_count = 0;
+                preview.clear();
+                suffix.clear();
+                raw_input.clear();
+                raw_hiragana.clear();
+                // UI calls are optional - works without server
+                try_ipc!(|ipc: &mut IPCService| ipc.hide_window());
+                try_ipc!(|ipc: &mut IPCService| ipc.set_candidates(&Candidates::default()));
+                try_ipc!(|ipc: &mut IPCService| ipc.clear_text());
+            }
+            ClientAction::AppendText(text) => {
+                raw_input.push_str(&text);
+                shifted |= text.chars().any(|c| is_shifted(mode, shift_katakana, c));
+
+                let fullwidth_text = typed_text(mode, text)?;
+
+                // Try to get candidates from server, fall back to showing hiragana
+                let result = report_ipc!(
+                    require_ipc!().and_then(|ipc| ipc.append_text(fullwidth_text.clone()))
+                );
+                if let (Ok(new_candidates), Ok(ipc)) = (result, require_ipc!()) {
+                    candidates = new_candidates;
+                    // the new list can be shorter than the previous one
+                    if candidates.get(selection_index).is_none() {
+                        selection_index = 0;
                     }
+                    let candidate = candidates.get_or_hiragana(selection_index);
+                    raw_hiragana = candidates.hiragana.clone();
+
+                    corresponding_count = candidate.corresponding_count;
+                    (preview, suffix) = composing_text(
+                        mode,
+                        live,
+                        candidate.text,
+                        candidate.sub_text,
+                        &raw_hiragana,
+                    );
+
+                    set_text!(preview.clone(), suffix.clone());
+                    let _ = ipc.set_candidates(&candidates);
+                    let _ = ipc.set_selection(selection_index as i32);
+                } else {
+                    // Offline mode: just show the hiragana without conversion
+                    tracing::debug!("Offline mode: showing hiragana without conversion");
+                    raw_hiragana.push_str(&fullwidth_text);
+                    (preview, suffix) = composing_text(
+                        mode,
+                        live,
+                        raw_hiragana.clone(),
+                        String::new(),
+                        &raw_hiragana,
+                    );
+                    corresponding_count = raw_hiragana.chars().count() as i32;
+                    set_text!(preview.clone(), "".to_string());
                 }
-                ClientAction::RemoveText => {
-                    // Try to use server, fall back to local handling
-                    if let Ok(ipc) = require_ipc!() {
-                        candidates = ipc.remove_text()?;
-                        let empty = "".to_string();
-                        let text = candidates
-                            .texts
-                            .get(selection_index as usize)
-                            .cloned()
-                            .unwrap_or(empty.clone());
-                        let sub_text = candidates
-                            .sub_texts
-                            .get(selection_index as usize)
-                            .cloned()
-                            .unwrap_or(empty.clone());
-                        let hiragana = candidates.hiragana.clone();
-                        corresponding_count = candidates
-                            .corresponding_count
-                            .get(selection_index as usize)
-                            .cloned()
-                            .unwrap_or(0);
-
-                        raw_input = raw_input
-                            .chars()
-                            .take(corresponding_count as usize)
-                            .collect();
-                        preview = text.clone();
-                        suffix = sub_text.clone();
-                        raw_hiragana = hiragana.clone();
-
-                        self.set_text(&text, &sub_text)?;
-                        let _ = ipc.set_candidates(candidates.texts.clone());
-                        let _ = ipc.set_selection(selection_index as i32);
-                    } else {
-                        // Offline mode: remove last character from hiragana
-                        debug_log("Offline mode: removing last character");
-                        let mut chars: Vec<char> = raw_hiragana.chars().collect();
-                        chars.pop();
-                        raw_hiragana = chars.into_iter().collect();
-                        raw_input = raw_input.chars().take(raw_input.chars().count().saturating_sub(1)).collect();
-                        preview = raw_hiragana.clone();
-                        suffix.clear();
-                        corresponding_count = raw_hiragana.chars().count() as i32;
-                        self.set_text(&preview, "")?;
+                update_predictions!();
+
+                // long readings slow down the conversion, so commit the first clause
+                let max_length = IMEState::get()?.config.conversion.max_length;
+                if max_length > 0 && raw_hiragana.chars().count() > max_length && !suffix.is_empty()
+                {
+                    tracing::debug!("Reading is longer than {}, committing a clause", max_length);
+                    shrink!(String::new());
+                }
+            }
+            ClientAction::RemoveText => {
+                // Try to use server, fall back to local handling
+                let result = report_ipc!(require_ipc!().and_then(|ipc| ipc.remove_text()));
+                if let (Ok(new_candidates), Ok(ipc)) = (result, require_ipc!()) {
+                    candidates = new_candidates;
+                    if candidates.get(selection_index).is_none() {
+                        selection_index = 0;
                     }
+                    let candidate = candidates.get(selection_index).unwrap_or_default();
+                    corresponding_count = candidate.corresponding_count;
+
+                    raw_input = raw_input
+                        .chars()
+                        .take(corresponding_count as usize)
+                        .collect();
+                    raw_hiragana = candidates.hiragana.clone();
+                    (preview, suffix) = composing_text(
+                        mode,
+                        live,
+                        candidate.text,
+                        candidate.sub_text,
+                        &raw_hiragana,
+                    );
+
+                    set_text!(preview.clone(), suffix.clone());
+                    let _ = ipc.set_candidates(&candidates);
+                    let _ = ipc.set_selection(selection_index as i32);
+                } else {
+                    // Offline mode: remove last character from hiragana
+                    tracing::debug!("Offline mode: removing last character");
+                    let mut chars: Vec<char> = raw_hiragana.chars().collect();
+                    chars.pop();
+                    raw_hiragana = chars.into_iter().collect();
+                    raw_input = raw_input
+                        .chars()
+                        .take(raw_input.chars().count().saturating_sub(1))
+                        .collect();
+                    (preview, suffix) = composing_text(
+                        mode,
+                        live,
+                        raw_hiragana.clone(),
+                        String::new(),
+                        &raw_hiragana,
+                    );
+                    corresponding_count = raw_hiragana.chars().count() as i32;
+                    set_text!(preview.clone(), "".to_string());
                 }
-                ClientAction::MoveCursor(_offset) => {
-                    // TODO: I'll use azookey-kkc's composingText
-                    // self.set_cursor(offset)?;
+                update_predictions!();
+            }
+            ClientAction::MoveCursor(_offset) => {
+                // TODO: I'll use azookey-kkc's composingText
+                // self.set_cursor(offset)?;
+            }
+            ClientAction::SetIMEMode(mode) => {
+                // Update the IME state - this is the core functionality
+                IMEState::get()?.input_mode = mode.clone();
+                // the global is already switched when the key is queued, see `enqueue_actions`
+                let previous = std::mem::replace(&mut current_mode, mode.clone());
+
+                // update the language bar icon
+                ops.push(EditOp::UpdateLangBar);
+                if previous != *mode {
+                    ops.push(EditOp::FlashModeIndicator(mode.indicator()));
                 }
-                ClientAction::SetIMEMode(mode) => {
-                    // Update the IME state - this is the core functionality
-                    let mut ime_state = IMEState::get()?;
-                    ime_state.input_mode = mode.clone();
 
-                    // update the language bar icon
-                    let _ = self.update_lang_bar();
+                // Reset composition state (local only, no IPC)
+                clauses.clear();
+                selection_index = 0;
+                corresponding_count = 0;
+                preview.clear();
+                suffix.clear();
+                raw_input.clear();
+                raw_hiragana.clear();
 
-                    // Reset composition state (local only, no IPC)
-                    selection_index = 0;
-                    corresponding_count = 0;
-                    preview.clear();
-                    suffix.clear();
-                    raw_input.clear();
-                    raw_hiragana.clear();
-
-                    // Note: Skipping IPC calls (set_input_mode, clear_text) as they
-                    // use blocking gRPC which can freeze if server is not responding.
-                    // The language bar icon update is sufficient for mode indication.
+                // Note: Skipping IPC calls (clear_text) as they
+                // use blocking gRPC which can freeze if server is not responding.
+                // The mode is sent to the ui with the language bar icon, see `update_lang_bar`.
+            }
+            ClientAction::ToggleCapsLock => {
+                if let Err(e) = caps_lock::toggle() {
+                    tracing::warn!("Failed to toggle Caps Lock: {:?}", e);
                 }
-                ClientAction::SetSelection(selection) => {
-                    let candidates = {
-                        let text_service = self.borrow()?;
-                        let composition = text_service.borrow_composition()?.clone();
-                        let candidates = composition.candidates.clone();
-                        candidates
-                    };
-
-                    let texts = candidates.texts.clone();
-                    let sub_texts = candidates.sub_texts.clone();
-
-                    selection_index = match selection {
-                        SetSelectionType::Up => max(0, selection_index - 1),
-                        SetSelectionType::Down => min(texts.len() as i32 - 1, selection_index + 1),
-                        SetSelectionType::Number(number) => *number,
-                    };
-
-                    // Selection requires server - use ? to propagate error
-                    require_ipc!()?.set_selection(selection_index as i32)?;
-                    let text = texts[selection_index as usize].clone();
-                    let sub_text = sub_texts[selection_index as usize].clone();
-                    let hiragana = candidates.hiragana.clone();
-                    corresponding_count = candidates.corresponding_count[selection_index as usize];
-
-                    preview = text.clone();
-                    suffix = sub_text.clone();
-                    raw_hiragana = hiragana.clone();
-
-                    self.set_text(&text, &sub_text)?;
+            }
+            ClientAction::SetSelection(selection) => {
+                let boundary = IMEState::get()?.config.candidate.boundary.clone();
+                let page_size = ipc_service::page_size();
+                let Some(index) =
+                    selection.resolve(selection_index, candidates.len(), page_size, &boundary)
+                else {
+                    tracing::debug!("No candidate to select: {:?}", selection);
+                    continue;
+                };
+                selection_index = index;
+
+                // the candidates are already on the client, so the text is updated even if
+                // the window doesn't follow, as a failure would drop the rest of the batch
+                try_ipc!(|ipc: &mut IPCService| ipc.set_selection(selection_index));
+                // the first conversion is only in the text, the next one asks for the others
+                let browsing = !matches!(selection, SetSelectionType::Number(_));
+                if inline_first
+                    && browsing
+                    && composition.state == CompositionState::Previewing
+                    && quirks.candidate_window
+                {
+                    try_ipc!(|ipc: &mut IPCService| ipc.show_window());
                 }
-                ClientAction::ShrinkText(text) => {
-                    // shrink text - requires server for conversion
-                    raw_input.push_str(&text);
-                    raw_input = raw_input
-                        .chars()
-                        .skip(corresponding_count as usize)
-                        .collect();
+                let candidate = candidates.get_or_hiragana(selection_index);
+                corresponding_count = candidate.corresponding_count;
+
+                preview = candidate.text.clone();
+                suffix = candidate.sub_text.clone();
+                raw_hiragana = candidates.hiragana.clone();
+
+                set_text!(candidate.text, candidate.sub_text);
+            }
+            ClientAction::ShrinkText(text) => {
+                raw_input.push_str(&text);
+                shifted |= text.chars().any(|c| is_shifted(mode, shift_katakana, c));
+                let typed = typed_text(mode, text)?;
+                shrink!(typed);
 
-                    // Learn the selected candidate before shrinking
-                    let _ = require_ipc!()?.learn_candidate(selection_index);
-                    require_ipc!()?.shrink_text(corresponding_count.clone())?;
-                    let text = match mode {
-                        InputMode::Kana => to_fullwidth(text, false),
-                        InputMode::Latin => text.to_string(),
-                    };
-                    candidates = require_ipc!()?.append_text(text)?;
-                    selection_index = 0;
-
-                    let text = candidates.texts[selection_index as usize].clone();
-                    let sub_text = candidates.sub_texts[selection_index as usize].clone();
-                    let hiragana = candidates.hiragana.clone();
-                    self.shift_start(&preview, &text)?;
-
-                    corresponding_count = candidates.corresponding_count[selection_index as usize];
-                    preview = text.clone();
-                    suffix = sub_text.clone();
-                    raw_hiragana = hiragana.clone();
-
-                    require_ipc!()?.set_candidates(candidates.texts.clone())?;
-                    require_ipc!()?.set_selection(selection_index as i32)?;
-                    self.update_pos()?;
-
-                    transition = CompositionState::Composing;
+                transition = CompositionState::Composing;
+            }
+            ClientAction::CommitPrefix => {
+                if clauses.is_empty() {
+                    continue;
                 }
-                ClientAction::SetTextWithType(set_type) => {
-                    let text = match set_type {
-                        SetTextType::Hiragana => raw_hiragana.clone(),
-                        SetTextType::Katakana => to_katakana(&raw_hiragana),
-                        SetTextType::HalfKatakana => to_half_katakana(&raw_hiragana),
-                        SetTextType::FullLatin => to_fullwidth(&raw_input, true),
-                        SetTextType::HalfLatin => to_halfwidth(&raw_input),
-                    };
-
-                    self.set_text(&text, "")?;
+                let clause = clauses.remove(0);
+                report_commit!(clause.text.clone(), clause.reading.clone());
+
+                // the server has forgotten the clause already, only the document changes
+                let rest = format!("{}{preview}", joined(&clauses, |c| &c.text));
+                ops.push(EditOp::ShiftStart(clause.text, rest, suffix.clone()));
+                // mark the rest of the clauses as converted again
+                set_text!(preview.clone(), suffix.clone());
+            }
+            ClientAction::ClearText => {
+                preview.clear();
+                suffix.clear();
+                clauses.clear();
+                set_text!("".to_string(), "".to_string());
+            }
+            ClientAction::RevertToReading => {
+                // the server has forgotten the converted clauses, so give it the whole reading
+                let before = joined(&clauses, |c| &c.reading);
+                raw_input = format!("{}{raw_input}", joined(&clauses, |c| &c.raw_input));
+                clauses.clear();
+
+                // appending nothing converts the whole reading again and resets the clause length
+                let result = report_ipc!(require_ipc!().and_then(|ipc| {
+                    if before.is_empty() {
+                        ipc.append_text("".to_string())
+                    } else {
+                        ipc.clear_text()?;
+                        ipc.append_text(format!("{before}{raw_hiragana}"))
+                    }
+                }));
+                if let Ok(new_candidates) = result {
+                    candidates = new_candidates;
+                    raw_hiragana = candidates.hiragana.clone();
+                }
+
+                selection_index = 0;
+                preview = raw_hiragana.clone();
+                suffix.clear();
+                corresponding_count = raw_hiragana.chars().count() as i32;
+
+                set_text!(preview.clone(), "".to_string());
+                try_ipc!(|ipc: &mut IPCService| ipc.set_candidates(&candidates));
+                try_ipc!(|ipc: &mut IPCService| ipc.set_selection(selection_index));
+            }
+            ClientAction::ResizeSegment(offset) => {
+                // the server converts only the resized clause, the rest is kept as the suffix
+                // the conversion stays as it is if the server is not available
+                let result =
+                    report_ipc!(require_ipc!().and_then(|ipc| ipc.resize_segment(*offset)));
+                let Ok(new_candidates) = result else {
+                    continue;
+                };
+                candidates = new_candidates;
+                selection_index = 0;
+
+                let candidate = candidates.get_or_hiragana(selection_index);
+                corresponding_count = candidate.corresponding_count;
+                preview = candidate.text.clone();
+                suffix = candidate.sub_text.clone();
+                raw_hiragana = candidates.hiragana.clone();
+
+                set_text!(candidate.text, candidate.sub_text);
+                try_ipc!(|ipc: &mut IPCService| ipc.set_candidates(&candidates));
+                try_ipc!(|ipc: &mut IPCService| ipc.set_selection(selection_index));
+            }
+            ClientAction::FocusClause(offset) if *offset > 0 => {
+                if suffix.is_empty() {
+                    continue;
+                }
+                // the selection is final once the focus moves on
+                learn_selection!();
+
+                let result = report_ipc!(
+                    require_ipc!().and_then(|ipc| ipc.shrink_text(corresponding_count))
+                );
+                let Ok(new_candidates) = result else {
+                    continue;
+                };
+                clauses.push(Clause {
+                    text: preview.clone(),
+                    reading: raw_hiragana
+                        .strip_suffix(suffix.as_str())
+                        .unwrap_or(&raw_hiragana)
+                        .to_string(),
+                    raw_input: raw_input
+                        .chars()
+                        .take(corresponding_count as usize)
+                        .collect(),
+                });
+                raw_input = raw_input
+                    .chars()
+                    .skip(corresponding_count as usize)
+                    .collect();
+
+                candidates = new_candidates;
+                selection_index = 0;
+                let candidate = candidates.get_or_hiragana(selection_index);
+                corresponding_count = candidate.corresponding_count;
+                preview = candidate.text.clone();
+                suffix = candidate.sub_text.clone();
+                raw_hiragana = candidates.hiragana.clone();
+
+                set_text!(candidate.text, candidate.sub_text);
+                try_ipc!(|ipc: &mut IPCService| ipc.set_candidates(&candidates));
+                try_ipc!(|ipc: &mut IPCService| ipc.set_selection(selection_index));
+            }
+            ClientAction::FocusClause(_) => {
+                let Some(clause) = clauses.last().cloned() else {
+                    continue;
+                };
+
+                // the server forgot the clause when the focus moved on, so compose it again
+                // and resize the first clause back to it
+                let reading = format!("{}{raw_hiragana}", clause.reading);
+                let length = clause.reading.chars().count() as i32;
+                let result = report_ipc!(require_ipc!().and_then(|ipc| {
+                    ipc.clear_text()?;
+                    let whole = ipc.append_text(reading.clone())?;
+                    let first = length + raw_hiragana.chars().count() as i32
+                        - whole.get_or_hiragana(0).sub_text.chars().count() as i32;
+                    if first == length {
+                        Ok(whole)
+                    } else {
+                        ipc.resize_segment(length - first)
+                    }
+                }));
+                let Ok(new_candidates) = result else {
+                    continue;
+                };
+                clauses.pop();
+                raw_input = format!("{}{raw_input}", clause.raw_input);
+
+                // keep the candidate selected before
+                candidates = new_candidates;
+                selection_index = candidates
+                    .texts
+                    .iter()
+                    .position(|text| *text == clause.text)
+                    .unwrap_or(0) as i32;
+                let candidate = candidates.get_or_hiragana(selection_index);
+                corresponding_count = candidate.corresponding_count;
+                preview = candidate.text.clone();
+                suffix = candidate.sub_text.clone();
+                raw_hiragana = candidates.hiragana.clone();
+
+                set_text!(candidate.text, candidate.sub_text);
+                try_ipc!(|ipc: &mut IPCService| ipc.set_candidates(&candidates));
+                try_ipc!(|ipc: &mut IPCService| ipc.set_selection(selection_index));
+            }
+            ClientAction::InsertText(text) => {
+                last_commit = None;
+                ops.push(EditOp::InsertText(text.clone()));
+            }
+            ClientAction::ToggleDetail => {
+                try_ipc!(|ipc: &mut IPCService| ipc.toggle_detail());
+            }
+            ClientAction::ShowPalette => {
+                let categories = shared::kaomoji::categories()
+                    .into_iter()
+                    .map(|(name, faces)| PaletteCategory {
+                        name: name.to_string(),
+                        items: faces.into_iter().map(str::to_string).collect(),
+                    })
+                    .collect();
+                IMEState::get()?.palette = true;
+                // shown where the candidate window was last, there is no composition to follow
+                try_ipc!(|ipc: &mut IPCService| ipc.show_palette(categories));
+                try_ipc!(|ipc: &mut IPCService| ipc.show_window());
+            }
+            ClientAction::ClosePalette => {
+                IMEState::get()?.palette = false;
+                try_ipc!(|ipc: &mut IPCService| ipc.hide_window());
+            }
+            ClientAction::SelectPrediction => {
+                // Tab goes through the predictions and around to the first one
+                let index = prediction.map_or(0, |index| index + 1) % predictions.len().max(1);
+                let Some(chosen) = predictions.get(index) else {
+                    continue;
+                };
+                prediction = Some(index);
+
+                // the whole reading is replaced, Enter commits it
+                preview = chosen.text.clone();
+                suffix.clear();
+                set_text!(preview.clone(), "".to_string());
+                try_ipc!(|ipc: &mut IPCService| ipc.set_predictions(&predictions, index as i32));
+            }
+            ClientAction::SetContext(context) => {
+                // the context only improves the conversion, so it is fine to lose it
+                try_ipc!(|ipc: &mut IPCService| ipc.set_context(context.clone()));
+            }
+            ClientAction::SetTextWithType(set_type) => {
+                let text = match set_type {
+                    SetTextType::Hiragana => raw_hiragana.clone(),
+                    SetTextType::Katakana => to_katakana(&raw_hiragana),
+                    SetTextType::HalfKatakana => to_half_katakana(&raw_hiragana),
+                    SetTextType::FullLatin => to_fullwidth(&raw_input, true),
+                    SetTextType::HalfLatin => to_halfwidth(&raw_input),
+                    SetTextType::LongVowelKatakana => to_long_vowel_katakana(&raw_hiragana),
+                };
+
+                // the whole reading is replaced, so nothing is left as the suffix
+                preview = text.clone();
+                suffix.clear();
+                set_text!(text, "".to_string());
+            }
+            ClientAction::Reconvert(text) => {
+                // not a failure of the server, it doesn't know how to read the text
+                let result = require_ipc!().and_then(|ipc| ipc.reconvert(text.clone()));
+                let Ok(new_candidates) = result else {
+                    tracing::debug!("The selection can't be converted again: {:?}", result);
+                    transition = CompositionState::None;
+                    break;
+                };
+
+                // the composition replaces the selection only now that there is a reading
+                last_commit = None;
+                ops.push(EditOp::StartComposition);
+                ops.push(EditOp::UpdatePos);
+                if quirks.candidate_window {
+                    try_ipc!(|ipc: &mut IPCService| ipc.show_window());
                 }
+
+                candidates = new_candidates;
+                raw_hiragana = candidates.hiragana.clone();
+                raw_input = raw_hiragana.clone();
+                selection_index = 0;
+                try_ipc!(|ipc: &mut IPCService| ipc.set_candidates(&candidates));
+            }
+            ClientAction::ReopenLastCommit => {
+                // the composition is already started over the committed text on the TSF thread
+                let Some(commit) = last_commit.take() else {
+                    continue;
+                };
+                raw_input = commit.raw_input;
+                raw_hiragana = commit.raw_hiragana;
+                preview = commit.text;
+                suffix.clear();
+                corresponding_count = raw_hiragana.chars().count() as i32;
+
+                // the server forgot the reading when it was committed
+                let result = report_ipc!(
+                    require_ipc!().and_then(|ipc| ipc.append_text(raw_hiragana.clone()))
+                );
+                candidates = result.unwrap_or_default();
+                selection_index = 0;
             }
         }
+    }
 
-        let text_service = self.borrow()?;
-        let mut composition = text_service.borrow_mut_composition()?;
+    // the runs typed with Shift are shown in katakana instead of the conversion
+    let katakana = katakana_runs(
+        &composition.raw_hiragana,
+        &raw_hiragana,
+        &composition.katakana,
+        shifted,
+    );
+    if *mode == InputMode::Kana
+        && transition == CompositionState::Composing
+        && katakana.contains(&true)
+    {
+        preview = with_katakana_runs(&raw_hiragana, &katakana);
+        suffix.clear();
+        let shown = ops.iter_mut().rev().find_map(|op| match op {
+            EditOp::SetText(_, text, sub_text) | EditOp::ShiftStart(_, text, sub_text) => {
+                Some((text, sub_text))
+            }
+            _ => None,
+        });
+        if let Some((text, sub_text)) = shown {
+            *text = preview.clone();
+            sub_text.clear();
+        }
+    }
 
-        composition.preview = preview.clone();
-        composition.state = transition;
-        composition.selection_index = selection_index;
-        composition.raw_input = raw_input.clone();
-        composition.raw_hiragana = raw_hiragana.clone();
-        composition.candidates = candidates;
-        composition.suffix = suffix.clone();
-        composition.corresponding_count = corresponding_count;
+    // back to the reading, the window waits for the conversion again
+    if inline_first
+        && composition.state == CompositionState::Previewing
+        && transition == CompositionState::Composing
+    {
+        try_ipc!(|ipc: &mut IPCService| ipc.hide_window());
+    }
 
-        Ok(())
+    // the predictions only complete the reading while composing
+    if transition != CompositionState::Composing {
+        prediction = None;
+        if !predictions.is_empty() {
+            predictions.clear();
+            try_ipc!(|ipc: &mut IPCService| ipc.set_predictions(&predictions, -1));
+        }
     }
+
+    let composition = Composition {
+        preview,
+        suffix,
+        raw_input,
+        raw_hiragana,
+        corresponding_count,
+        selection_index,
+        candidates,
+        state: transition,
+        clauses,
+        predictions,
+        prediction,
+        last_commit,
+        katakana,
+        out_of_sync,
+    };
+
+    Ok((composition, ops))
 }
@@ -3,6 +3,8 @@
 
 use std::{collections::HashMap, sync::LazyLock};
 
+use shared::PunctuationStyle;
+
 // in azookey, fullwidth alphabet will not be processed
 static HALF_FULL_AZOOKEY: LazyLock<HashMap<&'static str, &'static str>> = LazyLock::new(|| {
     HashMap::from([
@@ -139,9 +141,13 @@ pub fn to_halfwidth(s: &str) -> String {
         .map(|c| {
             let key = c.to_string();
             if let Some((&k, _)) = HALF_FULL_AZOOKEY.iter().find(|(_, &v)| v == key) {
-                k.to_string()
-            } else {
-                c.to_string()
+                return k.to_string();
+            }
+            // the punctuation of `PunctuationStyle::Western`
+            match c {
+                '，' => ",".to_string(),
+                '．' => ".".to_string(),
+                _ => c.to_string(),
             }
         })
         .collect()
@@ -166,3 +172,124 @@ pub fn to_fullwidth(s: &str, process_alphabet: bool) -> String {
         })
         .collect()
 }
+
+// replace 、。 of to_fullwidth with the punctuation of the style
+pub fn with_punctuation(s: &str, style: PunctuationStyle) -> String {
+    let (comma, period) = match style {
+        PunctuationStyle::Japanese => return s.to_string(),
+        PunctuationStyle::Western => ('，', '．'),
+        PunctuationStyle::Mixed => ('，', '。'),
+    };
+    s.chars()
+        .map(|c| match c {
+            '、' => comma,
+            '。' => period,
+            _ => c,
+        })
+        .collect()
+}
+
+// full-width form of ASCII, used by the full-width alphanumeric mode
+// unlike to_fullwidth, punctuation is not replaced with the Japanese one
+pub fn to_full_latin(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            ' ' => '\u{3000}',
+            '!'..='~' => char::from_u32(c as u32 - 0x21 + 0xFF01).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn fullwidth_punctuation() {
+        assert_eq!(to_fullwidth("-", false), "ー");
+        assert_eq!(to_fullwidth(",.", false), "、。");
+        assert_eq!(to_fullwidth("[a]", false), "「a」");
+        assert_eq!(to_fullwidth("abc!", true), "ａｂｃ！");
+    }
+
+    #[test]
+    fn halfwidth_punctuation() {
+        assert_eq!(to_halfwidth("ー"), "-");
+        assert_eq!(to_halfwidth("「あ」、"), "[あ],");
+        assert_eq!(to_halfwidth("あ，い．"), "あ,い.");
+    }
+
+    #[test]
+    fn punctuation_style() {
+        let typed = to_fullwidth("a,b.", false);
+        assert_eq!(
+            with_punctuation(&typed, PunctuationStyle::Japanese),
+            "a、b。"
+        );
+        assert_eq!(
+            with_punctuation(&typed, PunctuationStyle::Western),
+            "a，b．"
+        );
+        assert_eq!(with_punctuation(&typed, PunctuationStyle::Mixed), "a，b。");
+    }
+
+    #[test]
+    fn full_latin() {
+        assert_eq!(
+            to_full_latin("Azookey 1.0!"),
+            "Ａｚｏｏｋｅｙ\u{3000}１．０！"
+        );
+        assert_eq!(to_full_latin("あ~"), "あ～");
+    }
+
+    proptest! {
+        #[test]
+        fn fullwidth_round_trip(s in "[ -~]*") {
+            prop_assert_eq!(to_halfwidth(&to_fullwidth(&s, false)), s);
+        }
+
+        #[test]
+        fn fullwidth_keeps_length(s in "[ -~]*", alphabet in any::<bool>()) {
+            prop_assert_eq!(to_fullwidth(&s, alphabet).chars().count(), s.chars().count());
+        }
+
+        // the alphanumerics are left for the conversion
+        #[test]
+        fn fullwidth_keeps_alphanumerics(s in "[a-zA-Z0-9]*") {
+            prop_assert_eq!(to_fullwidth(&s, false), s);
+        }
+
+        #[test]
+        fn fullwidth_alphabet(s in "[a-z]*") {
+            let full = to_fullwidth(&s, true);
+            prop_assert!(full.chars().all(|c| ('ａ'..='ｚ').contains(&c)));
+        }
+
+        #[test]
+        fn full_latin_has_no_ascii(s in "[ -~]*") {
+            prop_assert!(to_full_latin(&s).chars().all(|c| !c.is_ascii()));
+        }
+
+        #[test]
+        fn full_latin_round_trip(s in "[ -~]*") {
+            let back: String = to_full_latin(&s)
+                .chars()
+                .map(|c| match c {
+                    '\u{3000}' => ' ',
+                    _ => char::from_u32(c as u32 - 0xFF01 + 0x21).unwrap(),
+                })
+                .collect();
+            prop_assert_eq!(back, s);
+        }
+
+        #[test]
+        fn mixed_text_keeps_kana(kana in "[ぁ-ゖ]*", ascii in "[ -~]*") {
+            let s = format!("{}{}", kana, ascii);
+            let full = to_fullwidth(&s, true);
+            prop_assert!(full.starts_with(&kana));
+            prop_assert_eq!(to_full_latin(&s).chars().take(kana.chars().count()).collect::<String>(), kana);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use windows::{
+    core::{GUID, VARIANT},
+    Win32::UI::TextServices::{
+        GUID_COMPARTMENT_KEYBOARD_INPUTMODE_CONVERSION, GUID_COMPARTMENT_KEYBOARD_OPENCLOSE,
+    },
+};
+
+use anyhow::Result;
+
+use crate::{globals::GUID_COMPARTMENT_COMPOSING, tsf::factory::TextServiceFactory};
+
+use super::{input_mode::InputMode, state::IMEState};
+
+impl TextServiceFactory {
+    // publish the state through the global compartments
+    // other programs (status bars, window managers, ...) read them with
+    // ITfThreadMgr::GetGlobalCompartment, or get notified with ITfCompartmentEventSink
+    // `active` is false while the text service is deactivated
+    pub fn publish_status(&self, active: bool) -> Result<()> {
+        let text_service = self.borrow()?;
+        let thread_mgr = text_service.thread_mgr()?;
+
+        let mode = IMEState::get()?.input_mode.clone();
+        let composing = text_service.tip_composition.try_borrow()?.is_some();
+        let values: [(GUID, i32); 3] = [
+            (
+                GUID_COMPARTMENT_KEYBOARD_OPENCLOSE,
+                (active && mode != InputMode::Latin) as i32,
+            ),
+            (
+                GUID_COMPARTMENT_KEYBOARD_INPUTMODE_CONVERSION,
+                mode.conversion_mode() as i32,
+            ),
+            (GUID_COMPARTMENT_COMPOSING, (active && composing) as i32),
+        ];
+
+        unsafe {
+            let compartments = thread_mgr.GetGlobalCompartment()?;
+            for (guid, value) in values {
+                let compartment = compartments.GetCompartment(&guid)?;
+                // the sinks are notified on every set, so skip the unchanged values
+                let current = compartment
+                    .GetValue()
+                    .ok()
+                    .and_then(|v| i32::try_from(&v).ok());
+                if current != Some(value) {
+                    compartment.SetValue(text_service.tid, &VARIANT::from(value))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
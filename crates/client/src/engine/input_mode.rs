@@ -1,17 +1,132 @@
 use crate::tsf::factory::TextServiceFactory;
 
 use windows::{
-    core::Interface,
-    Win32::UI::TextServices::{ITfLangBarItemButton, ITfLangBarItemMgr},
+    core::{Interface, VARIANT},
+    Win32::UI::TextServices::{
+        ITfCompartmentMgr, ITfLangBarItemButton, ITfLangBarItemMgr,
+        GUID_COMPARTMENT_KEYBOARD_INPUTMODE_CONVERSION, GUID_COMPARTMENT_KEYBOARD_OPENCLOSE,
+        TF_CONVERSIONMODE_ALPHANUMERIC, TF_CONVERSIONMODE_FULLSHAPE, TF_CONVERSIONMODE_KATAKANA,
+        TF_CONVERSIONMODE_NATIVE, TF_CONVERSIONMODE_ROMAN,
+    },
 };
 
 use anyhow::Result;
+use shared::{
+    i18n::{text, Locale},
+    InputModeName, KeyConfig,
+};
+
+use super::state::IMEState;
 
 #[derive(Default, Clone, PartialEq, Debug)]
 pub enum InputMode {
     #[default]
     Latin,
     Kana,
+    // composed like Kana, but shown and committed as katakana without converting to kanji
+    Katakana,
+    // same as Katakana in half-width
+    HalfKatakana,
+    // typed characters are committed as full-width without composition
+    FullLatin,
+}
+
+impl InputMode {
+    // in the order of the language bar
+    pub const ALL: [InputMode; 5] = [
+        InputMode::Kana,
+        InputMode::Katakana,
+        InputMode::HalfKatakana,
+        InputMode::FullLatin,
+        InputMode::Latin,
+    ];
+
+    // whether typed keys start a composition
+    pub fn is_composing(&self) -> bool {
+        matches!(
+            self,
+            InputMode::Kana | InputMode::Katakana | InputMode::HalfKatakana
+        )
+    }
+
+    pub fn label(&self, locale: Locale) -> &'static str {
+        let key = match self {
+            InputMode::Latin => "mode.latin",
+            InputMode::Kana => "mode.kana",
+            InputMode::Katakana => "mode.katakana",
+            InputMode::HalfKatakana => "mode.half_katakana",
+            InputMode::FullLatin => "mode.full_latin",
+        };
+        text(locale, key)
+    }
+
+    // one character shown next to the caret when the mode is switched, like the built-in IME
+    pub fn indicator(&self) -> &'static str {
+        match self {
+            InputMode::Latin => "A",
+            InputMode::Kana => "あ",
+            InputMode::Katakana => "カ",
+            InputMode::HalfKatakana => "ｶ",
+            InputMode::FullLatin => "Ａ",
+        }
+    }
+
+    // value of GUID_COMPARTMENT_KEYBOARD_INPUTMODE_CONVERSION
+    pub fn conversion_mode(&self) -> u32 {
+        match self {
+            InputMode::Latin => TF_CONVERSIONMODE_ALPHANUMERIC,
+            InputMode::Kana => {
+                TF_CONVERSIONMODE_NATIVE | TF_CONVERSIONMODE_FULLSHAPE | TF_CONVERSIONMODE_ROMAN
+            }
+            InputMode::Katakana => {
+                TF_CONVERSIONMODE_NATIVE
+                    | TF_CONVERSIONMODE_KATAKANA
+                    | TF_CONVERSIONMODE_FULLSHAPE
+                    | TF_CONVERSIONMODE_ROMAN
+            }
+            InputMode::HalfKatakana => {
+                TF_CONVERSIONMODE_NATIVE | TF_CONVERSIONMODE_KATAKANA | TF_CONVERSIONMODE_ROMAN
+            }
+            InputMode::FullLatin => TF_CONVERSIONMODE_ALPHANUMERIC | TF_CONVERSIONMODE_FULLSHAPE,
+        }
+    }
+
+    // modes in the order of the config, all the modes if nothing is configured
+    pub fn cycle(config: &KeyConfig) -> Vec<InputMode> {
+        let mut cycle: Vec<InputMode> = vec![];
+        for name in &config.mode_cycle {
+            let mode = InputMode::from(*name);
+            if !cycle.contains(&mode) {
+                cycle.push(mode);
+            }
+        }
+
+        if cycle.is_empty() {
+            Self::ALL.to_vec()
+        } else {
+            cycle
+        }
+    }
+
+    // the first mode is used if the current one is not in the cycle
+    pub fn next(&self, cycle: &[InputMode]) -> InputMode {
+        match cycle.iter().position(|mode| mode == self) {
+            Some(index) => cycle[(index + 1) % cycle.len()].clone(),
+            None => cycle.first().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+impl From<InputModeName> for InputMode {
+    fn from(name: InputModeName) -> Self {
+        match name {
+            InputModeName::Kana => InputMode::Kana,
+            InputModeName::Katakana => InputMode::Katakana,
+            InputModeName::HalfKatakana => InputMode::HalfKatakana,
+            InputModeName::FullLatin => InputMode::FullLatin,
+            InputModeName::Latin => InputMode::Latin,
+        }
+    }
 }
 
 impl TextServiceFactory {
@@ -30,6 +145,34 @@ impl TextServiceFactory {
                 .AddItem(&text_service.this::<ITfLangBarItemButton>()?)?;
         };
 
+        // let other programs (e.g. the touch keyboard) know the mode
+        let mode = IMEState::get()?.input_mode.conversion_mode();
+        unsafe {
+            thread_mgr
+                .cast::<ITfCompartmentMgr>()?
+                .GetCompartment(&GUID_COMPARTMENT_KEYBOARD_INPUTMODE_CONVERSION)?
+                .SetValue(text_service.tid, &VARIANT::from(mode as i32))?;
+        };
+        // keep the Hankaku/Zenkaku key toggling from the mode set here, see `compartment_event_sink`
+        let open = IMEState::get()?.input_mode != InputMode::Latin;
+        unsafe {
+            thread_mgr
+                .cast::<ITfCompartmentMgr>()?
+                .GetCompartment(&GUID_COMPARTMENT_KEYBOARD_OPENCLOSE)?
+                .SetValue(text_service.tid, &VARIANT::from(open as i32))?;
+        };
+        // and the tray icon of the ui process, the request is sent in the background
+        let (indicator, ipc_service) = {
+            let state = IMEState::get()?;
+            (state.input_mode.indicator(), state.ipc_service.clone())
+        };
+        if let Some(mut ipc_service) = ipc_service {
+            if let Err(e) = ipc_service.set_input_mode(indicator) {
+                tracing::debug!("Failed to send the mode to the tray: {:?}", e);
+            }
+        }
+        self.publish_status(true)?;
+
         Ok(())
     }
 }
@@ -1,28 +1,118 @@
 use anyhow::Result;
-use hyper_util::rt::TokioIo;
 use shared::proto::{
-    azookey_service_client::AzookeyServiceClient, window_service_client::WindowServiceClient,
+    azookey_service_client::AzookeyServiceClient, suggestion, window_event,
+    window_service_client::WindowServiceClient, CandidateKind, CandidateSource, ComposingText,
+    PaletteCategory, Prediction, Suggestion, WindowPosition,
 };
-use std::{sync::Arc, time::Duration};
-use tokio::{net::windows::named_pipe::ClientOptions, time};
-use tonic::transport::Endpoint;
-use tower::service_fn;
-use windows::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_PIPE_BUSY};
+use shared::recording::ConversionRequest;
+use shared::transport::{channel, NamedPipe, Transport};
+use shared::{FontConfig, IpcConfig, TimeoutClass, CANDIDATE_PAGE_SIZE};
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, LazyLock, Mutex, OnceLock, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{runtime::Runtime, time};
+use tonic::transport::Channel;
+
+use super::{
+    metrics::{self, KeyMetrics, Stage},
+    recorder, request_id,
+};
+
+// timeouts, retries and the reconnection cooldown, replaced from the config on every activation
+static IPC_CONFIG: LazyLock<RwLock<IpcConfig>> = LazyLock::new(Default::default);
+
+pub fn configure(config: &IpcConfig) {
+    *IPC_CONFIG.write().unwrap_or_else(|e| e.into_inner()) = config.clone();
+}
+
+fn ipc_config() -> IpcConfig {
+    IPC_CONFIG.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
 
 // Timeout for IPC calls to prevent indefinite hanging when server crashes
-const IPC_TIMEOUT: Duration = Duration::from_millis(5000);
-// Maximum time to wait for server to start (retries on file not found)
-const MAX_CONNECT_RETRIES: u32 = 20;
-const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(100);
+fn ipc_timeout(class: TimeoutClass) -> Duration {
+    ipc_config().timeout(class)
+}
+
+// the idle worker pings the server with this timeout, so a crash is noticed within about a second
+const KEEPALIVE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// while the candidate window is not responding, requests are skipped for this interval
+// the launcher restarts the ui process in the meantime
+const WINDOW_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+// candidates the window can show at once, reported by the window when it is resized
+static PAGE_SIZE: AtomicUsize = AtomicUsize::new(CANDIDATE_PAGE_SIZE);
+
+pub fn page_size() -> usize {
+    PAGE_SIZE.load(Ordering::Relaxed)
+}
+
+// the last `PingResponse::config_version`, the server bumps it when the settings app saves
+static CONFIG_VERSION: AtomicU64 = AtomicU64::new(0);
+
+fn check_config_version(version: u64) {
+    if CONFIG_VERSION.swap(version, Ordering::Relaxed) == version {
+        return;
+    }
+
+    tracing::debug!("Config updated: version {}", version);
+    if let Err(e) = crate::trace::reload_filter() {
+        tracing::warn!("Failed to reload log filter: {:?}", e);
+    }
+}
+
+// reconnection state of one ipc worker
+// connection attempts are skipped for `reconnect_cooldown_ms` after one fails,
+// so that the keys typed while the server is down don't each wait for the connection
+// each thread manager has its own worker, so the instances don't share the cooldown
+#[derive(Debug, Default)]
+pub struct ReconnectState {
+    last_failure: Option<Instant>,
+}
+
+impl ReconnectState {
+    pub fn should_try(&self) -> bool {
+        let cooldown = Duration::from_millis(ipc_config().reconnect_cooldown_ms);
+        self.last_failure
+            .map_or(true, |failed_at| failed_at.elapsed() >= cooldown)
+    }
+
+    pub fn mark_failed(&mut self) {
+        self.last_failure = Some(Instant::now());
+    }
+}
 
 // connect to kkc server
 #[derive(Debug, Clone)]
 pub struct IPCService {
     // kkc server client
-    azookey_client: AzookeyServiceClient<tonic::transport::channel::Channel>,
+    azookey_client: AzookeyServiceClient<Channel>,
     // candidate window server client
-    window_client: WindowServiceClient<tonic::transport::channel::Channel>,
-    runtime: Arc<tokio::runtime::Runtime>,
+    window_client: WindowServiceClient<Channel>,
+    // shared between the clones, since the ipc worker works on a copy of the service
+    window_state: Arc<Mutex<WindowState>>,
+    // cleared by a failed keepalive, so that requests fail at once instead of waiting for the timeout
+    server_online: Arc<AtomicBool>,
+}
+
+// last state sent to the candidate window
+#[derive(Debug, Clone, Default)]
+struct WindowState {
+    visible: bool,
+    position: Option<shared::proto::SetPositionRequest>,
+    candidates: Vec<shared::proto::Candidate>,
+    kind: CandidateKind,
+    selection: i32,
+    // shown by the tray icon, see `InputMode::indicator`
+    input_mode: Option<String>,
+    // when the candidate window stopped responding
+    lost_at: Option<Instant>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -31,136 +121,357 @@ pub struct Candidates {
     pub sub_texts: Vec<String>,
     pub hiragana: String,
     pub corresponding_count: Vec<i32>,
+    pub annotations: Vec<String>,
+    pub readings: Vec<String>,
+    pub sources: Vec<CandidateSource>,
+    // completions of the reading, sent with the conversion
+    pub predictions: Vec<Prediction>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Candidate {
+    pub text: String,
+    pub sub_text: String,
+    pub corresponding_count: i32,
+}
+
+impl From<ComposingText> for Candidates {
+    fn from(composing_text: ComposingText) -> Self {
+        let suggestions = composing_text.suggestions;
+        let source = |s: &Suggestion| match s.source() {
+            suggestion::Source::Converter => CandidateSource::Converter,
+            suggestion::Source::UserDictionary => CandidateSource::UserDictionary,
+            suggestion::Source::Date => CandidateSource::Date,
+            suggestion::Source::Emoji => CandidateSource::Emoji,
+            suggestion::Source::English => CandidateSource::English,
+        };
+
+        let mut candidates = Candidates {
+            texts: suggestions.iter().map(|s| s.text.clone()).collect(),
+            sub_texts: suggestions.iter().map(|s| s.subtext.clone()).collect(),
+            hiragana: composing_text.hiragana,
+            corresponding_count: suggestions.iter().map(|s| s.corresponding_count).collect(),
+            annotations: suggestions.iter().map(|s| s.annotation.clone()).collect(),
+            readings: suggestions.iter().map(|s| s.reading.clone()).collect(),
+            sources: suggestions.iter().map(source).collect(),
+            predictions: composing_text.predictions,
+        };
+        candidates.augment();
+        candidates
+    }
+}
+
+impl Candidates {
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+
+    // returns None if the index is out of range
+    pub fn get(&self, index: i32) -> Option<Candidate> {
+        let index = usize::try_from(index).ok()?;
+
+        Some(Candidate {
+            text: self.texts.get(index)?.clone(),
+            sub_text: self.sub_texts.get(index).cloned().unwrap_or_default(),
+            corresponding_count: self.corresponding_count.get(index).copied().unwrap_or(0),
+        })
+    }
+
+    // the candidate at the index, or the reading if the server returned no candidates
+    pub fn get_or_hiragana(&self, index: i32) -> Candidate {
+        self.get(index).unwrap_or_else(|| Candidate {
+            text: self.hiragana.clone(),
+            sub_text: String::new(),
+            corresponding_count: self.hiragana.chars().count() as i32,
+        })
+    }
+
+    // candidates as shown in the candidate window
+    fn to_window(&self) -> Vec<shared::proto::Candidate> {
+        self.texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| shared::proto::Candidate {
+                text: text.clone(),
+                reading: self.readings.get(index).cloned().unwrap_or_default(),
+                annotation: self.annotations.get(index).cloned().unwrap_or_default(),
+                source: self.sources.get(index).copied().unwrap_or_default() as i32,
+                index: index as i32,
+            })
+            .collect()
+    }
+}
+
+// one runtime for all the ipc of the DLL, created on first use
+// `block_on` polls the request on the calling thread, so the request id and the metrics,
+// which are thread local, are still recorded; spawned tasks (e.g. window events) run on its worker
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+fn runtime() -> Result<&'static Runtime> {
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("azookey-ipc-runtime")
+        .enable_all()
+        .build()?;
+    Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+// run an ipc request to completion on the calling thread
+pub(crate) fn block_on<T>(future: impl Future<Output = Result<T>>) -> Result<T> {
+    runtime()?.block_on(future)
 }
 
 impl IPCService {
     pub fn new() -> Result<Self> {
-        tracing::info!("IPCService::new() - Starting IPC connection");
-        let runtime = tokio::runtime::Runtime::new()?;
-
-        tracing::info!("IPCService::new() - Connecting to azookey_server pipe...");
-        let server_channel = runtime.block_on(
-            Endpoint::try_from("http://[::]:50051")?.connect_with_connector(service_fn(
-                |_| async {
-                    let mut retries = 0u32;
-                    let client = loop {
-                        match ClientOptions::new().open(r"\\.\pipe\azookey_server") {
-                            Ok(client) => break client,
-                            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => {
-                                tracing::debug!("azookey_server pipe busy, retrying...");
-                            }
-                            // Retry on file not found (server not ready yet)
-                            Err(e) if e.raw_os_error() == Some(ERROR_FILE_NOT_FOUND.0 as i32) => {
-                                retries += 1;
-                                tracing::debug!("azookey_server pipe not found, retry {}/{}", retries, MAX_CONNECT_RETRIES);
-                                if retries >= MAX_CONNECT_RETRIES {
-                                    tracing::error!("FAILED to connect to azookey_server pipe after {} retries: {:?}", retries, e);
-                                    return Err(e);
-                                }
-                            }
-                            Err(e) => {
-                                tracing::error!("FAILED to connect to azookey_server pipe: {:?} (os_error: {:?})", e, e.raw_os_error());
-                                return Err(e);
-                            }
-                        }
+        block_on(Self::connect())
+    }
 
-                        time::sleep(CONNECT_RETRY_DELAY).await;
-                    };
-                    tracing::info!("Successfully connected to azookey_server pipe");
-
-                    Ok::<_, std::io::Error>(TokioIo::new(client))
-                },
-            )),
-        )?;
-
-        tracing::info!("IPCService::new() - Connecting to azookey_ui pipe...");
-        let ui_channel = runtime.block_on(
-            Endpoint::try_from("http://[::]:50052")?.connect_with_connector(service_fn(
-                |_| async {
-                    let mut retries = 0u32;
-                    let client = loop {
-                        match ClientOptions::new().open(r"\\.\pipe\azookey_ui") {
-                            Ok(client) => break client,
-                            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => {
-                                tracing::debug!("azookey_ui pipe busy, retrying...");
-                            }
-                            // Retry on file not found (server not ready yet)
-                            Err(e) if e.raw_os_error() == Some(ERROR_FILE_NOT_FOUND.0 as i32) => {
-                                retries += 1;
-                                tracing::debug!("azookey_ui pipe not found, retry {}/{}", retries, MAX_CONNECT_RETRIES);
-                                if retries >= MAX_CONNECT_RETRIES {
-                                    tracing::error!("FAILED to connect to azookey_ui pipe after {} retries: {:?}", retries, e);
-                                    return Err(e);
-                                }
-                            }
-                            Err(e) => {
-                                tracing::error!("FAILED to connect to azookey_ui pipe: {:?} (os_error: {:?})", e, e.raw_os_error());
-                                return Err(e);
-                            }
-                        }
+    pub async fn connect() -> Result<Self> {
+        let config = ipc_config();
+        let retry_delay = Duration::from_millis(config.connect_retry_delay_ms);
 
-                        time::sleep(CONNECT_RETRY_DELAY).await;
-                    };
-                    tracing::info!("Successfully connected to azookey_ui pipe");
+        Self::with_transports(
+            NamedPipe::new("azookey_server").with_retries(config.connect_retries, retry_delay),
+            NamedPipe::new("azookey_ui").with_retries(config.connect_retries, retry_delay),
+        )
+        .await
+    }
 
-                    Ok::<_, std::io::Error>(TokioIo::new(client))
-                },
-            )),
-        )?;
+    // connect through any transport, e.g. `InProcess` for tests
+    pub async fn with_transports(
+        server_transport: impl Transport,
+        ui_transport: impl Transport,
+    ) -> Result<Self> {
+        tracing::info!("IPCService::new() - Starting IPC connection");
+
+        tracing::info!("IPCService::new() - Connecting to azookey_server...");
+        let server_channel = channel("http://[::]:50051", server_transport).await?;
+
+        tracing::info!("IPCService::new() - Connecting to azookey_ui...");
+        let ui_channel = channel("http://[::]:50052", ui_transport).await?;
 
         let azookey_client = AzookeyServiceClient::new(server_channel);
         let window_client = WindowServiceClient::new(ui_channel);
-        tracing::info!("IPCService::new() - Successfully connected to both pipes");
+        tracing::info!("IPCService::new() - Successfully connected to both servers");
 
         Ok(Self {
             azookey_client,
             window_client,
-            runtime: Arc::new(runtime),
+            window_state: Arc::new(Mutex::new(WindowState::default())),
+            server_online: Arc::new(AtomicBool::new(true)),
         })
     }
 }
 
-// implement methods to interact with kkc server
+// attach the id of the current keystroke so that the server logs can be correlated
+fn new_request<T>(message: T) -> tonic::Request<T> {
+    let request = tonic::Request::new(message);
+
+    match request_id::current() {
+        Some(request_id) => shared::trace::with_request_id(request, &request_id),
+        None => request,
+    }
+}
+
+// record the time spent on a request for the latency overlay
+async fn measure<T>(stage: Stage, future: impl Future<Output = T>) -> T {
+    let start = Instant::now();
+    let result = future.await;
+    metrics::record(stage, start.elapsed());
+    result
+}
+
+// synchronous wrappers for the TSF thread and the ipc worker
 impl IPCService {
-    #[tracing::instrument]
-    pub fn append_text(&mut self, text: String) -> anyhow::Result<Candidates> {
-        let request = tonic::Request::new(shared::proto::AppendTextRequest {
-            text_to_append: text,
+    pub fn keepalive(&mut self) -> bool {
+        block_on(async { Ok(self.keepalive_async().await) }).unwrap_or(false)
+    }
+
+    pub fn append_text(&mut self, text: String) -> Result<Candidates> {
+        block_on(self.append_text_async(text))
+    }
+
+    pub fn remove_text(&mut self) -> Result<Candidates> {
+        block_on(self.remove_text_async())
+    }
+
+    pub fn clear_text(&mut self) -> Result<()> {
+        block_on(self.clear_text_async())
+    }
+
+    pub fn shrink_text(&mut self, offset: i32) -> Result<Candidates> {
+        block_on(self.shrink_text_async(offset))
+    }
+
+    pub fn resize_segment(&mut self, offset: i32) -> Result<Candidates> {
+        block_on(self.resize_segment_async(offset))
+    }
+
+    pub fn set_context(&mut self, context: String) -> Result<()> {
+        block_on(self.set_context_async(context))
+    }
+
+    pub fn learn_candidate(&mut self, candidate_index: i32) -> Result<()> {
+        block_on(self.learn_candidate_async(candidate_index))
+    }
+
+    pub fn report_commit(&mut self, text: String, reading: String) -> Result<()> {
+        block_on(self.report_commit_async(text, reading))
+    }
+
+    pub fn reconvert(&mut self, text: String) -> Result<Candidates> {
+        block_on(self.reconvert_async(text))
+    }
+
+    pub fn show_window(&mut self) -> Result<()> {
+        block_on(self.show_window_async())
+    }
+
+    pub fn hide_window(&mut self) -> Result<()> {
+        block_on(self.hide_window_async())
+    }
+
+    pub fn set_window_position(
+        &mut self,
+        caret: WindowPosition,
+        exclusion: WindowPosition,
+    ) -> Result<()> {
+        block_on(self.set_window_position_async(caret, exclusion))
+    }
+
+    pub fn set_candidates(&mut self, candidates: &Candidates) -> Result<()> {
+        block_on(self.set_candidates_async(candidates))
+    }
+
+    // called for every batch of keys, but only a change of the kind is sent to the window
+    pub fn set_candidate_kind(&mut self, kind: CandidateKind) -> Result<()> {
+        if self.candidate_kind() == kind {
+            return Ok(());
+        }
+        block_on(self.set_candidate_kind_async(kind))
+    }
+
+    pub fn set_predictions(&mut self, predictions: &[Prediction], selection: i32) -> Result<()> {
+        block_on(self.set_predictions_async(predictions, selection))
+    }
+
+    pub fn toggle_detail(&mut self) -> Result<()> {
+        block_on(self.toggle_detail_async())
+    }
+
+    pub fn show_palette(&mut self, categories: Vec<PaletteCategory>) -> Result<()> {
+        block_on(self.show_palette_async(categories))
+    }
+
+    pub fn set_layout(&mut self, vertical: bool) -> Result<()> {
+        block_on(self.set_layout_async(vertical))
+    }
+
+    pub fn set_font(&mut self, font: &FontConfig) -> Result<()> {
+        block_on(self.set_font_async(font))
+    }
+
+    pub fn set_selection(&mut self, index: i32) -> Result<()> {
+        block_on(self.set_selection_async(index))
+    }
+
+    // called on the TSF thread whenever the mode changes, so it doesn't wait for the ui process
+    pub fn set_input_mode(&mut self, mode: &str) -> Result<()> {
+        let ipc_service = self.clone();
+        let mode = mode.to_string();
+        runtime()?.spawn(async move {
+            if let Err(e) = ipc_service.set_input_mode_async(&mode).await {
+                tracing::debug!("Failed to send the mode to the tray: {:?}", e);
+            }
         });
+        Ok(())
+    }
+
+    pub fn flash_mode_indicator(&mut self, caret: WindowPosition, mode: &str) -> Result<()> {
+        block_on(self.flash_mode_indicator_async(caret, mode))
+    }
+
+    pub fn set_latency(&mut self, key_metrics: &KeyMetrics) -> Result<()> {
+        block_on(self.set_latency_async(key_metrics))
+    }
+}
+
+// implement methods to interact with kkc server
+impl IPCService {
+    // send a request to the kkc server, giving up after the conversion timeout
+    async fn call_server<T, F, Fut>(&self, call: F) -> Result<T>
+    where
+        F: FnOnce(AzookeyServiceClient<Channel>) -> Fut,
+        Fut: Future<Output = Result<tonic::Response<T>, tonic::Status>>,
+    {
+        // the flag only makes the requests fail fast while the server is down, and the keepalive
+        // doesn't run while keys keep coming, so ask the server again instead of trusting it
+        if !self.server_online.load(Ordering::Relaxed) && !self.keepalive_async().await {
+            anyhow::bail!("server is offline");
+        }
+
+        let client = self.azookey_client.clone();
+        let result = match time::timeout(ipc_timeout(TimeoutClass::Conversion), call(client)).await
+        {
+            Ok(Ok(response)) => Ok(response.into_inner()),
+            Ok(Err(status)) => Err(anyhow::anyhow!("gRPC error: {}", status)),
+            Err(_elapsed) => Err(anyhow::anyhow!("IPC timeout: server may have crashed")),
+        };
+        // any answer shows that the server is back
+        if result.is_ok() && !self.server_online.swap(true, Ordering::Relaxed) {
+            tracing::info!("Server is back online");
+        }
+        result
+    }
 
-        // Use timeout to prevent hanging when server crashes
+    // ping the server and remember whether it answered
+    pub async fn keepalive_async(&self) -> bool {
         let mut client = self.azookey_client.clone();
-        let response = self
-            .runtime
-            .clone()
-            .block_on(async {
-                match time::timeout(IPC_TIMEOUT, client.append_text(request)).await {
-                    Ok(Ok(response)) => Ok(response),
-                    Ok(Err(status)) => Err(anyhow::anyhow!("gRPC error: {}", status)),
-                    Err(_elapsed) => Err(anyhow::anyhow!("IPC timeout: server may have crashed")),
-                }
-            })?;
-        let composing_text = response.into_inner().composing_text;
-
-        let candidates = if let Some(composing_text) = composing_text {
-            Candidates {
-                texts: composing_text
-                    .suggestions
-                    .iter()
-                    .map(|s| s.text.clone())
-                    .collect(),
-                sub_texts: composing_text
-                    .suggestions
-                    .iter()
-                    .map(|s| s.subtext.clone())
-                    .collect(),
-                hiragana: composing_text.hiragana,
-                corresponding_count: composing_text
-                    .suggestions
-                    .iter()
-                    .map(|s| s.corresponding_count)
-                    .collect(),
+        let request = tonic::Request::new(shared::proto::PingRequest {});
+        let online = match time::timeout(KEEPALIVE_TIMEOUT, client.ping(request)).await {
+            Ok(Ok(response)) => {
+                check_config_version(response.into_inner().config_version);
+                true
             }
+            Ok(Err(_)) => false,
+            // a busy server answers late, only a failed connection means that it is down
+            // a server which hangs is caught by the timeout of the requests instead
+            Err(_elapsed) => return self.server_online.load(Ordering::Relaxed),
+        };
+
+        if self.server_online.swap(online, Ordering::Relaxed) != online {
+            tracing::info!("Server keepalive: online = {}", online);
+        }
+        online
+    }
+
+    #[tracing::instrument]
+    pub async fn append_text_async(&self, text: String) -> Result<Candidates> {
+        let recorded = text.clone();
+        let request = new_request(shared::proto::AppendTextRequest {
+            text_to_append: text,
+        });
+        let response = measure(
+            Stage::Conversion,
+            self.call_server(|mut client| async move { client.append_text(request).await }),
+        )
+        .await?;
+
+        recorder::record_request(
+            ConversionRequest::AppendText { text: recorded },
+            response.composing_text.as_ref(),
+        );
+
+        let candidates = if let Some(composing_text) = response.composing_text {
+            Candidates::from(composing_text)
         } else {
             anyhow::bail!("composing_text is None");
         };
@@ -169,33 +480,21 @@ impl IPCService {
     }
 
     #[tracing::instrument]
-    pub fn remove_text(&mut self) -> anyhow::Result<Candidates> {
-        let request = tonic::Request::new(shared::proto::RemoveTextRequest {});
-        let response = self
-            .runtime
-            .clone()
-            .block_on(self.azookey_client.remove_text(request))?;
-        let composing_text = response.into_inner().composing_text;
-
-        let candidates = if let Some(composing_text) = composing_text {
-            Candidates {
-                texts: composing_text
-                    .suggestions
-                    .iter()
-                    .map(|s| s.text.clone())
-                    .collect(),
-                sub_texts: composing_text
-                    .suggestions
-                    .iter()
-                    .map(|s| s.subtext.clone())
-                    .collect(),
-                hiragana: composing_text.hiragana,
-                corresponding_count: composing_text
-                    .suggestions
-                    .iter()
-                    .map(|s| s.corresponding_count)
-                    .collect(),
-            }
+    pub async fn remove_text_async(&self) -> Result<Candidates> {
+        let request = new_request(shared::proto::RemoveTextRequest {});
+        let response = measure(
+            Stage::Conversion,
+            self.call_server(|mut client| async move { client.remove_text(request).await }),
+        )
+        .await?;
+
+        recorder::record_request(
+            ConversionRequest::RemoveText,
+            response.composing_text.as_ref(),
+        );
+
+        let candidates = if let Some(composing_text) = response.composing_text {
+            Candidates::from(composing_text)
         } else {
             anyhow::bail!("composing_text is None");
         };
@@ -204,44 +503,31 @@ impl IPCService {
     }
 
     #[tracing::instrument]
-    pub fn clear_text(&mut self) -> anyhow::Result<()> {
-        let request = tonic::Request::new(shared::proto::ClearTextRequest {});
-        let _response = self
-            .runtime
-            .clone()
-            .block_on(self.azookey_client.clear_text(request))?;
+    pub async fn clear_text_async(&self) -> Result<()> {
+        let request = new_request(shared::proto::ClearTextRequest {});
+        self.call_server(|mut client| async move { client.clear_text(request).await })
+            .await?;
+        recorder::record_request(ConversionRequest::ClearText, None);
 
         Ok(())
     }
 
     #[tracing::instrument]
-    pub fn shrink_text(&mut self, offset: i32) -> anyhow::Result<Candidates> {
-        let request = tonic::Request::new(shared::proto::ShrinkTextRequest { offset });
-        let response = self
-            .runtime
-            .clone()
-            .block_on(self.azookey_client.shrink_text(request))?;
-        let composing_text = response.into_inner().composing_text;
-
-        let candidates = if let Some(composing_text) = composing_text {
-            Candidates {
-                texts: composing_text
-                    .suggestions
-                    .iter()
-                    .map(|s| s.text.clone())
-                    .collect(),
-                sub_texts: composing_text
-                    .suggestions
-                    .iter()
-                    .map(|s| s.subtext.clone())
-                    .collect(),
-                hiragana: composing_text.hiragana,
-                corresponding_count: composing_text
-                    .suggestions
-                    .iter()
-                    .map(|s| s.corresponding_count)
-                    .collect(),
-            }
+    pub async fn shrink_text_async(&self, offset: i32) -> Result<Candidates> {
+        let request = new_request(shared::proto::ShrinkTextRequest { offset });
+        let response = measure(
+            Stage::Conversion,
+            self.call_server(|mut client| async move { client.shrink_text(request).await }),
+        )
+        .await?;
+
+        recorder::record_request(
+            ConversionRequest::ShrinkText { offset },
+            response.composing_text.as_ref(),
+        );
+
+        let candidates = if let Some(composing_text) = response.composing_text {
+            Candidates::from(composing_text)
         } else {
             anyhow::bail!("composing_text is None");
         };
@@ -249,23 +535,82 @@ impl IPCService {
         Ok(candidates)
     }
 
-    pub fn set_context(&mut self, context: String) -> anyhow::Result<()> {
-        let request = tonic::Request::new(shared::proto::SetContextRequest { context });
-        let _response = self
-            .runtime
-            .clone()
-            .block_on(self.azookey_client.set_context(request))?;
+    #[tracing::instrument]
+    pub async fn resize_segment_async(&self, offset: i32) -> Result<Candidates> {
+        let request = new_request(shared::proto::ResizeSegmentRequest { offset });
+        let response = measure(
+            Stage::Conversion,
+            self.call_server(|mut client| async move { client.resize_segment(request).await }),
+        )
+        .await?;
+
+        recorder::record_request(
+            ConversionRequest::ResizeSegment { offset },
+            response.composing_text.as_ref(),
+        );
+
+        let candidates = if let Some(composing_text) = response.composing_text {
+            Candidates::from(composing_text)
+        } else {
+            anyhow::bail!("composing_text is None");
+        };
+
+        Ok(candidates)
+    }
+
+    pub async fn set_context_async(&self, context: String) -> Result<()> {
+        let recorded = context.clone();
+        let request = new_request(shared::proto::SetContextRequest { context });
+        self.call_server(|mut client| async move { client.set_context(request).await })
+            .await?;
+        recorder::record_request(ConversionRequest::SetContext { context: recorded }, None);
 
         Ok(())
     }
 
     #[tracing::instrument]
-    pub fn learn_candidate(&mut self, candidate_index: i32) -> anyhow::Result<()> {
-        let request = tonic::Request::new(shared::proto::LearnCandidateRequest { candidate_index });
-        let _response = self
-            .runtime
-            .clone()
-            .block_on(self.azookey_client.learn_candidate(request))?;
+    pub async fn learn_candidate_async(&self, candidate_index: i32) -> Result<()> {
+        let request = new_request(shared::proto::LearnCandidateRequest { candidate_index });
+        self.call_server(|mut client| async move { client.learn_candidate(request).await })
+            .await?;
+
+        Ok(())
+    }
+
+    // compose the text again from its reading, an error if the server doesn't know the reading
+    #[tracing::instrument]
+    pub async fn reconvert_async(&self, text: String) -> Result<Candidates> {
+        let recorded = text.clone();
+        let request = new_request(shared::proto::ReconvertRequest { text });
+        let response = measure(
+            Stage::Conversion,
+            self.call_server(|mut client| async move { client.reconvert(request).await }),
+        )
+        .await?;
+
+        recorder::record_request(
+            ConversionRequest::Reconvert { text: recorded },
+            response.composing_text.as_ref(),
+        );
+
+        let candidates = if let Some(composing_text) = response.composing_text {
+            Candidates::from(composing_text)
+        } else {
+            anyhow::bail!("composing_text is None");
+        };
+
+        Ok(candidates)
+    }
+
+    pub async fn report_commit_async(&self, text: String, reading: String) -> Result<()> {
+        let request = new_request(shared::proto::CommitEvent {
+            text,
+            reading,
+            process: super::app_quirks::host_process().to_string(),
+            ..Default::default()
+        });
+        self.call_server(|mut client| async move { client.report_commit(request).await })
+            .await?;
 
         Ok(())
     }
@@ -273,78 +618,371 @@ impl IPCService {
 
 // implement methods to interact with candidate window server
 impl IPCService {
+    // send a request to the candidate window
+    // `update` records the request so that it can be replayed after the ui process restarts
+    async fn call_window<F, Fut>(
+        &self,
+        update: impl FnOnce(&mut WindowState),
+        call: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(WindowServiceClient<Channel>) -> Fut,
+        Fut: Future<Output = Result<tonic::Response<shared::proto::EmptyResponse>, tonic::Status>>,
+    {
+        let replay = {
+            let mut state = self.window_state.lock().unwrap_or_else(|e| e.into_inner());
+            update(&mut state);
+
+            match state.lost_at {
+                // don't block the key handling while the ui process is restarting
+                Some(lost_at) if lost_at.elapsed() < WINDOW_RETRY_INTERVAL => {
+                    anyhow::bail!("candidate window is not available");
+                }
+                Some(_) => true,
+                None => false,
+            }
+        };
+
+        let result = if replay {
+            self.replay_window_state().await
+        } else {
+            self.send_window(call).await
+        };
+
+        let mut state = self.window_state.lock().unwrap_or_else(|e| e.into_inner());
+        match &result {
+            Ok(_) if state.lost_at.take().is_some() => {
+                tracing::info!("Candidate window is back, state restored");
+            }
+            Ok(_) => {}
+            Err(e) => {
+                if state.lost_at.is_none() {
+                    tracing::warn!("Candidate window is not responding: {:?}", e);
+                }
+                state.lost_at = Some(Instant::now());
+            }
+        }
+
+        result
+    }
+
+    async fn send_window<F, Fut>(&self, call: F) -> Result<()>
+    where
+        F: FnOnce(WindowServiceClient<Channel>) -> Fut,
+        Fut: Future<Output = Result<tonic::Response<shared::proto::EmptyResponse>, tonic::Status>>,
+    {
+        let client = self.window_client.clone();
+        let result = measure(
+            Stage::Window,
+            time::timeout(ipc_timeout(TimeoutClass::Ui), call(client)),
+        )
+        .await;
+
+        match result {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(status)) => Err(anyhow::anyhow!("gRPC error: {}", status)),
+            Err(_elapsed) => Err(anyhow::anyhow!("IPC timeout: ui may have crashed")),
+        }
+    }
+
+    // receive events from the candidate window (e.g. clicks) until the task is aborted
+    // the subscription is renewed when the ui process restarts
+    pub fn subscribe_window_events(
+        &self,
+        on_event: impl Fn(shared::proto::WindowEvent) + Send + 'static,
+    ) -> Result<tokio::task::JoinHandle<()>> {
+        let mut client = self.window_client.clone();
+
+        Ok(runtime()?.spawn(async move {
+            loop {
+                let request = new_request(shared::proto::EmptyResponse {});
+                match client.subscribe_events(request).await {
+                    Ok(response) => {
+                        let mut events = response.into_inner();
+                        while let Ok(Some(event)) = events.message().await {
+                            if let Some(window_event::Event::PageSize(size)) = event.event {
+                                let size = (size.max(1) as usize).min(CANDIDATE_PAGE_SIZE);
+                                PAGE_SIZE.store(size, Ordering::Relaxed);
+                            }
+                            on_event(event);
+                        }
+                    }
+                    Err(e) => {
+                        tracing::debug!("Failed to subscribe to window events: {:?}", e);
+                    }
+                }
+
+                time::sleep(WINDOW_RETRY_INTERVAL).await;
+            }
+        }))
+    }
+
+    // a restarted ui process starts with an empty window, so send everything again
+    async fn replay_window_state(&self) -> Result<()> {
+        let state = self
+            .window_state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+
+        if let Some(position) = state.position {
+            self.send_window(|mut client| async move {
+                client.set_window_position(new_request(position)).await
+            })
+            .await?;
+        }
+
+        let candidates = state.candidates;
+        let kind = state.kind.into();
+        self.send_window(|mut client| async move {
+            client
+                .set_candidate(new_request(shared::proto::SetCandidateRequest {
+                    candidates,
+                    kind,
+                }))
+                .await
+        })
+        .await?;
+
+        let index = state.selection;
+        self.send_window(|mut client| async move {
+            client
+                .set_selection(new_request(shared::proto::SetSelectionRequest { index }))
+                .await
+        })
+        .await?;
+
+        if let Some(mode) = state.input_mode {
+            self.send_window(|mut client| async move {
+                client
+                    .set_input_mode(new_request(shared::proto::SetInputModeRequest { mode }))
+                    .await
+            })
+            .await?;
+        }
+
+        let visible = state.visible;
+        self.send_window(|mut client| async move {
+            let request = new_request(shared::proto::EmptyResponse {});
+            if visible {
+                client.show_window(request).await
+            } else {
+                client.hide_window(request).await
+            }
+        })
+        .await
+    }
+
     #[tracing::instrument]
-    pub fn show_window(&mut self) -> anyhow::Result<()> {
-        let request = tonic::Request::new(shared::proto::EmptyResponse {});
-        self.runtime
-            .clone()
-            .block_on(self.window_client.show_window(request))?;
+    pub async fn show_window_async(&self) -> Result<()> {
+        let request = new_request(shared::proto::EmptyResponse {});
+        self.call_window(
+            |state| state.visible = true,
+            |mut client| async move { client.show_window(request).await },
+        )
+        .await
+    }
 
-        Ok(())
+    #[tracing::instrument]
+    pub async fn hide_window_async(&self) -> Result<()> {
+        let request = new_request(shared::proto::EmptyResponse {});
+        self.call_window(
+            |state| state.visible = false,
+            |mut client| async move { client.hide_window(request).await },
+        )
+        .await
     }
 
     #[tracing::instrument]
-    pub fn hide_window(&mut self) -> anyhow::Result<()> {
-        let request = tonic::Request::new(shared::proto::EmptyResponse {});
-        self.runtime
-            .clone()
-            .block_on(self.window_client.hide_window(request))?;
+    pub async fn set_window_position_async(
+        &self,
+        caret: WindowPosition,
+        exclusion: WindowPosition,
+    ) -> Result<()> {
+        let position = shared::proto::SetPositionRequest {
+            caret_height: caret.bottom - caret.top,
+            position: Some(caret),
+            exclusion: Some(exclusion),
+        };
+        let request = new_request(position.clone());
+        self.call_window(
+            |state| state.position = Some(position),
+            |mut client| async move { client.set_window_position(request).await },
+        )
+        .await
+    }
 
-        Ok(())
+    #[tracing::instrument]
+    pub async fn set_candidates_async(&self, candidates: &Candidates) -> Result<()> {
+        let candidates = candidates.to_window();
+        let request = new_request(shared::proto::SetCandidateRequest {
+            candidates: candidates.clone(),
+            kind: self.candidate_kind().into(),
+        });
+        self.call_window(
+            |state| state.candidates = candidates,
+            |mut client| async move { client.set_candidate(request).await },
+        )
+        .await
     }
 
+    // the kind last sent to the candidate window
+    fn candidate_kind(&self) -> CandidateKind {
+        self.window_state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .kind
+    }
+
+    // the candidates are sent again with the new kind, e.g. with the first Space
     #[tracing::instrument]
-    pub fn set_window_position(
-        &mut self,
-        top: i32,
-        left: i32,
-        bottom: i32,
-        right: i32,
-    ) -> anyhow::Result<()> {
-        let request = tonic::Request::new(shared::proto::SetPositionRequest {
-            position: Some(shared::proto::WindowPosition {
-                top,
-                left,
-                bottom,
-                right,
-            }),
+    pub async fn set_candidate_kind_async(&self, kind: CandidateKind) -> Result<()> {
+        let (candidates, selection) = {
+            let state = self.window_state.lock().unwrap_or_else(|e| e.into_inner());
+            (state.candidates.clone(), state.selection)
+        };
+
+        let request = new_request(shared::proto::SetCandidateRequest {
+            candidates,
+            kind: kind.into(),
         });
-        self.runtime
-            .clone()
-            .block_on(self.window_client.set_window_position(request))?;
+        self.call_window(
+            |state| state.kind = kind,
+            |mut client| async move { client.set_candidate(request).await },
+        )
+        .await?;
+        // new candidates start from the first one in the window
+        self.set_selection_async(selection).await
+    }
 
-        Ok(())
+    // `selection` is the prediction chosen with Tab, -1 for none
+    #[tracing::instrument]
+    pub async fn set_predictions_async(
+        &self,
+        predictions: &[Prediction],
+        selection: i32,
+    ) -> Result<()> {
+        let predictions = predictions
+            .iter()
+            .enumerate()
+            .map(|(index, prediction)| shared::proto::Candidate {
+                text: prediction.text.clone(),
+                reading: prediction.reading.clone(),
+                index: index as i32,
+                ..Default::default()
+            })
+            .collect();
+        let request = new_request(shared::proto::SetPredictionsRequest {
+            predictions,
+            selection,
+        });
+        // the predictions change with every key, so they are not replayed
+        self.call_window(
+            |_| {},
+            |mut client| async move { client.set_predictions(request).await },
+        )
+        .await
     }
 
     #[tracing::instrument]
-    pub fn set_candidates(&mut self, candidates: Vec<String>) -> anyhow::Result<()> {
-        let request = tonic::Request::new(shared::proto::SetCandidateRequest { candidates });
-        self.runtime
-            .clone()
-            .block_on(self.window_client.set_candidate(request))?;
+    pub async fn toggle_detail_async(&self) -> Result<()> {
+        let request = new_request(shared::proto::EmptyResponse {});
+        // the pane keeps its state in the ui process, so it is not replayed
+        self.call_window(
+            |_| {},
+            |mut client| async move { client.toggle_detail(request).await },
+        )
+        .await
+    }
 
-        Ok(())
+    #[tracing::instrument]
+    pub async fn show_palette_async(&self, categories: Vec<PaletteCategory>) -> Result<()> {
+        let request = new_request(shared::proto::ShowPaletteRequest { categories });
+        // the next candidates replace the palette, so it is not replayed
+        self.call_window(
+            |_| {},
+            |mut client| async move { client.show_palette(request).await },
+        )
+        .await
     }
 
     #[tracing::instrument]
-    pub fn set_selection(&mut self, index: i32) -> anyhow::Result<()> {
-        let request = tonic::Request::new(shared::proto::SetSelectionRequest { index });
-        self.runtime
-            .clone()
-            .block_on(self.window_client.set_selection(request))?;
+    pub async fn set_layout_async(&self, vertical: bool) -> Result<()> {
+        let request = new_request(shared::proto::SetLayoutRequest { vertical });
+        // the ui process reads the layout from the config when it starts, so it is not replayed
+        self.call_window(
+            |_| {},
+            |mut client| async move { client.set_layout(request).await },
+        )
+        .await
+    }
 
-        Ok(())
+    #[tracing::instrument]
+    pub async fn set_font_async(&self, font: &FontConfig) -> Result<()> {
+        let request = new_request(shared::proto::SetFontRequest {
+            family: font.family.clone(),
+            size: font.size,
+            line_spacing: font.line_spacing,
+        });
+        // read from the config when the ui process starts as well, like the layout
+        self.call_window(
+            |_| {},
+            |mut client| async move { client.set_font(request).await },
+        )
+        .await
     }
 
     #[tracing::instrument]
-    pub fn set_input_mode(&mut self, mode: &str) -> anyhow::Result<()> {
-        let request = tonic::Request::new(shared::proto::SetInputModeRequest {
+    pub async fn set_selection_async(&self, index: i32) -> Result<()> {
+        let request = new_request(shared::proto::SetSelectionRequest { index });
+        self.call_window(
+            |state| state.selection = index,
+            |mut client| async move { client.set_selection(request).await },
+        )
+        .await
+    }
+
+    #[tracing::instrument]
+    pub async fn set_input_mode_async(&self, mode: &str) -> Result<()> {
+        let request = new_request(shared::proto::SetInputModeRequest {
             mode: mode.to_string(),
         });
-        self.runtime
-            .clone()
-            .block_on(self.window_client.set_input_mode(request))?;
+        self.call_window(
+            |state| state.input_mode = Some(mode.to_string()),
+            |mut client| async move { client.set_input_mode(request).await },
+        )
+        .await
+    }
 
-        Ok(())
+    #[tracing::instrument]
+    pub async fn flash_mode_indicator_async(
+        &self,
+        caret: WindowPosition,
+        mode: &str,
+    ) -> Result<()> {
+        let request = new_request(shared::proto::FlashModeIndicatorRequest {
+            position: Some(caret),
+            mode: mode.to_string(),
+        });
+        // transient like the mode of `set_input_mode_async`
+        self.call_window(
+            |_| {},
+            |mut client| async move { client.flash_mode_indicator(request).await },
+        )
+        .await
+    }
+
+    #[tracing::instrument]
+    pub async fn set_latency_async(&self, key_metrics: &KeyMetrics) -> Result<()> {
+        let request = new_request(shared::proto::SetLatencyRequest {
+            queue_us: key_metrics.queue.as_micros() as u32,
+            conversion_us: key_metrics.conversion.as_micros() as u32,
+            window_us: key_metrics.window.as_micros() as u32,
+        });
+        self.call_window(
+            |_| {},
+            |mut client| async move { client.set_latency(request).await },
+        )
+        .await
     }
 }
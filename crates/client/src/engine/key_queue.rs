@@ -0,0 +1,466 @@
+use std::{
+    cell::Cell,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context as _, Result};
+use shared::proto::WindowPosition;
+
+use crate::{panic_guard, tsf::factory::TextServiceFactory};
+
+use super::{
+    client_action::ClientAction,
+    composition::{run_actions, Composition, CompositionState, EditOp},
+    input_mode::InputMode,
+    ipc_service::ReconnectState,
+    metrics, request_id,
+    state::IMEState,
+};
+
+// number of key batches which can wait for the ipc worker
+// if the queue is full, the TSF thread waits until the worker catches up
+const KEY_QUEUE_CAPACITY: usize = 64;
+
+// while composing or shortly after a key, the worker pings the server at this interval
+// so that a crashed server is noticed before the next keystroke has to wait for the timeout
+const KEEPALIVE_INTERVAL: Duration = Duration::from_millis(500);
+// how long after the last key the server is pinged at the full rate
+const KEEPALIVE_ACTIVE: Duration = Duration::from_secs(10);
+// while idle, the interval doubles with every ping up to this
+const KEEPALIVE_MAX_INTERVAL: Duration = Duration::from_secs(60);
+// the pings stop after this many failures in a row, the next key reconnects anyway
+const KEEPALIVE_MAX_FAILURES: u32 = 3;
+
+#[derive(Debug)]
+pub struct KeyJob {
+    pub seq: u64,
+    pub request_id: String,
+    pub actions: Vec<ClientAction>,
+    pub transition: CompositionState,
+    // input mode at the time the key was pressed
+    pub mode: InputMode,
+    pub queued_at: Instant,
+}
+
+#[derive(Debug)]
+enum Job {
+    Actions(KeyJob),
+    WindowPosition {
+        caret: WindowPosition,
+        exclusion: WindowPosition,
+    },
+}
+
+// when the worker pings the server, reset by every key
+#[derive(Debug)]
+struct Keepalive {
+    last_key: Instant,
+    interval: Duration,
+    failures: u32,
+}
+
+impl Keepalive {
+    fn new() -> Self {
+        Self {
+            last_key: Instant::now(),
+            interval: KEEPALIVE_INTERVAL,
+            failures: 0,
+        }
+    }
+
+    fn is_active(&self, composing: bool) -> bool {
+        composing || self.last_key.elapsed() < KEEPALIVE_ACTIVE
+    }
+
+    // how long to wait for a job before the next ping, None to wait without pinging
+    fn timeout(&self, composing: bool) -> Option<Duration> {
+        if self.failures >= KEEPALIVE_MAX_FAILURES {
+            None
+        } else if self.is_active(composing) {
+            Some(KEEPALIVE_INTERVAL)
+        } else {
+            Some(self.interval)
+        }
+    }
+
+    fn record(&mut self, online: bool, composing: bool) {
+        if online {
+            self.failures = 0;
+        } else {
+            self.failures += 1;
+        }
+        if !self.is_active(composing) {
+            self.interval = (self.interval * 2).min(KEEPALIVE_MAX_INTERVAL);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct JobResult {
+    pub seq: u64,
+    pub request_id: String,
+    pub ops: Vec<EditOp>,
+    pub composition: Composition,
+    // the worker panicked while processing the job
+    pub panicked: bool,
+}
+
+// queue between the key event sink and the ipc worker
+// keys are processed in order on the worker, and the results are applied on the TSF thread
+#[derive(Debug)]
+pub struct KeyQueue {
+    sender: SyncSender<Job>,
+    results: Receiver<JobResult>,
+    last_seq: Cell<u64>,
+    received_seq: Cell<u64>,
+}
+
+impl KeyQueue {
+    pub fn new(notify: impl Fn() + Send + 'static) -> Result<Self> {
+        let (sender, receiver) = mpsc::sync_channel(KEY_QUEUE_CAPACITY);
+        let (result_sender, results) = mpsc::channel();
+
+        std::thread::Builder::new()
+            .name("azookey-ipc-worker".to_string())
+            .spawn(move || run_worker(receiver, result_sender, notify))?;
+
+        Ok(Self {
+            sender,
+            results,
+            last_seq: Cell::new(0),
+            received_seq: Cell::new(0),
+        })
+    }
+
+    pub fn push(
+        &self,
+        request_id: String,
+        actions: Vec<ClientAction>,
+        transition: CompositionState,
+        mode: InputMode,
+    ) -> Result<u64> {
+        let seq = self.last_seq.get() + 1;
+        self.last_seq.set(seq);
+
+        self.sender
+            .send(Job::Actions(KeyJob {
+                seq,
+                request_id,
+                actions,
+                transition,
+                mode,
+                queued_at: Instant::now(),
+            }))
+            .context("IPC worker is not running")?;
+
+        Ok(seq)
+    }
+
+    pub fn set_window_position(
+        &self,
+        caret: WindowPosition,
+        exclusion: WindowPosition,
+    ) -> Result<()> {
+        self.sender
+            .send(Job::WindowPosition { caret, exclusion })
+            .context("IPC worker is not running")
+    }
+
+    pub fn is_latest(&self, seq: u64) -> bool {
+        seq >= self.last_seq.get()
+    }
+
+    // whether the result of `seq` has already been received
+    pub fn is_received(&self, seq: u64) -> bool {
+        seq <= self.received_seq.get()
+    }
+
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq.get()
+    }
+
+    pub fn try_recv(&self) -> Option<JobResult> {
+        let result = self.results.try_recv().ok()?;
+        self.received_seq.set(result.seq);
+        Some(result)
+    }
+
+    pub fn recv(&self) -> Result<JobResult> {
+        let result = self.results.recv().context("IPC worker is not running")?;
+        self.received_seq.set(result.seq);
+        Ok(result)
+    }
+}
+
+fn run_worker(receiver: Receiver<Job>, results: Sender<JobResult>, notify: impl Fn()) {
+    tracing::debug!("IPC worker started");
+
+    // the worker owns the composition, the TSF thread only keeps a copy of it
+    let mut composition = Composition::default();
+    // results are tagged with the last processed key, so that the keepalive doesn't disturb the order
+    let mut last_seq = 0;
+    let mut reconnect = ReconnectState::default();
+    let mut pings = Keepalive::new();
+
+    loop {
+        let composing = composition.state != CompositionState::None;
+        let job = match pings.timeout(composing) {
+            Some(timeout) => receiver.recv_timeout(timeout),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let job = match job {
+            Ok(job) => job,
+            Err(RecvTimeoutError::Timeout) => {
+                if keepalive(&mut pings, composing) {
+                    let result = JobResult {
+                        seq: last_seq,
+                        request_id: "keepalive".to_string(),
+                        ops: vec![EditOp::UpdateLangBar],
+                        composition: composition.clone(),
+                        panicked: false,
+                    };
+                    if results.send(result).is_err() {
+                        break;
+                    }
+                    notify();
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        match job {
+            Job::Actions(job) => {
+                pings = Keepalive::new();
+                let _request_id = request_id::enter(&job.request_id);
+                let span = tracing::info_span!("ipc_worker", request_id = %job.request_id);
+                let _span = span.enter();
+
+                if metrics::is_enabled() {
+                    metrics::start(job.queued_at.elapsed());
+                }
+
+                let result = panic_guard::catch(|| {
+                    run_actions(
+                        &composition,
+                        &job.mode,
+                        &job.actions,
+                        job.transition,
+                        &mut reconnect,
+                    )
+                });
+                // the worker keeps running for the next activation,
+                // the TSF thread is disabled instead when the result is applied
+                let panicked = panic_guard::is_disabled();
+                panic_guard::reset();
+
+                let ops = match result {
+                    Ok((next, ops)) => {
+                        composition = next;
+                        ops
+                    }
+                    Err(e) if panicked => {
+                        tracing::error!("Panicked on actions {:?}: {:?}", job.actions, e);
+                        // the composition may be half updated, so leave the text as it is
+                        composition = Composition::default();
+                        vec![EditOp::EndComposition]
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to process actions {:?}: {:?}", job.actions, e);
+                        vec![]
+                    }
+                };
+
+                if metrics::is_enabled() {
+                    report_metrics();
+                }
+
+                last_seq = job.seq;
+                let result = JobResult {
+                    seq: job.seq,
+                    request_id: job.request_id,
+                    ops,
+                    composition: composition.clone(),
+                    panicked,
+                };
+
+                if results.send(result).is_err() {
+                    break;
+                }
+                notify();
+            }
+            Job::WindowPosition { caret, exclusion } => {
+                let _ = panic_guard::catch(|| {
+                    let ipc_service = IMEState::get().ok().and_then(|s| s.ipc_service.clone());
+                    if let Some(mut ipc_service) = ipc_service {
+                        ipc_service.set_window_position(caret, exclusion)?;
+                    }
+                    Ok(())
+                });
+                panic_guard::reset();
+            }
+        }
+    }
+
+    tracing::debug!("IPC worker stopped");
+}
+
+// ping the server and update its status, returns true when the status changed
+fn keepalive(pings: &mut Keepalive, composing: bool) -> bool {
+    let ipc_service = IMEState::get().ok().and_then(|s| s.ipc_service.clone());
+    // without a connection, the next keystroke tries to reconnect instead
+    let Some(mut ipc_service) = ipc_service else {
+        pings.record(false, composing);
+        return false;
+    };
+
+    let online = ipc_service.keepalive();
+    pings.record(online, composing);
+    let Ok(mut state) = IMEState::get() else {
+        return false;
+    };
+    if online {
+        state.server_status.record_success()
+    } else {
+        state.server_status.mark_unavailable()
+    }
+}
+
+// show the timing of the keystroke in the candidate window
+fn report_metrics() {
+    let Some(key_metrics) = metrics::finish() else {
+        return;
+    };
+    tracing::debug!("Keystroke timing: {:?}", key_metrics);
+
+    let ipc_service = IMEState::get().ok().and_then(|s| s.ipc_service.clone());
+    if let Some(mut ipc_service) = ipc_service {
+        let _ = ipc_service.set_latency(&key_metrics);
+    }
+}
+
+impl TextServiceFactory {
+    // queue the actions for the ipc worker
+    // returns None if there is no queue and the actions were handled synchronously
+    pub fn enqueue_actions(
+        &self,
+        actions: Vec<ClientAction>,
+        transition: CompositionState,
+    ) -> Result<Option<u64>> {
+        let mode = IMEState::get()?.input_mode.clone();
+        let request_id = request_id::current().unwrap_or_else(request_id::next);
+
+        {
+            let text_service = self.borrow()?;
+            if let Some(key_queue) = &text_service.key_queue {
+                let next_mode = actions.iter().rev().find_map(|action| match action {
+                    ClientAction::SetIMEMode(mode) => Some(mode.clone()),
+                    _ => None,
+                });
+
+                let seq = key_queue.push(request_id, actions, transition.clone(), mode)?;
+
+                // predict the state so that the following keys are decided correctly
+                // before the worker finishes this batch
+                text_service.borrow_mut_composition()?.state = transition;
+                if let Some(next_mode) = next_mode {
+                    IMEState::get()?.input_mode = next_mode;
+                }
+
+                return Ok(Some(seq));
+            }
+        }
+
+        // the queue is not ready (e.g. before Activate), so handle it on this thread
+        // there is no worker to keep the reconnection state, so a fresh one is used
+        let composition = self.borrow()?.borrow_composition()?.clone();
+        let (composition, ops) = run_actions(
+            &composition,
+            &mode,
+            &actions,
+            transition,
+            &mut ReconnectState::default(),
+        )?;
+        self.apply_edit_ops(&ops, &composition.state)?;
+        *self.borrow()?.borrow_mut_composition()? = composition;
+
+        Ok(None)
+    }
+
+    // wait until the worker has processed everything up to `seq`
+    pub fn flush_key_queue(&self, seq: u64) -> Result<()> {
+        loop {
+            let result = {
+                let text_service = self.borrow()?;
+                match &text_service.key_queue {
+                    Some(key_queue) if !key_queue.is_received(seq) => key_queue.recv()?,
+                    _ => return Ok(()),
+                }
+            };
+
+            self.apply_job_result(result)?;
+        }
+    }
+
+    // wait until all the queued keys are processed
+    pub fn flush_pending_keys(&self) -> Result<()> {
+        let last_seq = match &self.borrow()?.key_queue {
+            Some(key_queue) => key_queue.last_seq(),
+            None => return Ok(()),
+        };
+
+        self.flush_key_queue(last_seq)
+    }
+
+    // apply the results which are already available without blocking
+    pub fn drain_key_queue(&self) -> Result<()> {
+        loop {
+            let result = {
+                let text_service = self.borrow()?;
+                match &text_service.key_queue {
+                    Some(key_queue) => key_queue.try_recv(),
+                    None => None,
+                }
+            };
+
+            match result {
+                Some(result) => self.apply_job_result(result)?,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn apply_job_result(&self, result: JobResult) -> Result<()> {
+        let _request_id = request_id::enter(&result.request_id);
+        let span = tracing::info_span!("apply_edit", request_id = %result.request_id);
+        let _span = span.enter();
+
+        // pass the keys through until the IME is activated again, as for a panic on this thread
+        if result.panicked {
+            panic_guard::disable();
+        }
+
+        // keep the copy in sync with the worker even if the edit session fails
+        if let Err(e) = self.apply_edit_ops(&result.ops, &result.composition.state) {
+            tracing::error!("Failed to apply edit operations: {:?}", e);
+        }
+
+        let text_service = self.borrow()?;
+        let is_latest = text_service
+            .key_queue
+            .as_ref()
+            .map_or(true, |key_queue| key_queue.is_latest(result.seq));
+
+        let mut composition = text_service.borrow_mut_composition()?;
+        let predicted_state = composition.state.clone();
+        *composition = result.composition;
+
+        // newer keys are still in the queue, so keep the predicted state
+        if !is_latest {
+            composition.state = predicted_state;
+        }
+
+        Ok(())
+    }
+}
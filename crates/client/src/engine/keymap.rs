@@ -0,0 +1,357 @@
+use anyhow::Result;
+use shared::{BindingAction, CompositionStateName, KeyBinding};
+use windows::Win32::{
+    Foundation::WPARAM,
+    UI::{
+        Input::KeyboardAndMouse::{VK_CONTROL, VK_MENU, VK_SHIFT},
+        TextServices::{TF_MOD_ALT, TF_MOD_CONTROL, TF_MOD_SHIFT, TF_PRESERVEDKEY},
+    },
+};
+
+use crate::tsf::factory::TextServiceFactory;
+
+use super::{
+    client_action::{ClientAction, SetSelectionType},
+    composition::{Composition, CompositionState},
+    state::IMEState,
+    sticky_keys,
+};
+
+// parse a shortcut like "Ctrl+Shift+F12"
+// the key is a letter, a digit, F1-F24 or one of the names in `virtual_key`
+pub fn parse_shortcut(shortcut: &str) -> Option<TF_PRESERVEDKEY> {
+    let mut modifiers = 0;
+    let mut key = None;
+    for part in shortcut.split('+').map(str::trim) {
+        match part.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => modifiers |= TF_MOD_CONTROL,
+            "shift" => modifiers |= TF_MOD_SHIFT,
+            "alt" => modifiers |= TF_MOD_ALT,
+            name if key.is_none() => key = Some(virtual_key(name)?),
+            _ => return None,
+        }
+    }
+
+    Some(TF_PRESERVEDKEY {
+        uVKey: key?,
+        uModifiers: modifiers,
+    })
+}
+
+fn virtual_key(name: &str) -> Option<u32> {
+    let named = match name {
+        "backspace" => Some(0x08),
+        "tab" => Some(0x09),
+        "enter" => Some(0x0D),
+        "escape" | "esc" => Some(0x1B),
+        "space" => Some(0x20),
+        "pageup" => Some(0x21),
+        "pagedown" => Some(0x22),
+        "end" => Some(0x23),
+        "home" => Some(0x24),
+        "left" => Some(0x25),
+        "up" => Some(0x26),
+        "right" => Some(0x27),
+        "down" => Some(0x28),
+        "delete" => Some(0x2E),
+        _ => None,
+    };
+    if named.is_some() {
+        return named;
+    }
+
+    let mut chars = name.chars();
+    match (chars.next()?, chars.as_str()) {
+        (c, "") if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase() as u32),
+        ('f', number) => match number.parse::<u32>() {
+            // VK_F1 is 0x70
+            Ok(n @ 1..=24) => Some(0x6F + n),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// the modifiers held now, as in TF_PRESERVEDKEY
+fn modifiers() -> u32 {
+    [
+        (VK_CONTROL, TF_MOD_CONTROL),
+        (VK_SHIFT, TF_MOD_SHIFT),
+        (VK_MENU, TF_MOD_ALT),
+    ]
+    .into_iter()
+    .filter(|(key, _)| sticky_keys::is_held(*key))
+    .fold(0, |modifiers, (_, modifier)| modifiers | modifier)
+}
+
+fn state_name(state: &CompositionState) -> CompositionStateName {
+    match state {
+        CompositionState::None => CompositionStateName::None,
+        CompositionState::Composing => CompositionStateName::Composing,
+        CompositionState::Previewing => CompositionStateName::Previewing,
+        CompositionState::Selecting => CompositionStateName::Selecting,
+    }
+}
+
+// the client actions of a binding and the state after them
+// None if one of them needs a composition and there is none, so the key is not taken
+fn binding_actions(
+    actions: &[BindingAction],
+    composition: &Composition,
+) -> Option<(Vec<ClientAction>, CompositionState)> {
+    let mut client_actions = vec![];
+    let mut state = composition.state.clone();
+
+    for action in actions {
+        let composing = state != CompositionState::None;
+        let (next, actions) = match action {
+            BindingAction::SetMode(mode) => (
+                CompositionState::None,
+                vec![ClientAction::SetIMEMode((*mode).into())],
+            ),
+            BindingAction::Insert(text) => (
+                CompositionState::None,
+                vec![ClientAction::InsertText(text.clone())],
+            ),
+            _ if !composing => return None,
+            BindingAction::Commit => (CompositionState::None, vec![ClientAction::EndComposition]),
+            BindingAction::Cancel => (
+                CompositionState::None,
+                vec![ClientAction::ClearText, ClientAction::EndComposition],
+            ),
+            // the last character ends the composition like the Backspace key
+            BindingAction::Backspace
+                if composition.preview.chars().count() == 1 && composition.clauses.is_empty() =>
+            {
+                (
+                    CompositionState::None,
+                    vec![ClientAction::RemoveText, ClientAction::EndComposition],
+                )
+            }
+            BindingAction::Backspace => {
+                (CompositionState::Composing, vec![ClientAction::RemoveText])
+            }
+            BindingAction::Revert => (
+                CompositionState::Composing,
+                vec![ClientAction::RevertToReading],
+            ),
+            BindingAction::MoveCursor(offset) => (
+                CompositionState::Composing,
+                vec![ClientAction::MoveCursor(*offset)],
+            ),
+            BindingAction::Convert | BindingAction::NextCandidate => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetSelection(SetSelectionType::Down)],
+            ),
+            BindingAction::PreviousCandidate => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetSelection(SetSelectionType::Up)],
+            ),
+            BindingAction::NextPage => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetSelection(SetSelectionType::Page(1))],
+            ),
+            BindingAction::PreviousPage => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetSelection(SetSelectionType::Page(-1))],
+            ),
+            BindingAction::ResizeSegment(offset) => (
+                CompositionState::Previewing,
+                vec![ClientAction::ResizeSegment(*offset)],
+            ),
+            BindingAction::FocusClause(offset) => (
+                CompositionState::Previewing,
+                vec![ClientAction::FocusClause(*offset)],
+            ),
+            // the converted clauses go one by one, then the conversion like Enter
+            BindingAction::CommitPrefix if !composition.clauses.is_empty() => (
+                CompositionState::Previewing,
+                vec![ClientAction::CommitPrefix],
+            ),
+            BindingAction::CommitPrefix if !composition.suffix.is_empty() => (
+                CompositionState::Composing,
+                vec![ClientAction::ShrinkText("".to_string())],
+            ),
+            BindingAction::CommitPrefix => {
+                (CompositionState::None, vec![ClientAction::EndComposition])
+            }
+            BindingAction::Transform(transform) => (
+                CompositionState::Previewing,
+                vec![ClientAction::SetTextWithType((*transform).into())],
+            ),
+            BindingAction::ToggleDetail => (state.clone(), vec![ClientAction::ToggleDetail]),
+        };
+
+        if composing && matches!(action, BindingAction::SetMode(_) | BindingAction::Insert(_)) {
+            client_actions.push(ClientAction::EndComposition);
+        }
+        client_actions.extend(actions);
+        state = next;
+    }
+
+    Some((client_actions, state))
+}
+
+// the first binding of the key with the modifiers held now which works in the state
+fn find_binding<'a>(
+    bindings: &'a [KeyBinding],
+    key: u32,
+    modifiers: u32,
+    state: &CompositionState,
+) -> Option<&'a KeyBinding> {
+    let state = state_name(state);
+    bindings.iter().find(|binding| {
+        let Some(shortcut) = parse_shortcut(&binding.key) else {
+            return false;
+        };
+        shortcut.uVKey == key
+            && shortcut.uModifiers == modifiers
+            && (binding.states.is_empty() || binding.states.contains(&state))
+    })
+}
+
+impl TextServiceFactory {
+    // the actions of `KeyConfig.bindings` for the key, None to leave it to the built-in keys
+    pub fn process_binding(
+        &self,
+        wparam: WPARAM,
+    ) -> Result<Option<(Vec<ClientAction>, CompositionState)>> {
+        let bindings = IMEState::get()?.config.keys.bindings.clone();
+        if bindings.is_empty() {
+            return Ok(None);
+        }
+        let composition = self.borrow()?.borrow_composition()?.clone();
+
+        let Some(binding) =
+            find_binding(&bindings, wparam.0 as u32, modifiers(), &composition.state)
+        else {
+            return Ok(None);
+        };
+        tracing::debug!("Key binding: {:?}", binding);
+
+        Ok(binding_actions(&binding.actions, &composition))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::engine::{composition::Clause, input_mode::InputMode};
+
+    fn composition(state: CompositionState, preview: &str) -> Composition {
+        Composition {
+            state,
+            preview: preview.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shortcuts() {
+        let key = parse_shortcut("Ctrl+Shift+Space").unwrap();
+        assert_eq!(key.uVKey, 0x20);
+        assert_eq!(key.uModifiers, TF_MOD_CONTROL | TF_MOD_SHIFT);
+        assert_eq!(parse_shortcut("ctrl+j").unwrap().uVKey, 0x4A);
+        assert_eq!(parse_shortcut("F12").unwrap().uVKey, 0x7B);
+        assert!(parse_shortcut("Ctrl+J+K").is_none());
+        assert!(parse_shortcut("Ctrl+Nothing").is_none());
+    }
+
+    #[test]
+    fn binding_for_the_state() {
+        let bindings = vec![KeyBinding {
+            key: "Ctrl+M".to_string(),
+            states: vec![CompositionStateName::Composing],
+            actions: vec![BindingAction::Commit],
+        }];
+        assert!(find_binding(
+            &bindings,
+            0x4D,
+            TF_MOD_CONTROL,
+            &CompositionState::Composing
+        )
+        .is_some());
+        assert!(find_binding(&bindings, 0x4D, TF_MOD_CONTROL, &CompositionState::None).is_none());
+        assert!(find_binding(&bindings, 0x4D, 0, &CompositionState::Composing).is_none());
+    }
+
+    #[test]
+    fn actions_of_a_binding() {
+        let composing = composition(CompositionState::Composing, "かな");
+        assert_eq!(
+            binding_actions(&[BindingAction::Convert], &composing),
+            Some((
+                vec![ClientAction::SetSelection(SetSelectionType::Down)],
+                CompositionState::Previewing
+            ))
+        );
+        // the composition is committed before the mode changes
+        assert_eq!(
+            binding_actions(
+                &[BindingAction::SetMode(shared::InputModeName::Latin)],
+                &composing
+            ),
+            Some((
+                vec![
+                    ClientAction::EndComposition,
+                    ClientAction::SetIMEMode(InputMode::Latin)
+                ],
+                CompositionState::None
+            ))
+        );
+        assert_eq!(
+            binding_actions(
+                &[BindingAction::Backspace],
+                &composition(CompositionState::Composing, "か")
+            ),
+            Some((
+                vec![ClientAction::RemoveText, ClientAction::EndComposition],
+                CompositionState::None
+            ))
+        );
+    }
+
+    #[test]
+    fn commit_prefix() {
+        let mut previewing = composition(CompositionState::Previewing, "漢字");
+        let actions = |composition: &Composition| {
+            binding_actions(&[BindingAction::CommitPrefix], composition)
+        };
+        assert_eq!(
+            actions(&previewing),
+            Some((vec![ClientAction::EndComposition], CompositionState::None))
+        );
+
+        previewing.suffix = "へんかん".to_string();
+        assert_eq!(
+            actions(&previewing),
+            Some((
+                vec![ClientAction::ShrinkText("".to_string())],
+                CompositionState::Composing
+            ))
+        );
+
+        previewing.clauses.push(Clause::default());
+        assert_eq!(
+            actions(&previewing),
+            Some((
+                vec![ClientAction::CommitPrefix],
+                CompositionState::Previewing
+            ))
+        );
+    }
+
+    #[test]
+    fn nothing_to_convert() {
+        let none = composition(CompositionState::None, "");
+        assert_eq!(binding_actions(&[BindingAction::Convert], &none), None);
+        assert_eq!(
+            binding_actions(&[BindingAction::Insert("→".to_string())], &none),
+            Some((
+                vec![ClientAction::InsertText("→".to_string())],
+                CompositionState::None
+            ))
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    cell::RefCell,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+// whether the latency overlay is enabled in the config
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // timing of the keystroke which is being processed on this thread
+    static CURRENT: RefCell<Option<KeyMetrics>> = const { RefCell::new(None) };
+}
+
+// per-keystroke timing breakdown, shown in the candidate window in debug mode
+#[derive(Debug, Default, Clone, Copy)]
+pub struct KeyMetrics {
+    // from the key event to the start of the ipc worker
+    pub queue: Duration,
+    // requests to the conversion server
+    pub conversion: Duration,
+    // requests to the candidate window
+    pub window: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    Conversion,
+    Window,
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+// start measuring a keystroke on this thread
+pub fn start(queue: Duration) {
+    CURRENT.with(|current| {
+        *current.borrow_mut() = Some(KeyMetrics {
+            queue,
+            ..Default::default()
+        })
+    });
+}
+
+// add the duration to the stage, does nothing if no keystroke is being measured
+pub fn record(stage: Stage, duration: Duration) {
+    CURRENT.with(|current| {
+        if let Some(metrics) = current.borrow_mut().as_mut() {
+            match stage {
+                Stage::Conversion => metrics.conversion += duration,
+                Stage::Window => metrics.window += duration,
+            }
+        }
+    });
+}
+
+pub fn measure<T>(stage: Stage, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    record(stage, start.elapsed());
+    result
+}
+
+// stop measuring and return the result
+pub fn finish() -> Option<KeyMetrics> {
+    CURRENT.with(|current| current.borrow_mut().take())
+}
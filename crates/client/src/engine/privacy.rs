@@ -0,0 +1,27 @@
+use anyhow::Result;
+
+use crate::tsf::factory::TextServiceFactory;
+
+use super::state::IMEState;
+
+impl TextServiceFactory {
+    // turn the privacy mode on or off, it stays until toggled again
+    // the conversion itself keeps working, but nothing typed is learned or kept
+    pub fn toggle_privacy_mode(&self) -> Result<()> {
+        let privacy = {
+            let mut state = IMEState::get()?;
+            state.privacy = !state.privacy;
+            state.privacy
+        };
+        tracing::info!("Privacy mode: {}", privacy);
+
+        // the text committed before may be sensitive as well
+        if privacy {
+            self.forget_last_commit()?;
+            self.borrow()?.borrow_mut_composition()?.last_commit = None;
+        }
+
+        // the language bar shows that the mode is on
+        self.update_lang_bar()
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write as _},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use shared::{
+    proto::ComposingText,
+    recording::{write_event, ConversionRequest, RecordedText, SessionEvent},
+};
+
+use super::{composition::CompositionState, input_mode::InputMode, state::IMEState};
+
+// whether session recording is enabled in the config
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// the file of this process, opened with the first event
+// keys are recorded on the TSF thread and requests on the ipc worker, so it is shared
+static WRITER: Mutex<Option<BufWriter<File>>> = Mutex::new(None);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn record(event: SessionEvent) {
+    // the text typed in the privacy mode must not be written anywhere
+    if IMEState::get().is_ok_and(|state| state.privacy) {
+        return;
+    }
+
+    let mut writer = WRITER.lock().unwrap_or_else(|e| e.into_inner());
+    if writer.is_none() {
+        let log_dir = shared::get_log_dir();
+        let _ = std::fs::create_dir_all(&log_dir);
+        let timestamp = chrono::Local::now().format("%Y-%m-%d-%H.%M.%S");
+        let path = log_dir.join(format!(
+            "session-{}-{}.jsonl",
+            timestamp,
+            std::process::id()
+        ));
+        match File::create(&path) {
+            Ok(file) => *writer = Some(BufWriter::new(file)),
+            Err(e) => {
+                tracing::warn!("Failed to create the session file: {:?}", e);
+                ENABLED.store(false, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    if let Some(writer) = writer.as_mut() {
+        // flushed every time, since the host application may be killed at any moment
+        let result = write_event(writer, &event).and_then(|_| writer.flush());
+        if let Err(e) = result {
+            tracing::warn!("Failed to record the session: {:?}", e);
+        }
+    }
+}
+
+pub fn record_key(wparam: usize, mode: &InputMode, state: &CompositionState) {
+    if ENABLED.load(Ordering::Relaxed) {
+        record(SessionEvent::Key {
+            wparam,
+            mode: format!("{:?}", mode),
+            state: format!("{:?}", state),
+        });
+    }
+}
+
+pub fn record_request(request: ConversionRequest, response: Option<&ComposingText>) {
+    if ENABLED.load(Ordering::Relaxed) {
+        record(SessionEvent::Request {
+            request,
+            response: response.map(RecordedText::from),
+        });
+    }
+}
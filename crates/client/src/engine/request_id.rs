@@ -0,0 +1,41 @@
+use std::{
+    cell::RefCell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    // id of the keystroke which is being processed on this thread
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// ids follow a keystroke from the key event sink to the server and back to the edit session
+// the process id is included so that ids from different applications don't collide
+pub fn next() -> String {
+    format!(
+        "{}-{}",
+        std::process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+pub fn current() -> Option<String> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+// set the current id until the guard is dropped
+pub fn enter(request_id: &str) -> RequestIdGuard {
+    let previous = CURRENT.with(|current| current.replace(Some(request_id.to_string())));
+    RequestIdGuard { previous }
+}
+
+pub struct RequestIdGuard {
+    previous: Option<String>,
+}
+
+impl Drop for RequestIdGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| *current.borrow_mut() = self.previous.take());
+    }
+}
@@ -0,0 +1,45 @@
+// number of consecutive failed conversion requests before the user is notified
+const FAILURE_THRESHOLD: u32 = 3;
+
+// message shown in the language bar while the conversion server is unavailable
+pub const UNAVAILABLE_MESSAGE: &str = "status.unavailable";
+
+// tracks whether the conversion server is reachable
+// a single failure can happen while the server is restarting, so the user is notified only
+// after several failures in a row, and only once until the server comes back
+#[derive(Debug, Default, Clone)]
+pub struct ServerStatus {
+    consecutive_failures: u32,
+    unavailable: bool,
+}
+
+impl ServerStatus {
+    // returns true when the server has just been marked as unavailable
+    pub fn record_failure(&mut self) -> bool {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+
+        if self.consecutive_failures >= FAILURE_THRESHOLD && !self.unavailable {
+            self.unavailable = true;
+            return true;
+        }
+
+        false
+    }
+
+    // the keepalive found the server down, so don't wait for requests to fail
+    // returns true when the server has just been marked as unavailable
+    pub fn mark_unavailable(&mut self) -> bool {
+        self.consecutive_failures = self.consecutive_failures.max(FAILURE_THRESHOLD);
+        !std::mem::replace(&mut self.unavailable, true)
+    }
+
+    // returns true when the server has just come back
+    pub fn record_success(&mut self) -> bool {
+        self.consecutive_failures = 0;
+        std::mem::take(&mut self.unavailable)
+    }
+
+    pub fn is_unavailable(&self) -> bool {
+        self.unavailable
+    }
+}
@@ -1,11 +1,14 @@
 use std::{
+    cell::Cell,
     collections::HashMap,
+    ops::{Deref, DerefMut},
     sync::{LazyLock, Mutex, MutexGuard},
 };
 
+use shared::AppConfig;
 use windows::{core::GUID, Win32::UI::TextServices::ITfContext};
 
-use super::{input_mode::InputMode, ipc_service::IPCService};
+use super::{input_mode::InputMode, ipc_service::IPCService, server_status::ServerStatus};
 
 #[derive(Debug)]
 pub struct IMEState {
@@ -13,6 +16,20 @@ pub struct IMEState {
     pub input_mode: InputMode,
     pub cookies: HashMap<GUID, u32>,
     pub context: Option<ITfContext>,
+    pub server_status: ServerStatus,
+    // learning, the last commit, the context and the session recording are suspended while on
+    pub privacy: bool,
+    // the Caps Lock typed by the IME itself is on the way, see `caps_lock::toggle`
+    pub caps_lock_sent: bool,
+    // the kaomoji palette is in the candidate window, Escape closes it
+    pub palette: bool,
+    // the mode before the focus came to a password field, None outside of them
+    // see `update_password_field`
+    pub password_field: Option<InputMode>,
+    // the application is in `ExclusionConfig`, the keys go through as if the IME was off
+    pub excluded: bool,
+    // reloaded on every activation, so that changes in the settings app are picked up
+    pub config: AppConfig,
 }
 
 pub static IME_STATE: LazyLock<Mutex<IMEState>> = LazyLock::new(|| {
@@ -22,16 +39,60 @@ pub static IME_STATE: LazyLock<Mutex<IMEState>> = LazyLock::new(|| {
         input_mode: InputMode::default(),
         cookies: HashMap::new(),
         context: None,
+        server_status: ServerStatus::default(),
+        privacy: false,
+        caps_lock_sent: false,
+        palette: false,
+        password_field: None,
+        excluded: false,
+        config: AppConfig::default(),
     })
 });
 unsafe impl Sync for IMEState {}
 unsafe impl Send for IMEState {}
 
+thread_local! {
+    // whether this thread holds the lock, locking it again would never return
+    static LOCKED: Cell<bool> = const { Cell::new(false) };
+}
+
+// the lock of `IME_STATE`, drop it before calling into TSF or the ipc
+// a callback of the application may come back to the IME on the same thread
+pub struct IMEStateGuard(MutexGuard<'static, IMEState>);
+
+impl Deref for IMEStateGuard {
+    type Target = IMEState;
+
+    fn deref(&self) -> &IMEState {
+        &self.0
+    }
+}
+
+impl DerefMut for IMEStateGuard {
+    fn deref_mut(&mut self) -> &mut IMEState {
+        &mut self.0
+    }
+}
+
+impl Drop for IMEStateGuard {
+    fn drop(&mut self) {
+        LOCKED.with(|locked| locked.set(false));
+    }
+}
+
 impl IMEState {
-    pub fn get() -> anyhow::Result<MutexGuard<'static, IMEState>> {
-        match IME_STATE.try_lock() {
-            Ok(guard) => Ok(guard),
-            Err(e) => anyhow::bail!("Failed to lock state: {:?}", e),
+    // the state is shared with the ipc worker, so wait for the lock instead of failing
+    // a panic while holding the lock must not break the other threads, so ignore poisoning
+    // locking it again on the same thread fails instead of waiting forever
+    pub fn get() -> anyhow::Result<IMEStateGuard> {
+        let locked = LOCKED.with(|locked| locked.get());
+        debug_assert!(!locked, "IMEState is locked twice on the same thread");
+        if locked {
+            anyhow::bail!("IMEState is already locked on this thread");
         }
+
+        let guard = IME_STATE.lock().unwrap_or_else(|e| e.into_inner());
+        LOCKED.with(|locked| locked.set(true));
+        Ok(IMEStateGuard(guard))
     }
 }
@@ -0,0 +1,169 @@
+use std::sync::Mutex;
+
+use anyhow::Result;
+use windows::Win32::UI::Input::KeyboardAndMouse::{VIRTUAL_KEY, VK_SHIFT};
+
+use super::state::IMEState;
+use crate::extension::VKeyExt as _;
+
+// Shift and Ctrl pressed and released alone are latched for the next key, see
+// `KeyConfig.sticky_modifiers`
+// pressing a latched one again releases it, and chords work as they always do
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Modifier {
+    Shift,
+    Control,
+}
+
+fn modifier(key: usize) -> Option<Modifier> {
+    match key {
+        0x10 | 0xA0 | 0xA1 => Some(Modifier::Shift), // VK_SHIFT, VK_LSHIFT, VK_RSHIFT
+        0x11 | 0xA2 | 0xA3 => Some(Modifier::Control), // VK_CONTROL, VK_LCONTROL, VK_RCONTROL
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+struct Latch {
+    // the modifier held with no other key since it was pressed, it is latched on release
+    pressed: Option<Modifier>,
+    latched: Vec<Modifier>,
+}
+
+impl Latch {
+    const fn new() -> Self {
+        Latch {
+            pressed: None,
+            latched: vec![],
+        }
+    }
+
+    fn key_down(&mut self, key: usize) {
+        self.pressed = match (modifier(key), self.pressed) {
+            // the other modifier makes a chord, e.g. Ctrl+Shift
+            (Some(modifier), Some(pressed)) if modifier != pressed => None,
+            (modifier, _) => modifier,
+        };
+    }
+
+    fn key_up(&mut self, key: usize) {
+        let Some(modifier) = modifier(key) else {
+            return;
+        };
+        if self.pressed.take() != Some(modifier) {
+            return;
+        }
+        match self.latched.iter().position(|latched| *latched == modifier) {
+            Some(index) => {
+                self.latched.remove(index);
+            }
+            None => self.latched.push(modifier),
+        }
+    }
+
+    // the latched modifiers have been used by a key
+    fn consume(&mut self, key: usize) {
+        if modifier(key).is_none() {
+            self.latched.clear();
+        }
+    }
+
+    fn is_latched(&self, modifier: Modifier) -> bool {
+        self.latched.contains(&modifier)
+    }
+}
+
+// kept apart from IMEState, so that `is_held` can be asked while the state is locked
+static LATCH: Mutex<Latch> = Mutex::new(Latch::new());
+
+fn latch() -> std::sync::MutexGuard<'static, Latch> {
+    LATCH.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// called for every key pressed, before the key is handled
+pub fn key_down(key: usize) -> Result<()> {
+    if !IMEState::get()?.config.keys.sticky_modifiers {
+        *latch() = Latch::new();
+        return Ok(());
+    }
+    latch().key_down(key);
+    Ok(())
+}
+
+pub fn key_up(key: usize) {
+    latch().key_up(key);
+}
+
+// called after a key has been handled, the modifiers only last for one key
+pub fn consume(key: usize) {
+    latch().consume(key);
+}
+
+// held down, or latched when the key is Shift or Ctrl
+pub fn is_held(key: VIRTUAL_KEY) -> bool {
+    key.is_pressed()
+        || modifier(key.0 as usize).is_some_and(|modifier| latch().is_latched(modifier))
+}
+
+// the keyboard state for ToUnicode, so that a latched Shift types the uppercase letters and
+// the symbols
+pub fn apply(key_state: &mut [u8; 256]) {
+    if latch().is_latched(Modifier::Shift) {
+        key_state[VK_SHIFT.0 as usize] |= 0x80;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHIFT: usize = 0x10;
+    const CONTROL: usize = 0x11;
+    const A: usize = 0x41;
+
+    fn tap(latch: &mut Latch, key: usize) {
+        latch.key_down(key);
+        latch.key_up(key);
+        latch.consume(key);
+    }
+
+    #[test]
+    fn latched_for_the_next_key() {
+        let mut latch = Latch::new();
+        tap(&mut latch, SHIFT);
+        assert!(latch.is_latched(Modifier::Shift));
+        tap(&mut latch, CONTROL);
+        assert!(latch.is_latched(Modifier::Control));
+
+        tap(&mut latch, A);
+        assert!(!latch.is_latched(Modifier::Shift));
+        assert!(!latch.is_latched(Modifier::Control));
+    }
+
+    #[test]
+    fn released_by_a_second_press() {
+        let mut latch = Latch::new();
+        tap(&mut latch, SHIFT);
+        tap(&mut latch, 0xA1);
+        assert!(!latch.is_latched(Modifier::Shift));
+    }
+
+    #[test]
+    fn chords_are_not_latched() {
+        let mut latch = Latch::new();
+        latch.key_down(SHIFT);
+        latch.key_down(A);
+        latch.key_up(A);
+        latch.consume(A);
+        latch.key_up(SHIFT);
+        assert!(!latch.is_latched(Modifier::Shift));
+
+        latch.key_down(CONTROL);
+        latch.key_down(SHIFT);
+        latch.key_up(SHIFT);
+        latch.key_up(CONTROL);
+        assert!(!latch.is_latched(Modifier::Shift));
+        assert!(!latch.is_latched(Modifier::Control));
+    }
+}
@@ -132,14 +132,45 @@ pub fn to_katakana(s: &str) -> String {
     result
 }
 
+// punctuation which has its own half-width katakana form
+// to_halfwidth would turn these into ASCII
+static HALF_KANA_PUNCTUATION: LazyLock<HashMap<char, char>> = LazyLock::new(|| {
+    HashMap::from([
+        ('ー', 'ｰ'),
+        ('。', '｡'),
+        ('、', '､'),
+        ('「', '｢'),
+        ('」', '｣'),
+        ('・', '･'),
+        ('゛', 'ﾞ'),
+        ('゜', 'ﾟ'),
+    ])
+});
+
+// katakana to the half-width form, so that katakana in the text is converted as well
+static KATAKANA_HALF: LazyLock<HashMap<&'static str, &'static str>> = LazyLock::new(|| {
+    KANA_MAP
+        .values()
+        .map(|&(katakana, half)| (katakana, half))
+        .collect()
+});
+
 pub fn to_half_katakana(s: &str) -> String {
     let mut result = String::new();
     let mut iter = s.chars().peekable();
 
     while let Some(c) = iter.next() {
+        if let Some(&punctuation) = HALF_KANA_PUNCTUATION.get(&c) {
+            result.push(punctuation);
+            continue;
+        }
+
         let c = to_halfwidth(&c.to_string()).chars().next().unwrap();
 
-        if let Some(&(_, hankaku_katakana)) = KANA_MAP.get(&c.to_string().as_str()) {
+        let key = c.to_string();
+        if let Some(&(_, hankaku_katakana)) = KANA_MAP.get(key.as_str()) {
+            result.push_str(hankaku_katakana);
+        } else if let Some(&hankaku_katakana) = KATAKANA_HALF.get(key.as_str()) {
             result.push_str(hankaku_katakana);
         } else {
             result.push(c);
@@ -148,3 +179,213 @@ pub fn to_half_katakana(s: &str) -> String {
 
     result
 }
+
+// the vowel of the kana, None for the ones without a vowel like ん and っ
+fn vowel(c: char) -> Option<char> {
+    const ROWS: [(char, &str); 5] = [
+        ('あ', "あぁかがさざただなはばぱまやゃらわゎ"),
+        ('い', "いぃきぎしじちぢにひびぴみり"),
+        ('う', "うぅくぐすずつづぬふぶぷむゆゅるゔ"),
+        ('え', "えぇけげせぜてでねへべぺめれ"),
+        ('お', "おぉこごそぞとどのほぼぽもよょろを"),
+    ];
+    ROWS.iter()
+        .find(|(_, row)| row.contains(c))
+        .map(|(vowel, _)| *vowel)
+}
+
+// katakana with the vowels which lengthen the one before written as ー
+// おう and えい are lengthened as well, so こうひい becomes コーヒー
+pub fn to_long_vowel_katakana(s: &str) -> String {
+    let mut result = String::new();
+    let mut last = None;
+
+    for c in s.chars() {
+        let long = match (last, c) {
+            (Some(vowel), c) if vowel == c => true,
+            (Some('お'), 'う') | (Some('え'), 'い') => true,
+            _ => false,
+        };
+        if long {
+            result.push('ー');
+            continue;
+        }
+        last = vowel(c);
+        result.push_str(&to_katakana(&c.to_string()));
+    }
+
+    result
+}
+
+// kana, kanji and the full-width forms, e.g. to tell whether the text before the caret is Japanese
+pub fn is_japanese(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3000}'..='\u{30FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{FF01}'..='\u{FF9F}'
+    )
+}
+
+// which characters of the new reading are in a run typed with Shift, see `KeyConfig.shift_katakana`
+// the kana the typed keys make takes the flag of the first key it replaces, so K then a is カ
+pub fn katakana_runs(old: &str, new: &str, runs: &[bool], shifted: bool) -> Vec<bool> {
+    let len = new.chars().count();
+    let common = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    // the first clause was committed and the rest of the reading is left
+    if common < len && old.ends_with(new) {
+        return runs[runs.len().saturating_sub(len)..].to_vec();
+    }
+
+    let flag = runs.get(common).copied().unwrap_or(false) || shifted;
+    let mut runs = runs[..common.min(runs.len())].to_vec();
+    runs.resize(len, flag);
+    runs
+}
+
+// the reading with the runs typed with Shift in katakana
+pub fn with_katakana_runs(hiragana: &str, runs: &[bool]) -> String {
+    hiragana
+        .chars()
+        .zip(runs.iter().chain(std::iter::repeat(&false)))
+        .map(|(c, katakana)| match katakana {
+            true => to_katakana(&c.to_string()),
+            false => c.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // hiragana which are a single character, the ones with a combining mark are two
+    fn hiragana() -> Vec<char> {
+        let mut chars: Vec<char> = KANA_MAP
+            .keys()
+            .filter(|k| k.chars().count() == 1)
+            .filter_map(|k| k.chars().next())
+            .collect();
+        chars.sort();
+        chars
+    }
+
+    // hiragana mixed with the prolonged sound mark and ASCII, like a reading being typed
+    fn reading() -> impl Strategy<Value = String> {
+        let kana = prop::sample::select(hiragana());
+        let other = prop::sample::select(vec!['ー', 'a', 'z', '1', '!', ' ']);
+        prop::collection::vec(prop_oneof![4 => kana, 1 => other], 0..32)
+            .prop_map(|chars| chars.into_iter().collect())
+    }
+
+    fn is_half_katakana(c: char) -> bool {
+        ('\u{FF61}'..='\u{FF9F}').contains(&c)
+    }
+
+    #[test]
+    fn katakana() {
+        assert_eq!(to_katakana("かたかな"), "カタカナ");
+        assert_eq!(to_katakana("ゔぁいおりん"), "ヴァイオリン");
+        assert_eq!(to_katakana("こーひー"), "コーヒー");
+        assert_eq!(to_katakana("abcと123"), "abcト123");
+    }
+
+    #[test]
+    fn long_vowels() {
+        assert_eq!(to_long_vowel_katakana("こうひい"), "コーヒー");
+        assert_eq!(to_long_vowel_katakana("けえき"), "ケーキ");
+        assert_eq!(to_long_vowel_katakana("ていぶる"), "テーブル");
+        assert_eq!(to_long_vowel_katakana("きょう"), "キョー");
+        // a vowel after ん or っ is not lengthened
+        assert_eq!(to_long_vowel_katakana("ほんや"), "ホンヤ");
+        assert_eq!(to_long_vowel_katakana("あおい"), "アオイ");
+    }
+
+    #[test]
+    fn japanese() {
+        assert!("あア亜、　ｱＡ".chars().all(is_japanese));
+        assert!(!"a1 ;".chars().any(is_japanese));
+    }
+
+    #[test]
+    fn shifted_runs() {
+        // K, a, t, a typed with Shift held on K only
+        let runs = katakana_runs("", "k", &[], true);
+        let runs = katakana_runs("k", "か", &runs, false);
+        let runs = katakana_runs("か", "かt", &runs, false);
+        let runs = katakana_runs("かt", "かた", &runs, false);
+        assert_eq!(with_katakana_runs("かた", &runs), "カた");
+
+        // backspace and the rest of the reading after a clause was committed
+        assert_eq!(katakana_runs("かた", "か", &[true, false], false), [true]);
+        assert_eq!(
+            katakana_runs("かたな", "たな", &[false, true, true], false),
+            [true, true]
+        );
+    }
+
+    #[test]
+    fn voiced_half_katakana() {
+        assert_eq!(to_half_katakana("が"), "ｶﾞ");
+        assert_eq!(to_half_katakana("ガ"), "ｶﾞ");
+        assert_eq!(to_half_katakana("ぱぴぷぺぽ"), "ﾊﾟﾋﾟﾌﾟﾍﾟﾎﾟ");
+        assert_eq!(to_half_katakana("ゔ"), "ｳﾞ");
+        assert_eq!(to_half_katakana("ヴ"), "ｳﾞ");
+    }
+
+    #[test]
+    fn prolonged_sound_mark() {
+        assert_eq!(to_half_katakana("ー"), "ｰ");
+        assert_eq!(to_half_katakana("こーひー"), "ｺｰﾋｰ");
+        assert_eq!(to_half_katakana("パーティー"), "ﾊﾟｰﾃｨｰ");
+    }
+
+    #[test]
+    fn half_katakana_punctuation() {
+        assert_eq!(to_half_katakana("「あ」、い。う・え"), "｢ｱ｣､ｲ｡ｳ･ｴ");
+        // the other full-width punctuation becomes ASCII
+        assert_eq!(to_half_katakana("え！"), "ｴ!");
+    }
+
+    #[test]
+    fn mixed_ascii() {
+        assert_eq!(to_half_katakana("abcあ123"), "abcｱ123");
+        assert_eq!(to_half_katakana("tいm e"), "tｲm e");
+    }
+
+    proptest! {
+        #[test]
+        fn katakana_keeps_length(s in reading()) {
+            prop_assert_eq!(to_katakana(&s).chars().count(), s.chars().count());
+        }
+
+        #[test]
+        fn katakana_has_no_hiragana(s in reading()) {
+            let hiragana = hiragana();
+            prop_assert!(to_katakana(&s).chars().all(|c| !hiragana.contains(&c)));
+        }
+
+        #[test]
+        fn half_katakana_of_katakana(s in reading()) {
+            prop_assert_eq!(to_half_katakana(&to_katakana(&s)), to_half_katakana(&s));
+        }
+
+        #[test]
+        fn half_katakana_of_kana(s in prop::collection::vec(prop::sample::select(hiragana()), 0..32)) {
+            let s: String = s.into_iter().collect();
+            prop_assert!(to_half_katakana(&s).chars().all(is_half_katakana));
+        }
+
+        #[test]
+        fn half_katakana_keeps_ascii(s in "[a-zA-Z0-9 ]*") {
+            prop_assert_eq!(to_half_katakana(&s), s);
+        }
+    }
+}
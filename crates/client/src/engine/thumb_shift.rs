@@ -0,0 +1,363 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use shared::ThumbKey;
+use windows::Win32::{
+    Foundation::WPARAM,
+    UI::{Input::KeyboardAndMouse::VK_CONTROL, TextServices::ITfContext},
+};
+
+use crate::tsf::factory::TextServiceFactory;
+
+use super::{
+    client_action::ClientAction, composition::CompositionState, state::IMEState, sticky_keys,
+    user_action::UserAction,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    Thumb(Side),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    Type(char),
+    // the character typed last is typed again with the thumb key pressed right after it
+    Replace(char),
+    // the thumb key was released without shifting anything, so it works as the key itself
+    Solo(Side),
+}
+
+// (key, alone, with the left thumb, with the right thumb) of the NICOLA layout
+// the keys are the characters of the keys without shift, so that it works on the US layout as well
+const LAYOUT: &[(char, char, Option<char>, Option<char>)] = &[
+    ('q', '。', Some('ぁ'), None),
+    ('w', 'か', Some('え'), Some('が')),
+    ('e', 'た', Some('り'), Some('だ')),
+    ('r', 'こ', Some('ゃ'), Some('ご')),
+    ('t', 'さ', Some('れ'), Some('ざ')),
+    ('y', 'ら', Some('ぱ'), Some('よ')),
+    ('u', 'ち', Some('ぢ'), Some('に')),
+    ('i', 'く', Some('ぐ'), Some('る')),
+    ('o', 'つ', Some('づ'), Some('ま')),
+    ('p', '、', Some('ぴ'), Some('ぇ')),
+    ('a', 'う', Some('を'), Some('ゔ')),
+    ('s', 'し', Some('あ'), Some('じ')),
+    ('d', 'て', Some('な'), Some('で')),
+    ('f', 'け', Some('ゅ'), Some('げ')),
+    ('g', 'せ', Some('も'), Some('ぜ')),
+    ('h', 'は', Some('ば'), Some('み')),
+    ('j', 'と', Some('ど'), Some('お')),
+    ('k', 'き', Some('ぎ'), Some('の')),
+    ('l', 'い', Some('ぽ'), Some('ょ')),
+    (';', 'ん', None, Some('っ')),
+    ('z', '．', Some('ぅ'), None),
+    ('x', 'ひ', Some('ー'), Some('び')),
+    ('c', 'す', Some('ろ'), Some('ず')),
+    ('v', 'ふ', Some('や'), Some('ぶ')),
+    ('b', 'へ', Some('ぃ'), Some('べ')),
+    ('n', 'め', Some('ぷ'), Some('ぬ')),
+    ('m', 'そ', Some('ぞ'), Some('ゆ')),
+    (',', 'ね', Some('ぺ'), Some('む')),
+    ('.', 'ほ', Some('ぼ'), Some('わ')),
+    ('/', '・', None, Some('ぉ')),
+];
+
+fn is_layout_key(key: char) -> bool {
+    LAYOUT.iter().any(|(k, ..)| *k == key)
+}
+
+// the kana of the key, None if there is nothing on that shift
+fn kana(key: char, shift: Option<Side>) -> Option<char> {
+    let (_, alone, left, right) = LAYOUT.iter().find(|(k, ..)| *k == key)?;
+    match shift {
+        None => Some(*alone),
+        Some(Side::Left) => *left,
+        Some(Side::Right) => *right,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Thumb {
+    side: Side,
+    // a character has been shifted with it
+    used: bool,
+}
+
+// pairs the character keys with the thumb keys pressed at the same time
+// a character is typed as soon as it is pressed, and typed again if its thumb key comes
+// within the window, so there is no timer waiting for the thumb key
+#[derive(Debug, Default)]
+pub struct Pairing {
+    // the thumb key held down
+    thumb: Option<Thumb>,
+    // the character typed last without a thumb key, and when
+    last: Option<(char, Instant)>,
+}
+
+impl Pairing {
+    pub fn key_down(&mut self, key: Key, now: Instant, window: Duration) -> Option<Output> {
+        match key {
+            Key::Char(c) => match &mut self.thumb {
+                // the thumb key is held, so it shifts every character until it is released
+                Some(thumb) => {
+                    thumb.used = true;
+                    self.last = None;
+                    kana(c, Some(thumb.side))
+                        .or(kana(c, None))
+                        .map(Output::Type)
+                }
+                None => {
+                    self.last = Some((c, now));
+                    kana(c, None).map(Output::Type)
+                }
+            },
+            // the key repeat of the thumb key
+            Key::Thumb(side) if self.thumb.is_some_and(|thumb| thumb.side == side) => None,
+            Key::Thumb(side) => {
+                let shifted = self
+                    .last
+                    .take()
+                    .filter(|(_, at)| now.duration_since(*at) <= window)
+                    .and_then(|(c, _)| kana(c, Some(side)));
+                self.thumb = Some(Thumb {
+                    side,
+                    used: shifted.is_some(),
+                });
+                shifted.map(Output::Replace)
+            }
+        }
+    }
+
+    pub fn key_up(&mut self, side: Side) -> Option<Output> {
+        match self.thumb {
+            Some(thumb) if thumb.side == side => {
+                self.thumb = None;
+                (!thumb.used).then_some(Output::Solo(side))
+            }
+            _ => None,
+        }
+    }
+
+    // another key came in between, so the last character can't be shifted anymore
+    pub fn interrupt(&mut self) {
+        self.last = None;
+    }
+}
+
+impl TextServiceFactory {
+    // the key as a key of the thumb-shift input, None if it is off or the key isn't part of it
+    pub fn thumb_shift_key(&self, wparam: WPARAM) -> Result<Option<Key>> {
+        let (config, composing) = {
+            let state = IMEState::get()?;
+            (
+                state.config.thumb_shift.clone(),
+                state.input_mode.is_composing(),
+            )
+        };
+        if !config.enable || !composing || sticky_keys::is_held(VK_CONTROL) {
+            return Ok(None);
+        }
+        if self.borrow()?.borrow_composition()?.state == CompositionState::Selecting {
+            return Ok(None);
+        }
+
+        if wparam.0 == config.left.virtual_key() {
+            return Ok(Some(Key::Thumb(Side::Left)));
+        }
+        if wparam.0 == config.right.virtual_key() {
+            return Ok(Some(Key::Thumb(Side::Right)));
+        }
+        match UserAction::try_from(wparam.0)? {
+            UserAction::Input(c) if is_layout_key(c) => Ok(Some(Key::Char(c))),
+            _ => Ok(None),
+        }
+    }
+
+    // the thumb-shift counterpart of `process_key`, None if the key only waits for another one
+    pub fn pair_thumb_shift(
+        &self,
+        wparam: WPARAM,
+        key: Key,
+    ) -> Result<Option<(Vec<ClientAction>, CompositionState)>> {
+        let window = Duration::from_millis(IMEState::get()?.config.thumb_shift.window_ms);
+        let output = self
+            .borrow_mut()?
+            .thumb_shift
+            .key_down(key, Instant::now(), window);
+
+        self.thumb_shift_output(wparam, output)
+    }
+
+    // called on every key up, true if the key was a thumb key of the thumb-shift input
+    pub fn handle_key_up(&self, context: Option<&ITfContext>, wparam: WPARAM) -> Result<bool> {
+        let Some(Key::Thumb(side)) = self.thumb_shift_key(wparam)? else {
+            return Ok(false);
+        };
+        if let Some(context) = context {
+            self.borrow_mut()?.context = Some(context.clone());
+        }
+
+        let output = self.borrow_mut()?.thumb_shift.key_up(side);
+        if output.is_some() {
+            // the key works as itself, which depends on the converted text
+            self.flush_pending_keys()?;
+        }
+        if let Some((actions, transition)) = self.thumb_shift_output(wparam, output)? {
+            let actions = self.with_context(actions);
+            self.enqueue_actions(actions, transition)?;
+        }
+
+        Ok(true)
+    }
+
+    fn thumb_shift_output(
+        &self,
+        wparam: WPARAM,
+        output: Option<Output>,
+    ) -> Result<Option<(Vec<ClientAction>, CompositionState)>> {
+        let Some(output) = output else {
+            return Ok(None);
+        };
+
+        match output {
+            Output::Type(c) => self.process_action(wparam, UserAction::Input(c)),
+            Output::Replace(c) => {
+                let state = self.borrow()?.borrow_composition()?.state.clone();
+                if state != CompositionState::Composing {
+                    return self.process_action(wparam, UserAction::Input(c));
+                }
+                Ok(Some((
+                    vec![
+                        ClientAction::RemoveText,
+                        ClientAction::AppendText(c.to_string()),
+                    ],
+                    CompositionState::Composing,
+                )))
+            }
+            Output::Solo(side) => {
+                tracing::debug!("Thumb key pressed alone: {:?}", side);
+                match self.process_action(wparam, UserAction::try_from(wparam.0)?)? {
+                    Some(processed) => Ok(Some(processed)),
+                    // the key was kept from the application, so type what it would have typed
+                    None if wparam.0 == ThumbKey::Space.virtual_key() => Ok(Some((
+                        vec![ClientAction::InsertText(" ".to_string())],
+                        CompositionState::None,
+                    ))),
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(100);
+
+    fn ms(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(kana('k', None), Some('き'));
+        assert_eq!(kana('k', Some(Side::Left)), Some('ぎ'));
+        assert_eq!(kana('k', Some(Side::Right)), Some('の'));
+        assert_eq!(kana(';', Some(Side::Left)), None);
+        assert!(!is_layout_key('1'));
+    }
+
+    #[test]
+    fn character_alone() {
+        let start = Instant::now();
+        let mut pairing = Pairing::default();
+        assert_eq!(
+            pairing.key_down(Key::Char('w'), start, WINDOW),
+            Some(Output::Type('か'))
+        );
+        // the thumb key came too late, so it is pressed alone
+        assert_eq!(
+            pairing.key_down(Key::Thumb(Side::Right), ms(start, 150), WINDOW),
+            None
+        );
+        assert_eq!(pairing.key_up(Side::Right), Some(Output::Solo(Side::Right)));
+    }
+
+    #[test]
+    fn thumb_first() {
+        let start = Instant::now();
+        let mut pairing = Pairing::default();
+        assert_eq!(
+            pairing.key_down(Key::Thumb(Side::Right), start, WINDOW),
+            None
+        );
+        assert_eq!(
+            pairing.key_down(Key::Char('w'), ms(start, 30), WINDOW),
+            Some(Output::Type('が'))
+        );
+        // the shift continues while the thumb key is held
+        assert_eq!(
+            pairing.key_down(Key::Char('k'), ms(start, 300), WINDOW),
+            Some(Output::Type('の'))
+        );
+        assert_eq!(pairing.key_up(Side::Right), None);
+        assert_eq!(
+            pairing.key_down(Key::Char('k'), ms(start, 400), WINDOW),
+            Some(Output::Type('き'))
+        );
+    }
+
+    #[test]
+    fn thumb_right_after_the_character() {
+        let start = Instant::now();
+        let mut pairing = Pairing::default();
+        assert_eq!(
+            pairing.key_down(Key::Char('s'), start, WINDOW),
+            Some(Output::Type('し'))
+        );
+        assert_eq!(
+            pairing.key_down(Key::Thumb(Side::Left), ms(start, 50), WINDOW),
+            Some(Output::Replace('あ'))
+        );
+        // the key repeat of the thumb key doesn't shift again
+        assert_eq!(
+            pairing.key_down(Key::Thumb(Side::Left), ms(start, 80), WINDOW),
+            None
+        );
+        assert_eq!(pairing.key_up(Side::Left), None);
+    }
+
+    #[test]
+    fn no_shift_on_that_side() {
+        let start = Instant::now();
+        let mut pairing = Pairing::default();
+        pairing.key_down(Key::Char(';'), start, WINDOW);
+        assert_eq!(
+            pairing.key_down(Key::Thumb(Side::Left), ms(start, 20), WINDOW),
+            None
+        );
+        assert_eq!(pairing.key_up(Side::Left), Some(Output::Solo(Side::Left)));
+    }
+
+    #[test]
+    fn interrupted() {
+        let start = Instant::now();
+        let mut pairing = Pairing::default();
+        pairing.key_down(Key::Char('s'), start, WINDOW);
+        pairing.interrupt();
+        assert_eq!(
+            pairing.key_down(Key::Thumb(Side::Left), ms(start, 20), WINDOW),
+            None
+        );
+    }
+}
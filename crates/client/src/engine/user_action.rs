@@ -1,4 +1,4 @@
-use crate::extension::VKeyExt;
+use super::{client_action::SetTextType, sticky_keys};
 use anyhow::{Context, Result};
 use windows::Win32::UI::Input::KeyboardAndMouse::{GetKeyboardState, ToUnicode, VK_SHIFT};
 
@@ -12,9 +12,20 @@ pub enum UserAction {
     Escape,
     Unknown,
     Navigation(Navigation),
-    Function(Function),
+    // 1 for F1 to 10 for F10, see `KeyConfig.function_keys`
+    Function(u8),
+    // a function key bound to a form of the reading
+    Transform(SetTextType),
     Number(i8),
+    PageUp,
+    PageDown,
     ToggleInputMode,
+    CycleInputMode,
+    // see `KeyConfig.henkan_muhenkan`
+    Henkan,
+    Muhenkan,
+    // Caps Lock or Eisū, see `KeyConfig.caps_lock`
+    CapsLock,
 }
 
 #[derive(Debug)]
@@ -25,15 +36,6 @@ pub enum Navigation {
     Right,
 }
 
-#[derive(Debug)]
-pub enum Function {
-    Six,
-    Seven,
-    Eight,
-    Nine,
-    Ten,
-}
-
 impl TryFrom<usize> for UserAction {
     type Error = anyhow::Error;
     fn try_from(key_code: usize) -> Result<UserAction> {
@@ -44,12 +46,15 @@ impl TryFrom<usize> for UserAction {
             0x20 => UserAction::Space,     // VK_SPACE
             0x1B => UserAction::Escape,    // VK_ESCAPE
 
+            0x21 => UserAction::PageUp,   // VK_PRIOR
+            0x22 => UserAction::PageDown, // VK_NEXT
+
             0x25 => UserAction::Navigation(Navigation::Left), // VK_LEFT
             0x26 => UserAction::Navigation(Navigation::Up),   // VK_UP
             0x27 => UserAction::Navigation(Navigation::Right), // VK_RIGHT
             0x28 => UserAction::Navigation(Navigation::Down), // VK_DOWN
 
-            0x30..=0x39 | 0x60..=0x69 if !VK_SHIFT.is_pressed() => {
+            0x30..=0x39 | 0x60..=0x69 if !sticky_keys::is_held(VK_SHIFT) => {
                 match key_code {
                     0x30 | 0x60 => UserAction::Number(0), // VK_0, VK_NUMPAD0
                     0x31 | 0x61 => UserAction::Number(1), // VK_1, VK_NUMPAD1
@@ -65,13 +70,14 @@ impl TryFrom<usize> for UserAction {
                 }
             }
 
-            0x75 => UserAction::Function(Function::Six), // VK_F6
-            0x76 => UserAction::Function(Function::Seven), // VK_F7
-            0x77 => UserAction::Function(Function::Eight), // VK_F8
-            0x78 => UserAction::Function(Function::Nine), // VK_F9
-            0x79 => UserAction::Function(Function::Ten), // VK_F10
+            0x70..=0x79 => UserAction::Function((key_code - 0x6F) as u8), // VK_F1 - VK_F10
 
             0xF3 | 0xF4 => UserAction::ToggleInputMode, // Zenkaku/Hankaku
+            0x19 => UserAction::ToggleInputMode,        // VK_KANJI, Alt+` on the US layout
+            0xF2 => UserAction::CycleInputMode,         // Katakana/Hiragana
+            0x1C => UserAction::Henkan,                 // VK_CONVERT
+            0x1D => UserAction::Muhenkan,               // VK_NONCONVERT
+            0x14 | 0xF0 => UserAction::CapsLock,        // VK_CAPITAL, VK_DBE_ALPHANUMERIC
 
             _ => {
                 let key_state = {
@@ -79,6 +85,7 @@ impl TryFrom<usize> for UserAction {
                     unsafe {
                         GetKeyboardState(&mut key_state)?;
                     }
+                    sticky_keys::apply(&mut key_state);
                     key_state
                 };
                 let unicode = {
@@ -9,11 +9,12 @@ use anyhow::{Context, Result};
 use windows::{
     core::GUID,
     Win32::{
-        Foundation::{FALSE, HMODULE, MAX_PATH},
+        Foundation::{BOOL, FALSE, HMODULE, MAX_PATH, TRUE},
         System::LibraryLoader::GetModuleFileNameW,
         UI::TextServices::{
-            TF_ATTR_TARGET_CONVERTED, TF_CT_NONE, TF_DA_COLOR, TF_DA_COLOR_0, TF_DISPLAYATTRIBUTE,
-            TF_LS_SOLID,
+            TF_ATTR_CONVERTED, TF_ATTR_INPUT, TF_ATTR_TARGET_CONVERTED, TF_CT_NONE,
+            TF_DA_ATTR_INFO, TF_DA_COLOR, TF_DA_COLOR_0, TF_DA_LINESTYLE, TF_DISPLAYATTRIBUTE,
+            TF_LS_DOT, TF_LS_SOLID,
         },
     },
 };
@@ -29,25 +30,66 @@ pub const GUID_TEXT_SERVICE: GUID = GUID::from_u128(0xffdefe79_2fc2_11ef_b16b_94
 pub const GUID_PROFILE: GUID = GUID::from_u128(0xffdefe7a_2fc2_11ef_b16b_94e70b2c378c);
 
 // DisplayAttribute用のGUID
-pub const GUID_DISPLAY_ATTRIBUTE: GUID = GUID::from_u128(0xffdefe7b_2fc2_11ef_b16b_94e70b2c378c);
+// ffdefe7b-2fc2-11ef-b16b-94e70b2c378c, the clause which is being converted
+pub const GUID_DISPLAY_ATTRIBUTE_TARGET: GUID =
+    GUID::from_u128(0xffdefe7b_2fc2_11ef_b16b_94e70b2c378c);
+// ffdefe7e-2fc2-11ef-b16b-94e70b2c378c, the reading which is not converted yet
+pub const GUID_DISPLAY_ATTRIBUTE_INPUT: GUID =
+    GUID::from_u128(0xffdefe7e_2fc2_11ef_b16b_94e70b2c378c);
+// ffdefe7f-2fc2-11ef-b16b-94e70b2c378c, the converted text which is not the target
+pub const GUID_DISPLAY_ATTRIBUTE_CONVERTED: GUID =
+    GUID::from_u128(0xffdefe7f_2fc2_11ef_b16b_94e70b2c378c);
+
+// preserved keys
+// ffdefe7c-2fc2-11ef-b16b-94e70b2c378c
+pub const GUID_PRESERVEDKEY_CONVERT_SELECTION: GUID =
+    GUID::from_u128(0xffdefe7c_2fc2_11ef_b16b_94e70b2c378c);
+
+pub const GUID_PRESERVEDKEY_FULL_LATIN: GUID =
+    GUID::from_u128(0xffdefe7d_2fc2_11ef_b16b_94e70b2c378c);
+
+// ffdefe81-2fc2-11ef-b16b-94e70b2c378c, the key is set in the config
+pub const GUID_PRESERVEDKEY_OPEN_SETTINGS: GUID =
+    GUID::from_u128(0xffdefe81_2fc2_11ef_b16b_94e70b2c378c);
+
+// ffdefe82-2fc2-11ef-b16b-94e70b2c378c
+pub const GUID_PRESERVEDKEY_PRIVACY: GUID = GUID::from_u128(0xffdefe82_2fc2_11ef_b16b_94e70b2c378c);
+
+// global compartment, 1 while a composition is open
+// ffdefe80-2fc2-11ef-b16b-94e70b2c378c
+pub const GUID_COMPARTMENT_COMPOSING: GUID =
+    GUID::from_u128(0xffdefe80_2fc2_11ef_b16b_94e70b2c378c);
+
+const fn display_attribute(
+    style: TF_DA_LINESTYLE,
+    bold: BOOL,
+    attr: TF_DA_ATTR_INFO,
+) -> TF_DISPLAYATTRIBUTE {
+    TF_DISPLAYATTRIBUTE {
+        crText: TF_DA_COLOR {
+            r#type: TF_CT_NONE,
+            Anonymous: TF_DA_COLOR_0 { nIndex: 0 },
+        },
+        crBk: TF_DA_COLOR {
+            r#type: TF_CT_NONE,
+            Anonymous: TF_DA_COLOR_0 { nIndex: 0 },
+        },
+        lsStyle: style,
+        fBoldLine: bold,
+        crLine: TF_DA_COLOR {
+            r#type: TF_CT_NONE,
+            Anonymous: TF_DA_COLOR_0 { nIndex: 0 },
+        },
+        bAttr: attr,
+    }
+}
 
-pub const DISPLAY_ATTRIBUTE: TF_DISPLAYATTRIBUTE = TF_DISPLAYATTRIBUTE {
-    crText: TF_DA_COLOR {
-        r#type: TF_CT_NONE,
-        Anonymous: TF_DA_COLOR_0 { nIndex: 0 },
-    },
-    crBk: TF_DA_COLOR {
-        r#type: TF_CT_NONE,
-        Anonymous: TF_DA_COLOR_0 { nIndex: 0 },
-    },
-    lsStyle: TF_LS_SOLID,
-    fBoldLine: FALSE,
-    crLine: TF_DA_COLOR {
-        r#type: TF_CT_NONE,
-        Anonymous: TF_DA_COLOR_0 { nIndex: 0 },
-    },
-    bAttr: TF_ATTR_TARGET_CONVERTED,
-};
+pub const DISPLAY_ATTRIBUTE_INPUT: TF_DISPLAYATTRIBUTE =
+    display_attribute(TF_LS_DOT, FALSE, TF_ATTR_INPUT);
+pub const DISPLAY_ATTRIBUTE_CONVERTED: TF_DISPLAYATTRIBUTE =
+    display_attribute(TF_LS_SOLID, FALSE, TF_ATTR_CONVERTED);
+pub const DISPLAY_ATTRIBUTE_TARGET: TF_DISPLAYATTRIBUTE =
+    display_attribute(TF_LS_SOLID, TRUE, TF_ATTR_TARGET_CONVERTED);
 
 // You can use any value for this cookie.
 pub const TEXTSERVICE_LANGBARITEMSINK_COOKIE: u32 = 0;
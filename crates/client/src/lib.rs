@@ -1,7 +1,10 @@
+#[cfg(feature = "bench")]
+pub mod bench;
 mod engine;
 mod extension;
 mod globals;
 mod macros;
+mod panic_guard;
 mod register;
 mod trace;
 mod tracing_chrome;
@@ -33,18 +36,19 @@ pub extern "system" fn DllMain(
     _lpv_reserved: *mut c_void,
 ) -> bool {
     if fdw_reason == DLL_PROCESS_ATTACH {
-        let result: anyhow::Result<()> = (|| {
+        let result: anyhow::Result<()> = panic_guard::catch(|| {
             let mut dll_instance = DllModule::new();
             dll_instance.hinst = Some(hinst);
             DLL_INSTANCE
                 .set(Mutex::new(dll_instance))
                 .map_err(|e| anyhow::anyhow!(format!("{:?}", e)))?;
             Ok(())
-        })();
+        });
 
         // use unwrap only in this function
         // Setup logger synchronously to capture all logs from the start
-        let _ = trace::setup_logger();
+        let _ = panic_guard::catch(trace::setup_logger);
+        panic_guard::install_hook();
 
         tracing::debug!("DllMain");
 
@@ -52,7 +56,7 @@ pub extern "system" fn DllMain(
     } else if fdw_reason == DLL_PROCESS_DETACH {
         tracing::debug!("DLL_PROCESS_DETACH");
 
-        let result: anyhow::Result<()> = (|| {
+        let result: anyhow::Result<()> = panic_guard::catch(|| {
             let mut dll_instance = DllModule::get()?;
             dll_instance.hinst = None;
             // send a signal to the tracing writer thread to exit
@@ -61,7 +65,7 @@ pub extern "system" fn DllMain(
             }
 
             Ok(())
-        })();
+        });
 
         check_err!(result, true, false)
     } else {
@@ -83,7 +87,7 @@ pub unsafe extern "system" fn DllGetClassObject(
     // https://zenn.dev/link/comments/d918e46723da80
     tracing::debug!("DllGetClassObject");
 
-    let result: anyhow::Result<()> = (|| {
+    let result: anyhow::Result<()> = panic_guard::catch(|| {
         let rclsid = unsafe { *rclsid };
         let riid = unsafe { *riid };
         let ppv = unsafe { &mut *ppv };
@@ -106,7 +110,7 @@ pub unsafe extern "system" fn DllGetClassObject(
             _ => return Err(anyhow::anyhow!(E_UNEXPECTED)),
         };
         Ok(())
-    })();
+    });
 
     check_err!(result)
 }
@@ -117,7 +121,7 @@ pub extern "system" fn DllRegisterServer() -> HRESULT {
     // Called when the DLL is registered using regsvr32
     tracing::debug!("DllRegisterServer");
 
-    let result: anyhow::Result<()> = (|| {
+    let result: anyhow::Result<()> = panic_guard::catch(|| {
         let dll_path = DllModule::get_path()?;
 
         ProfileMgr::register(&dll_path)?;
@@ -125,7 +129,7 @@ pub extern "system" fn DllRegisterServer() -> HRESULT {
         CategoryMgr::register()?;
 
         Ok(())
-    })();
+    });
 
     // to show the error, SELFREG_E_CLASS is needed
     check_err!(result, SELFREG_E_CLASS)
@@ -137,13 +141,13 @@ pub extern "system" fn DllUnregisterServer() -> HRESULT {
     // Called when the DLL is unregistered using regsvr32
     tracing::debug!("DllUnregisterServer");
 
-    let result: anyhow::Result<()> = (|| {
+    let result: anyhow::Result<()> = panic_guard::catch(|| {
         ProfileMgr::unregister()?;
         CLSIDMgr::unregister()?;
         CategoryMgr::unregister()?;
 
         Ok(())
-    })();
+    });
 
     check_err!(result, SELFREG_E_CLASS)
 }
@@ -0,0 +1,51 @@
+use std::{
+    cell::Cell,
+    panic::{self, AssertUnwindSafe},
+};
+
+thread_local! {
+    // set when a panic is caught on this thread
+    static DISABLED: Cell<bool> = const { Cell::new(false) };
+}
+
+// log panics before they are caught by `catch`
+pub fn install_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        tracing::error!("Panic: {}", info);
+        previous(info);
+    }));
+}
+
+// run a COM entry point and turn a panic into an error
+// a panic must never unwind into the host application, so every entry point goes through this
+// after a panic, the IME stops handling keys on this thread until it is activated again
+pub fn catch<T>(f: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => {
+            DISABLED.with(|disabled| disabled.set(true));
+
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+
+            anyhow::bail!("IME is disabled on this thread due to a panic: {}", message)
+        }
+    }
+}
+
+pub fn is_disabled() -> bool {
+    DISABLED.with(|disabled| disabled.get())
+}
+
+// disable the IME on this thread after a panic on another thread, e.g. the ipc worker
+pub fn disable() {
+    DISABLED.with(|disabled| disabled.set(true));
+}
+
+pub fn reset() {
+    DISABLED.with(|disabled| disabled.set(false));
+}
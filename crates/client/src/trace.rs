@@ -1,15 +1,25 @@
 use std::fmt::Write as _;
+use std::sync::OnceLock;
 use tracing::field::{Field, Visit};
-use tracing_core::LevelFilter;
-use tracing_subscriber::filter::Targets;
-use tracing_subscriber::{layer::SubscriberExt as _, util::SubscriberInitExt};
+use tracing_core::{Event, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, SubscriberExt as _},
+    reload,
+    util::SubscriberInitExt,
+    EnvFilter, Layer, Registry,
+};
 use windows::{core::PCWSTR, Win32::System::Diagnostics::Debug::OutputDebugStringW};
 
+use shared::{AppConfig, LogConfig};
+
 use crate::extension::StringExt as _;
 use crate::globals::DllModule;
 use crate::tracing_chrome::{ChromeLayerBuilder, EventOrSpan};
 
-const LOG_FOLDER: &str = "G:/Projects/azooKey-Windows/logs";
+// used when the filter in the config is invalid
+const DEFAULT_FILTER: &str = "azookey_windows=info";
+
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
 
 pub struct StringVisitor<'a> {
     string: &'a mut String,
@@ -24,70 +34,89 @@ impl<'a> Visit for StringVisitor<'a> {
     }
 }
 
-pub fn setup_logger() -> anyhow::Result<()> {
-    // Disable tracing in release builds - it causes progressive slowdown
-    // because tracing_chrome rewrites the entire file on every event (O(n²))
-    #[cfg(not(debug_assertions))]
-    {
-        return Ok(());
-    }
-    #[allow(unreachable_code)]
-    let timestamp = chrono::Local::now().format("%Y-%m-%d-%H.%M.%S");
-    let path = format!("{}/{}.json", LOG_FOLDER, timestamp);
-
-    let writer = {
-        if let Ok(file) = std::fs::File::create(&path) {
-            file
-        } else {
-            return Ok(());
-        }
-    };
+fn event_message(event: &Event<'_>) -> String {
+    let mut message = String::new();
+    event.record(&mut StringVisitor {
+        string: &mut message,
+    });
+    message
+}
 
-    let builder = ChromeLayerBuilder::new()
-        .file(writer)
-        .include_locations(true)
-        .include_args(true)
-        .name_fn(Box::new(|event_or_span| match event_or_span {
-            EventOrSpan::Event(event) => {
-                let message = {
-                    let mut message = String::new();
-                    event.record(&mut StringVisitor {
-                        string: &mut message,
-                    });
-                    message
-                };
-
-                let (level, file, line) = {
-                    let metadeta = event.metadata();
-                    let level = metadeta.level().as_str();
-                    let file = metadeta.file().unwrap_or_default();
-                    let line = metadeta.line().unwrap_or_default();
-
-                    (level, file, line)
-                };
-
-                let str = format!("[{}: {}:{}] {}", level, file, line, message);
-                let wide: Vec<u16> = str.as_str().to_wide_16();
-                unsafe { OutputDebugStringW(PCWSTR(wide.as_ptr())) };
-
-                message
-            }
-            EventOrSpan::Span(span) => span.metadata().name().to_string(),
-        }));
+// sends events to the debugger (e.g. DebugView)
+struct DebugOutputLayer;
+
+impl<S: Subscriber> Layer<S> for DebugOutputLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let str = format!(
+            "[{}: {}:{}] {}",
+            metadata.level().as_str(),
+            metadata.file().unwrap_or_default(),
+            metadata.line().unwrap_or_default(),
+            event_message(event)
+        );
+        let wide: Vec<u16> = str.as_str().to_wide_16();
+        unsafe { OutputDebugStringW(PCWSTR(wide.as_ptr())) };
+    }
+}
 
-    let (chrome_layer, sender) = builder.build();
+fn build_filter(config: &LogConfig) -> EnvFilter {
+    EnvFilter::try_new(&config.filter).unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER))
+}
 
-    DllModule::get()?.sender = Some(sender);
+pub fn setup_logger() -> anyhow::Result<()> {
+    let config = AppConfig::try_read().unwrap_or_default().log;
+
+    let (filter, handle) = reload::Layer::new(build_filter(&config));
+    let _ = FILTER_HANDLE.set(handle);
+
+    // the chrome trace rewrites the entire file on every event, so it is opt-in
+    let chrome_layer = if config.file {
+        let timestamp = chrono::Local::now().format("%Y-%m-%d-%H.%M.%S");
+        let log_dir = shared::get_log_dir();
+        let _ = std::fs::create_dir_all(&log_dir);
+        let path = log_dir.join(format!("{}.json", timestamp));
+
+        match std::fs::File::create(&path) {
+            Ok(file) => {
+                let (chrome_layer, sender) = ChromeLayerBuilder::new()
+                    .file(file)
+                    .include_locations(true)
+                    .include_args(true)
+                    .name_fn(Box::new(|event_or_span| match event_or_span {
+                        EventOrSpan::Event(event) => event_message(event),
+                        EventOrSpan::Span(span) => span.metadata().name().to_string(),
+                    }))
+                    .build();
+
+                DllModule::get()?.sender = Some(sender);
+                Some(chrome_layer)
+            }
+            Err(_) => None,
+        }
+    } else {
+        None
+    };
 
-    // ignore traces from other crates
-    let filter = Targets::new()
-        .with_target("azookey_windows", LevelFilter::DEBUG)
-        .with_default(LevelFilter::OFF);
+    // every event is formatted for the debugger, so release builds leave it to the log file
+    let debug_output_layer = cfg!(debug_assertions).then_some(DebugOutputLayer);
 
     tracing_subscriber::registry()
         .with(filter)
+        .with(debug_output_layer)
         .with(chrome_layer)
-        .init();
+        .try_init()?;
+
+    Ok(())
+}
+
+// apply the filter in the config without restarting the host application
+pub fn reload_filter() -> anyhow::Result<()> {
+    let config = AppConfig::try_read()?.log;
+
+    if let Some(handle) = FILTER_HANDLE.get() {
+        handle.reload(build_filter(&config))?;
+    }
 
     Ok(())
 }
@@ -1,8 +1,13 @@
+pub(super) mod compartment_event_sink;
 pub(super) mod display_attribute;
 pub(super) mod edit_session;
 pub(super) mod factory;
+pub(super) mod input_scope;
 pub(super) mod key_event_sink;
 pub(super) mod language_bar;
+pub(super) mod message_window;
+pub(super) mod mouse_sink;
+pub(super) mod preserved_key;
 pub(super) mod surrounded_text;
 pub(super) mod text_input_proccesor;
 pub(super) mod text_layout_sink;
@@ -0,0 +1,57 @@
+use windows::{
+    core::{Interface as _, GUID},
+    Win32::UI::TextServices::{
+        ITfCompartment, ITfCompartmentEventSink_Impl, ITfCompartmentMgr, ITfThreadMgr,
+        GUID_COMPARTMENT_KEYBOARD_OPENCLOSE,
+    },
+};
+
+use anyhow::Result;
+
+use crate::engine::{
+    client_action::ClientAction, composition::CompositionState, input_mode::InputMode,
+    state::IMEState,
+};
+
+use super::factory::TextServiceFactory_Impl;
+
+// Windows opens and closes the keyboard with the Hankaku/Zenkaku key by itself, so the key
+// never comes to the key event sink, only the open/close compartment of the thread changes
+impl ITfCompartmentEventSink_Impl for TextServiceFactory_Impl {
+    #[macros::anyhow]
+    fn OnChange(&self, rguid: *const GUID) -> Result<()> {
+        if unsafe { *rguid } != GUID_COMPARTMENT_KEYBOARD_OPENCLOSE {
+            return Ok(());
+        }
+
+        let thread_mgr = self.borrow()?.thread_mgr()?;
+        let open = unsafe { open_close(&thread_mgr)?.GetValue()? };
+        let open = i32::try_from(&open).unwrap_or_default() != 0;
+        // the value is also set by `update_lang_bar` to follow the mode
+        if open == (IMEState::get()?.input_mode != InputMode::Latin) {
+            return Ok(());
+        }
+        tracing::debug!("Keyboard opened: {}", open);
+
+        let mode = match open {
+            true => InputMode::Kana,
+            false => InputMode::Latin,
+        };
+        self.handle_action(
+            &[ClientAction::EndComposition, ClientAction::SetIMEMode(mode)],
+            CompositionState::None,
+        )?;
+
+        Ok(())
+    }
+}
+
+// the open/close compartment of the thread, not the global one of `publish_status`
+pub fn open_close(thread_mgr: &ITfThreadMgr) -> Result<ITfCompartment> {
+    let compartment = unsafe {
+        thread_mgr
+            .cast::<ITfCompartmentMgr>()?
+            .GetCompartment(&GUID_COMPARTMENT_KEYBOARD_OPENCLOSE)?
+    };
+    Ok(compartment)
+}
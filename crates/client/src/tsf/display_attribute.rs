@@ -12,7 +12,10 @@ use windows::{
 
 use anyhow::Result;
 
-use crate::globals::{DISPLAY_ATTRIBUTE, GUID_DISPLAY_ATTRIBUTE};
+use crate::globals::{
+    DISPLAY_ATTRIBUTE_CONVERTED, DISPLAY_ATTRIBUTE_INPUT, DISPLAY_ATTRIBUTE_TARGET,
+    GUID_DISPLAY_ATTRIBUTE_CONVERTED, GUID_DISPLAY_ATTRIBUTE_INPUT, GUID_DISPLAY_ATTRIBUTE_TARGET,
+};
 
 use super::factory::TextServiceFactory_Impl;
 
@@ -101,10 +104,14 @@ pub struct EnumDisplayAttributeInfo {
 #[allow(clippy::new_without_default)]
 impl EnumDisplayAttributeInfo {
     pub fn new() -> Self {
-        let attributes = vec![DisplayAttributeInfo::new(
-            GUID_DISPLAY_ATTRIBUTE,
-            DISPLAY_ATTRIBUTE,
-        )];
+        let attributes = vec![
+            DisplayAttributeInfo::new(GUID_DISPLAY_ATTRIBUTE_INPUT, DISPLAY_ATTRIBUTE_INPUT),
+            DisplayAttributeInfo::new(
+                GUID_DISPLAY_ATTRIBUTE_CONVERTED,
+                DISPLAY_ATTRIBUTE_CONVERTED,
+            ),
+            DisplayAttributeInfo::new(GUID_DISPLAY_ATTRIBUTE_TARGET, DISPLAY_ATTRIBUTE_TARGET),
+        ];
 
         EnumDisplayAttributeInfo {
             attributes,
@@ -1,12 +1,13 @@
 use macros::anyhow;
 use windows::{
-    core::{implement, AsImpl, VARIANT},
+    core::{implement, AsImpl, GUID, VARIANT},
     Win32::{
         Foundation::RECT,
         UI::TextServices::{
             ITfComposition, ITfCompositionSink, ITfContext, ITfContextComposition, ITfEditSession,
-            ITfEditSession_Impl, ITfInsertAtSelection, ITfRange, GUID_PROP_ATTRIBUTE, TF_AE_NONE,
-            TF_ANCHOR_END, TF_ANCHOR_START, TF_ES_READWRITE, TF_IAS_QUERYONLY, TF_SELECTION,
+            ITfEditSession_Impl, ITfInsertAtSelection, ITfProperty, ITfRange, GUID_PROP_ATTRIBUTE,
+            INSERT_TEXT_AT_SELECTION_FLAGS, TF_AE_NONE, TF_ANCHOR_END, TF_ANCHOR_START,
+            TF_DEFAULT_SELECTION, TF_ES_READWRITE, TF_IAS_QUERYONLY, TF_SELECTION,
             TF_SELECTIONSTYLE, TF_ST_CORRECTION, TF_TF_MOVESTART,
         },
     },
@@ -15,8 +16,13 @@ use windows::{
 use std::{cell::Cell, mem::ManuallyDrop, rc::Rc};
 
 use anyhow::{Context, Result};
+use shared::proto::WindowPosition;
 
-use crate::{engine::state::IMEState, extension::StringExt as _, globals::GUID_DISPLAY_ATTRIBUTE};
+use crate::{
+    engine::state::IMEState,
+    extension::StringExt as _,
+    globals::{GUID_DISPLAY_ATTRIBUTE_CONVERTED, GUID_DISPLAY_ATTRIBUTE_INPUT},
+};
 
 use super::factory::TextServiceFactory;
 
@@ -51,6 +57,60 @@ pub fn edit_session<T>(
     }
 }
 
+// split the range into parts of the given lengths and set the display attribute of each part
+unsafe fn set_attributes(
+    prop: &ITfProperty,
+    cookie: u32,
+    range: &ITfRange,
+    attributes: &[(i32, Option<u32>)],
+) -> Result<()> {
+    let mut start = 0;
+    for (len, atom) in attributes {
+        if let (true, Some(atom)) = (*len > 0, atom) {
+            let part = range.Clone()?;
+            part.Collapse(cookie, TF_ANCHOR_START)?;
+            let mut shifted: i32 = 0;
+            part.ShiftEnd(cookie, start + len, &mut shifted, std::ptr::null())?;
+            part.ShiftStart(cookie, start, &mut shifted, std::ptr::null())?;
+            prop.SetValue(cookie, &part, &VARIANT::from(*atom as i32))?;
+        }
+        start += len;
+    }
+
+    Ok(())
+}
+
+// screen rects of the start of the range (the caret line) and of the whole range
+// the candidate window is placed at the former and must not cover the latter
+unsafe fn measure_range(
+    context: &ITfContext,
+    cookie: u32,
+    range: &ITfRange,
+) -> Result<(RECT, RECT)> {
+    let view = context.GetActiveView()?;
+    let mut clipped = false.into();
+
+    let mut whole = RECT::default();
+    view.GetTextExt(cookie, range, &mut whole, &mut clipped)?;
+
+    let start = range.Clone()?;
+    start.Collapse(cookie, TF_ANCHOR_START)?;
+    let mut caret = RECT::default();
+    view.GetTextExt(cookie, &start, &mut caret, &mut clipped)?;
+
+    Ok((caret, whole))
+}
+
+// the window position in the proto, which doesn't depend on the windows crate
+fn window_position(rect: &RECT) -> WindowPosition {
+    WindowPosition {
+        top: rect.top,
+        left: rect.left,
+        bottom: rect.bottom,
+        right: rect.right,
+    }
+}
+
 impl<'a, T> ITfEditSession_Impl for EditSession_Impl<'a, T> {
     #[anyhow]
     fn DoEditSession(&self, cookie: u32) -> Result<()> {
@@ -65,16 +125,13 @@ impl TextServiceFactory {
     pub fn start_composition(&self) -> Result<()> {
         tracing::debug!("start_composition");
 
-        let text_service = self.borrow_mut()?;
+        let text_service = self.borrow()?;
         let context = text_service.context()?;
         let context_composition = text_service.context::<ITfContextComposition>()?;
         let sink = text_service.this::<ITfCompositionSink>()?;
         let insert = text_service.context::<ITfInsertAtSelection>()?;
 
-        let tip_exists = {
-            let composition = text_service.borrow_composition()?;
-            composition.tip_composition.is_some()
-        };
+        let tip_exists = text_service.tip_composition.try_borrow()?.is_some();
 
         if tip_exists {
             self.end_composition()?;
@@ -96,7 +153,8 @@ impl TextServiceFactory {
         )?;
 
         tracing::debug!("Composition started {composition:?}");
-        text_service.borrow_mut_composition()?.tip_composition = composition;
+        *text_service.tip_composition.try_borrow_mut()? = composition;
+        self.track_mouse();
 
         Ok(())
     }
@@ -104,10 +162,13 @@ impl TextServiceFactory {
     #[tracing::instrument]
     pub fn end_composition(&self) -> Result<()> {
         tracing::debug!("end_composition");
+        // the composition ends either way
+        let _ = self.unadvise_mouse_sink();
         let text_service = self.borrow()?;
 
-        if let Some(composition) = text_service.borrow_composition()?.tip_composition.clone() {
-            edit_session(
+        let tip_composition = text_service.tip_composition.try_borrow()?.clone();
+        if let Some(composition) = tip_composition {
+            let committed = edit_session(
                 text_service.tid,
                 text_service.context()?,
                 Rc::new({
@@ -116,6 +177,8 @@ impl TextServiceFactory {
                     move |cookie| unsafe {
                         // clear display attribute first
                         let range: ITfRange = composition.GetRange()?;
+                        // kept so that the text can be converted again right after the commit
+                        let committed = range.Clone()?;
 
                         // set existing text to the composition
                         let mut text = vec![0; 1024];
@@ -143,50 +206,146 @@ impl TextServiceFactory {
                         context.SetSelection(cookie, &[selection])?;
 
                         composition.EndComposition(cookie)?;
-                        Ok(())
+                        Ok((text_len > 0).then_some(committed))
                     }
                 }),
             )?;
+            *text_service.last_commit_range.try_borrow_mut()? = committed.flatten();
         } else {
             tracing::warn!("Composition is not started");
         }
 
-        text_service.borrow_mut_composition()?.tip_composition = None;
+        *text_service.tip_composition.try_borrow_mut()? = None;
+
+        Ok(())
+    }
+
+    // start a composition over the text committed last
+    // returns false if the text was changed after the commit
+    #[tracing::instrument]
+    pub fn reopen_last_commit(&self) -> Result<bool> {
+        let text_service = self.borrow()?;
+
+        let Some(range) = text_service.last_commit_range.try_borrow_mut()?.take() else {
+            return Ok(false);
+        };
+        let Some(last_commit) = text_service.borrow_composition()?.last_commit.clone() else {
+            return Ok(false);
+        };
+
+        let composition = edit_session(
+            text_service.tid,
+            text_service.context()?,
+            Rc::new({
+                let context_composition = text_service.context::<ITfContextComposition>()?;
+                let sink = text_service.this::<ITfCompositionSink>()?;
+                let expected = last_commit.text.to_wide_16_unpadded();
+
+                move |cookie| unsafe {
+                    let mut text = vec![0; expected.len() + 1];
+                    let mut text_len = 0;
+                    range.GetText(cookie, 0, &mut text, &mut text_len)?;
+                    if text[..text_len as usize] != expected[..] {
+                        return Ok(None);
+                    }
+
+                    let composition =
+                        context_composition.StartComposition(cookie, &range, &sink)?;
+                    Ok(Some(composition))
+                }
+            }),
+        )?
+        .flatten();
+
+        let Some(composition) = composition else {
+            tracing::debug!("The last commit was changed, not reopening it");
+            return Ok(false);
+        };
+        *text_service.tip_composition.try_borrow_mut()? = Some(composition);
+        self.track_mouse();
+
+        Ok(true)
+    }
+
+    pub fn forget_last_commit(&self) -> Result<()> {
+        *self.borrow()?.last_commit_range.try_borrow_mut()? = None;
+        Ok(())
+    }
+
+    // insert the text at the caret without starting a composition
+    #[tracing::instrument]
+    pub fn insert_text(&self, text: &str) -> Result<()> {
+        let text_service = self.borrow()?;
+
+        edit_session(
+            text_service.tid,
+            text_service.context()?,
+            Rc::new({
+                let text = text.to_wide_16_unpadded();
+                let context = text_service.context::<ITfContext>()?;
+                let insert = text_service.context::<ITfInsertAtSelection>()?;
+
+                move |cookie| unsafe {
+                    let range = insert.InsertTextAtSelection(
+                        cookie,
+                        INSERT_TEXT_AT_SELECTION_FLAGS(0),
+                        &text,
+                    )?;
+
+                    range.Collapse(cookie, TF_ANCHOR_END)?;
+                    let selection = TF_SELECTION {
+                        range: ManuallyDrop::new(Some(range)),
+                        style: TF_SELECTIONSTYLE {
+                            ase: TF_AE_NONE,
+                            fInterimChar: false.into(),
+                        },
+                    };
+
+                    context.SetSelection(cookie, &[selection])?;
+
+                    Ok(())
+                }
+            }),
+        )?;
 
         Ok(())
     }
 
+    // `clause` is the display attribute of `text`, `subtext` is always shown as the reading
+    // and the converted `clauses` before them as converted text
     #[tracing::instrument]
-    pub fn set_text(&self, text: &str, subtext: &str) -> Result<()> {
+    pub fn set_text(&self, clauses: &str, text: &str, subtext: &str, clause: &GUID) -> Result<()> {
         let text_service = self.borrow()?;
+        let atom = |guid: &GUID| text_service.display_attribute_atom.get(guid).copied();
 
-        if let Some(composition) = text_service.borrow_composition()?.tip_composition.clone() {
+        let tip_composition = text_service.tip_composition.try_borrow()?.clone();
+        if let Some(composition) = tip_composition {
             edit_session(
                 text_service.tid,
                 text_service.context()?,
                 Rc::new({
+                    let clauses_len = clauses.chars().count() as i32;
                     let text_len = text.chars().count() as i32;
+                    let subtext_len = subtext.chars().count() as i32;
 
                     // unpadded is all you need!
-                    let text = format!("{text}{subtext}").as_str().to_wide_16_unpadded();
+                    let text = format!("{clauses}{text}{subtext}")
+                        .as_str()
+                        .to_wide_16_unpadded();
                     let context = text_service.context::<ITfContext>()?;
-                    let display_attribute_atom = text_service.display_attribute_atom.clone();
+                    let attributes = [
+                        (clauses_len, atom(&GUID_DISPLAY_ATTRIBUTE_CONVERTED)),
+                        (text_len, atom(clause)),
+                        (subtext_len, atom(&GUID_DISPLAY_ATTRIBUTE_INPUT)),
+                    ];
 
                     move |cookie| unsafe {
                         let range = composition.GetRange()?;
                         range.SetText(cookie, TF_ST_CORRECTION, &text)?;
 
-                        // first, set the display attribute to the "text" part
-                        let text_range = range.Clone()?;
-                        text_range.Collapse(cookie, TF_ANCHOR_START)?;
-                        let mut shifted: i32 = 0;
-                        text_range.ShiftEnd(cookie, text_len, &mut shifted, std::ptr::null())?;
-                        let display_attribute = display_attribute_atom.get(&GUID_DISPLAY_ATTRIBUTE);
-                        if let Some(display_attribute) = display_attribute {
-                            let pvar = VARIANT::from(*display_attribute as i32);
-                            let prop = context.GetProperty(&GUID_PROP_ATTRIBUTE)?;
-                            prop.SetValue(cookie, &text_range, &pvar)?;
-                        }
+                        // first, set the display attributes
+                        let prop = context.GetProperty(&GUID_PROP_ATTRIBUTE)?;
+                        set_attributes(&prop, cookie, &range, &attributes)?;
 
                         range.Collapse(cookie, TF_ANCHOR_END)?;
                         let selection = TF_SELECTION {
@@ -210,19 +369,34 @@ impl TextServiceFactory {
         Ok(())
     }
 
+    // commit `committed` and keep `text` + `subtext` composing
+    // everything is done in one edit session so the remainder never shows up without its attribute
     #[tracing::instrument]
-    pub fn shift_start(&self, text: &str, subtext: &str) -> Result<()> {
+    pub fn shift_start(
+        &self,
+        committed: &str,
+        text: &str,
+        subtext: &str,
+        clause: &GUID,
+    ) -> Result<()> {
         let text_service = self.borrow()?;
+        let atom = |guid: &GUID| text_service.display_attribute_atom.get(guid).copied();
 
-        if let Some(composition) = text_service.borrow_composition()?.tip_composition.clone() {
-            edit_session(
+        let tip_composition = text_service.tip_composition.try_borrow()?.clone();
+        if let Some(composition) = tip_composition {
+            let rects = edit_session(
                 text_service.tid,
                 text_service.context()?,
                 Rc::new({
+                    let committed_len = committed.chars().count() as i32;
                     let text_len = text.chars().count() as i32;
-                    let subtext = subtext.to_wide_16_unpadded();
+                    let subtext_len = subtext.chars().count() as i32;
+                    let text = format!("{text}{subtext}").as_str().to_wide_16_unpadded();
                     let context = text_service.context::<ITfContext>()?;
-                    let display_attribute_atom = text_service.display_attribute_atom.clone();
+                    let attributes = [
+                        (text_len, atom(clause)),
+                        (subtext_len, atom(&GUID_DISPLAY_ATTRIBUTE_INPUT)),
+                    ];
 
                     move |cookie| unsafe {
                         // first, shift the start of the composition
@@ -234,21 +408,18 @@ impl TextServiceFactory {
                         prop.Clear(cookie, &range)?;
 
                         range.Collapse(cookie, TF_ANCHOR_START)?;
-                        range.ShiftStart(cookie, text_len, &mut shifted, std::ptr::null())?;
+                        range.ShiftStart(cookie, committed_len, &mut shifted, std::ptr::null())?;
 
                         composition.ShiftStart(cookie, &range)?;
 
-                        // then, set the display attribute
+                        // then, replace the remainder and mark it again
                         let range = composition.GetRange()?;
+                        range.SetText(cookie, TF_ST_CORRECTION, &text)?;
 
-                        range.SetText(cookie, TF_ST_CORRECTION, &subtext)?;
+                        set_attributes(&prop, cookie, &range, &attributes)?;
 
-                        let display_attribute = display_attribute_atom.get(&GUID_DISPLAY_ATTRIBUTE);
-                        if let Some(display_attribute) = display_attribute {
-                            let pvar = VARIANT::from(*display_attribute as i32);
-                            let prop = context.GetProperty(&GUID_PROP_ATTRIBUTE)?;
-                            prop.SetValue(cookie, &range, &pvar)?;
-                        }
+                        // the composition moved, so measure it for the candidate window here too
+                        let rects = measure_range(&context, cookie, &range)?;
 
                         range.Collapse(cookie, TF_ANCHOR_END)?;
                         let selection = TF_SELECTION {
@@ -261,10 +432,16 @@ impl TextServiceFactory {
 
                         context.SetSelection(cookie, &[selection])?;
 
-                        Ok(())
+                        Ok(rects)
                     }
                 }),
             )?;
+
+            if let Some((caret, whole)) = rects {
+                self.move_window(caret, whole)?;
+            }
+            // the committed text is no longer a part of the composition
+            self.track_mouse();
         } else {
             tracing::warn!("Composition is not started");
         }
@@ -275,40 +452,71 @@ impl TextServiceFactory {
     #[tracing::instrument]
     pub fn update_pos(&self) -> Result<()> {
         let text_service = self.borrow()?;
-        let composition = text_service.borrow_composition()?;
+        let tip_composition = text_service.tip_composition.try_borrow()?.clone();
 
-        if let Some(tip_composition) = composition.tip_composition.clone() {
-            edit_session(
+        if let Some(tip_composition) = tip_composition {
+            let rects = edit_session(
                 text_service.tid,
                 text_service.context()?,
                 Rc::new({
                     let context = text_service.context::<ITfContext>()?;
 
                     move |cookie| unsafe {
-                        let view = context.GetActiveView()?;
                         let range = tip_composition.GetRange()?;
-                        let mut ipc_service = IMEState::get()?
-                            .ipc_service
-                            .clone()
-                            .context("ipc_service is None")?;
-
-                        let mut rect = RECT::default();
-                        let mut clipped = false.into();
-                        view.GetTextExt(cookie, &range, &mut rect, &mut clipped)?;
-
-                        ipc_service.set_window_position(
-                            rect.top,
-                            rect.left,
-                            rect.bottom,
-                            rect.right,
-                        )?;
-
-                        Ok(())
+                        measure_range(&context, cookie, &range)
                     }
                 }),
             )?;
 
-            return Ok(());
+            if let Some((caret, whole)) = rects {
+                self.move_window(caret, whole)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // the mode is shown below the caret, which is at the selection when nothing is composed
+    pub fn flash_mode_indicator(&self, mode: &str) -> Result<()> {
+        let text_service = self.borrow()?;
+        let context = text_service.context::<ITfContext>()?;
+        let caret = edit_session(
+            text_service.tid,
+            text_service.context()?,
+            Rc::new(move |cookie| unsafe {
+                let mut selection: [TF_SELECTION; 1] = [TF_SELECTION::default()];
+                let mut fetched = 0;
+                context.GetSelection(cookie, TF_DEFAULT_SELECTION, &mut selection, &mut fetched)?;
+                let range = selection[0].range.as_ref().context("Range not found")?;
+                let (caret, _) = measure_range(&context, cookie, range)?;
+                Ok(caret)
+            }),
+        )?
+        .context("Caret not found")?;
+
+        let mut ipc_service = IMEState::get()?
+            .ipc_service
+            .clone()
+            .context("ipc_service is None")?;
+        ipc_service.flash_mode_indicator(window_position(&caret), mode)?;
+
+        Ok(())
+    }
+
+    fn move_window(&self, caret: RECT, exclusion: RECT) -> Result<()> {
+        let text_service = self.borrow()?;
+        let (caret, exclusion) = (window_position(&caret), window_position(&exclusion));
+
+        // the candidate window is moved by the ipc worker so that it follows the queued keys
+        if let Some(key_queue) = &text_service.key_queue {
+            key_queue.set_window_position(caret, exclusion)?;
+        } else {
+            let mut ipc_service = IMEState::get()?
+                .ipc_service
+                .clone()
+                .context("ipc_service is None")?;
+
+            ipc_service.set_window_position(caret, exclusion)?;
         }
 
         Ok(())
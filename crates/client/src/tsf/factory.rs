@@ -9,9 +9,10 @@ use windows::{
         Foundation::{BOOL, E_NOINTERFACE},
         System::Com::{IClassFactory, IClassFactory_Impl},
         UI::TextServices::{
-            ITfCompositionSink, ITfDisplayAttributeProvider, ITfKeyEventSink, ITfLangBarItem,
-            ITfLangBarItemButton, ITfSource, ITfTextInputProcessor, ITfTextInputProcessorEx,
-            ITfTextLayoutSink, ITfThreadMgrEventSink,
+            ITfCompartmentEventSink, ITfCompositionSink, ITfDisplayAttributeProvider,
+            ITfKeyEventSink, ITfLangBarItem, ITfLangBarItemButton, ITfMouseSink, ITfSource,
+            ITfTextInputProcessor, ITfTextInputProcessorEx, ITfTextLayoutSink,
+            ITfThreadMgrEventSink,
         },
     },
 };
@@ -29,11 +30,13 @@ use super::text_service::TextService;
     ITfTextInputProcessorEx,
     ITfKeyEventSink,
     ITfThreadMgrEventSink,
+    ITfCompartmentEventSink,
     ITfTextLayoutSink,
     ITfCompositionSink,
     ITfDisplayAttributeProvider,
     ITfLangBarItem,
     ITfLangBarItemButton,
+    ITfMouseSink,
     ITfSource
 )]
 #[derive(Debug)]
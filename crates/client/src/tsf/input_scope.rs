@@ -0,0 +1,93 @@
+use std::rc::Rc;
+
+use anyhow::{Context as _, Result};
+use windows::{
+    core::{IUnknown, Interface as _},
+    Win32::{
+        System::Com::CoTaskMemFree,
+        UI::TextServices::{
+            ITfContext, ITfDocumentMgr, ITfInputScope, InputScope, GUID_PROP_INPUTSCOPE,
+            IS_NUMERIC_PASSWORD, IS_PASSWORD, TF_DEFAULT_SELECTION, TF_SELECTION,
+        },
+    },
+};
+
+use crate::engine::{
+    client_action::ClientAction, composition::CompositionState, input_mode::InputMode,
+    state::IMEState,
+};
+
+use super::{edit_session::edit_session, factory::TextServiceFactory};
+
+impl TextServiceFactory {
+    // the input scopes the application set on the control at the selection, empty if none
+    fn input_scopes(&self, context: ITfContext) -> Result<Vec<InputScope>> {
+        let tid = self.borrow()?.tid;
+
+        let scopes = edit_session::<Vec<InputScope>>(
+            tid,
+            context.clone(),
+            Rc::new(move |cookie| unsafe {
+                let mut selection: [TF_SELECTION; 1] = [TF_SELECTION::default()];
+                let mut fetched = 0;
+                context.GetSelection(cookie, TF_DEFAULT_SELECTION, &mut selection, &mut fetched)?;
+                let range = selection[0].range.as_ref().context("Range not found")?;
+
+                let value = context
+                    .GetAppProperty(&GUID_PROP_INPUTSCOPE)?
+                    .GetValue(cookie, range)?;
+                let Ok(unknown) = IUnknown::try_from(&value) else {
+                    return Ok(vec![]);
+                };
+
+                let mut pointer = std::ptr::null_mut();
+                let mut count = 0;
+                unknown
+                    .cast::<ITfInputScope>()?
+                    .GetInputScopes(&mut pointer, &mut count)?;
+                if pointer.is_null() {
+                    return Ok(vec![]);
+                }
+                let scopes = std::slice::from_raw_parts(pointer, count as usize).to_vec();
+                CoTaskMemFree(Some(pointer as *const _));
+
+                Ok(scopes)
+            }),
+        )?;
+
+        Ok(scopes.unwrap_or_default())
+    }
+
+    // the IME is off in password fields, so that nothing typed there is composed or sent to the
+    // server, and the mode comes back when the focus leaves the field
+    pub fn update_password_field(&self, focus: Option<&ITfDocumentMgr>) -> Result<()> {
+        let context = focus.and_then(|focus| unsafe { focus.GetTop() }.ok());
+        let password = context.is_some_and(|context| {
+            self.input_scopes(context)
+                .unwrap_or_else(|e| {
+                    tracing::debug!("Failed to read the input scopes: {:?}", e);
+                    vec![]
+                })
+                .iter()
+                .any(|scope| [IS_PASSWORD, IS_NUMERIC_PASSWORD].contains(scope))
+        });
+
+        let mode = {
+            let mut state = IMEState::get()?;
+            match (password, state.password_field.take()) {
+                (true, None) => {
+                    state.password_field = Some(state.input_mode.clone());
+                    InputMode::Latin
+                }
+                (false, Some(previous)) => previous,
+                (_, previous) => {
+                    state.password_field = previous;
+                    return Ok(());
+                }
+            }
+        };
+        tracing::debug!("Password field: {}", password);
+
+        self.handle_action(&[ClientAction::SetIMEMode(mode)], CompositionState::None)
+    }
+}
@@ -8,8 +8,18 @@ use windows::{
 
 use anyhow::Result;
 
+use crate::{
+    engine::{caps_lock, state::IMEState, sticky_keys, thumb_shift::Key},
+    panic_guard,
+};
+
 use super::factory::TextServiceFactory_Impl;
 
+// every key goes to the application after a panic, and in the applications of `ExclusionConfig`
+fn passes_through() -> bool {
+    panic_guard::is_disabled() || IMEState::get().is_ok_and(|state| state.excluded)
+}
+
 // sink (aka event listener) for key events
 impl ITfKeyEventSink_Impl for TextServiceFactory_Impl {
     #[macros::anyhow]
@@ -22,7 +32,23 @@ impl ITfKeyEventSink_Impl for TextServiceFactory_Impl {
     ) -> Result<BOOL> {
         // this function checks if the key event will be handled by "OnKeyUp" function
         // so we need to return TRUE if we want to handle the key event
-        let result = self.process_key(pic, wparam)?.is_some();
+        if passes_through() {
+            return Ok(false.into());
+        }
+        sticky_keys::key_down(wparam.0)?;
+
+        // the Caps Lock typed by the IME itself goes to the application
+        if caps_lock::take_sent(wparam)? {
+            return Ok(false.into());
+        }
+
+        // thumb keys are kept even when they do nothing alone, they may shift the next key
+        let result =
+            self.thumb_shift_key(wparam)?.is_some() || self.process_key(pic, wparam)?.is_some();
+        // OnKeyDown is not called for the keys left to the application
+        if !result {
+            sticky_keys::consume(wparam.0);
+        }
 
         Ok(result.into())
     }
@@ -32,34 +58,63 @@ impl ITfKeyEventSink_Impl for TextServiceFactory_Impl {
     fn OnKeyDown(&self, pic: Option<&ITfContext>, wparam: WPARAM, _lparam: LPARAM) -> Result<BOOL> {
         // this function is called when a key is pressed
         // we can handle key events here
-        let result = self.handle_key(pic, wparam)?;
+        if passes_through() {
+            return Ok(false.into());
+        }
 
-        Ok(result.into())
+        sticky_keys::key_down(wparam.0)?;
+        let result = self.handle_key(pic, wparam);
+        sticky_keys::consume(wparam.0);
+
+        Ok(result?.into())
     }
 
     #[macros::anyhow]
     fn OnTestKeyUp(
         &self,
         _pic: Option<&ITfContext>,
-        _wparam: WPARAM,
+        wparam: WPARAM,
         _lparam: LPARAM,
     ) -> Result<BOOL> {
         // same as OnTestKeyDown
-        Ok(false.into())
+        // only the thumb keys of the thumb-shift input are handled on release
+        if passes_through() {
+            return Ok(false.into());
+        }
+        // the modifiers are latched on release, and OnKeyUp is not called for them
+        sticky_keys::key_up(wparam.0);
+
+        let result = matches!(self.thumb_shift_key(wparam)?, Some(Key::Thumb(_)));
+
+        Ok(result.into())
     }
 
     #[macros::anyhow]
-    fn OnKeyUp(&self, _pic: Option<&ITfContext>, _wparam: WPARAM, _lparam: LPARAM) -> Result<BOOL> {
+    #[tracing::instrument]
+    fn OnKeyUp(&self, pic: Option<&ITfContext>, wparam: WPARAM, _lparam: LPARAM) -> Result<BOOL> {
         // this function is called when a key is released
-        // but we handle key events in OnKeyDown function
-        // so just return S_OK
-        Ok(false.into())
+        // the other keys are handled in OnKeyDown function
+        if passes_through() {
+            return Ok(false.into());
+        }
+
+        sticky_keys::key_up(wparam.0);
+        let result = self.handle_key_up(pic, wparam)?;
+
+        Ok(result.into())
     }
 
     #[macros::anyhow]
-    fn OnPreservedKey(&self, _pic: Option<&ITfContext>, _rguid: *const GUID) -> Result<BOOL> {
-        // this function is actually not used
-        Ok(true.into())
+    #[tracing::instrument]
+    fn OnPreservedKey(&self, pic: Option<&ITfContext>, rguid: *const GUID) -> Result<BOOL> {
+        // called when one of the keys registered in preserved_key.rs is pressed
+        if passes_through() || rguid.is_null() {
+            return Ok(false.into());
+        }
+
+        let result = self.handle_preserved_key(pic, unsafe { &*rguid })?;
+
+        Ok(result.into())
     }
 
     #[macros::anyhow]
@@ -2,14 +2,19 @@ use windows::{
     core::{IUnknown, Interface as _, BSTR, GUID, PCWSTR},
     Win32::{
         Foundation::{BOOL, E_INVALIDARG, POINT, RECT},
+        Graphics::Gdi::HBITMAP,
         System::Ole::CONNECT_E_CANNOTCONNECT,
         UI::{
             TextServices::{
                 ITfLangBarItemButton_Impl, ITfLangBarItemSink, ITfLangBarItem_Impl, ITfMenu,
                 ITfSource_Impl, TfLBIClick, GUID_LBI_INPUTMODE, TF_LANGBARITEMINFO,
-                TF_LBI_STYLE_BTN_BUTTON,
+                TF_LBI_CLK_RIGHT, TF_LBI_STYLE_BTN_BUTTON, TF_LBI_STYLE_BTN_MENU,
+                TF_LBMENUF_CHECKED, TF_LBMENUF_RADIOCHECKED, TF_LBMENUF_SEPARATOR,
+            },
+            WindowsAndMessaging::{
+                FindWindowW, IsIconic, LoadImageW, SetForegroundWindow, ShowWindow, HICON,
+                IMAGE_ICON, LR_DEFAULTCOLOR, SW_RESTORE,
             },
-            WindowsAndMessaging::{LoadImageW, HICON, IMAGE_ICON, LR_DEFAULTCOLOR},
         },
     },
 };
@@ -17,23 +22,35 @@ use windows::{
 use crate::{
     engine::{
         client_action::ClientAction, composition::CompositionState, input_mode::InputMode,
-        state::IMEState, theme::get_theme,
+        server_status::UNAVAILABLE_MESSAGE, state::IMEState, theme::get_theme,
     },
+    extension::StringExt as _,
     globals::{DllModule, GUID_TEXT_SERVICE, TEXTSERVICE_LANGBARITEMSINK_COOKIE},
 };
 
 use anyhow::{Context as _, Result};
+use shared::{
+    i18n::text,
+    install::{settings_app_path, SETTINGS_APP_TITLE},
+};
 
 use super::factory::TextServiceFactory_Impl;
 
 const INFO: TF_LANGBARITEMINFO = TF_LANGBARITEMINFO {
     clsidService: GUID_TEXT_SERVICE,
     guidItem: GUID_LBI_INPUTMODE,
-    dwStyle: TF_LBI_STYLE_BTN_BUTTON,
+    // clicking toggles the mode, and the menu lists all of them
+    dwStyle: TF_LBI_STYLE_BTN_BUTTON | TF_LBI_STYLE_BTN_MENU,
     ulSort: 0,
     szDescription: [0; 32],
 };
 
+// ids of the menu items, the input modes use their index in InputMode::ALL
+const MENU_RECONVERT: u32 = 100;
+const MENU_SETTINGS: u32 = 101;
+const MENU_PRIVACY: u32 = 102;
+const MENU_KAOMOJI: u32 = 103;
+
 // you need to implement these three interfaces to create a language bar item
 // if not, you will get E_FAIL error in ITfLangBarItemMgr::AddItem
 
@@ -59,18 +76,27 @@ impl ITfLangBarItem_Impl for TextServiceFactory_Impl {
     // this will be shown as a tooltip when you hover the language bar item
     #[macros::anyhow]
     fn GetTooltipString(&self) -> Result<BSTR> {
-        Ok(BSTR::default())
+        let state = IMEState::get()?;
+        let locale = state.config.locale;
+        let mut tooltip = state.input_mode.label(locale).to_string();
+        for message in status_messages(&state) {
+            tooltip = format!("{} - {}", tooltip, text(locale, message));
+        }
+        Ok(BSTR::from(tooltip))
     }
 }
 
 impl ITfLangBarItemButton_Impl for TextServiceFactory_Impl {
     #[macros::anyhow]
-    fn OnClick(&self, _click: TfLBIClick, _pt: &POINT, _prcarea: *const RECT) -> Result<()> {
+    fn OnClick(&self, click: TfLBIClick, _pt: &POINT, _prcarea: *const RECT) -> Result<()> {
         let mode = {
-            let ime_mode = &IMEState::get()?.input_mode;
-            match ime_mode {
-                InputMode::Latin => InputMode::Kana,
-                InputMode::Kana => InputMode::Latin,
+            let state = IMEState::get()?;
+            let ime_mode = &state.input_mode;
+            // left click toggles Japanese and Latin, right click goes through the mode cycle
+            match (click, ime_mode) {
+                (TF_LBI_CLK_RIGHT, _) => ime_mode.next(&InputMode::cycle(&state.config.keys)),
+                (_, InputMode::Latin) => InputMode::Kana,
+                (_, _) => InputMode::Latin,
             }
         };
 
@@ -80,15 +106,57 @@ impl ITfLangBarItemButton_Impl for TextServiceFactory_Impl {
         Ok(())
     }
 
-    // this method should not be called
     #[macros::anyhow]
-    fn InitMenu(&self, _pmenu: Option<&ITfMenu>) -> Result<()> {
+    fn InitMenu(&self, pmenu: Option<&ITfMenu>) -> Result<()> {
+        let menu = pmenu.context("Menu is null")?;
+        let (current, locale, privacy) = {
+            let state = IMEState::get()?;
+            (state.input_mode.clone(), state.config.locale, state.privacy)
+        };
+
+        for (id, mode) in InputMode::ALL.iter().enumerate() {
+            let flags = if *mode == current {
+                TF_LBMENUF_RADIOCHECKED
+            } else {
+                0
+            };
+            add_menu_item(menu, id as u32, flags, mode.label(locale))?;
+        }
+        add_menu_item(menu, 0, TF_LBMENUF_SEPARATOR, "")?;
+        add_menu_item(menu, MENU_RECONVERT, 0, text(locale, "menu.reconvert"))?;
+        add_menu_item(menu, MENU_KAOMOJI, 0, text(locale, "menu.kaomoji_palette"))?;
+        let flags = if privacy { TF_LBMENUF_CHECKED } else { 0 };
+        add_menu_item(menu, MENU_PRIVACY, flags, text(locale, "menu.privacy_mode"))?;
+        add_menu_item(menu, MENU_SETTINGS, 0, text(locale, "menu.settings"))?;
+
         Ok(())
     }
 
-    // this method should not be called
     #[macros::anyhow]
-    fn OnMenuSelect(&self, _w_id: u32) -> Result<()> {
+    fn OnMenuSelect(&self, w_id: u32) -> Result<()> {
+        match w_id {
+            MENU_RECONVERT => {
+                let context = self.borrow()?.context.clone();
+                self.convert_selection(context.as_ref())?;
+            }
+            MENU_KAOMOJI => {
+                // the text being composed is committed before the palette takes the window
+                let mut actions = vec![ClientAction::ShowPalette];
+                if self.borrow()?.borrow_composition()?.state != CompositionState::None {
+                    actions.insert(0, ClientAction::EndComposition);
+                }
+                self.handle_action(&actions, CompositionState::None)?;
+            }
+            MENU_SETTINGS => open_settings()?,
+            MENU_PRIVACY => self.toggle_privacy_mode()?,
+            id => {
+                if let Some(mode) = InputMode::ALL.get(id as usize) {
+                    let actions = vec![ClientAction::SetIMEMode(mode.clone())];
+                    self.handle_action(&actions, CompositionState::None)?;
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -100,14 +168,15 @@ impl ITfLangBarItemButton_Impl for TextServiceFactory_Impl {
         let theme = get_theme()?;
 
         let icon_id = match input_mode {
-            InputMode::Kana => {
+            // there is no dedicated icon, the mode is shown in the tooltip
+            InputMode::Kana | InputMode::Katakana | InputMode::HalfKatakana => {
                 if theme {
                     102
                 } else {
                     104
                 }
             }
-            InputMode::Latin => {
+            InputMode::Latin | InputMode::FullLatin => {
                 if theme {
                     103
                 } else {
@@ -132,10 +201,73 @@ impl ITfLangBarItemButton_Impl for TextServiceFactory_Impl {
 
     #[macros::anyhow]
     fn GetText(&self) -> Result<BSTR> {
-        Ok(BSTR::default())
+        status_text()
     }
 }
 
+fn add_menu_item(menu: &ITfMenu, id: u32, flags: u32, text: &str) -> Result<()> {
+    let text = text.to_wide_16_unpadded();
+    unsafe {
+        menu.AddMenuItem(
+            id,
+            flags,
+            HBITMAP::default(),
+            HBITMAP::default(),
+            &text,
+            std::ptr::null_mut(),
+        )?;
+    }
+
+    Ok(())
+}
+
+// bring the settings app to the front, or start it if it is not running
+pub fn open_settings() -> Result<()> {
+    let title = SETTINGS_APP_TITLE.to_wide_16();
+    if let Ok(hwnd) = unsafe { FindWindowW(PCWSTR::null(), PCWSTR(title.as_ptr())) } {
+        unsafe {
+            if IsIconic(hwnd).as_bool() {
+                let _ = ShowWindow(hwnd, SW_RESTORE);
+            }
+            // allowed because the key or the click was sent to the foreground application
+            let _ = SetForegroundWindow(hwnd);
+        }
+        return Ok(());
+    }
+
+    let path = settings_app_path();
+    std::process::Command::new(&path)
+        .spawn()
+        .with_context(|| format!("Failed to start {}", path.display()))?;
+
+    Ok(())
+}
+
+// keys of the messages about the state which is not shown by the icon
+// the server status tells why the input is not converted, and the privacy mode must be
+// noticeable so that it is not left on by mistake
+fn status_messages(state: &IMEState) -> Vec<&'static str> {
+    let mut messages = vec![];
+    if state.server_status.is_unavailable() {
+        messages.push(UNAVAILABLE_MESSAGE);
+    }
+    if state.privacy {
+        messages.push("status.privacy_mode");
+    }
+    messages
+}
+
+// empty unless there is something to tell
+fn status_text() -> Result<BSTR> {
+    let state = IMEState::get()?;
+    let locale = state.config.locale;
+    let messages: Vec<&str> = status_messages(&state)
+        .into_iter()
+        .map(|message| text(locale, message))
+        .collect();
+    Ok(BSTR::from(messages.join(" / ")))
+}
+
 impl ITfSource_Impl for TextServiceFactory_Impl {
     #[macros::anyhow]
     fn AdviseSink(&self, riid: *const GUID, punk: Option<&IUnknown>) -> Result<u32> {
@@ -0,0 +1,246 @@
+use std::{cell::RefCell, sync::Once};
+
+use windows::{
+    core::{w, AsImpl as _, PCWSTR},
+    Win32::{
+        Foundation::{HINSTANCE, HMODULE, HWND, LPARAM, LRESULT, WPARAM},
+        UI::{
+            TextServices::ITfTextInputProcessor,
+            WindowsAndMessaging::{
+                CreateWindowExW, DefWindowProcW, DestroyWindow, KillTimer, PostMessageW,
+                RegisterClassExW, SetTimer, HMENU, HWND_MESSAGE, WINDOW_EX_STYLE, WINDOW_STYLE,
+                WM_APP, WM_TIMER, WNDCLASSEXW,
+            },
+        },
+    },
+};
+
+use anyhow::Result;
+use shared::proto::window_event;
+use tokio::task::JoinHandle;
+
+use crate::engine::ipc_service::IPCService;
+
+use super::factory::TextServiceFactory;
+
+// posted by the ipc worker when results of queued keys are ready
+const WM_KEY_QUEUE: u32 = WM_APP + 1;
+// posted when a candidate is clicked in the candidate window, wparam is the index
+const WM_CANDIDATE_CLICKED: u32 = WM_APP + 2;
+// posted when a kaomoji is clicked in the palette, wparam is the index
+const WM_PALETTE_CLICKED: u32 = WM_APP + 3;
+// posted when a mode is chosen in the tray menu, wparam is 1 for ひらがな
+const WM_INPUT_MODE_SELECTED: u32 = WM_APP + 4;
+
+// timer of `ConversionConfig.auto_commit_ms`, started again by every key
+const IDLE_TIMER: usize = 1;
+
+const CLASS_NAME: PCWSTR = w!("AzookeyMessageWindow");
+
+static REGISTER_CLASS: Once = Once::new();
+
+thread_local! {
+    // text service which owns the message window on this thread
+    // TSF creates one text service per thread, so one slot is enough
+    static TARGET: RefCell<Option<ITfTextInputProcessor>> = const { RefCell::new(None) };
+}
+
+// hidden message-only window used to get back to the TSF thread
+// results from the ipc worker must be applied on the thread which owns the context
+#[derive(Debug)]
+pub struct MessageWindow {
+    hwnd: HWND,
+    window_events: Option<JoinHandle<()>>,
+}
+
+impl MessageWindow {
+    pub fn new(hinst: HMODULE, target: ITfTextInputProcessor) -> Result<Self> {
+        let hinstance = HINSTANCE::from(hinst);
+
+        REGISTER_CLASS.call_once(|| unsafe {
+            let class = WNDCLASSEXW {
+                cbSize: std::mem::size_of::<WNDCLASSEXW>() as u32,
+                lpfnWndProc: Some(window_proc),
+                hInstance: hinstance,
+                lpszClassName: CLASS_NAME,
+                ..Default::default()
+            };
+            RegisterClassExW(&class);
+        });
+
+        let hwnd = unsafe {
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                CLASS_NAME,
+                w!(""),
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                HMENU::default(),
+                hinstance,
+                None,
+            )?
+        };
+
+        TARGET.with(|slot| *slot.borrow_mut() = Some(target));
+
+        Ok(Self {
+            hwnd,
+            window_events: None,
+        })
+    }
+
+    // returns a callback which wakes up the TSF thread from any thread
+    pub fn notifier(&self) -> impl Fn() + Send + 'static {
+        // HWND is not Send, so pass it as an integer
+        let hwnd = self.hwnd.0 as isize;
+
+        move || unsafe {
+            let _ = PostMessageW(
+                HWND(hwnd as *mut std::ffi::c_void),
+                WM_KEY_QUEUE,
+                WPARAM(0),
+                LPARAM(0),
+            );
+        }
+    }
+}
+
+impl MessageWindow {
+    // WM_TIMER comes to this thread, so the composition can be committed without another key
+    pub fn start_idle_timer(&self, ms: u64) {
+        unsafe { SetTimer(self.hwnd, IDLE_TIMER, ms.min(u32::MAX as u64) as u32, None) };
+    }
+
+    pub fn stop_idle_timer(&self) {
+        unsafe {
+            let _ = KillTimer(self.hwnd, IDLE_TIMER);
+        }
+    }
+
+    // forward events from the candidate window to this thread
+    pub fn subscribe_window_events(&mut self, ipc_service: &IPCService) -> Result<()> {
+        let hwnd = self.hwnd.0 as isize;
+
+        let handle = ipc_service.subscribe_window_events(move |event| {
+            let (msg, index) = match event.event {
+                Some(window_event::Event::CandidateClicked(index)) => (WM_CANDIDATE_CLICKED, index),
+                Some(window_event::Event::PaletteClicked(index)) => (WM_PALETTE_CLICKED, index),
+                Some(window_event::Event::InputModeSelected(kana)) => {
+                    (WM_INPUT_MODE_SELECTED, kana as i32)
+                }
+                _ => return,
+            };
+            unsafe {
+                let _ = PostMessageW(
+                    HWND(hwnd as *mut std::ffi::c_void),
+                    msg,
+                    WPARAM(index as usize),
+                    LPARAM(0),
+                );
+            }
+        })?;
+
+        if let Some(previous) = self.window_events.replace(handle) {
+            previous.abort();
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for MessageWindow {
+    fn drop(&mut self) {
+        if let Some(window_events) = self.window_events.take() {
+            window_events.abort();
+        }
+
+        TARGET.with(|slot| slot.borrow_mut().take());
+
+        unsafe {
+            let _ = DestroyWindow(self.hwnd);
+        }
+    }
+}
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_KEY_QUEUE {
+        let target = TARGET.with(|slot| slot.borrow().clone());
+
+        if let Some(target) = target {
+            let factory: &TextServiceFactory = target.as_impl();
+            if let Err(e) = crate::panic_guard::catch(|| factory.drain_key_queue()) {
+                tracing::error!("Failed to apply queued keys: {:?}", e);
+            }
+        }
+
+        return LRESULT(0);
+    }
+
+    if msg == WM_CANDIDATE_CLICKED {
+        let target = TARGET.with(|slot| slot.borrow().clone());
+
+        if let Some(target) = target {
+            let factory: &TextServiceFactory = target.as_impl();
+            let index = wparam.0 as i32;
+            if let Err(e) = crate::panic_guard::catch(|| factory.commit_candidate(index)) {
+                tracing::error!("Failed to commit the clicked candidate: {:?}", e);
+            }
+        }
+
+        return LRESULT(0);
+    }
+
+    if msg == WM_PALETTE_CLICKED {
+        let target = TARGET.with(|slot| slot.borrow().clone());
+
+        if let Some(target) = target {
+            let factory: &TextServiceFactory = target.as_impl();
+            let index = wparam.0 as i32;
+            if let Err(e) = crate::panic_guard::catch(|| factory.commit_palette(index)) {
+                tracing::error!("Failed to commit the clicked kaomoji: {:?}", e);
+            }
+        }
+
+        return LRESULT(0);
+    }
+
+    if msg == WM_INPUT_MODE_SELECTED {
+        let target = TARGET.with(|slot| slot.borrow().clone());
+
+        if let Some(target) = target {
+            let factory: &TextServiceFactory = target.as_impl();
+            let kana = wparam.0 != 0;
+            if let Err(e) = crate::panic_guard::catch(|| factory.select_input_mode(kana)) {
+                tracing::error!("Failed to switch the mode from the tray: {:?}", e);
+            }
+        }
+
+        return LRESULT(0);
+    }
+
+    if msg == WM_TIMER && wparam.0 == IDLE_TIMER {
+        // fired once, the next key starts it again
+        let _ = KillTimer(hwnd, IDLE_TIMER);
+        let target = TARGET.with(|slot| slot.borrow().clone());
+
+        if let Some(target) = target {
+            let factory: &TextServiceFactory = target.as_impl();
+            if let Err(e) = crate::panic_guard::catch(|| factory.auto_commit()) {
+                tracing::error!("Failed to commit after the idle timeout: {:?}", e);
+            }
+        }
+
+        return LRESULT(0);
+    }
+
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
@@ -0,0 +1,103 @@
+use std::rc::Rc;
+
+use windows::Win32::{
+    Foundation::BOOL,
+    System::SystemServices::{MK_LBUTTON, MK_RBUTTON},
+    UI::TextServices::{
+        ITfMouseSink, ITfMouseSink_Impl, ITfMouseTracker, TF_GRAVITY_BACKWARD, TF_GRAVITY_FORWARD,
+    },
+};
+
+use anyhow::Result;
+
+use crate::engine::composition::CompositionState;
+
+use super::{
+    edit_session::edit_session,
+    factory::{TextServiceFactory, TextServiceFactory_Impl},
+};
+
+impl ITfMouseSink_Impl for TextServiceFactory_Impl {
+    // called for the mouse events over the composition, see `advise_mouse_sink`
+    // the events which are not eaten end the composition in most applications
+    #[macros::anyhow]
+    fn OnMouseEvent(&self, uedge: u32, uquadrant: u32, dwbtnstatus: u32) -> Result<BOOL> {
+        // the events keep coming while a button is held, only the press is a click
+        let pressed = {
+            let text_service = self.borrow()?;
+            let pressed = dwbtnstatus & !text_service.mouse_buttons.get();
+            text_service.mouse_buttons.set(dwbtnstatus);
+            pressed
+        };
+
+        // the quadrants 0 and 1 are left of the edge, in the character before it
+        let offset = match uquadrant {
+            0 | 1 => uedge.saturating_sub(1),
+            _ => uedge,
+        } as usize;
+
+        let eaten = if pressed & MK_RBUTTON.0 != 0 {
+            self.click_composition(offset, true)?
+        } else if pressed & MK_LBUTTON.0 != 0 {
+            self.click_composition(offset, false)?
+        } else {
+            self.borrow()?.borrow_composition()?.state != CompositionState::None
+        };
+
+        Ok(eaten.into())
+    }
+}
+
+impl TextServiceFactory {
+    // follow the clicks on the composition
+    // called again whenever the composition starts or its start moves
+    pub fn advise_mouse_sink(&self) -> Result<()> {
+        self.unadvise_mouse_sink()?;
+
+        let text_service = self.borrow()?;
+        let Some(composition) = text_service.tip_composition.try_borrow()?.clone() else {
+            return Ok(());
+        };
+        let tracker = text_service.context::<ITfMouseTracker>()?;
+
+        let cookie = edit_session(
+            text_service.tid,
+            text_service.context()?,
+            Rc::new({
+                let tracker = tracker.clone();
+                let sink = text_service.this::<ITfMouseSink>()?;
+
+                move |cookie| unsafe {
+                    // the range grows with the text set at either end of the composition
+                    let range = composition.GetRange()?;
+                    range.SetGravity(cookie, TF_GRAVITY_BACKWARD, TF_GRAVITY_FORWARD)?;
+
+                    Ok(tracker.AdviseMouseSink(&range, &sink)?)
+                }
+            }),
+        )?;
+
+        if let Some(cookie) = cookie {
+            *text_service.mouse_sink.try_borrow_mut()? = Some((tracker, cookie));
+        }
+
+        Ok(())
+    }
+
+    pub fn unadvise_mouse_sink(&self) -> Result<()> {
+        let text_service = self.borrow()?;
+        text_service.mouse_buttons.set(0);
+        if let Some((tracker, cookie)) = text_service.mouse_sink.try_borrow_mut()?.take() {
+            unsafe { tracker.UnadviseMouseSink(cookie)? };
+        }
+
+        Ok(())
+    }
+
+    // not every application supports the mouse tracker, the clicks end the composition there
+    pub fn track_mouse(&self) {
+        if let Err(e) = self.advise_mouse_sink() {
+            tracing::debug!("Failed to advise the mouse sink: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use windows::{
+    core::GUID,
+    Win32::UI::TextServices::{
+        ITfContext, ITfKeystrokeMgr, TF_MOD_CONTROL, TF_MOD_SHIFT, TF_PRESERVEDKEY,
+    },
+};
+
+use anyhow::Result;
+use shared::i18n::text;
+
+use crate::{
+    engine::{
+        client_action::ClientAction, composition::CompositionState, input_mode::InputMode,
+        keymap::parse_shortcut, state::IMEState,
+    },
+    extension::StringExt as _,
+    globals::{
+        GUID_PRESERVEDKEY_CONVERT_SELECTION, GUID_PRESERVEDKEY_FULL_LATIN,
+        GUID_PRESERVEDKEY_OPEN_SETTINGS, GUID_PRESERVEDKEY_PRIVACY,
+    },
+};
+
+use super::{factory::TextServiceFactory, language_bar::open_settings};
+
+// shortcuts which are handled by the IME instead of the application
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hotkey {
+    // convert the selected romaji / kana
+    ConvertSelection,
+    // switch to the full-width alphanumeric mode and back
+    FullLatinMode,
+    // bring up the settings app, only if `keys.open_settings` is set
+    OpenSettings,
+    // stop learning and keeping the text, for passwords and other sensitive text
+    PrivacyMode,
+}
+
+struct PreservedKey {
+    hotkey: Hotkey,
+    guid: GUID,
+    key: TF_PRESERVEDKEY,
+    // key of the localized string
+    description: &'static str,
+}
+
+const PRESERVED_KEYS: &[PreservedKey] = &[
+    PreservedKey {
+        hotkey: Hotkey::ConvertSelection,
+        guid: GUID_PRESERVEDKEY_CONVERT_SELECTION,
+        // Ctrl+Shift+R
+        key: TF_PRESERVEDKEY {
+            uVKey: 0x52,
+            uModifiers: TF_MOD_CONTROL | TF_MOD_SHIFT,
+        },
+        description: "hotkey.convert_selection",
+    },
+    PreservedKey {
+        hotkey: Hotkey::FullLatinMode,
+        guid: GUID_PRESERVEDKEY_FULL_LATIN,
+        // Ctrl+Shift+Y
+        key: TF_PRESERVEDKEY {
+            uVKey: 0x59,
+            uModifiers: TF_MOD_CONTROL | TF_MOD_SHIFT,
+        },
+        description: "mode.full_latin",
+    },
+    PreservedKey {
+        hotkey: Hotkey::PrivacyMode,
+        guid: GUID_PRESERVEDKEY_PRIVACY,
+        // Ctrl+Shift+F9
+        key: TF_PRESERVEDKEY {
+            uVKey: 0x78,
+            uModifiers: TF_MOD_CONTROL | TF_MOD_SHIFT,
+        },
+        description: "hotkey.privacy_mode",
+    },
+];
+
+// the shortcut to open the settings app, if it is configured
+fn settings_shortcut() -> Result<Option<TF_PRESERVEDKEY>> {
+    let shortcut = IMEState::get()?.config.keys.open_settings.clone();
+    if shortcut.is_empty() {
+        return Ok(None);
+    }
+
+    let key = parse_shortcut(&shortcut);
+    if key.is_none() {
+        tracing::warn!("Invalid shortcut to open the settings: {}", shortcut);
+    }
+    Ok(key)
+}
+
+impl TextServiceFactory {
+    // returns the configured shortcut, which is needed to unpreserve it
+    pub fn preserve_keys(
+        &self,
+        keystroke_mgr: &ITfKeystrokeMgr,
+        tid: u32,
+    ) -> Result<Option<TF_PRESERVEDKEY>> {
+        let locale = IMEState::get()?.config.locale;
+        for preserved in PRESERVED_KEYS {
+            let description = text(locale, preserved.description).to_wide_16_unpadded();
+            unsafe {
+                keystroke_mgr.PreserveKey(tid, &preserved.guid, &preserved.key, &description)?;
+            }
+        }
+
+        let settings_key = settings_shortcut()?;
+        if let Some(key) = &settings_key {
+            let description = text(locale, "hotkey.open_settings").to_wide_16_unpadded();
+            unsafe {
+                keystroke_mgr.PreserveKey(
+                    tid,
+                    &GUID_PRESERVEDKEY_OPEN_SETTINGS,
+                    key,
+                    &description,
+                )?;
+            }
+        }
+
+        Ok(settings_key)
+    }
+
+    pub fn unpreserve_keys(&self, keystroke_mgr: &ITfKeystrokeMgr) -> Result<()> {
+        for preserved in PRESERVED_KEYS {
+            unsafe {
+                keystroke_mgr.UnpreserveKey(&preserved.guid, &preserved.key)?;
+            }
+        }
+
+        if let Some(key) = self.borrow()?.settings_key {
+            unsafe {
+                keystroke_mgr.UnpreserveKey(&GUID_PRESERVEDKEY_OPEN_SETTINGS, &key)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // returns whether the key is eaten
+    pub fn handle_preserved_key(&self, context: Option<&ITfContext>, guid: &GUID) -> Result<bool> {
+        let hotkey = if *guid == GUID_PRESERVEDKEY_OPEN_SETTINGS {
+            Hotkey::OpenSettings
+        } else {
+            let Some(preserved) = PRESERVED_KEYS.iter().find(|key| key.guid == *guid) else {
+                return Ok(false);
+            };
+            preserved.hotkey
+        };
+        tracing::debug!("Preserved key: {:?}", hotkey);
+
+        match hotkey {
+            Hotkey::ConvertSelection => self.convert_selection(context),
+            Hotkey::FullLatinMode => {
+                let mode = match IMEState::get()?.input_mode {
+                    InputMode::FullLatin => InputMode::Latin,
+                    _ => InputMode::FullLatin,
+                };
+                self.handle_action(&[ClientAction::SetIMEMode(mode)], CompositionState::None)?;
+                Ok(true)
+            }
+            Hotkey::OpenSettings => {
+                open_settings()?;
+                Ok(true)
+            }
+            Hotkey::PrivacyMode => {
+                self.toggle_privacy_mode()?;
+                Ok(true)
+            }
+        }
+    }
+}
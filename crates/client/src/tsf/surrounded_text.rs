@@ -13,10 +13,15 @@ use windows::{
     },
 };
 
-use crate::engine::state::IMEState;
+use crate::engine::{app_quirks, client_action::ClientAction, state::IMEState};
 
 use super::{edit_session::edit_session, factory::TextServiceFactory};
 
+// number of characters before the caret sent as the context
+const CONTEXT_LENGTH: i32 = 30;
+// longest selection which is read for conversion
+const SELECTION_LENGTH: usize = 256;
+
 impl TextServiceFactory {
     fn to_parent_document_if_exists(
         &self,
@@ -109,7 +114,50 @@ impl TextServiceFactory {
         }
     }
 
+    // prepend the text before the caret when a composition starts or a clause was committed
+    pub fn with_context(&self, actions: Vec<ClientAction>) -> Vec<ClientAction> {
+        let pending = self.borrow().ok().and_then(|text_service| {
+            let mut pending = text_service.pending_context.try_borrow_mut().ok()?;
+            pending.take()
+        });
+
+        if !actions.contains(&ClientAction::StartComposition)
+            || !app_quirks::current().surrounding_text
+            || IMEState::get().is_ok_and(|state| state.privacy)
+        {
+            let mut actions = actions;
+            if let Some(context) = pending {
+                actions.insert(0, ClientAction::SetContext(context));
+            }
+            return actions;
+        }
+
+        // an empty context is sent as well, so the one from the previous composition is not used
+        let context = self.preceding_text("").unwrap_or_else(|e| {
+            tracing::debug!("Failed to read the text before the caret: {:?}", e);
+            String::new()
+        });
+
+        let mut actions = actions;
+        actions.insert(0, ClientAction::SetContext(context));
+        actions
+    }
+
+    // read the context after a commit, it is sent to the server by the worker with the next key
+    // so that this thread doesn't wait for the server
     pub fn update_context(&self, preview: &str) -> Result<()> {
+        if IMEState::get()?.privacy {
+            return Ok(());
+        }
+
+        let preceding_text = self.preceding_text(preview)?;
+        *self.borrow()?.pending_context.try_borrow_mut()? = Some(preceding_text);
+
+        Ok(())
+    }
+
+    // text before the composition, at most CONTEXT_LENGTH characters
+    pub fn preceding_text(&self, preview: &str) -> Result<String> {
         unsafe {
             let text_service = self.borrow()?;
 
@@ -148,7 +196,7 @@ impl TextServiceFactory {
                         preceding_range.Collapse(cookie, TF_ANCHOR_START)?;
                         preceding_range.ShiftStart(
                             cookie,
-                            -30,
+                            -CONTEXT_LENGTH,
                             &mut preceding_range_shifted,
                             &halt_cond,
                         )?;
@@ -160,7 +208,7 @@ impl TextServiceFactory {
                             &halt_cond,
                         )?;
 
-                        let mut pchtext = [0u16; 64];
+                        let mut pchtext = [0u16; 2 * CONTEXT_LENGTH as usize];
                         let mut pcch = 0;
                         preceding_range.GetText(
                             cookie,
@@ -174,14 +222,45 @@ impl TextServiceFactory {
                 }),
             )?;
 
-            let mut ipc_service = IMEState::get()?
-                .ipc_service
-                .clone()
-                .context("ipc_service is None")?;
-
-            ipc_service.set_context(preceding_text.context("preceding_text is null")?)?;
-
-            Ok(())
+            preceding_text.context("preceding_text is null")
         }
     }
+
+    // text of the current selection, empty if nothing is selected
+    pub fn selected_text(&self) -> Result<String> {
+        let text_service = self.borrow()?;
+        let context = text_service.context::<ITfContext>()?;
+
+        let text = edit_session::<String>(
+            text_service.tid,
+            context.clone(),
+            Rc::new(move |cookie| unsafe {
+                let mut pselection: [TF_SELECTION; 1] = [TF_SELECTION::default()];
+                let mut pfetched = 0;
+                context.GetSelection(
+                    cookie,
+                    TF_DEFAULT_SELECTION,
+                    &mut pselection,
+                    &mut pfetched,
+                )?;
+                if pfetched == 0 {
+                    return Ok(String::new());
+                }
+
+                let range = pselection[0]
+                    .range
+                    .as_ref()
+                    .context("Range not found")?
+                    .Clone()?;
+
+                let mut pchtext = [0u16; SELECTION_LENGTH];
+                let mut pcch = 0;
+                range.GetText(cookie, TF_TF_MOVESTART, &mut pchtext, &mut pcch)?;
+
+                Ok(String::from_utf16_lossy(&pchtext[..pcch as usize]))
+            }),
+        )?;
+
+        Ok(text.unwrap_or_default())
+    }
 }
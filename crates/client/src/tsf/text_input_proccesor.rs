@@ -1,69 +1,93 @@
 use std::collections::HashMap;
-use std::io::Write;
-
-// Debug helper - write to file since println doesn't work in DLLs
-fn debug_log(msg: &str) {
-    if let Ok(mut file) = std::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("G:/Projects/azooKey-Windows/logs/debug.log")
-    {
-        let _ = writeln!(file, "[{}] {}", chrono::Local::now().format("%H:%M:%S%.3f"), msg);
-    }
-}
 
 use crate::{
-    engine::{ipc_service, state::IMEState},
-    globals::{DllModule, GUID_DISPLAY_ATTRIBUTE},
+    engine::{
+        app_quirks, composition::Composition, ipc_service, key_queue::KeyQueue, metrics, recorder,
+        state::IMEState,
+    },
+    globals::{
+        DllModule, GUID_DISPLAY_ATTRIBUTE_CONVERTED, GUID_DISPLAY_ATTRIBUTE_INPUT,
+        GUID_DISPLAY_ATTRIBUTE_TARGET,
+    },
 };
 
-use super::factory::TextServiceFactory_Impl;
+use super::{
+    compartment_event_sink::open_close, factory::TextServiceFactory_Impl,
+    message_window::MessageWindow,
+};
 use windows::{
     core::Interface as _,
     Win32::{
         Foundation::BOOL,
         System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER},
         UI::TextServices::{
-            CLSID_TF_CategoryMgr, ITfCategoryMgr, ITfKeyEventSink, ITfKeystrokeMgr,
-            ITfLangBarItemButton, ITfLangBarItemMgr, ITfSource, ITfTextInputProcessorEx_Impl,
-            ITfTextInputProcessor_Impl, ITfThreadMgr, ITfThreadMgrEventSink,
+            CLSID_TF_CategoryMgr, ITfCategoryMgr, ITfCompartmentEventSink, ITfKeyEventSink,
+            ITfKeystrokeMgr, ITfLangBarItemButton, ITfLangBarItemMgr, ITfSource,
+            ITfTextInputProcessor, ITfTextInputProcessorEx_Impl, ITfTextInputProcessor_Impl,
+            ITfThreadMgr, ITfThreadMgrEventSink,
         },
     },
 };
 
 use anyhow::{Context, Result};
+use shared::AppConfig;
 
 impl ITfTextInputProcessor_Impl for TextServiceFactory_Impl {
     #[macros::anyhow]
     #[tracing::instrument]
     fn Activate(&self, ptim: Option<&ITfThreadMgr>, tid: u32) -> Result<()> {
-        debug_log(&format!("Activate called with tid: {}", tid));
         tracing::debug!("Activated with tid: {tid}");
 
+        // give the IME another chance if it was disabled by a panic
+        crate::panic_guard::reset();
+
+        // pick up changes made in the settings app
+        if let Err(e) = crate::trace::reload_filter() {
+            tracing::warn!("Failed to reload log filter: {:?}", e);
+        }
+
+        let config = AppConfig::try_read().unwrap_or_else(|e| {
+            tracing::warn!("Failed to read config: {:?}", e);
+            AppConfig::default()
+        });
+        metrics::set_enabled(config.debug.latency_overlay);
+        recorder::set_enabled(config.debug.record_session);
+        ipc_service::configure(&config.ipc);
+        let (vertical, font) = (config.ui.vertical, config.ui.font.clone());
+        IMEState::get()?.config = config;
+        app_quirks::update_excluded()?;
+
         // add reference to the dll instance to prevent it from being unloaded
         let mut dll_instance = DllModule::get()?;
         dll_instance.add_ref();
 
         // initialize ipc_service (optional - continue activation even if it fails)
         // The IPC service will be lazily reconnected when the user types
-        debug_log("Initializing IPC service...");
+        tracing::debug!("Initializing IPC service...");
         match ipc_service::IPCService::new() {
             Ok(mut ipc_service) => {
-                debug_log("IPC service created, testing...");
+                tracing::debug!("IPC service created, testing...");
                 if let Err(e) = ipc_service.append_text("".to_string()) {
-                    debug_log(&format!("IPC service test failed: {:?}", e));
                     tracing::warn!("IPC service test failed: {:?}", e);
                 } else {
+                    // the layout may have been changed in the settings app
+                    if let Err(e) = ipc_service.set_layout(vertical) {
+                        tracing::warn!("Failed to set the layout of the candidates: {:?}", e);
+                    }
+                    if let Err(e) = ipc_service.set_font(&font) {
+                        tracing::warn!("Failed to set the font of the candidates: {:?}", e);
+                    }
                     IMEState::get()?.ipc_service = Some(ipc_service);
-                    debug_log("IPC service initialized successfully");
                     tracing::debug!("IPC service initialized successfully");
                 }
             }
             Err(e) => {
                 // Don't return early - continue activation without IPC
                 // The IME will try to reconnect when the user types
-                debug_log(&format!("Failed to initialize IPC service: {:?}", e));
-                tracing::warn!("Failed to initialize IPC service: {:?}. Will retry on input.", e);
+                tracing::warn!(
+                    "Failed to initialize IPC service: {:?}. Will retry on input.",
+                    e
+                );
             }
         }
 
@@ -73,8 +97,21 @@ impl ITfTextInputProcessor_Impl for TextServiceFactory_Impl {
         let thread_mgr = ptim.context("Thread manager is null")?;
         text_service.thread_mgr = Some(thread_mgr.clone());
 
+        // initialize key queue
+        // keys are converted on the ipc worker, and the results come back through the message window
+        tracing::debug!("Initialize key queue");
+        let hinst = dll_instance.hinst.context("DLL instance is null")?;
+        let mut message_window =
+            MessageWindow::new(hinst, text_service.this::<ITfTextInputProcessor>()?)?;
+        // if the server is not running yet, clicks are not available until the next activation
+        let ipc_service = IMEState::get()?.ipc_service.clone();
+        if let Some(ipc_service) = ipc_service {
+            message_window.subscribe_window_events(&ipc_service)?;
+        }
+        text_service.key_queue = Some(KeyQueue::new(message_window.notifier())?);
+        text_service.message_window = Some(message_window);
+
         // initialize key event sink
-        debug_log("Setting up key event sink...");
         tracing::debug!("AdviseKeyEventSink");
 
         unsafe {
@@ -84,7 +121,12 @@ impl ITfTextInputProcessor_Impl for TextServiceFactory_Impl {
                 BOOL::from(true),
             )?;
         };
-        debug_log("Key event sink setup complete");
+        tracing::debug!("Key event sink setup complete");
+
+        // shortcuts are registered after the key event sink
+        tracing::debug!("PreserveKey");
+        text_service.settings_key =
+            self.preserve_keys(&thread_mgr.cast::<ITfKeystrokeMgr>()?, tid)?;
 
         // initialize thread manager event sink
         tracing::debug!("AdviseThreadMgrEventSink");
@@ -98,13 +140,24 @@ impl ITfTextInputProcessor_Impl for TextServiceFactory_Impl {
                 .insert(ITfThreadMgrEventSink::IID, cookie);
         };
 
+        // the Hankaku/Zenkaku key only changes the open/close compartment
+        tracing::debug!("AdviseCompartmentEventSink");
+        unsafe {
+            let cookie = open_close(&thread_mgr)?.cast::<ITfSource>()?.AdviseSink(
+                &ITfCompartmentEventSink::IID,
+                &text_service.this::<ITfCompartmentEventSink>()?,
+            )?;
+            IMEState::get()?
+                .cookies
+                .insert(ITfCompartmentEventSink::IID, cookie);
+        };
+
         // Set default input mode to Kana (Japanese) when IME activates
         // This ensures Japanese input works immediately after switching to Azookey
         {
             use crate::engine::input_mode::InputMode;
             let mut ime_state = IMEState::get()?;
             ime_state.input_mode = InputMode::Kana;
-            debug_log("Set input mode to Kana");
             tracing::debug!("Set input mode to Kana");
         }
 
@@ -122,8 +175,14 @@ impl ITfTextInputProcessor_Impl for TextServiceFactory_Impl {
             let category_mgr: ITfCategoryMgr =
                 CoCreateInstance(&CLSID_TF_CategoryMgr, None, CLSCTX_INPROC_SERVER)?;
 
-            let atom = category_mgr.RegisterGUID(&GUID_DISPLAY_ATTRIBUTE)?;
-            map.insert(GUID_DISPLAY_ATTRIBUTE, atom);
+            for guid in [
+                GUID_DISPLAY_ATTRIBUTE_INPUT,
+                GUID_DISPLAY_ATTRIBUTE_CONVERTED,
+                GUID_DISPLAY_ATTRIBUTE_TARGET,
+            ] {
+                let atom = category_mgr.RegisterGUID(&guid)?;
+                map.insert(guid, atom);
+            }
             map
         };
 
@@ -165,6 +224,12 @@ impl ITfTextInputProcessor_Impl for TextServiceFactory_Impl {
 
             // end composition
             self.end_composition()?;
+            // other programs should not show the state of a deactivated text service
+            let _ = self.publish_status(false);
+
+            // remove shortcuts
+            tracing::debug!("UnpreserveKey");
+            self.unpreserve_keys(&thread_mgr.cast::<ITfKeystrokeMgr>()?)?;
 
             // remove key event sink
             tracing::debug!("UnadviseKeyEventSink");
@@ -185,13 +250,31 @@ impl ITfTextInputProcessor_Impl for TextServiceFactory_Impl {
         let mut text_service = self.borrow_mut()?;
         let thread_mgr = text_service.thread_mgr()?;
 
+        // stop the ipc worker
+        // dropping the sender ends the worker loop
+        tracing::debug!("Stop key queue");
+        text_service.key_queue = None;
+        text_service.message_window = None;
+        *text_service.borrow_mut_composition()? = Composition::default();
+
         // remove thread manager event sink
         tracing::debug!("UnadviseThreadMgrEventSink");
-        unsafe {
-            if let Some(cookie) = IMEState::get()?.cookies.remove(&ITfThreadMgrEventSink::IID) {
-                thread_mgr.cast::<ITfSource>()?.UnadviseSink(cookie)?;
-            }
-        };
+        let cookie = IMEState::get()?.cookies.remove(&ITfThreadMgrEventSink::IID);
+        if let Some(cookie) = cookie {
+            unsafe { thread_mgr.cast::<ITfSource>()?.UnadviseSink(cookie)? };
+        }
+
+        tracing::debug!("UnadviseCompartmentEventSink");
+        let cookie = IMEState::get()?
+            .cookies
+            .remove(&ITfCompartmentEventSink::IID);
+        if let Some(cookie) = cookie {
+            unsafe {
+                open_close(&thread_mgr)?
+                    .cast::<ITfSource>()?
+                    .UnadviseSink(cookie)?
+            };
+        }
 
         // remove text layout sink
         tracing::debug!("UnadviseTextLayoutSink");
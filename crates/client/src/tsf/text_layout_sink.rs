@@ -52,13 +52,17 @@ impl TextService {
     }
 
     pub fn unadvise_text_layout_sink(&mut self) -> Result<()> {
-        unsafe {
+        // the application may call back into the IME while the sink is removed
+        let (context, cookie) = {
             let mut state = IMEState::get()?;
+            let context = state.context.take();
+            let cookie = state.cookies.remove(&ITfTextLayoutSink::IID);
+            (context, cookie)
+        };
 
-            if let Some(context) = state.context.take() {
-                if let Some(cookie) = state.cookies.remove(&ITfTextLayoutSink::IID) {
-                    context.cast::<ITfSource>()?.UnadviseSink(cookie)?;
-                }
+        unsafe {
+            if let (Some(context), Some(cookie)) = (context, cookie) {
+                context.cast::<ITfSource>()?.UnadviseSink(cookie)?;
             }
 
             Ok(())
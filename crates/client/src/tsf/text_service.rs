@@ -1,16 +1,23 @@
 use std::{
-    cell::{Ref, RefCell, RefMut},
+    cell::{Cell, Ref, RefCell, RefMut},
     collections::HashMap,
 };
 
 use windows::{
     core::{Interface, GUID},
-    Win32::UI::TextServices::{ITfContext, ITfTextInputProcessor, ITfThreadMgr},
+    Win32::UI::TextServices::{
+        ITfComposition, ITfContext, ITfMouseTracker, ITfRange, ITfTextInputProcessor, ITfThreadMgr,
+        TF_PRESERVEDKEY,
+    },
 };
 
 use anyhow::{Context, Result};
 
-use crate::engine::{composition::Composition, input_mode::InputMode};
+use crate::engine::{
+    composition::Composition, input_mode::InputMode, key_queue::KeyQueue, thumb_shift::Pairing,
+};
+
+use super::message_window::MessageWindow;
 
 #[derive(Default, Debug)]
 pub struct TextService {
@@ -18,9 +25,24 @@ pub struct TextService {
     pub thread_mgr: Option<ITfThreadMgr>,
     pub context: Option<ITfContext>,
     pub composition: RefCell<Composition>,
+    pub tip_composition: RefCell<Option<ITfComposition>>,
+    // the tracker of the clicks on the composition and its cookie, see `advise_mouse_sink`
+    pub mouse_sink: RefCell<Option<(ITfMouseTracker, u32)>>,
+    // the buttons held at the last mouse event, so that a click is handled once
+    pub mouse_buttons: Cell<u32>,
+    // range of the text committed last, cleared when another key is pressed
+    pub last_commit_range: RefCell<Option<ITfRange>>,
+    // the text before the caret read after a clause is committed, sent with the next key
+    pub pending_context: RefCell<Option<String>>,
     pub display_attribute_atom: HashMap<GUID, u32>,
     pub mode: InputMode,
     pub this: Option<ITfTextInputProcessor>,
+    pub key_queue: Option<KeyQueue>,
+    pub message_window: Option<MessageWindow>,
+    // shortcut to open the settings app, registered in Activate
+    pub settings_key: Option<TF_PRESERVEDKEY>,
+    // the keys of the thumb-shift input waiting for their pair
+    pub thumb_shift: Pairing,
 }
 
 impl TextService {
@@ -2,7 +2,7 @@ use windows::Win32::UI::TextServices::{ITfContext, ITfDocumentMgr, ITfThreadMgrE
 
 use anyhow::Result;
 
-use crate::engine::{client_action::ClientAction, composition::CompositionState};
+use crate::engine::{app_quirks, client_action::ClientAction, composition::CompositionState};
 
 use super::factory::TextServiceFactory_Impl;
 
@@ -33,6 +33,9 @@ impl ITfThreadMgrEventSink_Impl for TextServiceFactory_Impl {
         let actions = vec![ClientAction::EndComposition];
         self.handle_action(&actions, CompositionState::None)?;
 
+        app_quirks::update_excluded()?;
+        self.update_password_field(focus)?;
+
         Ok(())
     }
 
@@ -0,0 +1,292 @@
+// the mocked conversion server and candidate window, shared by the benchmarks and the tests
+// the bench feature is needed, see `azookey_windows::bench`
+
+use std::net::Ipv4Addr;
+use std::pin::Pin;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use azookey_windows::bench;
+use shared::proto::{
+    azookey_service_server::{AzookeyService, AzookeyServiceServer},
+    window_service_server::{WindowService, WindowServiceServer},
+    AppendTextRequest, AppendTextResponse, ClearTextRequest, ClearTextResponse, CommitEvent,
+    ComposingText, EmptyResponse, FlashModeIndicatorRequest, LearnCandidateRequest,
+    LearnCandidateResponse, MoveCursorRequest, MoveCursorResponse, PingRequest, PingResponse,
+    ReconvertRequest, ReconvertResponse, RemoveTextRequest, RemoveTextResponse,
+    ReportCommitResponse, ResizeSegmentRequest, ResizeSegmentResponse, SetCandidateRequest,
+    SetContextRequest, SetContextResponse, SetFontRequest, SetInputModeRequest, SetLatencyRequest,
+    SetLayoutRequest, SetPositionRequest, SetPredictionsRequest, SetSelectionRequest,
+    ShowPaletteRequest, ShrinkTextRequest, ShrinkTextResponse, Suggestion, UpdateConfigRequest,
+    UpdateConfigResponse, WindowEvent,
+};
+use shared::transport::{Tcp, Transport};
+use tokio::runtime::Runtime;
+use tokio_stream::Stream;
+use tonic::{transport::Server, Request, Response, Status};
+
+pub const MOCK_SERVER_PORT: u16 = 50151;
+const MOCK_UI_PORT: u16 = 50152;
+
+// answers immediately with the typed text as the only candidate, so only the client is measured
+#[derive(Debug, Default)]
+struct MockServer {
+    input: Mutex<String>,
+}
+
+impl MockServer {
+    fn composing_text(&self) -> Option<ComposingText> {
+        let input = self.input.lock().unwrap().clone();
+        Some(ComposingText {
+            hiragana: input.clone(),
+            suggestions: vec![Suggestion {
+                corresponding_count: input.chars().count() as i32,
+                text: input,
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl AzookeyService for MockServer {
+    async fn append_text(
+        &self,
+        request: Request<AppendTextRequest>,
+    ) -> Result<Response<AppendTextResponse>, Status> {
+        let text = request.into_inner().text_to_append;
+        self.input.lock().unwrap().push_str(&text);
+
+        Ok(Response::new(AppendTextResponse {
+            composing_text: self.composing_text(),
+        }))
+    }
+
+    async fn remove_text(
+        &self,
+        _request: Request<RemoveTextRequest>,
+    ) -> Result<Response<RemoveTextResponse>, Status> {
+        self.input.lock().unwrap().pop();
+
+        Ok(Response::new(RemoveTextResponse {
+            composing_text: self.composing_text(),
+        }))
+    }
+
+    async fn shrink_text(
+        &self,
+        _request: Request<ShrinkTextRequest>,
+    ) -> Result<Response<ShrinkTextResponse>, Status> {
+        Ok(Response::new(ShrinkTextResponse {
+            composing_text: self.composing_text(),
+        }))
+    }
+
+    async fn resize_segment(
+        &self,
+        _request: Request<ResizeSegmentRequest>,
+    ) -> Result<Response<ResizeSegmentResponse>, Status> {
+        Ok(Response::new(ResizeSegmentResponse {
+            composing_text: self.composing_text(),
+        }))
+    }
+
+    async fn move_cursor(
+        &self,
+        _request: Request<MoveCursorRequest>,
+    ) -> Result<Response<MoveCursorResponse>, Status> {
+        Ok(Response::new(MoveCursorResponse {
+            composing_text: self.composing_text(),
+        }))
+    }
+
+    async fn clear_text(
+        &self,
+        _request: Request<ClearTextRequest>,
+    ) -> Result<Response<ClearTextResponse>, Status> {
+        self.input.lock().unwrap().clear();
+        Ok(Response::new(ClearTextResponse {}))
+    }
+
+    async fn set_context(
+        &self,
+        _request: Request<SetContextRequest>,
+    ) -> Result<Response<SetContextResponse>, Status> {
+        Ok(Response::new(SetContextResponse::default()))
+    }
+
+    async fn update_config(
+        &self,
+        _request: Request<UpdateConfigRequest>,
+    ) -> Result<Response<UpdateConfigResponse>, Status> {
+        Ok(Response::new(UpdateConfigResponse::default()))
+    }
+
+    async fn learn_candidate(
+        &self,
+        _request: Request<LearnCandidateRequest>,
+    ) -> Result<Response<LearnCandidateResponse>, Status> {
+        Ok(Response::new(LearnCandidateResponse::default()))
+    }
+
+    async fn ping(&self, _request: Request<PingRequest>) -> Result<Response<PingResponse>, Status> {
+        Ok(Response::new(PingResponse::default()))
+    }
+
+    async fn report_commit(
+        &self,
+        _request: Request<CommitEvent>,
+    ) -> Result<Response<ReportCommitResponse>, Status> {
+        Ok(Response::new(ReportCommitResponse {}))
+    }
+
+    async fn reconvert(
+        &self,
+        request: Request<ReconvertRequest>,
+    ) -> Result<Response<ReconvertResponse>, Status> {
+        *self.input.lock().unwrap() = request.into_inner().text;
+
+        Ok(Response::new(ReconvertResponse {
+            composing_text: self.composing_text(),
+        }))
+    }
+}
+
+// candidate window which shows nothing
+struct MockUi;
+
+#[tonic::async_trait]
+impl WindowService for MockUi {
+    type SubscribeEventsStream = Pin<Box<dyn Stream<Item = Result<WindowEvent, Status>> + Send>>;
+
+    async fn show_window(
+        &self,
+        _request: Request<EmptyResponse>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn hide_window(
+        &self,
+        _request: Request<EmptyResponse>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_candidate(
+        &self,
+        _request: Request<SetCandidateRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_selection(
+        &self,
+        _request: Request<SetSelectionRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_predictions(
+        &self,
+        _request: Request<SetPredictionsRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_window_position(
+        &self,
+        _request: Request<SetPositionRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_input_mode(
+        &self,
+        _request: Request<SetInputModeRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn flash_mode_indicator(
+        &self,
+        _request: Request<FlashModeIndicatorRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_latency(
+        &self,
+        _request: Request<SetLatencyRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn toggle_detail(
+        &self,
+        _request: Request<EmptyResponse>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn show_palette(
+        &self,
+        _request: Request<ShowPaletteRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_font(
+        &self,
+        _request: Request<SetFontRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_layout(
+        &self,
+        _request: Request<SetLayoutRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn subscribe_events(
+        &self,
+        _request: Request<EmptyResponse>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        let events = tokio_stream::pending::<Result<WindowEvent, Status>>();
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+// the mocks run on their own runtime for the whole benchmark
+static MOCKS: LazyLock<Runtime> = LazyLock::new(|| {
+    let runtime = Runtime::new().expect("failed to create the runtime");
+
+    runtime.spawn(
+        Server::builder()
+            .add_service(AzookeyServiceServer::new(MockServer::default()))
+            .serve_with_incoming(Tcp::loopback(MOCK_SERVER_PORT).incoming()),
+    );
+    runtime.spawn(
+        Server::builder()
+            .add_service(WindowServiceServer::new(MockUi))
+            .serve_with_incoming(Tcp::loopback(MOCK_UI_PORT).incoming()),
+    );
+
+    // tcp has no retries, so wait until the listeners are up
+    for port in [MOCK_SERVER_PORT, MOCK_UI_PORT] {
+        while std::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port)).is_err() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    runtime
+});
+
+// connect the client to the server and the mocked candidate window
+pub fn connect(server: impl Transport) -> anyhow::Result<()> {
+    LazyLock::force(&MOCKS);
+    bench::connect(server, Tcp::loopback(MOCK_UI_PORT))
+}
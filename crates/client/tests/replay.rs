@@ -0,0 +1,60 @@
+// replay the keys of a session recorded with `debug.record_session` through the client
+//
+//   cargo test -p azookey-windows --features bench --test replay
+//   AZOOKEY_REPLAY=path/to/session.jsonl cargo test -p azookey-windows --features bench --test replay
+//
+// the keys go through the same path as in the IME against the mocked server, which converts
+// nothing, so the states after a conversion may differ from the recording and are only printed
+// the mode doesn't depend on the server, so it has to match
+
+mod common;
+
+use std::path::PathBuf;
+
+use azookey_windows::bench::Session;
+use common::{connect, MOCK_SERVER_PORT};
+use shared::recording::{read_session, SessionEvent};
+use shared::transport::Tcp;
+
+#[test]
+fn replay() {
+    let path = std::env::var("AZOOKEY_REPLAY").map_or_else(
+        |_| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/sessions/typing.jsonl"),
+        PathBuf::from,
+    );
+    let events = read_session(&path).expect("failed to read the session");
+
+    connect(Tcp::loopback(MOCK_SERVER_PORT)).expect("failed to connect to the mocks");
+    let mut session = Session::new().expect("failed to start the session");
+
+    for (line, event) in events.iter().enumerate() {
+        // the requests are replayed against the real server, see the server's replay test
+        let SessionEvent::Key {
+            wparam,
+            mode,
+            state,
+        } = event
+        else {
+            continue;
+        };
+
+        assert_eq!(
+            mode,
+            &session.mode().expect("failed to read the mode"),
+            "the mode differs before the key at line {}",
+            line + 1
+        );
+        if state != &session.state() {
+            println!(
+                "the state differs before the key at line {}:\n  recorded: {}\n  replayed: {}",
+                line + 1,
+                state,
+                session.state()
+            );
+        }
+
+        session
+            .press_key(*wparam)
+            .unwrap_or_else(|e| panic!("the key at line {} failed: {:?}", line + 1, e));
+    }
+}
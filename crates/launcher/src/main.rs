@@ -1,8 +1,16 @@
 use shared::AppConfig;
 use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
 use std::{env, thread};
 
+// wait before restarting the ui process, doubled on every crash in a row
+const UI_RESTART_DELAY: Duration = Duration::from_millis(500);
+const UI_MAX_RESTART_DELAY: Duration = Duration::from_secs(30);
+// the ui is considered healthy if it has been running for this long
+const UI_STABLE_DURATION: Duration = Duration::from_secs(60);
+
 fn main() -> anyhow::Result<()> {
     let config = AppConfig::new();
 
@@ -21,12 +29,19 @@ fn main() -> anyhow::Result<()> {
     new_path = format!("{};{}", backend_path_str, new_path);
     env::set_var("PATH", &new_path);
 
+    let ui_path = if config.ui.path.is_empty() {
+        exe_path.join("ui.exe")
+    } else {
+        PathBuf::from(&config.ui.path)
+    };
+
     let server_process = start_process("azookey-server.exe", "[server]");
-    let ui_process = start_process("ui.exe", "[ui]");
+    let ui_process = start_process(&ui_path.to_string_lossy(), "[ui]");
 
-    if let (Some(mut server), Some(mut ui)) = (server_process, ui_process) {
+    if let (Some(mut server), Some(ui)) = (server_process, ui_process) {
         let server_handle = thread::spawn(move || server.wait());
-        let ui_handle = thread::spawn(move || ui.wait());
+        let auto_restart = config.ui.auto_restart;
+        let ui_handle = thread::spawn(move || supervise_ui(ui, &ui_path, auto_restart));
 
         let _ = server_handle.join();
         let _ = ui_handle.join();
@@ -35,6 +50,34 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+// the client replays the window state when the ui comes back,
+// so restarting the process is enough to recover the candidate window
+fn supervise_ui(mut ui: Child, path: &Path, auto_restart: bool) {
+    let mut delay = UI_RESTART_DELAY;
+
+    loop {
+        let started_at = Instant::now();
+        let status = ui.wait();
+        println!("[launcher]: ui exited: {:?}", status);
+
+        if !auto_restart || matches!(&status, Ok(status) if status.success()) {
+            return;
+        }
+
+        if started_at.elapsed() >= UI_STABLE_DURATION {
+            delay = UI_RESTART_DELAY;
+        }
+        thread::sleep(delay);
+        delay = (delay * 2).min(UI_MAX_RESTART_DELAY);
+
+        println!("[launcher]: restarting ui");
+        match start_process(&path.to_string_lossy(), "[ui]") {
+            Some(child) => ui = child,
+            None => return,
+        }
+    }
+}
+
 fn start_process(exe: &str, prefix: &str) -> Option<Child> {
     let mut child = Command::new(exe)
         .stdout(Stdio::piped())
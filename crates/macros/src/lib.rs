@@ -33,7 +33,7 @@ fn extract_ok_type(return_type: &ReturnType) -> Result<&Type, TokenStream> {
 /// This macro wraps a function that returns a Result with an `anyhow::Result`.
 ///
 /// If the function returns `anyhow::Result<OkType>`, it will be converted to `windows::core::Result<OkType>`.
-///
+/// Panics are caught by `crate::panic_guard::catch` so that they never unwind into the caller.
 ///
 /// ```rust
 /// #[macros::anyhow]
@@ -44,7 +44,7 @@ fn extract_ok_type(return_type: &ReturnType) -> Result<&Type, TokenStream> {
 /// // will be converted to
 ///
 /// fn some_func() -> windows::core::Result<Sometype> {
-///   let result: anyhow::Result<Sometype> = (|| Ok(Sometype))();
+///   let result: anyhow::Result<Sometype> = crate::panic_guard::catch(|| Ok(Sometype));
 ///   match result {
 ///     Ok(v) => Ok(v),
 ///     Err(e) => {
@@ -83,7 +83,7 @@ pub fn anyhow(_: TokenStream, input: TokenStream) -> TokenStream {
     // generate the new function
     let generated = quote! {
         fn #fn_name(#fn_inputs) -> windows::core::Result<#output> {
-            let result: Result<#output> = (|| #fn_body)();
+            let result: Result<#output> = crate::panic_guard::catch(|| #fn_body);
 
             match result {
                 Ok(v) => Ok(v),
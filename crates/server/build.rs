@@ -1,8 +1,47 @@
 use std::env;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 fn main() {
     // link dll
     let project_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     println!("cargo:rustc-link-search={}/target/", project_dir);
     println!("cargo:rustc-link-lib=azookey-server");
+
+    // build info for the about page of the settings app
+    // rerun when a commit is made, since rerun-if-changed turns off the default check
+    println!("cargo:rerun-if-changed={}/../../.git/HEAD", project_dir);
+    println!("cargo:rerun-if-changed={}/../../.git/index", project_dir);
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=AZOOKEY_GIT_COMMIT={}", commit);
+
+    let build_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    println!("cargo:rustc-env=AZOOKEY_BUILD_TIME={}", build_time);
+
+    let resolved = format!("{}/../../server-swift/Package.resolved", project_dir);
+    println!("cargo:rerun-if-changed={}", resolved);
+    let kkc_revision = std::fs::read_to_string(&resolved)
+        .ok()
+        .and_then(|resolved| kkc_revision(&resolved))
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=AZOOKEY_KKC_REVISION={}", kkc_revision);
+}
+
+// revision of AzooKeyKanaKanjiConverter pinned in Package.resolved
+fn kkc_revision(resolved: &str) -> Option<String> {
+    let pin = resolved
+        .split("\"identity\"")
+        .find(|pin| pin.contains("azookeykanakanjiconverter"))?;
+    let revision = pin.split("\"revision\"").nth(1)?;
+    let revision = revision.split('"').nth(1)?;
+    Some(revision.chars().take(7).collect())
 }
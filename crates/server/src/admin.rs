@@ -0,0 +1,82 @@
+use tonic::{Request, Response, Status};
+
+use shared::proto::admin_service_server::AdminService;
+use shared::proto::{
+    BuildInfo, ExportDataRequest, ExportDataResponse, GetBuildInfoRequest,
+    ReloadDictionariesRequest, ReloadDictionariesResponse, SubscribeCommitsRequest, TailLogRequest,
+};
+
+use crate::commit_events::{self, CommitStream};
+use crate::log_tail::{self, LogStream};
+
+// the model format passed to the converter, see `versionDependentMode` in azookey_server.swift
+const ZENZAI_VERSION: &str = "v3";
+
+// requests from the settings app
+#[derive(Debug, Default)]
+pub struct MyAdminService;
+
+#[tonic::async_trait]
+impl AdminService for MyAdminService {
+    type TailLogStream = LogStream;
+    type SubscribeCommitsStream = CommitStream;
+
+    async fn tail_log(
+        &self,
+        request: Request<TailLogRequest>,
+    ) -> Result<Response<Self::TailLogStream>, Status> {
+        log_tail::tail_log(request.into_inner()).map(Response::new)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn reload_dictionaries(
+        &self,
+        _request: Request<ReloadDictionariesRequest>,
+    ) -> Result<Response<ReloadDictionariesResponse>, Status> {
+        // building the new dictionaries takes a while, so keep it off the async workers
+        let user_entries = tokio::task::spawn_blocking(|| unsafe { crate::ReloadDictionaries() })
+            .await
+            .map_err(|e| Status::internal(format!("failed to reload dictionaries: {}", e)))?;
+
+        tracing::info!("Reloaded dictionaries, {} user entries", user_entries);
+        Ok(Response::new(ReloadDictionariesResponse {
+            user_entries: user_entries.max(0) as u32,
+        }))
+    }
+
+    async fn get_build_info(
+        &self,
+        _request: Request<GetBuildInfoRequest>,
+    ) -> Result<Response<BuildInfo>, Status> {
+        let build_time = env!("AZOOKEY_BUILD_TIME").parse().unwrap_or(0);
+        let build_date = chrono::DateTime::from_timestamp(build_time, 0)
+            .map(|date| date.with_timezone(&chrono::Local).to_rfc3339())
+            .unwrap_or_default();
+        let zenzai = shared::AppConfig::try_read().unwrap_or_default().zenzai;
+
+        Ok(Response::new(BuildInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: env!("AZOOKEY_GIT_COMMIT").to_string(),
+            build_date,
+            kkc_revision: env!("AZOOKEY_KKC_REVISION").to_string(),
+            zenzai_version: ZENZAI_VERSION.to_string(),
+            backend: zenzai.backend,
+            zenzai_enabled: zenzai.enable,
+        }))
+    }
+
+    async fn subscribe_commits(
+        &self,
+        _request: Request<SubscribeCommitsRequest>,
+    ) -> Result<Response<Self::SubscribeCommitsStream>, Status> {
+        Ok(Response::new(commit_events::subscribe()))
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn export_data(
+        &self,
+        request: Request<ExportDataRequest>,
+    ) -> Result<Response<ExportDataResponse>, Status> {
+        crate::export::export(request.into_inner()).map(Response::new)
+    }
+}
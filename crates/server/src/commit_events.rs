@@ -0,0 +1,43 @@
+use std::pin::Pin;
+use std::sync::OnceLock;
+
+use async_stream::stream;
+use futures_core::Stream;
+use tokio::sync::broadcast;
+use tonic::Status;
+
+use shared::proto::CommitEvent;
+
+// commits reported by the clients, passed on to the automations subscribed to them
+// nothing is kept, so a subscriber only sees the commits after it subscribed
+static COMMITS: OnceLock<broadcast::Sender<CommitEvent>> = OnceLock::new();
+
+fn sender() -> &'static broadcast::Sender<CommitEvent> {
+    COMMITS.get_or_init(|| broadcast::channel(64).0)
+}
+
+pub fn publish(mut event: CommitEvent) {
+    event.timestamp = chrono::Local::now().to_rfc3339();
+    // there may be no subscriber, so ignore the error
+    let _ = sender().send(event);
+}
+
+pub type CommitStream = Pin<Box<dyn Stream<Item = Result<CommitEvent, Status>> + Send>>;
+
+pub fn subscribe() -> CommitStream {
+    let mut receiver = sender().subscribe();
+
+    let events = stream! {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => yield Ok(event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("A commit subscriber lagged behind, {} commits skipped", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    };
+
+    Box::pin(events)
+}
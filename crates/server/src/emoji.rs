@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::LazyLock;
+
+use shared::proto::{suggestion::Source, Suggestion};
+
+// emoji for a whole reading, e.g. ねこ, added after the candidates of the converter
+// emoji.tsv has a "reading<TAB>emoji emoji ..." line per reading, lines starting with # are comments
+fn parse(content: &str) -> HashMap<&str, Vec<&str>> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (reading, emoji) = line.split_once('\t')?;
+            Some((reading, emoji.split_whitespace().collect()))
+        })
+        .collect()
+}
+
+static TABLE: LazyLock<HashMap<&'static str, Vec<&'static str>>> =
+    LazyLock::new(|| parse(include_str!("emoji/emoji.tsv")));
+
+// `CandidateConfig.emoji`, kept here so that the config isn't read on every key
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+// called on start and on every config update
+pub fn reload() {
+    let enabled = shared::AppConfig::try_read()
+        .unwrap_or_default()
+        .candidate
+        .emoji;
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn extend(suggestions: &mut Vec<Suggestion>, reading: &str) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(emoji) = TABLE.get(reading) else {
+        return;
+    };
+
+    for emoji in emoji {
+        if suggestions.iter().any(|s| s.text == *emoji) {
+            continue;
+        }
+        let mut suggestion = Suggestion {
+            text: emoji.to_string(),
+            subtext: String::new(),
+            corresponding_count: reading.chars().count() as i32,
+            annotation: "絵文字".to_string(),
+            reading: reading.to_string(),
+            ..Default::default()
+        };
+        suggestion.set_source(Source::Emoji);
+        suggestions.push(suggestion);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_table() {
+        let table = parse("# comment\nねこ\t🐱 🐈\nbroken\n");
+        assert_eq!(table.len(), 1);
+        assert_eq!(table["ねこ"], ["🐱", "🐈"]);
+    }
+
+    #[test]
+    fn shipped_table() {
+        assert!(TABLE["えもじ"].len() > 1);
+        assert!(TABLE.values().all(|emoji| !emoji.is_empty()));
+    }
+
+    #[test]
+    fn emoji_after_the_candidates() {
+        let mut suggestions = vec![Suggestion {
+            text: "猫".to_string(),
+            ..Default::default()
+        }];
+        extend(&mut suggestions, "ねこ");
+        assert_eq!(suggestions[0].text, "猫");
+        assert_eq!(suggestions[1].text, "🐱");
+        assert_eq!(suggestions[1].source(), Source::Emoji);
+
+        let mut suggestions = vec![];
+        extend(&mut suggestions, "ねこじゃない");
+        assert!(suggestions.is_empty());
+    }
+}
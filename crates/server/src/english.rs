@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::LazyLock;
+
+use shared::proto::{suggestion::Source, Suggestion};
+
+// English words typed without leaving the kana mode, e.g. apple composed as あっｐぇ
+// the romaji is spelled again from the reading, and the spellings found in english.tsv are
+// added after the candidates with their katakana
+// only readings with letters left over are looked up, the converter handles the rest
+
+// the spellings of each kana, the ones typed more often first
+const KANA: &[(char, &[&str])] = &[
+    ('あ', &["a"]),
+    ('い', &["i", "yi"]),
+    ('う', &["u", "wu"]),
+    ('え', &["e"]),
+    ('お', &["o"]),
+    ('か', &["ka", "ca"]),
+    ('き', &["ki"]),
+    ('く', &["ku", "cu", "qu"]),
+    ('け', &["ke"]),
+    ('こ', &["ko", "co"]),
+    ('が', &["ga"]),
+    ('ぎ', &["gi"]),
+    ('ぐ', &["gu"]),
+    ('げ', &["ge"]),
+    ('ご', &["go"]),
+    ('さ', &["sa"]),
+    ('し', &["si", "shi", "ci"]),
+    ('す', &["su"]),
+    ('せ', &["se", "ce"]),
+    ('そ', &["so"]),
+    ('ざ', &["za"]),
+    ('じ', &["zi", "ji"]),
+    ('ず', &["zu"]),
+    ('ぜ', &["ze"]),
+    ('ぞ', &["zo"]),
+    ('た', &["ta"]),
+    ('ち', &["ti", "chi"]),
+    ('つ', &["tu", "tsu"]),
+    ('て', &["te"]),
+    ('と', &["to"]),
+    ('だ', &["da"]),
+    ('ぢ', &["di"]),
+    ('づ', &["du"]),
+    ('で', &["de"]),
+    ('ど', &["do"]),
+    ('な', &["na"]),
+    ('に', &["ni"]),
+    ('ぬ', &["nu"]),
+    ('ね', &["ne"]),
+    ('の', &["no"]),
+    ('は', &["ha"]),
+    ('ひ', &["hi"]),
+    ('ふ', &["hu", "fu"]),
+    ('へ', &["he"]),
+    ('ほ', &["ho"]),
+    ('ば', &["ba"]),
+    ('び', &["bi"]),
+    ('ぶ', &["bu"]),
+    ('べ', &["be"]),
+    ('ぼ', &["bo"]),
+    ('ぱ', &["pa"]),
+    ('ぴ', &["pi"]),
+    ('ぷ', &["pu"]),
+    ('ぺ', &["pe"]),
+    ('ぽ', &["po"]),
+    ('ま', &["ma"]),
+    ('み', &["mi"]),
+    ('む', &["mu"]),
+    ('め', &["me"]),
+    ('も', &["mo"]),
+    ('や', &["ya"]),
+    ('ゆ', &["yu"]),
+    ('よ', &["yo"]),
+    ('ら', &["ra"]),
+    ('り', &["ri"]),
+    ('る', &["ru"]),
+    ('れ', &["re"]),
+    ('ろ', &["ro"]),
+    ('わ', &["wa"]),
+    ('を', &["wo"]),
+    ('ん', &["n", "nn"]),
+    ('ゔ', &["vu"]),
+    ('ぁ', &["la", "xa"]),
+    ('ぃ', &["li", "xi"]),
+    ('ぅ', &["lu", "xu"]),
+    ('ぇ', &["le", "xe"]),
+    ('ぉ', &["lo", "xo"]),
+    ('ゃ', &["lya", "xya"]),
+    ('ゅ', &["lyu", "xyu"]),
+    ('ょ', &["lyo", "xyo"]),
+    ('ー', &["-"]),
+];
+
+// spellings are multiplied by every ambiguous kana, so long readings stop here
+const MAX_SPELLINGS: usize = 256;
+
+// a letter typed in the kana mode, left as it is by the converter
+fn latin(c: char) -> Option<char> {
+    let c = match c {
+        'ａ'..='ｚ' | 'Ａ'..='Ｚ' => char::from_u32(c as u32 - 0xFEE0)?,
+        _ => c,
+    };
+    c.is_ascii_alphabetic().then(|| c.to_ascii_lowercase())
+}
+
+fn kana(c: char) -> Option<&'static [&'static str]> {
+    KANA.iter()
+        .find(|(kana, _)| *kana == c)
+        .map(|(_, spellings)| *spellings)
+}
+
+// the spellings of a kana read together with the small kana after it, e.g. kya for きゃ
+// and che for ちぇ, None if they are not read together
+fn contracted(spellings: &[&str], small: char) -> Option<Vec<String>> {
+    let (vowel, y) = match small {
+        'ゃ' => ("a", true),
+        'ゅ' => ("u", true),
+        'ょ' => ("o", true),
+        'ぁ' => ("a", false),
+        'ぃ' => ("i", false),
+        'ぅ' => ("u", false),
+        'ぇ' => ("e", false),
+        'ぉ' => ("o", false),
+        _ => return None,
+    };
+
+    let mut contracted = vec![];
+    for spelling in spellings {
+        if let Some(stem) = spelling.strip_suffix('i') {
+            if y {
+                contracted.push(format!("{stem}y{vowel}"));
+            }
+            if stem.ends_with('h') || stem == "j" {
+                contracted.push(format!("{stem}{vowel}"));
+            }
+        } else if let Some(stem) = spelling.strip_suffix('u') {
+            // ふぁ is fa, and うぇ is we
+            let stem = if stem.is_empty() { "w" } else { stem };
+            if !y && ["f", "v", "ts", "q", "w"].contains(&stem) {
+                contracted.push(format!("{stem}{vowel}"));
+            }
+        }
+    }
+    if contracted.is_empty() {
+        return None;
+    }
+
+    // a small vowel may also have been typed on its own, like the le of apple
+    if !y {
+        for spelling in spellings {
+            for small in kana(small)? {
+                contracted.push(format!("{spelling}{small}"));
+            }
+        }
+    }
+    contracted.sort();
+    contracted.dedup();
+    Some(contracted)
+}
+
+// the romaji which may have been typed for the reading, None if it has anything but kana and
+// letters
+fn spellings(reading: &str) -> Option<Vec<String>> {
+    let chars: Vec<char> = reading.chars().collect();
+    let mut spellings = vec![String::new()];
+    // っ doubles the consonant after it
+    let mut double = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        if c == 'っ' {
+            double = true;
+            continue;
+        }
+
+        let mut parts: Vec<String> = match latin(c) {
+            Some(letter) => vec![letter.to_string()],
+            None => {
+                let spellings = kana(c)?;
+                match chars.get(i).and_then(|&small| contracted(spellings, small)) {
+                    Some(contracted) => {
+                        i += 1;
+                        contracted
+                    }
+                    None => spellings.iter().map(|s| s.to_string()).collect(),
+                }
+            }
+        };
+        if std::mem::take(&mut double) {
+            parts = parts
+                .into_iter()
+                .filter_map(|part| {
+                    let first = part.chars().next()?;
+                    (!"aiueon-".contains(first)).then(|| format!("{first}{part}"))
+                })
+                .collect();
+        }
+
+        spellings = spellings
+            .iter()
+            .flat_map(|spelling| parts.iter().map(move |part| format!("{spelling}{part}")))
+            .collect();
+        if spellings.is_empty() || spellings.len() > MAX_SPELLINGS {
+            return None;
+        }
+    }
+
+    (!double).then_some(spellings)
+}
+
+// "word<TAB>katakana" lines, lines starting with # are comments
+fn parse(content: &str) -> HashMap<String, (&str, &str)> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (word, katakana) = line.split_once('\t')?;
+            Some((word.to_lowercase(), (word, katakana)))
+        })
+        .collect()
+}
+
+static TABLE: LazyLock<HashMap<String, (&'static str, &'static str)>> =
+    LazyLock::new(|| parse(include_str!("english/english.tsv")));
+
+// `CandidateConfig.english`, kept here so that the config isn't read on every key
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+// called on start and on every config update
+pub fn reload() {
+    let enabled = shared::AppConfig::try_read()
+        .unwrap_or_default()
+        .candidate
+        .english;
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+// the words and their katakana for a reading, in the order of the spellings
+fn lookup(reading: &str) -> Vec<(&'static str, &'static str)> {
+    if !reading.chars().any(|c| latin(c).is_some()) {
+        return vec![];
+    }
+    let Some(spellings) = spellings(reading) else {
+        return vec![];
+    };
+
+    let mut words = vec![];
+    for spelling in spellings {
+        if let Some(&word) = TABLE.get(&spelling) {
+            if !words.contains(&word) {
+                words.push(word);
+            }
+        }
+    }
+    words
+}
+
+pub fn extend(suggestions: &mut Vec<Suggestion>, reading: &str) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    for (word, katakana) in lookup(reading) {
+        for (text, annotation) in [(word, "英単語"), (katakana, word)] {
+            if suggestions.iter().any(|s| s.text == text) {
+                continue;
+            }
+            let mut suggestion = Suggestion {
+                text: text.to_string(),
+                subtext: String::new(),
+                corresponding_count: reading.chars().count() as i32,
+                annotation: annotation.to_string(),
+                reading: reading.to_string(),
+                ..Default::default()
+            };
+            suggestion.set_source(Source::English);
+            suggestions.push(suggestion);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spell_readings() {
+        let apple = spellings("あっｐぇ").unwrap();
+        assert!(apple.contains(&"apple".to_string()));
+        assert!(spellings("しゃｒｐ")
+            .unwrap()
+            .contains(&"sharp".to_string()));
+        assert!(spellings("ちぇ").unwrap().contains(&"chile".to_string()));
+        assert!(spellings("漢ｘ").is_none());
+    }
+
+    #[test]
+    fn english_words() {
+        assert_eq!(lookup("あっｐぇ"), [("apple", "アップル")]);
+        assert_eq!(lookup("てｓｔ"), [("test", "テスト")]);
+        assert_eq!(lookup("えんｇぃｓｈ"), [("english", "イングリッシュ")]);
+        assert_eq!(lookup("ちぇｃｋ"), [("check", "チェック")]);
+        // the converter finds the words without letters left over, e.g. てすと
+        assert!(lookup("てすと").is_empty());
+        assert!(lookup("ｘｙｚ").is_empty());
+    }
+
+    #[test]
+    fn words_after_the_candidates() {
+        let mut suggestions = vec![Suggestion {
+            text: "テスト".to_string(),
+            ..Default::default()
+        }];
+        extend(&mut suggestions, "てｓｔ");
+        let texts: Vec<_> = suggestions.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, ["テスト", "test"]);
+        assert_eq!(suggestions[1].source(), Source::English);
+    }
+}
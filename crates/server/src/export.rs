@@ -0,0 +1,202 @@
+use tonic::Status;
+
+use shared::proto::{
+    export_data_request::Format, ExportDataRequest, ExportDataResponse, ExportedFile,
+};
+
+use crate::stats;
+
+// a table of the export, a csv file or a key of the json
+struct Table {
+    name: &'static str,
+    columns: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+// the candidates learned so far, logged by the converter next to its own data
+fn learning() -> Table {
+    let log = std::fs::read_to_string(shared::get_memory_dir().join("learning_log.tsv"))
+        .unwrap_or_default();
+    let rows = log
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let time = fields.next()?.parse().ok()?;
+            let ruby = fields.next()?;
+            let word = fields.next()?;
+
+            let learned_at = chrono::DateTime::from_timestamp(time, 0)?
+                .with_timezone(&chrono::Local)
+                .to_rfc3339();
+            Some(vec![learned_at, to_hiragana(ruby), word.to_string()])
+        })
+        .collect();
+
+    Table {
+        name: "learning",
+        columns: vec!["learned_at", "reading", "word"],
+        rows,
+    }
+}
+
+fn dictionary() -> Table {
+    let entries = shared::AppConfig::try_read()
+        .unwrap_or_default()
+        .dictionary
+        .entries;
+    let rows = entries
+        .into_iter()
+        .map(|entry| vec![entry.reading, entry.word, entry.note])
+        .collect();
+
+    Table {
+        name: "dictionary",
+        columns: vec!["reading", "word", "note"],
+        rows,
+    }
+}
+
+// a single row, so that each statistic can be selected like a column
+fn statistics() -> Table {
+    let (columns, row) = stats::snapshot().into_iter().unzip();
+
+    Table {
+        name: "statistics",
+        columns,
+        rows: vec![row],
+    }
+}
+
+// the ruby of the converter is in katakana
+pub(crate) fn to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+// the values of the kept columns
+fn pick<T>(values: Vec<T>, keep: &[bool]) -> Vec<T> {
+    values
+        .into_iter()
+        .zip(keep)
+        .filter(|(_, keep)| **keep)
+        .map(|(value, _)| value)
+        .collect()
+}
+
+// keep the selected columns, everything when nothing is selected
+fn select(tables: Vec<Table>, fields: &[String]) -> Result<Vec<Table>, Status> {
+    for field in fields {
+        let (name, column) = match field.split_once('.') {
+            Some((name, column)) => (name, Some(column)),
+            None => (field.as_str(), None),
+        };
+        let known = tables.iter().any(|table| {
+            table.name == name && column.iter().all(|column| table.columns.contains(column))
+        });
+        if !known {
+            return Err(Status::invalid_argument(format!(
+                "unknown field: {}",
+                field
+            )));
+        }
+    }
+    if fields.is_empty() {
+        return Ok(tables);
+    }
+
+    let selected = |table: &str, column: &str| {
+        fields
+            .iter()
+            .any(|field| field == table || *field == format!("{table}.{column}"))
+    };
+    let tables = tables
+        .into_iter()
+        .filter_map(|table| {
+            let keep: Vec<bool> = table
+                .columns
+                .iter()
+                .map(|column| selected(table.name, column))
+                .collect();
+            if !keep.contains(&true) {
+                return None;
+            }
+
+            Some(Table {
+                name: table.name,
+                columns: pick(table.columns, &keep),
+                rows: table.rows.into_iter().map(|row| pick(row, &keep)).collect(),
+            })
+        })
+        .collect();
+
+    Ok(tables)
+}
+
+fn to_json(tables: &[Table]) -> String {
+    let object: serde_json::Map<String, serde_json::Value> = tables
+        .iter()
+        .map(|table| {
+            let rows = table
+                .rows
+                .iter()
+                .map(|row| {
+                    let fields = table
+                        .columns
+                        .iter()
+                        .zip(row)
+                        .map(|(column, value)| (column.to_string(), value.clone().into()))
+                        .collect();
+                    serde_json::Value::Object(fields)
+                })
+                .collect();
+            (table.name.to_string(), serde_json::Value::Array(rows))
+        })
+        .collect();
+
+    serde_json::to_string_pretty(&object).unwrap_or_default()
+}
+
+fn to_csv(table: &Table) -> String {
+    // quoted only when needed, like most spreadsheets write it
+    let escape = |value: &str| {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+    let line = |values: Vec<String>| values.join(",") + "\r\n";
+
+    let mut csv = line(table.columns.iter().map(|c| escape(c)).collect());
+    for row in &table.rows {
+        csv += &line(row.iter().map(|value| escape(value)).collect());
+    }
+    csv
+}
+
+pub fn export(request: ExportDataRequest) -> Result<ExportDataResponse, Status> {
+    let tables = select(
+        vec![learning(), dictionary(), statistics()],
+        &request.fields,
+    )?;
+
+    let files = match request.format() {
+        Format::Json => vec![ExportedFile {
+            name: "azookey.json".to_string(),
+            content: to_json(&tables),
+        }],
+        Format::Csv => tables
+            .iter()
+            .map(|table| ExportedFile {
+                name: format!("{}.csv", table.name),
+                content: to_csv(table),
+            })
+            .collect(),
+    };
+
+    Ok(ExportDataResponse { files })
+}
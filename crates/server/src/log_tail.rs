@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::pin::Pin;
+use std::str::FromStr as _;
+use std::sync::{Mutex, OnceLock};
+
+use async_stream::stream;
+use futures_core::Stream;
+use tokio::sync::broadcast;
+use tonic::Status;
+use tracing::{field::Field, Event, Level, Subscriber};
+use tracing_subscriber::{layer::Context, Layer};
+
+use shared::proto::{LogLine, TailLogRequest};
+
+// lines kept for new subscribers
+const RECENT_LINES: usize = 1000;
+
+// recent log lines and the subscribers of new ones
+// the settings app reads them from here, so it doesn't have to open the rotated log files
+struct LogTail {
+    recent: Mutex<VecDeque<(Level, LogLine)>>,
+    sender: broadcast::Sender<(Level, LogLine)>,
+}
+
+static LOG_TAIL: OnceLock<LogTail> = OnceLock::new();
+
+fn log_tail() -> &'static LogTail {
+    LOG_TAIL.get_or_init(|| LogTail {
+        recent: Mutex::new(VecDeque::with_capacity(RECENT_LINES)),
+        sender: broadcast::channel(256).0,
+    })
+}
+
+// tracing layer which records the events passing the log filter
+pub struct TailLayer;
+
+impl<S: Subscriber> Layer<S> for TailLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let metadata = event.metadata();
+        let line = LogLine {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: visitor.message + &visitor.fields,
+        };
+
+        let tail = log_tail();
+        let mut recent = tail.recent.lock().unwrap_or_else(|e| e.into_inner());
+        if recent.len() == RECENT_LINES {
+            recent.pop_front();
+        }
+        recent.push_back((*metadata.level(), line.clone()));
+
+        // sent under the lock, see `tail_log`
+        // there may be no subscriber, so ignore the error
+        let _ = tail.sender.send((*metadata.level(), line));
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl tracing::field::Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+pub type LogStream = Pin<Box<dyn Stream<Item = Result<LogLine, Status>> + Send>>;
+
+// the recent lines at the requested level, followed by the new ones
+pub fn tail_log(request: TailLogRequest) -> Result<LogStream, Status> {
+    // more verbose levels compare greater, e.g. DEBUG > INFO
+    let max_level = if request.level.is_empty() {
+        Level::TRACE
+    } else {
+        Level::from_str(&request.level)
+            .map_err(|_| Status::invalid_argument(format!("unknown level: {}", request.level)))?
+    };
+
+    let tail = log_tail();
+    // lines are sent while the lock is held, so subscribing under it neither loses nor repeats one
+    let (backlog, mut receiver) = {
+        let recent = tail.recent.lock().unwrap_or_else(|e| e.into_inner());
+        let receiver = tail.sender.subscribe();
+        let lines: Vec<LogLine> = recent
+            .iter()
+            .filter(|(level, _)| *level <= max_level)
+            .map(|(_, line)| line.clone())
+            .collect();
+        let skip = lines.len().saturating_sub(request.backlog as usize);
+        (lines.into_iter().skip(skip).collect::<Vec<_>>(), receiver)
+    };
+
+    let lines = stream! {
+        for line in backlog {
+            yield Ok(line);
+        }
+
+        loop {
+            match receiver.recv().await {
+                Ok((level, line)) if level <= max_level => yield Ok(line),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    };
+
+    Ok(Box::pin(lines))
+}
@@ -1,19 +1,95 @@
-use azookey_server::TonicNamedPipeServer;
+use azookey_server::TransportServer;
 use tonic::{transport::Server, Request, Response, Status};
 use tonic_reflection::server::Builder as ReflectionBuilder;
+use tracing_subscriber::{
+    fmt::format::FmtSpan, layer::SubscriberExt as _, reload, util::SubscriberInitExt as _,
+    EnvFilter, Layer as _, Registry,
+};
 
+use admin::MyAdminService;
+use log_tail::TailLayer;
+use shared::proto::admin_service_server::AdminServiceServer;
 use shared::proto::azookey_service_server::{AzookeyService, AzookeyServiceServer};
 use shared::proto::{
     AppendTextRequest, AppendTextResponse, ClearTextRequest, ClearTextResponse, ComposingText,
     MoveCursorRequest, MoveCursorResponse, RemoveTextRequest, RemoveTextResponse,
-    ShrinkTextRequest, ShrinkTextResponse, Suggestion,
+    ResizeSegmentRequest, ResizeSegmentResponse, ShrinkTextRequest, ShrinkTextResponse, Suggestion,
 };
+use shared::transport::{check_tcp_token, create_tcp_token, Tcp};
 
 use std::ffi::{c_char, c_int, CStr, CString};
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+mod admin;
+mod commit_events;
+mod emoji;
+mod english;
+mod export;
+mod log_tail;
+mod reconvert;
+mod romaji;
+mod stats;
+mod typo;
 
 const USE_ZENZAI: bool = true;
 
+// used when the filter in the config is invalid
+const DEFAULT_LOG_FILTER: &str = "azookey_server=info";
+
+static LOG_FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+// counts the `UpdateConfig` calls, sent with every ping so that the clients notice them
+static CONFIG_VERSION: AtomicU64 = AtomicU64::new(0);
+
+fn build_log_filter(config: &shared::LogConfig) -> EnvFilter {
+    EnvFilter::try_new(&config.filter).unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER))
+}
+
+fn setup_logger() {
+    let config = shared::AppConfig::read().log;
+
+    let (filter, handle) = reload::Layer::new(build_log_filter(&config));
+    let _ = LOG_FILTER_HANDLE.set(handle);
+
+    // log how long each request takes, with the request id from the client
+    let stdout_layer = tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE);
+
+    let file_layer = if config.file {
+        let log_dir = shared::get_log_dir();
+        let _ = std::fs::create_dir_all(&log_dir);
+        let timestamp = chrono::Local::now().format("%Y-%m-%d-%H.%M.%S");
+        std::fs::File::create(log_dir.join(format!("server-{}.log", timestamp)))
+            .ok()
+            .map(|file| {
+                tracing_subscriber::fmt::layer()
+                    .with_span_events(FmtSpan::CLOSE)
+                    .with_ansi(false)
+                    .with_writer(Mutex::new(file))
+                    .boxed()
+            })
+    } else {
+        None
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(stdout_layer)
+        .with(file_layer)
+        .with(TailLayer)
+        .init();
+}
+
+fn reload_log_filter() {
+    let config = shared::AppConfig::read().log;
+    if let Some(handle) = LOG_FILTER_HANDLE.get() {
+        if let Err(e) = handle.reload(build_log_filter(&config)) {
+            tracing::warn!("Failed to reload log filter: {:?}", e);
+        }
+    }
+}
+
 struct RawComposingText {
     text: String,
     cursor: i8,
@@ -26,6 +102,8 @@ struct FFICandidate {
     subtext: *mut c_char,
     hiragana: *mut c_char,
     corresponding_count: c_int,
+    annotation: *mut c_char,
+    source: c_int,
 }
 
 unsafe extern "C" {
@@ -35,10 +113,14 @@ unsafe extern "C" {
     fn RemoveText(cursorPtr: *mut c_int) -> *mut c_char;
     fn MoveCursor(offset: c_int, cursorPtr: *mut c_int) -> *mut c_char;
     fn ShrinkText(offset: c_int) -> *mut c_char;
+    fn ResizeSegment(offset: c_int) -> *mut c_char;
     fn ClearText();
     fn GetComposedText(lengthPtr: *mut c_int) -> *mut *mut FFICandidate;
     fn LoadConfig();
     fn LearnCandidate(candidateIndex: c_int);
+    fn PredictText() -> *mut c_char;
+    fn ReloadDictionaries() -> c_int;
+    fn SetVocabularyPolicy(policy: c_int);
 }
 
 fn initialize(path: &str) {
@@ -48,6 +130,16 @@ fn initialize(path: &str) {
     }
 }
 
+// pass the policy to the converter, which reads only settings.json by itself
+// -1 leaves the vocabulary to the settings, 0 and 1 force it off and on
+fn apply_policy() {
+    let policy = match shared::policy::restricted_vocabulary() {
+        Some(restricted) => restricted as c_int,
+        None => -1,
+    };
+    unsafe { SetVocabularyPolicy(policy) };
+}
+
 fn add_text(input: &str) -> RawComposingText {
     unsafe {
         let input = CString::new(input).expect("CString::new failed");
@@ -117,11 +209,21 @@ fn get_composed_text() -> Vec<Suggestion> {
                 .to_string_lossy()
                 .into_owned();
             let corresponding_count = candidate.corresponding_count;
+            let annotation = CStr::from_ptr(candidate.annotation)
+                .to_string_lossy()
+                .into_owned();
+            let reading = CStr::from_ptr(candidate.hiragana)
+                .to_string_lossy()
+                .into_owned();
 
             let suggestion = Suggestion {
                 text,
                 subtext,
                 corresponding_count,
+                annotation,
+                reading,
+                // the swift side uses the same values as the proto
+                source: candidate.source,
             };
 
             // check if suggestions have the same text
@@ -138,6 +240,57 @@ fn get_composed_text() -> Vec<Suggestion> {
     }
 }
 
+// `CandidateConfig.predictions`, kept here so that the config isn't read on every key
+static PREDICTIONS: AtomicBool = AtomicBool::new(true);
+
+// called on start and on every config update
+fn reload_predictions() {
+    let enabled = shared::AppConfig::try_read()
+        .unwrap_or_default()
+        .candidate
+        .predictions;
+    PREDICTIONS.store(enabled, Ordering::Relaxed);
+}
+
+// completions found by the last conversion, sent as "text\treading" lines
+// the client doesn't show them when they are off, so they aren't looked up either
+fn predict() -> Vec<shared::proto::Prediction> {
+    if !PREDICTIONS.load(Ordering::Relaxed) {
+        return vec![];
+    }
+
+    let lines = unsafe { CStr::from_ptr(PredictText()).to_string_lossy().into_owned() };
+
+    lines
+        .lines()
+        .filter_map(|line| {
+            let (text, reading) = line.split_once('\t')?;
+            Some(shared::proto::Prediction {
+                text: text.to_string(),
+                reading: reading.to_string(),
+            })
+        })
+        .collect()
+}
+
+// the conversion of the composing text, with the romaji still waiting for the rest of an entry
+// of the user's table after it, see `romaji`
+fn composed(composing_text: RawComposingText) -> ComposingText {
+    let pending = romaji::pending();
+    let mut suggestions = get_composed_text();
+    emoji::extend(&mut suggestions, &composing_text.text);
+    english::extend(&mut suggestions, &composing_text.text);
+    for suggestion in &mut suggestions {
+        suggestion.subtext.push_str(&pending);
+    }
+
+    ComposingText {
+        hiragana: composing_text.text + &pending,
+        suggestions,
+        predictions: predict(),
+    }
+}
+
 fn shrink_text(offset: i8) -> RawComposingText {
     unsafe {
         let offset = c_int::from(offset);
@@ -152,78 +305,120 @@ fn shrink_text(offset: i8) -> RawComposingText {
     }
 }
 
+fn resize_segment(offset: i32) -> RawComposingText {
+    unsafe {
+        let result = ResizeSegment(offset);
+
+        let text = CStr::from_ptr(&*result as *const c_char).to_str().unwrap();
+
+        RawComposingText {
+            text: text.to_string(),
+            cursor: 0,
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct MyAzookeyService;
 
 #[tonic::async_trait]
 impl AzookeyService for MyAzookeyService {
+    #[tracing::instrument(skip_all, fields(request_id = shared::trace::request_id(&request)))]
     async fn append_text(
         &self,
         request: Request<AppendTextRequest>,
     ) -> Result<Response<AppendTextResponse>, Status> {
         let input = request.into_inner().text_to_append;
-        let composing_text = add_text(&input);
+        // the key before this one is taken back when it was typed by mistake, see `typo`
+        let (text, remove_previous) = typo::correct(&input);
+        if remove_previous && !romaji::remove() {
+            remove_text();
+        }
+        let composing_text = add_text(&romaji::feed(&text));
+        stats::count(stats::Counter::Typed, input.chars().count() as u64);
 
         Ok(Response::new(AppendTextResponse {
-            composing_text: Some(ComposingText {
-                hiragana: composing_text.text,
-                suggestions: get_composed_text().to_vec(),
-            }),
+            composing_text: Some(composed(composing_text)),
         }))
     }
 
+    #[tracing::instrument(skip_all, fields(request_id = shared::trace::request_id(&request)))]
     async fn remove_text(
         &self,
-        _: Request<RemoveTextRequest>,
+        request: Request<RemoveTextRequest>,
     ) -> Result<Response<RemoveTextResponse>, Status> {
-        let composing_text = remove_text();
+        typo::remove();
+        // the pending romaji has not reached the converter yet
+        let composing_text = if romaji::remove() {
+            add_text("")
+        } else {
+            remove_text()
+        };
+        stats::count(stats::Counter::Removed, 1);
 
         Ok(Response::new(RemoveTextResponse {
-            composing_text: Some(ComposingText {
-                hiragana: composing_text.text,
-                suggestions: get_composed_text().to_vec(),
-            }),
+            composing_text: Some(composed(composing_text)),
         }))
     }
 
+    #[tracing::instrument(skip_all, fields(request_id = shared::trace::request_id(&request)))]
     async fn move_cursor(
         &self,
         request: Request<MoveCursorRequest>,
     ) -> Result<Response<MoveCursorResponse>, Status> {
         let offset = request.into_inner().offset as i8;
+        typo::reset();
+        let pending = romaji::flush();
+        if !pending.is_empty() {
+            add_text(&pending);
+        }
         let composing_text = move_cursor(offset);
 
         Ok(Response::new(MoveCursorResponse {
-            composing_text: Some(ComposingText {
-                hiragana: composing_text.text,
-                suggestions: get_composed_text().to_vec(),
-            }),
+            composing_text: Some(composed(composing_text)),
         }))
     }
 
+    #[tracing::instrument(skip_all, fields(request_id = shared::trace::request_id(&request)))]
     async fn clear_text(
         &self,
-        _: Request<ClearTextRequest>,
+        request: Request<ClearTextRequest>,
     ) -> Result<Response<ClearTextResponse>, Status> {
         clear_text();
+        romaji::reset();
+        typo::reset();
         Ok(Response::new(ClearTextResponse {}))
     }
 
+    #[tracing::instrument(skip_all, fields(request_id = shared::trace::request_id(&request)))]
     async fn shrink_text(
         &self,
         request: Request<ShrinkTextRequest>,
     ) -> Result<Response<ShrinkTextResponse>, Status> {
         let offset = request.into_inner().offset as i8;
         let composing_text = shrink_text(offset);
+        typo::reset();
+        stats::count(stats::Counter::Shrunk, 1);
 
         Ok(Response::new(ShrinkTextResponse {
-            composing_text: Some(ComposingText {
-                hiragana: composing_text.text,
-                suggestions: get_composed_text().to_vec(),
-            }),
+            composing_text: Some(composed(composing_text)),
         }))
     }
 
+    #[tracing::instrument(skip_all, fields(request_id = shared::trace::request_id(&request)))]
+    async fn resize_segment(
+        &self,
+        request: Request<ResizeSegmentRequest>,
+    ) -> Result<Response<ResizeSegmentResponse>, Status> {
+        let offset = request.into_inner().offset;
+        let composing_text = resize_segment(offset);
+
+        Ok(Response::new(ResizeSegmentResponse {
+            composing_text: Some(composed(composing_text)),
+        }))
+    }
+
+    #[tracing::instrument(skip_all, fields(request_id = shared::trace::request_id(&request)))]
     async fn set_context(
         &self,
         request: Request<shared::proto::SetContextRequest>,
@@ -241,32 +436,127 @@ impl AzookeyService for MyAzookeyService {
         Ok(Response::new(shared::proto::SetContextResponse {}))
     }
 
+    #[tracing::instrument(skip_all, fields(request_id = shared::trace::request_id(&request)))]
     async fn update_config(
         &self,
-        _: Request<shared::proto::UpdateConfigRequest>,
+        request: Request<shared::proto::UpdateConfigRequest>,
     ) -> Result<Response<shared::proto::UpdateConfigResponse>, Status> {
         unsafe { LoadConfig() };
+        apply_policy();
+        romaji::reload();
+        emoji::reload();
+        english::reload();
+        typo::reload();
+        reload_predictions();
+        reload_log_filter();
+        CONFIG_VERSION.fetch_add(1, Ordering::Relaxed);
         Ok(Response::new(shared::proto::UpdateConfigResponse {}))
     }
 
+    #[tracing::instrument(skip_all, fields(request_id = shared::trace::request_id(&request)))]
     async fn learn_candidate(
         &self,
         request: Request<shared::proto::LearnCandidateRequest>,
     ) -> Result<Response<shared::proto::LearnCandidateResponse>, Status> {
         let candidate_index = request.into_inner().candidate_index;
         unsafe { LearnCandidate(candidate_index) };
+        stats::count(stats::Counter::Learned, 1);
         Ok(Response::new(shared::proto::LearnCandidateResponse {}))
     }
+
+    // not instrumented, since the clients send it every few hundred milliseconds
+    async fn ping(
+        &self,
+        _request: Request<shared::proto::PingRequest>,
+    ) -> Result<Response<shared::proto::PingResponse>, Status> {
+        Ok(Response::new(shared::proto::PingResponse {
+            config_version: CONFIG_VERSION.load(Ordering::Relaxed),
+        }))
+    }
+
+    // the text is not logged, it is only passed on to the subscribers
+    #[tracing::instrument(skip_all, fields(request_id = shared::trace::request_id(&request)))]
+    async fn report_commit(
+        &self,
+        request: Request<shared::proto::CommitEvent>,
+    ) -> Result<Response<shared::proto::ReportCommitResponse>, Status> {
+        commit_events::publish(request.into_inner());
+        Ok(Response::new(shared::proto::ReportCommitResponse {}))
+    }
+
+    // the text is not logged either, it may be anything the user selected
+    #[tracing::instrument(skip_all, fields(request_id = shared::trace::request_id(&request)))]
+    async fn reconvert(
+        &self,
+        request: Request<shared::proto::ReconvertRequest>,
+    ) -> Result<Response<shared::proto::ReconvertResponse>, Status> {
+        let text = request.into_inner().text;
+        let Some(reading) = reconvert::reading(&text) else {
+            return Err(Status::not_found("the reading of the text is not known"));
+        };
+
+        // the text is composed from scratch, whatever was composing before is gone
+        clear_text();
+        romaji::reset();
+        typo::reset();
+        let composing_text = add_text(&reading);
+
+        Ok(Response::new(shared::proto::ReconvertResponse {
+            composing_text: Some(composed(composing_text)),
+        }))
+    }
+}
+
+fn spawn_tcp_listener(port: u16, token: String) {
+    let transport = Tcp::loopback(port);
+    let check = check_tcp_token(token);
+    println!("AzookeyServer starting tcp listener on port {}...", port);
+
+    tokio::spawn(async move {
+        let result = Server::builder()
+            .add_service(AzookeyServiceServer::with_interceptor(
+                MyAzookeyService::default(),
+                check.clone(),
+            ))
+            .add_service(AdminServiceServer::with_interceptor(MyAdminService, check))
+            // the reflection only describes the services, so it works without the token
+            .add_service(
+                ReflectionBuilder::configure()
+                    .register_encoded_file_descriptor_set(shared::proto::FILE_DESCRIPTOR_SET)
+                    .build_v1()
+                    .unwrap(),
+            )
+            .serve_with_incoming(TransportServer::with_transport(&transport))
+            .await;
+
+        if let Err(e) = result {
+            println!("AzookeyServer tcp listener error: {:?}", e);
+        }
+    });
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    setup_logger();
+    stats::start();
+    romaji::reload();
+    emoji::reload();
+    english::reload();
+    typo::reload();
+    reload_predictions();
+
     println!("AzookeyServer started");
 
     // Kill existing server to allow clean restart
     let current_pid = std::process::id();
     let kill_result = Command::new("taskkill")
-        .args(["/F", "/IM", "azookey-server.exe", "/FI", &format!("PID ne {}", current_pid)])
+        .args([
+            "/F",
+            "/IM",
+            "azookey-server.exe",
+            "/FI",
+            &format!("PID ne {}", current_pid),
+        ])
         .output();
     if let Ok(output) = &kill_result {
         if output.status.success() {
@@ -279,6 +569,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let current_exe = std::env::current_exe()?;
     let parent_dir = current_exe.parent().unwrap();
     initialize(parent_dir.to_str().unwrap());
+    apply_policy();
+
+    // optional tcp listener for developer tools
+    // it is not served without the token, the clients use the named pipe anyway
+    let config = shared::AppConfig::read();
+    if config.ipc.tcp_listener {
+        match create_tcp_token() {
+            Ok(token) => spawn_tcp_listener(config.ipc.tcp_port, token),
+            Err(e) => println!("AzookeyServer failed to write the tcp token: {:?}", e),
+        }
+    }
 
     let service = MyAzookeyService::default();
 
@@ -286,13 +587,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let result = Server::builder()
         .add_service(AzookeyServiceServer::new(service))
+        .add_service(AdminServiceServer::new(MyAdminService))
         .add_service(
             ReflectionBuilder::configure()
                 .register_encoded_file_descriptor_set(shared::proto::FILE_DESCRIPTOR_SET)
                 .build_v1()
                 .unwrap(),
         )
-        .serve_with_incoming(TonicNamedPipeServer::new("azookey_server"))
+        .serve_with_incoming(TransportServer::new("azookey_server"))
         .await;
 
     match &result {
@@ -0,0 +1,102 @@
+use crate::export::to_hiragana;
+
+// the reading of committed text, so that it can be composed again
+// the converter can't read kanji back, so the words are taken from what it has learned and
+// from the user dictionary, the kana is read as it is
+//
+// (word, reading in hiragana)
+fn known_words() -> Vec<(String, String)> {
+    let log = std::fs::read_to_string(shared::get_memory_dir().join("learning_log.tsv"))
+        .unwrap_or_default();
+    let learned = log.lines().filter_map(|line| {
+        let mut fields = line.splitn(3, '\t');
+        let _time = fields.next()?;
+        let ruby = fields.next()?;
+        let word = fields.next()?;
+        Some((word.to_string(), to_hiragana(ruby)))
+    });
+
+    let entries = shared::AppConfig::try_read()
+        .unwrap_or_default()
+        .dictionary
+        .entries;
+    let dictionary = entries
+        .into_iter()
+        .map(|entry| (entry.word, to_hiragana(&entry.reading)));
+
+    learned.chain(dictionary).collect()
+}
+
+fn is_kanji(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{2FA1F}'
+            | '々'
+    )
+}
+
+// the longest known word is taken at each position, None if some kanji is left unread
+fn read(text: &str, words: &[(String, String)]) -> Option<String> {
+    let mut reading = String::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let word = words
+            .iter()
+            .filter(|(word, _)| !word.is_empty() && rest.starts_with(word.as_str()))
+            .max_by_key(|(word, _)| word.len());
+        match word {
+            Some((word, ruby)) => {
+                reading.push_str(ruby);
+                rest = &rest[word.len()..];
+            }
+            None if is_kanji(c) => return None,
+            // kana, symbols and alphanumerics are typed as they are
+            None => {
+                reading.push_str(&to_hiragana(&c.to_string()));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    Some(reading)
+}
+
+pub fn reading(text: &str) -> Option<String> {
+    read(text, &known_words())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words() -> Vec<(String, String)> {
+        [("東京", "とうきょう"), ("東", "ひがし"), ("行", "い")]
+            .iter()
+            .map(|(word, ruby)| (word.to_string(), ruby.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn longest_word_first() {
+        assert_eq!(
+            read("東京に行く", &words()),
+            Some("とうきょうにいく".to_string())
+        );
+        assert_eq!(read("東へ", &words()), Some("ひがしへ".to_string()));
+    }
+
+    #[test]
+    fn kana_is_read_as_it_is() {
+        assert_eq!(read("カタカナ", &[]), Some("かたかな".to_string()));
+        assert_eq!(read("ひらがな。", &[]), Some("ひらがな。".to_string()));
+    }
+
+    #[test]
+    fn unknown_kanji() {
+        assert_eq!(read("京都", &words()), None);
+    }
+}
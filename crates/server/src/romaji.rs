@@ -0,0 +1,308 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{LazyLock, Mutex, RwLock};
+
+use shared::RomajiLayout;
+
+// a romaji table of the user, which replaces the romaji to kana conversion of the converter
+// for its inputs, the other inputs are still converted by the converter's own rules
+//
+// romaji.tsv in the config dir has a "input<TAB>output" or "input<TAB>output<TAB>next" line
+// per entry like the tables of Google Japanese Input, `next` is typed again after the output
+// e.g. "kk<TAB>っ<TAB>k", lines starting with # are comments
+#[derive(Debug, Default)]
+pub struct RomajiTable {
+    entries: HashMap<String, Entry>,
+    // every shorter start of an input, so that the rest of it is waited for
+    prefixes: HashSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    output: String,
+    next: String,
+}
+
+impl RomajiTable {
+    pub fn parse(content: &str) -> Self {
+        let mut table = RomajiTable::default();
+
+        for line in content.lines() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            let (Some(input), Some(output)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let next = fields.next().unwrap_or_default();
+            // an entry which types itself again would never end
+            if input.is_empty() || next == input {
+                continue;
+            }
+
+            for (end, _) in input.char_indices().skip(1) {
+                table.prefixes.insert(input[..end].to_string());
+            }
+            table.entries.insert(
+                input.to_string(),
+                Entry {
+                    output: output.to_string(),
+                    next: next.to_string(),
+                },
+            );
+        }
+
+        table
+    }
+
+    // the entries of `other` replace the ones with the same input
+    pub fn extend(&mut self, other: RomajiTable) {
+        self.entries.extend(other.entries);
+        self.prefixes.extend(other.prefixes);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // pass the typed text through the table
+    // returns the text for the converter, the input which may still become a longer entry
+    // is kept in `pending` until the next keys
+    pub fn feed(&self, pending: &mut String, input: &str) -> String {
+        let mut output = String::new();
+        let mut queue: VecDeque<char> = input.chars().collect();
+
+        while let Some(c) = queue.pop_front() {
+            let mut typed = std::mem::take(pending);
+            typed.push(c);
+
+            if self.prefixes.contains(&typed) {
+                *pending = typed;
+                continue;
+            }
+            if let Some(entry) = self.entries.get(&typed) {
+                output.push_str(&entry.output);
+                for c in entry.next.chars().rev() {
+                    queue.push_front(c);
+                }
+                continue;
+            }
+
+            // the longer input didn't come, so what was typed before this key is settled
+            typed.pop();
+            if typed.is_empty() {
+                output.push(c);
+                continue;
+            }
+            queue.push_front(c);
+            match self.entries.get(&typed) {
+                Some(entry) => {
+                    output.push_str(&entry.output);
+                    for c in entry.next.chars().rev() {
+                        queue.push_front(c);
+                    }
+                }
+                // left to the converter as typed
+                None => output.push_str(&typed),
+            }
+        }
+
+        output
+    }
+}
+
+// the built-in tables of `AppConfig.romaji_layout`, in the format of romaji.tsv
+fn preset(layout: RomajiLayout) -> &'static str {
+    match layout {
+        RomajiLayout::Standard => "",
+        RomajiLayout::Azik => include_str!("romaji/azik.tsv"),
+        RomajiLayout::Symbols => include_str!("romaji/symbols.tsv"),
+    }
+}
+
+static TABLE: LazyLock<RwLock<RomajiTable>> = LazyLock::new(Default::default);
+
+// romaji typed since the last output of the table, shown after the converted text
+static PENDING: Mutex<String> = Mutex::new(String::new());
+
+// build the table again from the layout and romaji.tsv, called on start and on every config update
+pub fn reload() {
+    let layout = shared::AppConfig::try_read()
+        .unwrap_or_default()
+        .romaji_layout;
+    let mut table = RomajiTable::parse(preset(layout));
+
+    let user = match std::fs::read_to_string(shared::get_romaji_table_path()) {
+        Ok(content) => RomajiTable::parse(&content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => RomajiTable::default(),
+        Err(e) => {
+            tracing::warn!("Failed to read the romaji table: {:?}", e);
+            RomajiTable::default()
+        }
+    };
+    table.extend(user);
+    if !table.is_empty() {
+        tracing::info!(
+            "Romaji table loaded, {:?} layout, {} entries",
+            layout,
+            table.len()
+        );
+    }
+
+    *TABLE.write().unwrap_or_else(|e| e.into_inner()) = table;
+    reset();
+}
+
+pub fn feed(input: &str) -> String {
+    let table = TABLE.read().unwrap_or_else(|e| e.into_inner());
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    table.feed(&mut pending, input)
+}
+
+pub fn pending() -> String {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+// remove the last pending key, false if there is none and the converter has to remove it
+pub fn remove() -> bool {
+    PENDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .pop()
+        .is_some()
+}
+
+// the pending keys as typed, e.g. before the cursor moves
+pub fn flush() -> String {
+    std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+pub fn reset() {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // feed the keys one at a time like the client, returns the output and what is pending
+    fn type_keys(table: &RomajiTable, keys: &str) -> (String, String) {
+        let mut pending = String::new();
+        let output = keys
+            .chars()
+            .map(|c| table.feed(&mut pending, &c.to_string()))
+            .collect();
+        (output, pending)
+    }
+
+    #[test]
+    fn parse() {
+        let table = RomajiTable::parse("# comment\n\nkz\tかん\nkk\tっ\tk\nbroken\na\ta\ta\n");
+        assert_eq!(table.len(), 2);
+        assert!(table.prefixes.contains("k"));
+    }
+
+    #[test]
+    fn empty_table_passes_through() {
+        let table = RomajiTable::default();
+        assert_eq!(
+            type_keys(&table, "kanji"),
+            ("kanji".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn entry_replaces_the_keys() {
+        let table = RomajiTable::parse("kz\tかん\n");
+        assert_eq!(type_keys(&table, "kz"), ("かん".to_string(), String::new()));
+        assert_eq!(type_keys(&table, "k"), (String::new(), "k".to_string()));
+    }
+
+    #[test]
+    fn other_keys_are_left_to_the_converter() {
+        let table = RomajiTable::parse("kz\tかん\n");
+        assert_eq!(
+            type_keys(&table, "kakz"),
+            ("kaかん".to_string(), String::new())
+        );
+        assert_eq!(
+            type_keys(&table, "xkz"),
+            ("xかん".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn next_is_typed_again() {
+        let table = RomajiTable::parse("kk\tっ\tk\n");
+        assert_eq!(type_keys(&table, "kk"), ("っ".to_string(), "k".to_string()));
+        assert_eq!(
+            type_keys(&table, "kka"),
+            ("っka".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn shorter_entry_when_the_longer_one_does_not_come() {
+        let table = RomajiTable::parse("n\tん\nna\tな\n");
+        assert_eq!(type_keys(&table, "na"), ("な".to_string(), String::new()));
+        assert_eq!(type_keys(&table, "nk"), ("んk".to_string(), String::new()));
+        assert_eq!(type_keys(&table, "n"), (String::new(), "n".to_string()));
+    }
+
+    #[test]
+    fn whole_text_at_once() {
+        let table = RomajiTable::parse("kz\tかん\n");
+        let mut pending = String::new();
+        assert_eq!(table.feed(&mut pending, "kzji"), "かんji");
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn user_table_replaces_the_preset() {
+        let mut table = RomajiTable::parse(preset(RomajiLayout::Azik));
+        table.extend(RomajiTable::parse("kz\tかず\n"));
+        assert_eq!(type_keys(&table, "kz"), ("かず".to_string(), String::new()));
+        assert_eq!(type_keys(&table, "kp"), ("こう".to_string(), String::new()));
+    }
+
+    #[test]
+    fn standard_preset_is_empty() {
+        assert!(RomajiTable::parse(preset(RomajiLayout::Standard)).is_empty());
+    }
+
+    #[test]
+    fn azik_preset() {
+        let table = RomajiTable::parse(preset(RomajiLayout::Azik));
+        for (keys, output) in [
+            ("kz", "かん"),
+            ("kk", "きん"),
+            ("kp", "こう"),
+            ("sh", "すう"),
+            ("xp", "しょう"),
+            ("kyh", "きゅう"),
+            ("q", "ん"),
+            ("；", "っ"),
+        ] {
+            assert_eq!(type_keys(&table, keys), (output.to_string(), String::new()));
+        }
+        // the keys out of the table are still converted by the converter
+        assert_eq!(type_keys(&table, "kya"), ("kya".to_string(), String::new()));
+        assert_eq!(
+            type_keys(&table, "tokyo"),
+            ("tokyo".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn symbols_preset() {
+        let table = RomajiTable::parse(preset(RomajiLayout::Symbols));
+        for (keys, output) in [("zh", "←"), ("z。", "…"), ("zー", "〜"), ("z「", "『")] {
+            assert_eq!(type_keys(&table, keys), (output.to_string(), String::new()));
+        }
+        assert_eq!(type_keys(&table, "za"), ("za".to_string(), String::new()));
+    }
+}
@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+// typing statistics since the server started, for the export of the settings app
+// only the counts are kept, never the text
+static STARTED_AT: OnceLock<chrono::DateTime<chrono::Local>> = OnceLock::new();
+static TYPED: AtomicU64 = AtomicU64::new(0);
+static REMOVED: AtomicU64 = AtomicU64::new(0);
+static SHRUNK: AtomicU64 = AtomicU64::new(0);
+static LEARNED: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Counter {
+    // characters sent by the clients
+    Typed,
+    // characters removed with backspace
+    Removed,
+    // clauses committed before the rest of the reading
+    Shrunk,
+    // candidates learned
+    Learned,
+}
+
+pub fn start() {
+    STARTED_AT.get_or_init(chrono::Local::now);
+}
+
+pub fn count(counter: Counter, n: u64) {
+    let counter = match counter {
+        Counter::Typed => &TYPED,
+        Counter::Removed => &REMOVED,
+        Counter::Shrunk => &SHRUNK,
+        Counter::Learned => &LEARNED,
+    };
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+// the statistics as (name, value), in the order of the export
+pub fn snapshot() -> Vec<(&'static str, String)> {
+    let started_at = STARTED_AT
+        .get()
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default();
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed).to_string();
+
+    vec![
+        ("started_at", started_at),
+        ("typed_characters", load(&TYPED)),
+        ("removed_characters", load(&REMOVED)),
+        ("committed_clauses", load(&SHRUNK)),
+        ("learned_candidates", load(&LEARNED)),
+    ]
+}
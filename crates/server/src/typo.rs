@@ -0,0 +1,176 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+// correction of the romaji typed by mistake, see `ConversionConfig.typo_correction`
+// a consonant which can't start a syllable with the next one is a stray key and is dropped,
+// unless the two make a syllable the other way around, e.g. hs for the sh of shi
+// the keys are corrected as they come, so only the mistakes seen at the next key are found
+
+// two consonants which start a syllable together, like the s and h of shi
+const PAIRS: &[&str] = &[
+    "by", "ch", "cy", "dh", "dw", "dy", "fw", "fy", "gw", "gy", "hy", "jy", "kw", "ky", "lk", "lt",
+    "lw", "ly", "my", "ny", "py", "qw", "qy", "ry", "sh", "sw", "sy", "th", "ts", "tw", "ty", "vy",
+    "wh", "wy", "xk", "xt", "xw", "xy", "zy",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fix {
+    // the previous key was typed by mistake
+    Drop,
+    // the previous key belongs after this one
+    Swap,
+}
+
+fn is_consonant(c: char) -> bool {
+    c.is_ascii_lowercase() && !"aiueo".contains(c)
+}
+
+fn is_pair(first: char, second: char) -> bool {
+    PAIRS.contains(&format!("{first}{second}").as_str())
+}
+
+// the fix of `previous` when `typed` comes after it, `before` is the key before `previous`
+pub fn fix(before: Option<char>, previous: char, typed: char) -> Option<Fix> {
+    // a doubled consonant is っ, and n before a consonant is ん
+    if !is_consonant(previous) || !is_consonant(typed) || previous == typed || previous == 'n' {
+        return None;
+    }
+    // only the first consonant of a syllable can be a stray key
+    let starts_syllable = match before {
+        Some(before) if is_consonant(before) => {
+            before == previous || (before == 'n' && previous != 'y')
+        }
+        _ => true,
+    };
+    if !starts_syllable || is_pair(previous, typed) {
+        return None;
+    }
+
+    if "hwy".contains(previous) && is_pair(typed, previous) {
+        Some(Fix::Swap)
+    } else {
+        Some(Fix::Drop)
+    }
+}
+
+// `ConversionConfig.typo_correction`, kept here so that the config isn't read on every key
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// the keys of the composition, to find out what the next key means
+static KEYS: Mutex<Vec<char>> = Mutex::new(vec![]);
+
+// called on start and on every config update
+pub fn reload() {
+    let enabled = shared::AppConfig::try_read()
+        .unwrap_or_default()
+        .conversion
+        .typo_correction;
+    ENABLED.store(enabled, Ordering::Relaxed);
+    reset();
+}
+
+// the text to type instead of `input`, and whether the key before it has to be removed first
+// the client sends one key at a time, longer inputs are typed as they are
+pub fn correct(input: &str) -> (String, bool) {
+    let mut keys = KEYS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut chars = input.chars();
+    let typed = match (chars.next(), chars.next()) {
+        (Some(typed), None) if ENABLED.load(Ordering::Relaxed) => typed,
+        _ => {
+            keys.extend(input.chars());
+            return (input.to_string(), false);
+        }
+    };
+
+    let fix = match keys.as_slice() {
+        [.., before, previous] => fix(Some(*before), *previous, typed),
+        [previous] => fix(None, *previous, typed),
+        [] => None,
+    };
+    let previous = match fix {
+        Some(_) => keys.pop(),
+        None => None,
+    };
+    keys.push(typed);
+
+    match (fix, previous) {
+        (Some(Fix::Swap), Some(previous)) => {
+            tracing::debug!("Swapped a typo: {}{}", previous, typed);
+            keys.push(previous);
+            (format!("{typed}{previous}"), true)
+        }
+        (Some(Fix::Drop), Some(previous)) => {
+            tracing::debug!("Dropped a typo: {}{}", previous, typed);
+            (typed.to_string(), true)
+        }
+        _ => (typed.to_string(), false),
+    }
+}
+
+pub fn remove() {
+    KEYS.lock().unwrap_or_else(|e| e.into_inner()).pop();
+}
+
+// the composition is gone or changed, e.g. after a clause was committed
+pub fn reset() {
+    KEYS.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the romaji after fixing the keys one at a time, like `correct`
+    fn fixed(keys: &str) -> String {
+        let mut typed = String::new();
+        for c in keys.chars() {
+            let mut chars = typed.chars().rev();
+            let (previous, before) = (chars.next(), chars.next());
+            match previous.and_then(|previous| fix(before, previous, c)) {
+                Some(Fix::Drop) => {
+                    typed.pop();
+                    typed.push(c);
+                }
+                Some(Fix::Swap) => {
+                    let previous = typed.pop().unwrap();
+                    typed.push(c);
+                    typed.push(previous);
+                }
+                None => typed.push(c),
+            }
+        }
+        typed
+    }
+
+    #[test]
+    fn stray_keys() {
+        assert_eq!(fixed("kotnoha"), "konoha");
+        assert_eq!(fixed("sajkura"), "sakura");
+        assert_eq!(fixed("fgakkou"), "gakkou");
+    }
+
+    #[test]
+    fn transposed_keys() {
+        assert_eq!(fixed("watahsi"), "watashi");
+        assert_eq!(fixed("ykou"), "kyou");
+        assert_eq!(fixed("ohca"), "ocha");
+    }
+
+    #[test]
+    fn valid_romaji() {
+        for romaji in [
+            "konnnichiha",
+            "kyouha",
+            "tsukue",
+            "kitte",
+            "sanpo",
+            "kannji",
+            "xtsu",
+            "thi",
+            "nyanko",
+            "sshi",
+        ] {
+            assert_eq!(fixed(romaji), romaji);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+// starting the real server for the tests which talk to it through the named pipe
+
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use shared::proto::azookey_service_client::AzookeyServiceClient;
+use shared::transport::{channel, NamedPipe};
+use tonic::transport::Channel;
+
+pub type Client = AzookeyServiceClient<Channel>;
+
+// killed when the test ends, even if it panics
+pub struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+pub async fn start_server() -> (Server, Client) {
+    let child = Command::new(env!("CARGO_BIN_EXE_azookey-server"))
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("failed to start the server");
+    let server = Server(child);
+
+    // loading the dictionaries and the model takes a while
+    let transport = NamedPipe::new("azookey_server").with_retries(600, Duration::from_millis(100));
+    let channel = channel("http://[::]:50051", transport)
+        .await
+        .expect("failed to connect to the server");
+
+    (server, AzookeyServiceClient::new(channel))
+}
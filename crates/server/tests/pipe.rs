@@ -0,0 +1,170 @@
+// end-to-end scenarios against the real server, through the same named pipe and channel as the client
+//
+// the server needs the conversion engine next to the executable, and it replaces any running
+// server when it starts, so these are not run by default:
+//   cargo test -p azookey-server --test pipe -- --ignored
+
+mod common;
+
+use common::{start_server, Client};
+use shared::proto::{
+    AppendTextRequest, ClearTextRequest, ComposingText, PingRequest, ReconvertRequest,
+    RemoveTextRequest, ResizeSegmentRequest, ShrinkTextRequest,
+};
+
+// type the text one character at a time like the client
+async fn type_text(client: &mut Client, text: &str) -> ComposingText {
+    let mut composing = ComposingText::default();
+    for c in text.chars() {
+        composing = client
+            .append_text(AppendTextRequest {
+                text_to_append: c.to_string(),
+            })
+            .await
+            .expect("append_text failed")
+            .into_inner()
+            .composing_text
+            .expect("no composing text");
+    }
+    composing
+}
+
+async fn clear(client: &mut Client) {
+    client
+        .clear_text(ClearTextRequest {})
+        .await
+        .expect("clear_text failed");
+}
+
+async fn append(client: &mut Client) {
+    let composing = type_text(client, "kanji").await;
+    assert_eq!(composing.hiragana, "かんじ");
+    assert!(!composing.suggestions.is_empty());
+    assert!(
+        composing.suggestions.iter().any(|s| s.text == "漢字"),
+        "漢字 is not in {:?}",
+        composing.suggestions
+    );
+    clear(client).await;
+}
+
+async fn remove(client: &mut Client) {
+    type_text(client, "aiu").await;
+    let composing = client
+        .remove_text(RemoveTextRequest {})
+        .await
+        .expect("remove_text failed")
+        .into_inner()
+        .composing_text
+        .expect("no composing text");
+    assert_eq!(composing.hiragana, "あい");
+    clear(client).await;
+}
+
+// commit the first clause and keep converting the rest, like Enter on a long reading
+async fn shrink(client: &mut Client) {
+    let composing = type_text(client, "kyouhaiitenki").await;
+    assert_eq!(composing.hiragana, "きょうはいいてんき");
+
+    let first = &composing.suggestions[0];
+    assert!(first.corresponding_count > 0);
+    let shrunk = client
+        .shrink_text(ShrinkTextRequest {
+            offset: first.corresponding_count,
+        })
+        .await
+        .expect("shrink_text failed")
+        .into_inner()
+        .composing_text
+        .expect("no composing text");
+
+    assert!(shrunk.hiragana.len() < composing.hiragana.len());
+    assert!(composing.hiragana.ends_with(&shrunk.hiragana));
+    clear(client).await;
+}
+
+async fn resize(client: &mut Client) {
+    type_text(client, "kyouhaiitenki").await;
+    let composing = client
+        .resize_segment(ResizeSegmentRequest { offset: -1 })
+        .await
+        .expect("resize_segment failed")
+        .into_inner()
+        .composing_text
+        .expect("no composing text");
+    assert!(!composing.suggestions.is_empty());
+    clear(client).await;
+}
+
+// every candidate covers part of the input
+async fn candidates(client: &mut Client) {
+    let input = "henkan";
+    let composing = type_text(client, input).await;
+    // counted in the typed characters, not in the hiragana
+    let length = input.len() as i32;
+    for suggestion in &composing.suggestions {
+        assert!(!suggestion.text.is_empty());
+        assert!(
+            (1..=length).contains(&suggestion.corresponding_count),
+            "{:?}",
+            suggestion
+        );
+    }
+    clear(client).await;
+}
+
+// every prediction continues the typed reading
+async fn predict(client: &mut Client) {
+    let composing = type_text(client, "henkan").await;
+    for prediction in &composing.predictions {
+        assert!(!prediction.text.is_empty());
+        assert!(
+            prediction.reading.starts_with(&composing.hiragana)
+                && prediction.reading.len() > composing.hiragana.len(),
+            "{:?}",
+            prediction
+        );
+    }
+    clear(client).await;
+}
+
+// kana is read as it is, while kanji nobody taught the server can't be read back
+async fn reconvert(client: &mut Client) {
+    let composing = client
+        .reconvert(ReconvertRequest {
+            text: "カンジ".to_string(),
+        })
+        .await
+        .expect("reconvert failed")
+        .into_inner()
+        .composing_text
+        .expect("no composing text");
+    assert_eq!(composing.hiragana, "かんじ");
+    assert!(!composing.suggestions.is_empty());
+    clear(client).await;
+
+    let status = client
+        .reconvert(ReconvertRequest {
+            text: "\u{20B9F}".to_string(),
+        })
+        .await
+        .expect_err("an unknown reading was found");
+    assert_eq!(status.code(), tonic::Code::NotFound);
+}
+
+#[tokio::test]
+#[ignore = "needs the conversion engine and replaces the running server"]
+async fn scenarios() {
+    let (_server, mut client) = start_server().await;
+
+    client.ping(PingRequest {}).await.expect("ping failed");
+
+    // the server keeps one composition, so the scenarios run one after another
+    append(&mut client).await;
+    remove(&mut client).await;
+    shrink(&mut client).await;
+    resize(&mut client).await;
+    candidates(&mut client).await;
+    predict(&mut client).await;
+    reconvert(&mut client).await;
+}
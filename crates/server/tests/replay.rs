@@ -0,0 +1,127 @@
+// replay a session recorded with `debug.record_session` against the real server
+//
+//   AZOOKEY_REPLAY=path/to/session.jsonl cargo test -p azookey-server --test replay -- --ignored
+//
+// the readings have to match the recording, the candidates depend on the learning data,
+// so their differences are only printed
+// the keys of the same session go through the client in the client's replay test
+
+mod common;
+
+use std::path::PathBuf;
+
+use common::{start_server, Client};
+use shared::proto::{
+    AppendTextRequest, ClearTextRequest, ComposingText, ReconvertRequest, RemoveTextRequest,
+    ResizeSegmentRequest, SetContextRequest, ShrinkTextRequest,
+};
+use shared::recording::{read_session, ConversionRequest, RecordedText, SessionEvent};
+
+async fn send(client: &mut Client, request: &ConversionRequest) -> Option<ComposingText> {
+    match request.clone() {
+        ConversionRequest::AppendText { text } => {
+            client
+                .append_text(AppendTextRequest {
+                    text_to_append: text,
+                })
+                .await
+                .expect("append_text failed")
+                .into_inner()
+                .composing_text
+        }
+        ConversionRequest::RemoveText => {
+            client
+                .remove_text(RemoveTextRequest {})
+                .await
+                .expect("remove_text failed")
+                .into_inner()
+                .composing_text
+        }
+        ConversionRequest::ShrinkText { offset } => {
+            client
+                .shrink_text(ShrinkTextRequest { offset })
+                .await
+                .expect("shrink_text failed")
+                .into_inner()
+                .composing_text
+        }
+        ConversionRequest::ResizeSegment { offset } => {
+            client
+                .resize_segment(ResizeSegmentRequest { offset })
+                .await
+                .expect("resize_segment failed")
+                .into_inner()
+                .composing_text
+        }
+        ConversionRequest::ClearText => {
+            client
+                .clear_text(ClearTextRequest {})
+                .await
+                .expect("clear_text failed");
+            None
+        }
+        ConversionRequest::SetContext { context } => {
+            client
+                .set_context(SetContextRequest { context })
+                .await
+                .expect("set_context failed");
+            None
+        }
+        // the reading may have been learned after the recording
+        ConversionRequest::Reconvert { text } => {
+            client
+                .reconvert(ReconvertRequest { text })
+                .await
+                .ok()?
+                .into_inner()
+                .composing_text
+        }
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a recorded session and the conversion engine"]
+async fn replay() {
+    let path = PathBuf::from(std::env::var("AZOOKEY_REPLAY").expect("AZOOKEY_REPLAY is not set"));
+    let events = read_session(&path).expect("failed to read the session");
+
+    let (_server, mut client) = start_server().await;
+
+    // the keys are only shown to tell where the replay went wrong
+    let mut last_key = None;
+    for (line, event) in events.iter().enumerate() {
+        let (request, expected) = match event {
+            SessionEvent::Key { .. } => {
+                last_key = Some(event);
+                continue;
+            }
+            SessionEvent::Request { request, response } => (request, response),
+        };
+
+        let actual = send(&mut client, request)
+            .await
+            .as_ref()
+            .map(RecordedText::from);
+        let (Some(expected), Some(actual)) = (expected, actual) else {
+            continue;
+        };
+
+        assert_eq!(
+            expected.hiragana,
+            actual.hiragana,
+            "the reading differs at line {} ({:?}) after {:?}",
+            line + 1,
+            request,
+            last_key
+        );
+        if expected.candidates != actual.candidates {
+            println!(
+                "the candidates differ at line {} ({:?}):\n  recorded: {:?}\n  replayed: {:?}",
+                line + 1,
+                request,
+                expected.candidates,
+                actual.candidates
+            );
+        }
+    }
+}
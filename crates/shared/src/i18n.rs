@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+// language of the settings app and the language bar
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Locale {
+    #[default]
+    Ja,
+    En,
+}
+
+// every key must be here, the other languages fall back to these
+const JA: &[(&str, &str)] = &[
+    // input modes
+    ("mode.kana", "ひらがな"),
+    ("mode.katakana", "全角カタカナ"),
+    ("mode.half_katakana", "半角カタカナ"),
+    ("mode.full_latin", "全角英数"),
+    ("mode.latin", "半角英数"),
+    // language bar
+    ("menu.reconvert", "再変換"),
+    ("menu.settings", "設定"),
+    (
+        "status.unavailable",
+        "変換サーバーに接続できません（かな入力のみ）",
+    ),
+    ("hotkey.convert_selection", "選択範囲を変換"),
+    ("hotkey.open_settings", "設定を開く"),
+    ("hotkey.privacy_mode", "プライバシーモードの切り替え"),
+    ("menu.privacy_mode", "プライバシーモード"),
+    ("menu.kaomoji_palette", "顔文字パレット"),
+    (
+        "status.privacy_mode",
+        "プライバシーモード（学習・履歴なし）",
+    ),
+    // settings app
+    ("nav.settings", "設定"),
+    ("nav.general", "全般"),
+    ("nav.zenzai", "Zenzai"),
+    ("nav.dictionary", "辞書"),
+    ("nav.logs", "ログ"),
+    ("nav.about", "Azookeyについて"),
+    ("general.locale", "表示言語"),
+    (
+        "general.locale.description",
+        "設定画面と言語バーの表示に使う言語を選択します",
+    ),
+    ("about.title", "このソフトについて"),
+    (
+        "about.discord.description",
+        "Azookey公式Discordサーバーに参加して、最新情報を入手する",
+    ),
+    ("about.discord.join", "参加する"),
+    ("about.build", "ビルド情報"),
+    ("about.build.version", "バージョン"),
+    ("about.build.commit", "コミット"),
+    ("about.build.date", "ビルド日時"),
+    ("about.build.engine", "変換エンジン"),
+    ("about.build.backend", "バックエンド"),
+    ("about.build.enabled", "有効"),
+    ("about.build.disabled", "無効"),
+    (
+        "about.build.unavailable",
+        "サーバーに接続できないため、ビルド情報を取得できませんでした",
+    ),
+    ("logs.title", "サーバーのログ"),
+    ("logs.level", "表示するレベル"),
+    (
+        "logs.level.description",
+        "選んだレベル以上の重要度のログを表示します",
+    ),
+    ("logs.level.placeholder", "レベルを選択"),
+    ("logs.level.error", "エラー"),
+    ("logs.level.warn", "警告"),
+    ("logs.level.info", "情報"),
+    ("logs.level.debug", "デバッグ"),
+    ("logs.level.trace", "トレース"),
+    ("error.server", "サーバーに接続できませんでした"),
+    ("error.update_config", "設定の更新に失敗しました"),
+];
+
+const EN: &[(&str, &str)] = &[
+    ("mode.kana", "Hiragana"),
+    ("mode.katakana", "Full-width Katakana"),
+    ("mode.half_katakana", "Half-width Katakana"),
+    ("mode.full_latin", "Full-width Alphanumeric"),
+    ("mode.latin", "Half-width Alphanumeric"),
+    ("menu.reconvert", "Reconvert"),
+    ("menu.settings", "Settings"),
+    (
+        "status.unavailable",
+        "Cannot connect to the conversion server (kana input only)",
+    ),
+    ("hotkey.convert_selection", "Convert the selection"),
+    ("hotkey.open_settings", "Open the settings"),
+    ("hotkey.privacy_mode", "Toggle the privacy mode"),
+    ("menu.privacy_mode", "Privacy mode"),
+    ("menu.kaomoji_palette", "Kaomoji palette"),
+    (
+        "status.privacy_mode",
+        "Privacy mode (no learning, no history)",
+    ),
+    ("nav.settings", "Settings"),
+    ("nav.general", "General"),
+    ("nav.zenzai", "Zenzai"),
+    ("nav.dictionary", "Dictionary"),
+    ("nav.logs", "Logs"),
+    ("nav.about", "About Azookey"),
+    ("general.locale", "Language"),
+    (
+        "general.locale.description",
+        "The language of the settings and the language bar",
+    ),
+    ("about.title", "About"),
+    (
+        "about.discord.description",
+        "Join the official Azookey Discord server for the latest news",
+    ),
+    ("about.discord.join", "Join"),
+    ("about.build", "Build information"),
+    ("about.build.version", "Version"),
+    ("about.build.commit", "Commit"),
+    ("about.build.date", "Build date"),
+    ("about.build.engine", "Conversion engine"),
+    ("about.build.backend", "Backend"),
+    ("about.build.enabled", "enabled"),
+    ("about.build.disabled", "disabled"),
+    (
+        "about.build.unavailable",
+        "Cannot connect to the server, so the build information is not available",
+    ),
+    ("logs.title", "Server logs"),
+    ("logs.level", "Level"),
+    (
+        "logs.level.description",
+        "Show the logs of this level and the more important ones",
+    ),
+    ("logs.level.placeholder", "Select a level"),
+    ("logs.level.error", "Error"),
+    ("logs.level.warn", "Warning"),
+    ("logs.level.info", "Info"),
+    ("logs.level.debug", "Debug"),
+    ("logs.level.trace", "Trace"),
+    ("error.server", "Cannot connect to the server"),
+    ("error.update_config", "Failed to update the settings"),
+];
+
+fn table(locale: Locale) -> &'static [(&'static str, &'static str)] {
+    match locale {
+        Locale::Ja => JA,
+        Locale::En => EN,
+    }
+}
+
+// the string for the key, the key itself if it is unknown
+pub fn text(locale: Locale, key: &'static str) -> &'static str {
+    let find = |table: &'static [(&'static str, &'static str)]| {
+        table.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    };
+    find(table(locale)).or_else(|| find(JA)).unwrap_or(key)
+}
+
+// all the strings of the locale, for the settings app
+pub fn strings(locale: Locale) -> BTreeMap<&'static str, &'static str> {
+    let mut strings: BTreeMap<_, _> = JA.iter().copied().collect();
+    strings.extend(table(locale).iter().copied());
+    strings
+}
@@ -0,0 +1,11 @@
+use std::path::PathBuf;
+
+// the settings app is installed per user by its own installer
+pub fn settings_app_path() -> PathBuf {
+    let local_appdata = PathBuf::from(std::env::var("LOCALAPPDATA").unwrap_or_default());
+    local_appdata.join("Azookey").join("Azookey.exe")
+}
+
+// title of the main window of the settings app, see frontend/src-tauri/tauri.conf.json
+// used to bring the running app to the front instead of starting another one
+pub const SETTINGS_APP_TITLE: &str = "Azookey";
@@ -0,0 +1,86 @@
+use std::sync::LazyLock;
+
+// faces shipped with the IME, converted from their readings and listed in the kaomoji palette
+// kaomoji.tsv has a "category<TAB>face<TAB>readings" line per face, lines starting with # are
+// comments
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kaomoji {
+    pub category: &'static str,
+    pub face: &'static str,
+    pub readings: Vec<&'static str>,
+}
+
+fn parse(content: &'static str) -> Vec<Kaomoji> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let category = fields.next()?;
+            let face = fields.next().filter(|face| !face.is_empty())?;
+            let readings = fields.next().unwrap_or_default();
+            Some(Kaomoji {
+                category,
+                face,
+                readings: readings.split_whitespace().collect(),
+            })
+        })
+        .collect()
+}
+
+static TABLE: LazyLock<Vec<Kaomoji>> = LazyLock::new(|| parse(include_str!("kaomoji/kaomoji.tsv")));
+
+// the faces for a whole reading, e.g. にこにこ
+pub fn lookup(reading: &str) -> Vec<&'static str> {
+    TABLE
+        .iter()
+        .filter(|kaomoji| kaomoji.readings.contains(&reading))
+        .map(|kaomoji| kaomoji.face)
+        .collect()
+}
+
+// the categories in the order of the table, with their faces
+// the faces are numbered through all the categories, see `face`
+pub fn categories() -> Vec<(&'static str, Vec<&'static str>)> {
+    let mut categories: Vec<(&str, Vec<&str>)> = vec![];
+    for kaomoji in TABLE.iter() {
+        match categories.last_mut() {
+            Some((category, faces)) if *category == kaomoji.category => faces.push(kaomoji.face),
+            _ => categories.push((kaomoji.category, vec![kaomoji.face])),
+        }
+    }
+    categories
+}
+
+// the face of the palette item clicked in the candidate window
+pub fn face(index: usize) -> Option<&'static str> {
+    TABLE.get(index).map(|kaomoji| kaomoji.face)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_table() {
+        let table = parse("# comment\n笑顔\t(^_^)\tにこにこ えがお\n笑顔\t(^o^)\nbroken\n");
+        assert_eq!(table.len(), 2);
+        assert_eq!(table[0].face, "(^_^)");
+        assert_eq!(table[0].readings, ["にこにこ", "えがお"]);
+        assert!(table[1].readings.is_empty());
+    }
+
+    #[test]
+    fn shipped_table() {
+        assert!(lookup("かおもじ").len() > 1);
+        assert!(lookup("かおもじじゃない").is_empty());
+
+        // the faces of a category are together, so the numbering of the palette is the table's
+        let categories = categories();
+        let names: std::collections::HashSet<_> = categories.iter().map(|(name, _)| name).collect();
+        assert_eq!(names.len(), categories.len());
+        let (_, faces) = &categories[1];
+        let first = categories[0].1.len();
+        assert_eq!(face(first), Some(faces[0]));
+    }
+}
@@ -1,5 +1,13 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
+
+pub mod i18n;
+pub mod install;
+pub mod kaomoji;
+pub mod policy;
+pub mod recording;
+pub mod trace;
+pub mod transport;
 
 pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/azookey.rs"));
@@ -13,6 +21,30 @@ fn get_config_root() -> PathBuf {
     appdata.join("Azookey")
 }
 
+pub fn get_log_dir() -> PathBuf {
+    get_config_root().join("logs")
+}
+
+// token of the tcp listener, see `transport::create_tcp_token`
+pub fn get_tcp_token_path() -> PathBuf {
+    get_config_root().join("tcp_token")
+}
+
+// learning data of the converter, written by the server
+pub fn get_memory_dir() -> PathBuf {
+    get_config_root().join("memory")
+}
+
+// romaji table of the user, read by the server
+pub fn get_romaji_table_path() -> PathBuf {
+    get_config_root().join("romaji.tsv")
+}
+
+// files exported from the settings app
+pub fn get_export_dir() -> PathBuf {
+    get_config_root().join("export")
+}
+
 const SETTINGS_FILENAME: &str = "settings.json";
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -26,6 +58,8 @@ pub struct ZenzaiConfig {
 pub struct DictionaryEntry {
     pub word: String,    // The word to register (kanji/katakana)
     pub reading: String, // The reading (hiragana)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String, // Shown in the detail pane of the candidate window
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
@@ -33,12 +67,836 @@ pub struct DictionaryConfig {
     pub entries: Vec<DictionaryEntry>,
 }
 
+// how the conversion history is used
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LearningMode {
+    // learn from committed candidates and use the history
+    #[default]
+    Learn,
+    // use the existing history, but don't learn anymore
+    ReadOnly,
+    // neither learn nor use the history
+    Off,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ConversionConfig {
+    pub learning: LearningMode,
+    // the first clause is committed when the reading gets longer than this, 0 to disable
+    pub max_length: usize,
+    // show the first candidate while typing instead of the reading, like azooKey on macOS
+    pub live_conversion: bool,
+    // commit the selected candidate after this many milliseconds without a key, 0 to disable
+    pub auto_commit_ms: u64,
+    // fix the romaji keys typed by mistake, e.g. a stray consonant or hs for sh
+    pub typo_correction: bool,
+}
+
+impl Default for ConversionConfig {
+    fn default() -> Self {
+        ConversionConfig {
+            learning: LearningMode::default(),
+            max_length: 64,
+            live_conversion: true,
+            auto_commit_ms: 0,
+            typo_correction: false,
+        }
+    }
+}
+
+// what happens when moving past the first or the last candidate
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SelectionBoundary {
+    // stay on the first / last candidate
+    #[default]
+    Stop,
+    // go around to the other end of the list
+    Wrap,
+}
+
+// order of the candidates returned by the server
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateOrder {
+    // as ranked by the converter, including what was learned
+    #[default]
+    Learning,
+    // by the score from the dictionary
+    Frequency,
+    // shorter candidates first
+    Shortest,
+}
+
+// which words can be candidates
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Vocabulary {
+    #[default]
+    All,
+    // kana and the kanji learned at school only, with the reading as the annotation
+    Restricted,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct CandidateConfig {
+    pub boundary: SelectionBoundary,
+    pub order: CandidateOrder,
+    // hide offensive words unless their exact reading is typed, for screen sharing and classrooms
+    pub filter_offensive: bool,
+    // can be enforced with the RestrictedVocabulary policy, see `policy`
+    pub vocabulary: Vocabulary,
+    // completions of the reading above the candidates while composing, chosen with Tab
+    pub predictions: bool,
+    // emoji for readings like ねこ, after the other candidates
+    pub emoji: bool,
+    // English words for the romaji typed in the kana mode, e.g. apple, with their katakana
+    pub english: bool,
+    pub window: CandidateWindowTiming,
+}
+
+impl Default for CandidateConfig {
+    fn default() -> Self {
+        CandidateConfig {
+            boundary: SelectionBoundary::default(),
+            order: CandidateOrder::default(),
+            filter_offensive: true,
+            vocabulary: Vocabulary::default(),
+            predictions: true,
+            emoji: true,
+            english: true,
+            window: CandidateWindowTiming::default(),
+        }
+    }
+}
+
+// when the candidate window comes up during a composition
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateWindowTiming {
+    // from the first key, with the predictions
+    #[default]
+    Always,
+    // the first candidate is only shown in the text, the window comes with the second Space
+    SecondSpace,
+}
+
+// most candidates shown at once in the candidate window
+// the window reports how many actually fit, which can be fewer after resizing
+// the number keys select from the shown ones, so this must not be more than 9
+pub const CANDIDATE_PAGE_SIZE: usize = 9;
+
+// what Escape does during composition
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EscapeBehavior {
+    // end the composition at once
+    #[default]
+    Cancel,
+    // go back to the reading first, then cancel on the second press (MS-IME style)
+    Revert,
+}
+
+// what Backspace does while a conversion is shown
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackspaceBehavior {
+    // delete the last character of the reading
+    #[default]
+    Delete,
+    // go back to the reading without deleting anything
+    Revert,
+}
+
+// what Tab does while the candidates are shown
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TabBehavior {
+    // show or hide the annotations of the candidates
+    #[default]
+    Detail,
+    // select the next candidate like Space
+    Next,
+}
+
+// input modes as written in the config
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InputModeName {
+    Kana,
+    Katakana,
+    HalfKatakana,
+    FullLatin,
+    Latin,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct KeyConfig {
+    pub escape: EscapeBehavior,
+    pub backspace: BackspaceBehavior,
+    pub tab: TabBehavior,
+    // modes switched by the Katakana/Hiragana key and the right click of the language bar
+    pub mode_cycle: Vec<InputModeName>,
+    // shortcut which opens the settings app, e.g. "Ctrl+Shift+F12", empty to disable
+    pub open_settings: String,
+    // letters typed with Shift while composing become katakana in the reading like ATOK,
+    // instead of being sent to the converter in uppercase
+    pub shift_katakana: bool,
+    // Shift and Ctrl pressed and released alone apply to the next key, for typing with one hand
+    // the shortcuts of the applications still need the keys held down
+    pub sticky_modifiers: bool,
+    // what F1-F10 turn the reading into, the keys which are not listed go to the application
+    pub function_keys: Vec<FunctionKeyBinding>,
+    // Henkan turns the kana input on and Muhenkan off, instead of going to the application
+    pub henkan_muhenkan: bool,
+    // what Caps Lock and Eisū do while the kana input is on
+    pub caps_lock: CapsLockBehavior,
+    // keys of the user, which come before the built-in ones, e.g. for emacs-like keys
+    pub bindings: Vec<KeyBinding>,
+}
+
+// a form of the whole reading, chosen with the function keys
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextTransform {
+    Hiragana,
+    Katakana,
+    HalfKatakana,
+    FullLatin,
+    HalfLatin,
+    // katakana with the long vowels written as ー, e.g. コーヒー for こうひい
+    LongVowelKatakana,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FunctionKeyBinding {
+    // 1 for F1 to 10 for F10
+    pub key: u8,
+    pub transform: TextTransform,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CapsLockBehavior {
+    // Caps Lock as it is, Eisū does nothing
+    #[default]
+    Caps,
+    // switch to the alphanumeric input and back, Caps Lock stays as it is
+    Latin,
+    // both at once, like the Caps Lock key of macOS
+    Both,
+}
+
+// e.g. {"key": "Ctrl+M", "states": ["composing"], "actions": ["commit"]}
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct KeyBinding {
+    // a shortcut like "Ctrl+J" or "Shift+Space"
+    pub key: String,
+    // the states it works in, every state when empty
+    #[serde(default)]
+    pub states: Vec<CompositionStateName>,
+    pub actions: Vec<BindingAction>,
+}
+
+// states of the composition as written in the config
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompositionStateName {
+    // nothing is composed
+    None,
+    Composing,
+    // the conversion is shown
+    Previewing,
+    // the candidate window is open
+    Selecting,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingAction {
+    Commit,
+    Cancel,
+    Backspace,
+    Convert,
+    // show the reading again instead of the conversion
+    Revert,
+    NextCandidate,
+    PreviousCandidate,
+    NextPage,
+    PreviousPage,
+    MoveCursor(i32),
+    ResizeSegment(i32),
+    FocusClause(i32),
+    // commit the first clause and keep the rest composing
+    CommitPrefix,
+    Transform(TextTransform),
+    ToggleDetail,
+    // the composition is committed before these two
+    SetMode(InputModeName),
+    Insert(String),
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        use TextTransform::*;
+
+        KeyConfig {
+            escape: EscapeBehavior::default(),
+            backspace: BackspaceBehavior::default(),
+            tab: TabBehavior::default(),
+            mode_cycle: vec![
+                InputModeName::Kana,
+                InputModeName::Katakana,
+                InputModeName::HalfKatakana,
+                InputModeName::FullLatin,
+                InputModeName::Latin,
+            ],
+            open_settings: String::new(),
+            shift_katakana: false,
+            sticky_modifiers: false,
+            // the keys of MS-IME and most other IMEs
+            function_keys: [
+                (6, Hiragana),
+                (7, Katakana),
+                (8, HalfKatakana),
+                (9, FullLatin),
+                (10, HalfLatin),
+            ]
+            .into_iter()
+            .map(|(key, transform)| FunctionKeyBinding { key, transform })
+            .collect(),
+            henkan_muhenkan: false,
+            caps_lock: CapsLockBehavior::default(),
+            // the Ctrl keys of MS-IME and ATOK while composing, and Shift+Enter of Kotoeri
+            bindings: composing_bindings(vec![
+                ("Ctrl+U", BindingAction::Transform(Hiragana)),
+                ("Ctrl+I", BindingAction::Transform(Katakana)),
+                ("Ctrl+O", BindingAction::Transform(HalfKatakana)),
+                ("Ctrl+P", BindingAction::Transform(FullLatin)),
+                ("Ctrl+T", BindingAction::Transform(HalfLatin)),
+                ("Ctrl+H", BindingAction::Backspace),
+                ("Shift+Enter", BindingAction::CommitPrefix),
+            ]),
+        }
+    }
+}
+
+// shortcuts which work while composing and while the conversion is shown
+fn composing_bindings(keys: Vec<(&str, BindingAction)>) -> Vec<KeyBinding> {
+    keys.into_iter()
+        .map(|(key, action)| KeyBinding {
+            key: key.to_string(),
+            states: vec![
+                CompositionStateName::Composing,
+                CompositionStateName::Previewing,
+            ],
+            actions: vec![action],
+        })
+        .collect()
+}
+
+// key settings close to other IMEs, for users switching from them
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyPreset {
+    MsIme,
+    Atok,
+    // the Japanese input of macOS
+    Kotoeri,
+}
+
+impl KeyConfig {
+    pub fn function_key(&self, key: u8) -> Option<TextTransform> {
+        self.function_keys
+            .iter()
+            .find(|binding| binding.key == key)
+            .map(|binding| binding.transform)
+    }
+
+    // replace the key behaviors and the shortcuts with the preset
+    // the shortcuts added by the user are replaced as well, the preset is a fresh start
+    pub fn apply_preset(&mut self, preset: KeyPreset) {
+        use InputModeName::*;
+        use TextTransform::*;
+
+        let (escape, backspace, tab, mode_cycle) = match preset {
+            KeyPreset::MsIme => (
+                EscapeBehavior::Revert,
+                BackspaceBehavior::Delete,
+                TabBehavior::Next,
+                vec![Kana, Katakana, HalfKatakana, FullLatin, Latin],
+            ),
+            KeyPreset::Atok => (
+                EscapeBehavior::Revert,
+                BackspaceBehavior::Revert,
+                TabBehavior::Next,
+                vec![Kana, Katakana, HalfKatakana, FullLatin, Latin],
+            ),
+            // there are only the kana and the alphanumeric modes on macOS
+            KeyPreset::Kotoeri => (
+                EscapeBehavior::Revert,
+                BackspaceBehavior::Delete,
+                TabBehavior::Next,
+                vec![Kana, Latin],
+            ),
+        };
+        let bindings = match preset {
+            KeyPreset::MsIme => composing_bindings(vec![
+                ("Ctrl+U", BindingAction::Transform(Hiragana)),
+                ("Ctrl+I", BindingAction::Transform(Katakana)),
+                ("Ctrl+O", BindingAction::Transform(HalfKatakana)),
+                ("Ctrl+P", BindingAction::Transform(FullLatin)),
+                ("Ctrl+T", BindingAction::Transform(HalfLatin)),
+                ("Ctrl+H", BindingAction::Backspace),
+            ]),
+            KeyPreset::Atok => {
+                let mut bindings = composing_bindings(vec![
+                    ("Ctrl+U", BindingAction::Transform(Hiragana)),
+                    ("Ctrl+I", BindingAction::Transform(Katakana)),
+                    ("Ctrl+O", BindingAction::Transform(HalfKatakana)),
+                    ("Ctrl+P", BindingAction::Transform(FullLatin)),
+                    ("Ctrl+H", BindingAction::Backspace),
+                ]);
+                // the clause being converted is resized with Ctrl+K and Ctrl+L
+                for (key, offset) in [("Ctrl+K", -1), ("Ctrl+L", 1)] {
+                    bindings.push(KeyBinding {
+                        key: key.to_string(),
+                        states: vec![CompositionStateName::Previewing],
+                        actions: vec![BindingAction::ResizeSegment(offset)],
+                    });
+                }
+                bindings
+            }
+            // Ctrl+; for the half-width katakana is not a key `parse_shortcut` knows
+            KeyPreset::Kotoeri => composing_bindings(vec![
+                ("Ctrl+J", BindingAction::Transform(Hiragana)),
+                ("Ctrl+K", BindingAction::Transform(Katakana)),
+                ("Ctrl+L", BindingAction::Transform(FullLatin)),
+                ("Ctrl+H", BindingAction::Backspace),
+                ("Shift+Enter", BindingAction::CommitPrefix),
+            ]),
+        };
+
+        self.escape = escape;
+        self.backspace = backspace;
+        self.tab = tab;
+        self.mode_cycle = mode_cycle;
+        self.bindings = bindings;
+    }
+}
+
+// keys which can be the thumb keys of the thumb-shift input
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThumbKey {
+    Muhenkan,
+    Henkan,
+    Space,
+}
+
+impl ThumbKey {
+    pub fn virtual_key(self) -> usize {
+        match self {
+            ThumbKey::Muhenkan => 0x1D, // VK_NONCONVERT
+            ThumbKey::Henkan => 0x1C,   // VK_CONVERT
+            ThumbKey::Space => 0x20,    // VK_SPACE
+        }
+    }
+}
+
+// thumb-shift (NICOLA) input, the character keys type kana instead of romaji
+// a thumb key pressed alone works as the key itself when it is released
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ThumbShiftConfig {
+    pub enable: bool,
+    pub left: ThumbKey,
+    pub right: ThumbKey,
+    // a thumb key pressed within this after a character key shifts that character
+    pub window_ms: u64,
+}
+
+impl Default for ThumbShiftConfig {
+    fn default() -> Self {
+        ThumbShiftConfig {
+            enable: false,
+            left: ThumbKey::Muhenkan,
+            right: ThumbKey::Space,
+            window_ms: 100,
+        }
+    }
+}
+
+// Qt apps crash when multiple RequestEditSession calls happen in rapid succession
+const QT_APPS: &[&str] = &[
+    "qtcreator.exe",
+    "telegram.exe",
+    "obs64.exe",
+    "keepassxc.exe",
+    "vlc.exe",
+    "anki.exe",
+    "wireshark.exe",
+];
+
+// applications where the text around the caret is read
+// reading it needs extra edit sessions, which some applications do not handle well
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct SurroundingTextConfig {
+    // executable names which are always allowed, even if they are in `deny`
+    pub allow: Vec<String>,
+    // executable names which are never read
+    pub deny: Vec<String>,
+}
+
+impl Default for SurroundingTextConfig {
+    fn default() -> Self {
+        SurroundingTextConfig {
+            allow: vec![],
+            deny: QT_APPS.iter().map(|app| app.to_string()).collect(),
+        }
+    }
+}
+
+impl SurroundingTextConfig {
+    pub fn is_allowed(&self, process: &str) -> bool {
+        let matches = |apps: &[String]| apps.iter().any(|app| app.eq_ignore_ascii_case(process));
+        matches(&self.allow) || !matches(&self.deny)
+    }
+}
+
+// what to do with the candidate window while a fullscreen application (a game, a video) is in front
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FullscreenBehavior {
+    // don't show the candidate window, the candidates are still selected in the text
+    #[default]
+    Hide,
+    // show the candidate window on top of the application as usual
+    Overlay,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct FullscreenConfig {
+    pub behavior: FullscreenBehavior,
+    // executable names with their own behavior, e.g. games which handle the overlay well
+    pub apps: BTreeMap<String, FullscreenBehavior>,
+}
+
+impl FullscreenConfig {
+    pub fn behavior_for(&self, process: &str) -> FullscreenBehavior {
+        self.apps
+            .iter()
+            .find(|(app, _)| app.eq_ignore_ascii_case(process))
+            .map_or(self.behavior, |(_, behavior)| *behavior)
+    }
+}
+
+// applications where the IME lets every key through, e.g. games which read the keys themselves
+// there is no composition and no candidate window in them
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct ExclusionConfig {
+    // executable names, e.g. game.exe
+    pub apps: Vec<String>,
+}
+
+impl ExclusionConfig {
+    pub fn is_excluded(&self, process: &str) -> bool {
+        self.apps
+            .iter()
+            .any(|app| app.eq_ignore_ascii_case(process))
+    }
+}
+
+// settings for developer tools, not shown in the settings app
+// the DLL reads them on every activation
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct IpcConfig {
+    // also listen on 127.0.0.1 so that standard gRPC clients can connect
+    // they send the token in `get_tcp_token_path` as the azookey-token metadata
+    pub tcp_listener: bool,
+    pub tcp_port: u16,
+    // requests which take longer are treated as a crash of the other process, see `TimeoutClass`
+    pub conversion_timeout_ms: u64,
+    pub ui_timeout_ms: u64,
+    pub admin_timeout_ms: u64,
+    // attempts to open the pipe while the server is starting
+    pub connect_retries: u32,
+    pub connect_retry_delay_ms: u64,
+    // after a failed reconnection, the keys are handled without the server for this long
+    pub reconnect_cooldown_ms: u64,
+}
+
+impl Default for IpcConfig {
+    fn default() -> Self {
+        IpcConfig {
+            tcp_listener: false,
+            tcp_port: 50051,
+            conversion_timeout_ms: 5000,
+            ui_timeout_ms: 2000,
+            admin_timeout_ms: 30000,
+            connect_retries: transport::MAX_CONNECT_RETRIES,
+            connect_retry_delay_ms: transport::CONNECT_RETRY_DELAY.as_millis() as u64,
+            reconnect_cooldown_ms: 10000,
+        }
+    }
+}
+
+// kinds of requests, which are given up after different times
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutClass {
+    // conversion requests, which take longer with a big model
+    Conversion,
+    // requests to the candidate window, which should never keep the keys waiting
+    Ui,
+    // requests from the settings app, e.g. reloading the dictionaries
+    Admin,
+}
+
+impl IpcConfig {
+    pub fn timeout(&self, class: TimeoutClass) -> std::time::Duration {
+        let ms = match class {
+            TimeoutClass::Conversion => self.conversion_timeout_ms,
+            TimeoutClass::Ui => self.ui_timeout_ms,
+            TimeoutClass::Admin => self.admin_timeout_ms,
+        };
+        std::time::Duration::from_millis(ms)
+    }
+}
+
+// settings for troubleshooting, not shown in the settings app
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct DebugConfig {
+    // show the per-keystroke timing breakdown in the candidate window
+    pub latency_overlay: bool,
+    // write the keys and the conversion results of each session to the log directory,
+    // so that they can be replayed against the server
+    pub record_session: bool,
+}
+
+// how the candidate window is drawn
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UiRenderer {
+    // HTML in a webview
+    #[default]
+    Webview,
+    // Direct2D and DirectWrite, sharper at high DPI and needed for vertical text
+    Direct2d,
+}
+
+// settings for the candidate window process
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct UiConfig {
+    // path to ui.exe, empty to use the one next to the launcher
+    pub path: String,
+    // restart the process when it exits unexpectedly
+    pub auto_restart: bool,
+    // read when the process starts
+    pub renderer: UiRenderer,
+    // lay out the candidates in columns of vertical text, only with the direct2d renderer
+    // sent to the window again on every activation, so it is changed without a restart
+    pub vertical: bool,
+    // sent on every activation as well
+    pub font: FontConfig,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        UiConfig {
+            path: "".to_string(),
+            auto_restart: true,
+            renderer: UiRenderer::default(),
+            vertical: false,
+            font: FontConfig::default(),
+        }
+    }
+}
+
+// the text of the candidates, the numbers and the annotations keep their size
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct FontConfig {
+    // empty for the default of the window, Yu Gothic UI
+    pub family: String,
+    // in DIPs, scaled with the DPI of the monitor
+    pub size: f32,
+    // height of a line relative to the size
+    pub line_spacing: f32,
+}
+
+impl FontConfig {
+    // the file may have been edited by hand, so the window is kept usable
+    pub fn clamped(&self) -> FontConfig {
+        FontConfig {
+            family: self.family.trim().to_string(),
+            size: self.size.clamp(8.0, 48.0),
+            line_spacing: self.line_spacing.clamp(1.0, 3.0),
+        }
+    }
+}
+
+impl Default for FontConfig {
+    fn default() -> Self {
+        FontConfig {
+            family: String::new(),
+            size: 14.4,
+            line_spacing: 1.5,
+        }
+    }
+}
+
+// log settings shared by the DLL and the server
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct LogConfig {
+    // env-filter style directives, e.g. "azookey_windows=debug,azookey_windows::tsf=trace"
+    pub filter: String,
+    // write logs to the log folder in addition to the debugger output / stdout
+    pub file: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            filter: "azookey_windows=info,azookey_server=info".to_string(),
+            file: false,
+        }
+    }
+}
+
+// hooks for the user's own tools, all off unless enabled explicitly
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct AutomationConfig {
+    // report committed text to the subscribers of the admin service, see `SubscribeCommits`
+    pub commit_events: bool,
+}
+
+// what , and . type in the kana modes
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PunctuationStyle {
+    // 、。
+    #[default]
+    Japanese,
+    // ，．
+    Western,
+    // ，。
+    Mixed,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolWidth {
+    // the Japanese symbol, e.g. ・ for / and 「 for [
+    #[default]
+    Full,
+    Half,
+}
+
+// how a symbol key types in the kana modes
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SymbolRule {
+    pub symbol: char,
+    #[serde(default)]
+    pub width: SymbolWidth,
+    // commit the composition and type the symbol after it, e.g. for ! at the end of a sentence
+    #[serde(default)]
+    pub commit: bool,
+}
+
+// the symbols which are not listed are typed in full-width and composed with the reading
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct SymbolConfig {
+    pub rules: Vec<SymbolRule>,
+}
+
+impl SymbolConfig {
+    pub fn rule(&self, symbol: char) -> Option<&SymbolRule> {
+        self.rules.iter().find(|rule| rule.symbol == symbol)
+    }
+
+    pub fn commits(&self, symbol: char) -> bool {
+        self.rule(symbol).is_some_and(|rule| rule.commit)
+    }
+}
+
+// what Space types in the kana modes when nothing is composing
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpaceWidth {
+    // the space of the application, as in the alphanumeric mode
+    #[default]
+    Half,
+    Full,
+    // full-width only right after Japanese, so that code is still spaced in half-width
+    AfterJapanese,
+}
+
+// a built-in romaji table, romaji.tsv of the user is applied over it
+// the keys in neither of them are converted by the converter's own rules
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RomajiLayout {
+    #[default]
+    Standard,
+    // AZIK, e.g. "kz" for かん and "kp" for こう
+    Azik,
+    // symbols after z like Google Japanese Input, e.g. "zh" for ←
+    Symbols,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AppConfig {
     pub version: String,
     pub zenzai: ZenzaiConfig,
     #[serde(default)]
     pub dictionary: DictionaryConfig,
+    #[serde(default)]
+    pub conversion: ConversionConfig,
+    #[serde(default)]
+    pub candidate: CandidateConfig,
+    #[serde(default)]
+    pub keys: KeyConfig,
+    #[serde(default)]
+    pub surrounding_text: SurroundingTextConfig,
+    #[serde(default)]
+    pub fullscreen: FullscreenConfig,
+    #[serde(default)]
+    pub exclusion: ExclusionConfig,
+    #[serde(default)]
+    pub ipc: IpcConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub ui: UiConfig,
+    #[serde(default)]
+    pub debug: DebugConfig,
+    #[serde(default)]
+    pub locale: i18n::Locale,
+    #[serde(default)]
+    pub automation: AutomationConfig,
+    #[serde(default)]
+    pub romaji_layout: RomajiLayout,
+    #[serde(default)]
+    pub thumb_shift: ThumbShiftConfig,
+    #[serde(default)]
+    pub punctuation_style: PunctuationStyle,
+    #[serde(default)]
+    pub symbols: SymbolConfig,
+    #[serde(default)]
+    pub space_width: SpaceWidth,
 }
 
 impl Default for AppConfig {
@@ -51,6 +909,23 @@ impl Default for AppConfig {
                 backend: "cpu".to_string(),
             },
             dictionary: DictionaryConfig::default(),
+            conversion: ConversionConfig::default(),
+            candidate: CandidateConfig::default(),
+            keys: KeyConfig::default(),
+            surrounding_text: SurroundingTextConfig::default(),
+            fullscreen: FullscreenConfig::default(),
+            exclusion: ExclusionConfig::default(),
+            ipc: IpcConfig::default(),
+            log: LogConfig::default(),
+            ui: UiConfig::default(),
+            debug: DebugConfig::default(),
+            locale: i18n::Locale::default(),
+            automation: AutomationConfig::default(),
+            romaji_layout: RomajiLayout::default(),
+            thumb_shift: ThumbShiftConfig::default(),
+            punctuation_style: PunctuationStyle::default(),
+            symbols: SymbolConfig::default(),
+            space_width: SpaceWidth::default(),
         }
     }
 }
@@ -63,12 +938,18 @@ impl AppConfig {
     }
 
     pub fn read() -> Self {
+        Self::try_read().unwrap()
+    }
+
+    // same as `read`, but doesn't panic
+    // use this in the DLL, where a panic takes down the host application
+    pub fn try_read() -> std::io::Result<Self> {
         let config_path = get_config_root().join(SETTINGS_FILENAME);
         if !config_path.exists() {
-            return AppConfig::default();
+            return Ok(AppConfig::default());
         }
-        let config_str = std::fs::read_to_string(config_path).unwrap();
-        serde_json::from_str(&config_str).unwrap()
+        let config_str = std::fs::read_to_string(config_path)?;
+        Ok(serde_json::from_str(&config_str)?)
     }
 
     pub fn new() -> Self {
@@ -0,0 +1,33 @@
+use windows::{
+    core::{w, PCWSTR},
+    Win32::System::Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD},
+};
+
+// settings enforced by the administrator, e.g. on classroom machines
+// they are set with group policy under HKLM\SOFTWARE\Policies\Azookey and win over settings.json
+const POLICY_KEY: PCWSTR = w!(r"SOFTWARE\Policies\Azookey");
+
+fn read_dword(name: PCWSTR) -> Option<u32> {
+    let mut data = 0u32;
+    let mut data_size = std::mem::size_of::<u32>() as u32;
+
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            POLICY_KEY,
+            name,
+            RRF_RT_REG_DWORD,
+            None,
+            Some((&mut data as *mut u32).cast()),
+            Some(&mut data_size),
+        )
+    };
+
+    result.is_ok().then_some(data)
+}
+
+// RestrictedVocabulary: 1 forces the restricted vocabulary, 0 turns it off,
+// and without the value the user decides
+pub fn restricted_vocabulary() -> Option<bool> {
+    read_dword(w!("RestrictedVocabulary")).map(|value| value != 0)
+}
@@ -0,0 +1,70 @@
+use std::io::{self, BufRead as _, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::proto::ComposingText;
+
+// one line of a recorded session
+// the client writes them as json lines, and the replay test reads them back
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SessionEvent {
+    // a key which reached the IME, with the input mode and the composition state before it
+    Key {
+        wparam: usize,
+        mode: String,
+        state: String,
+    },
+    // a request to the conversion server and its answer, if it has one
+    Request {
+        request: ConversionRequest,
+        response: Option<RecordedText>,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub enum ConversionRequest {
+    AppendText { text: String },
+    RemoveText,
+    ClearText,
+    ShrinkText { offset: i32 },
+    ResizeSegment { offset: i32 },
+    SetContext { context: String },
+    Reconvert { text: String },
+}
+
+// the reading and the candidates the server answered with
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+pub struct RecordedText {
+    pub hiragana: String,
+    pub candidates: Vec<String>,
+}
+
+impl From<&ComposingText> for RecordedText {
+    fn from(composing_text: &ComposingText) -> Self {
+        Self {
+            hiragana: composing_text.hiragana.clone(),
+            candidates: composing_text
+                .suggestions
+                .iter()
+                .map(|suggestion| suggestion.text.clone())
+                .collect(),
+        }
+    }
+}
+
+pub fn write_event(writer: &mut impl Write, event: &SessionEvent) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, event)?;
+    writeln!(writer)
+}
+
+pub fn read_session(path: &Path) -> io::Result<Vec<SessionEvent>> {
+    let file = std::fs::File::open(path)?;
+    io::BufReader::new(file)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
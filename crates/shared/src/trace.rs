@@ -0,0 +1,20 @@
+use tonic::{metadata::MetadataValue, Request};
+
+// gRPC metadata key which carries the id of a keystroke across processes
+pub const REQUEST_ID_METADATA: &str = "x-azookey-request-id";
+
+pub fn with_request_id<T>(mut request: Request<T>, request_id: &str) -> Request<T> {
+    if let Ok(value) = MetadataValue::try_from(request_id) {
+        request.metadata_mut().insert(REQUEST_ID_METADATA, value);
+    }
+
+    request
+}
+
+pub fn request_id<T>(request: &Request<T>) -> Option<String> {
+    request
+        .metadata()
+        .get(REQUEST_ID_METADATA)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
@@ -0,0 +1,362 @@
+use async_stream::stream;
+use futures_core::stream::Stream;
+use hyper_util::rt::TokioIo;
+use std::{
+    ffi::c_void,
+    future::Future,
+    net::{Ipv4Addr, SocketAddr},
+    ptr::addr_of_mut,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use subtle::ConstantTimeEq as _;
+use tokio::{
+    io::{self, AsyncRead, AsyncWrite, DuplexStream},
+    net::{
+        windows::named_pipe::{ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions},
+        TcpListener, TcpStream,
+    },
+    sync::mpsc,
+    time,
+};
+use tonic::transport::{Channel, Endpoint};
+use tower::service_fn;
+use windows::{
+    core::w,
+    Win32::{
+        Foundation::{ERROR_FILE_NOT_FOUND, ERROR_PIPE_BUSY},
+        Security::{
+            Authorization::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION},
+            Cryptography::{BCryptGenRandom, BCRYPT_ALG_HANDLE, BCRYPT_USE_SYSTEM_PREFERRED_RNG},
+            PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES,
+        },
+    },
+};
+
+// Maximum time to wait for server to start (retries on file not found)
+// defaults of `IpcConfig`, see `NamedPipe::with_retries`
+pub const MAX_CONNECT_RETRIES: u32 = 20;
+pub const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+// buffer size of the in-process channel
+const IN_PROCESS_BUFFER_SIZE: usize = 64 * 1024;
+
+// byte stream which gRPC runs on
+// the server side is used by `TransportServer`, and the client side is used by `IPCService`
+pub trait Transport: Send + Sync + 'static {
+    type Client: AsyncRead + AsyncWrite + Send + Unpin + 'static;
+    type Server: AsyncRead + AsyncWrite + Send + Unpin + 'static;
+
+    // connect to the server
+    fn connect(&self) -> impl Future<Output = io::Result<Self::Client>> + Send;
+
+    // accept connections from clients
+    fn incoming(&self) -> impl Stream<Item = io::Result<Self::Server>> + Send + 'static;
+}
+
+#[allow(dead_code)]
+struct UnsafeSecurityAttributes(SECURITY_ATTRIBUTES);
+
+unsafe impl Send for UnsafeSecurityAttributes {}
+unsafe impl Sync for UnsafeSecurityAttributes {}
+
+// named pipe, used in production
+#[derive(Debug, Clone)]
+pub struct NamedPipe {
+    name: String,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl NamedPipe {
+    pub fn new(path: &str) -> Self {
+        Self {
+            name: format!("\\\\.\\pipe\\{}", path),
+            retries: MAX_CONNECT_RETRIES,
+            retry_delay: CONNECT_RETRY_DELAY,
+        }
+    }
+
+    // wait longer (or shorter) for the server to create the pipe
+    pub fn with_retries(mut self, retries: u32, retry_delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+}
+
+impl Transport for NamedPipe {
+    type Client = NamedPipeClient;
+    type Server = NamedPipeServer;
+
+    async fn connect(&self) -> io::Result<NamedPipeClient> {
+        let mut retries = 0u32;
+        let client = loop {
+            match ClientOptions::new().open(&self.name) {
+                Ok(client) => break client,
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => {
+                    tracing::debug!("{} busy, retrying...", self.name);
+                }
+                // Retry on file not found (server not ready yet)
+                Err(e) if e.raw_os_error() == Some(ERROR_FILE_NOT_FOUND.0 as i32) => {
+                    retries += 1;
+                    tracing::debug!(
+                        "{} not found, retry {}/{}",
+                        self.name,
+                        retries,
+                        self.retries
+                    );
+                    if retries >= self.retries {
+                        tracing::error!(
+                            "FAILED to connect to {} after {} retries: {:?}",
+                            self.name,
+                            retries,
+                            e
+                        );
+                        return Err(e);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(
+                        "FAILED to connect to {}: {:?} (os_error: {:?})",
+                        self.name,
+                        e,
+                        e.raw_os_error()
+                    );
+                    return Err(e);
+                }
+            }
+
+            time::sleep(self.retry_delay).await;
+        };
+        tracing::info!("Successfully connected to {}", self.name);
+
+        Ok(client)
+    }
+
+    fn incoming(&self) -> impl Stream<Item = io::Result<NamedPipeServer>> + Send + 'static {
+        // set security attributes to allow ipc from sandboxed processes
+        // see https://nathancorvussolis.blogspot.com/2018/05/windows-ime-security.html
+
+        let name = self.name.clone();
+        println!("Creating named pipe: {}", name);
+
+        let mut security_descriptor = PSECURITY_DESCRIPTOR::default();
+
+        unsafe {
+            // WD=Everyone, AC=All App Containers, RC=Restricted Code, SY=System, BA=Admins, BU=Users
+            // ML=Low Mandatory Level - allows access from low integrity processes
+            let sd_result = ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                w!("D:(A;;GA;;;WD)(A;;GA;;;AC)(A;;GA;;;RC)(A;;GA;;;SY)(A;;GA;;;BA)(A;;GA;;;BU)S:(ML;;NW;;;LW)"),
+                SDDL_REVISION,
+                &mut security_descriptor,
+                None,
+            );
+            if let Err(e) = &sd_result {
+                println!("Failed to create security descriptor: {:?}", e);
+            }
+            sd_result.unwrap();
+
+            let mut security_attributes = UnsafeSecurityAttributes(SECURITY_ATTRIBUTES {
+                nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+                lpSecurityDescriptor: security_descriptor.0,
+                bInheritHandle: false.into(),
+            });
+
+            stream! {
+                println!("Stream started, creating pipe instance...");
+                let server_result = ServerOptions::new()
+                    .first_pipe_instance(true)
+                    .create_with_security_attributes_raw(
+                        &name,
+                        addr_of_mut!(security_attributes) as *mut c_void
+                    );
+
+                let mut server = match server_result {
+                    Ok(s) => {
+                        println!("Named pipe created successfully: {}", name);
+                        s
+                    }
+                    Err(e) => {
+                        println!("Failed to create named pipe: {:?}", e);
+                        yield Err(e);
+                        return;
+                    }
+                };
+
+                loop {
+                    println!("Waiting for client connection...");
+                    server.connect().await?;
+                    println!("Client connected!");
+
+                    yield Ok(server);
+
+                    server = ServerOptions::new()
+                        .create_with_security_attributes_raw(
+                            &name,
+                            addr_of_mut!(security_attributes) as *mut c_void
+                        )?;
+                }
+            }
+        }
+    }
+}
+
+// in-process channel, used for tests and debugging without pipes
+// clones share the same listener, so the server and the client can live in the same process
+#[derive(Debug, Clone)]
+pub struct InProcess {
+    sender: mpsc::UnboundedSender<DuplexStream>,
+    receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<DuplexStream>>>>,
+}
+
+impl InProcess {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        Self {
+            sender,
+            receiver: Arc::new(Mutex::new(Some(receiver))),
+        }
+    }
+}
+
+impl Default for InProcess {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for InProcess {
+    type Client = DuplexStream;
+    type Server = DuplexStream;
+
+    async fn connect(&self) -> io::Result<DuplexStream> {
+        let (client, server) = io::duplex(IN_PROCESS_BUFFER_SIZE);
+
+        self.sender.send(server).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "in-process server is not running",
+            )
+        })?;
+
+        Ok(client)
+    }
+
+    fn incoming(&self) -> impl Stream<Item = io::Result<DuplexStream>> + Send + 'static {
+        // only one server can listen on the channel
+        let receiver = self.receiver.lock().ok().and_then(|mut r| r.take());
+
+        stream! {
+            let Some(mut receiver) = receiver else {
+                yield Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "in-process transport is already listening",
+                ));
+                return;
+            };
+
+            while let Some(server) = receiver.recv().await {
+                yield Ok(server);
+            }
+        }
+    }
+}
+
+// tcp on the loopback interface, used by developer tools and WSL-side scripts
+// this is never exposed to other machines
+#[derive(Debug, Clone)]
+pub struct Tcp {
+    addr: SocketAddr,
+}
+
+impl Tcp {
+    pub fn loopback(port: u16) -> Self {
+        Self {
+            addr: SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+        }
+    }
+}
+
+impl Transport for Tcp {
+    type Client = TcpStream;
+    type Server = TcpStream;
+
+    async fn connect(&self) -> io::Result<TcpStream> {
+        let stream = TcpStream::connect(self.addr).await?;
+        stream.set_nodelay(true)?;
+
+        Ok(stream)
+    }
+
+    fn incoming(&self) -> impl Stream<Item = io::Result<TcpStream>> + Send + 'static {
+        let addr = self.addr;
+
+        stream! {
+            let listener = TcpListener::bind(addr).await?;
+            println!("Listening on tcp://{}", addr);
+
+            loop {
+                let (stream, _) = listener.accept().await?;
+                stream.set_nodelay(true)?;
+
+                yield Ok(stream);
+            }
+        }
+    }
+}
+
+// metadata which the requests over tcp must have, since any process of any user can connect to
+// the loopback interface, e.g. `grpcurl -H "azookey-token: <the token>"`
+pub const TCP_TOKEN_METADATA: &str = "azookey-token";
+
+// a new token for the tcp listener, written to `get_tcp_token_path` every time the server starts
+// the file is under the user's profile, which other users can't read
+pub fn create_tcp_token() -> io::Result<String> {
+    let mut bytes = [0u8; 32];
+    unsafe {
+        BCryptGenRandom(
+            BCRYPT_ALG_HANDLE::default(),
+            &mut bytes,
+            BCRYPT_USE_SYSTEM_PREFERRED_RNG,
+        )
+    }
+    .ok()
+    .map_err(io::Error::other)?;
+    let token: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+
+    let path = crate::get_tcp_token_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, &token)?;
+
+    Ok(token)
+}
+
+// interceptor of the services served over tcp, see `TCP_TOKEN_METADATA`
+// the token is compared in constant time, so the time of the answer doesn't tell how much matched
+pub fn check_tcp_token(
+    token: String,
+) -> impl Fn(tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> + Clone {
+    move |request| match request.metadata().get(TCP_TOKEN_METADATA) {
+        Some(value) if bool::from(value.as_bytes().ct_eq(token.as_bytes())) => Ok(request),
+        _ => Err(tonic::Status::unauthenticated("missing or wrong token")),
+    }
+}
+
+// gRPC channel over the transport, shared by the client, the settings app and the tests
+// the uri is only used for the headers, the connection always goes through the transport
+pub async fn channel<T: Transport>(
+    uri: &'static str,
+    transport: T,
+) -> Result<Channel, tonic::transport::Error> {
+    let transport = Arc::new(transport);
+    Endpoint::from_static(uri)
+        .connect_with_connector(service_fn(move |_| {
+            let transport = transport.clone();
+            async move { Ok::<_, io::Error>(TokioIo::new(transport.connect().await?)) }
+        }))
+        .await
+}
@@ -13,6 +13,8 @@ use windows::Win32::{
 };
 use wry::WebViewBuilder;
 
+use shared::{FontConfig, CANDIDATE_PAGE_SIZE};
+
 use crate::UserEvent;
 
 pub fn create_candidate_window(event_loop: &EventLoop<UserEvent>) -> Result<Window> {
@@ -42,7 +44,8 @@ pub fn create_candidate_window(event_loop: &EventLoop<UserEvent>) -> Result<Wind
     Ok(window)
 }
 
-pub fn create_candidate_webview<'a>() -> Result<WebViewBuilder<'a>> {
+pub fn create_candidate_webview<'a>(font: &FontConfig) -> Result<WebViewBuilder<'a>> {
+    let font = serde_json::to_string(font).context("Failed to serialize the font")?;
     let webview_builder = WebViewBuilder::new()
     .with_transparent(true)
     .with_html(
@@ -77,7 +80,6 @@ pub fn create_candidate_webview<'a>() -> Result<WebViewBuilder<'a>> {
                         scroll-snap-type: y proximity;
                         list-style-position: inside;
                         list-style-type: none;
-                        counter-reset: number 0;
                         user-select: none;
                         cursor: pointer;
 
@@ -92,14 +94,16 @@ pub fn create_candidate_webview<'a>() -> Result<WebViewBuilder<'a>> {
                     }
                     li {
                         padding: 0.5rem;
-                        font-size: 0.9rem;
+                        /* see applyFont */
+                        font-family: var(--font-family);
+                        font-size: var(--font-size, 0.9rem);
+                        line-height: var(--line-spacing, normal);
                         display: flex;
                         align-items: center;
                         scroll-snap-align: start;
 
                         &::before {
-                            content: counter(number);
-                            counter-increment: number 1;
+                            content: attr(data-number);
                             color: #636363;
                             font-weight: bold;
                             font-size: 0.75rem;
@@ -114,6 +118,133 @@ pub fn create_candidate_webview<'a>() -> Result<WebViewBuilder<'a>> {
                             outline: 1px solid #2CB5FF;
                             outline-offset: -1px;
                         }
+
+                        .annotation {
+                            margin-left: auto;
+                            padding-left: 0.75rem;
+                            max-width: 50%;
+                            color: #636363;
+                            font-size: 0.75rem;
+                            white-space: nowrap;
+                            overflow: hidden;
+                            text-overflow: ellipsis;
+                        }
+                    }
+                    /* the predictions while typing, fewer and without the numbers */
+                    main[data-compact] li {
+                        &::before {
+                            display: none;
+                        }
+
+                        &:nth-child(n+4) {
+                            display: none;
+                        }
+
+                        &[data-selected] {
+                            background-color: transparent;
+                            outline: none;
+                        }
+                    }
+                    #predictions {
+                        display: flex;
+                        align-items: center;
+                        gap: 2px;
+                        padding: 0 0 6px 0;
+                        margin-bottom: 4px;
+                        border-bottom: 1px solid #E4E4E4;
+                        font-size: 0.8rem;
+                        white-space: nowrap;
+                        overflow: hidden;
+                        user-select: none;
+
+                        &::before {
+                            content: 'Tab';
+                            color: #636363;
+                            font-weight: bold;
+                            font-size: 0.7rem;
+                            margin: 0 6px 0 4px;
+                        }
+
+                        &[hidden] {
+                            display: none;
+                        }
+
+                        span {
+                            padding: 2px 6px;
+                        }
+
+                        span[data-selected] {
+                            background-color: #D4F0FF;
+                            border-radius: 3px;
+                            outline: 1px solid #2CB5FF;
+                            outline-offset: -1px;
+                        }
+                    }
+                    #palette {
+                        flex: 1;
+                        display: flex;
+                        flex-direction: column;
+                        min-height: 0;
+                        user-select: none;
+                        cursor: pointer;
+
+                        &[hidden] {
+                            display: none;
+                        }
+                    }
+                    #palette-tabs {
+                        display: flex;
+                        gap: 2px;
+                        padding: 0 0 6px 0;
+                        margin-bottom: 4px;
+                        border-bottom: 1px solid #E4E4E4;
+                        font-size: 0.8rem;
+                        white-space: nowrap;
+                        overflow-x: auto;
+
+                        span {
+                            padding: 2px 6px;
+                        }
+
+                        span[data-selected] {
+                            background-color: #D4F0FF;
+                            border-radius: 3px;
+                            outline: 1px solid #2CB5FF;
+                            outline-offset: -1px;
+                        }
+                    }
+                    #palette-grid {
+                        flex: 1;
+                        display: grid;
+                        grid-template-columns: repeat(4, 1fr);
+                        align-content: start;
+                        overflow-y: auto;
+                        font-size: 0.9rem;
+
+                        span {
+                            padding: 0.5rem;
+                            border-radius: 3px;
+                            white-space: nowrap;
+                            overflow: hidden;
+                        }
+
+                        span:hover {
+                            background-color: #D4F0FF;
+                        }
+                    }
+                    #detail {
+                        padding: 6px 10px;
+                        border-top: 1px solid #E4E4E4;
+                        color: #636363;
+                        font-size: 0.8rem;
+                        line-height: 1.2rem;
+                        height: 2.4rem;
+                        overflow: hidden;
+                        user-select: none;
+
+                        &[hidden] {
+                            display: none;
+                        }
                     }
                     footer {
                         display: flex;
@@ -124,6 +255,15 @@ pub fn create_candidate_webview<'a>() -> Result<WebViewBuilder<'a>> {
                         font-size: 0.8rem;
                         user-select: none;
                     }
+                    #latency {
+                        color: #636363;
+                        font-family: monospace;
+                        font-size: 0.7rem;
+                    }
+                    #position {
+                        color: #636363;
+                        font-size: 0.75rem;
+                    }
 
                     @media (prefers-color-scheme: dark) {
                         body {
@@ -143,29 +283,122 @@ pub fn create_candidate_webview<'a>() -> Result<WebViewBuilder<'a>> {
                                 color: #BDBDBD;
                             }
 
+                            .annotation {
+                                color: #BDBDBD;
+                            }
+
                             &[data-selected] {
                                 background-color: #3949AB;
                                 outline: 1px solid #5C6BC0;
                             }
                         }
                             
+                        #predictions {
+                            border-bottom: 1px solid #424242;
+
+                            &::before {
+                                color: #BDBDBD;
+                            }
+
+                            span[data-selected] {
+                                background-color: #3949AB;
+                                outline: 1px solid #5C6BC0;
+                            }
+                        }
+                        #palette-tabs {
+                            border-bottom: 1px solid #424242;
+
+                            span[data-selected] {
+                                background-color: #3949AB;
+                                outline: 1px solid #5C6BC0;
+                            }
+                        }
+                        #palette-grid span:hover {
+                            background-color: #3949AB;
+                        }
+                        #detail {
+                            color: #BDBDBD;
+                            border-top: 1px solid #424242;
+                        }
                         footer {
                             border-top: 1px solid #424242;
                         }
+                        #latency, #position {
+                            color: #BDBDBD;
+                        }
                     }
                 </style>
                 <script>
-                    function updateCandidates(candidates) {
+                    // timing of the last keystroke, only sent in debug mode
+                    let latency = null;
+                    let renderTime = 0;
+                    // the number keys select from the shown page, see CANDIDATE_PAGE_SIZE
+                    const PAGE_SIZE = {PAGE_SIZE};
+                    // candidates which fit in the list, reported to the client for the number keys
+                    let pageSize = PAGE_SIZE;
+                    let itemHeight = 0;
+                    // candidates with their reading, annotation, source and index
+                    let items = [];
+                    let selectedIndex = 0;
+                    // height of the window without the detail pane
+                    let baseHeight = 0;
+                    // categories of the palette shown instead of the list, and the chosen one
+                    let palette = [];
+                    let paletteCategory = 0;
+                    // the predictions while typing are shown in a smaller window until Space
+                    const COMPACT_PAGE_SIZE = 3;
+                    let compact = false;
+                    // the text of the candidates, see UiConfig.font
+                    let font = {FONT};
+
+                    function applyFont() {
+                        const style = document.documentElement.style;
+                        style.setProperty('--font-size', `${font.size}px`);
+                        style.setProperty('--line-spacing', font.line_spacing);
+                        if (font.family) {
+                            style.setProperty('--font-family', JSON.stringify(font.family));
+                        } else {
+                            style.removeProperty('--font-family');
+                        }
+                    }
+
+                    function updateFont(value) {
+                        font = value;
+                        applyFont();
+                        // the page is measured again with the new height of the items
+                        adjustWindowSize();
+                        updateCandidates(items, compact);
+                        updateSelection(selectedIndex);
+                    }
+
+                    function updateCandidates(candidates, isCompact = false) {
+                        const start = performance.now();
+                        items = candidates;
+                        if (compact !== isCompact) {
+                            compact = isCompact;
+                            document.querySelector('main').toggleAttribute('data-compact', compact);
+                            resizeWindow();
+                        }
+                        // the palette is shown until the next candidates
+                        document.getElementById('palette').hidden = true;
+                        document.getElementById('candidate-list').hidden = false;
                         const candidateList = document.getElementById('candidate-list');
 
                         const existingItems = Array.from(candidateList.children);
 
                         candidates.forEach((candidate, index) => {
-                            if (existingItems[index]) {
-                                existingItems[index].textContent = candidate;
-                            } else {
-                                const li = document.createElement('li');
-                                li.textContent = candidate;
+                            const li = existingItems[index] ?? document.createElement('li');
+                            li.textContent = candidate.text;
+                            // dimmed after the text, the whole one is in the detail pane
+                            if (candidate.annotation) {
+                                const annotation = document.createElement('span');
+                                annotation.className = 'annotation';
+                                annotation.textContent = candidate.annotation;
+                                li.appendChild(annotation);
+                            }
+                            li.dataset.number = (index % pageSize) + 1;
+                            li.dataset.source = candidate.source;
+                            if (!existingItems[index]) {
                                 candidateList.appendChild(li);
                             }
                         });
@@ -173,29 +406,177 @@ pub fn create_candidate_webview<'a>() -> Result<WebViewBuilder<'a>> {
                         while (existingItems.length > candidates.length) {
                             candidateList.removeChild(existingItems.pop());
                         }
+
+                        showDetail();
+                        showPosition();
+
+                        if (latency) {
+                            requestAnimationFrame(() => {
+                                renderTime = performance.now() - start;
+                                showLatency();
+                            });
+                        }
+                    }
+
+                    // completions of the reading, shown above the list while composing
+                    function updatePredictions({ predictions, selection }) {
+                        const row = document.getElementById('predictions');
+                        row.replaceChildren(...predictions.map((prediction, index) => {
+                            const span = document.createElement('span');
+                            span.textContent = prediction.text;
+                            if (index === selection) {
+                                span.setAttribute('data-selected', '');
+                            }
+                            return span;
+                        }));
+
+                        const hidden = predictions.length === 0;
+                        if (row.hidden !== hidden) {
+                            row.hidden = hidden;
+                            resizeWindow();
+                        }
+                    }
+
+                    // faces and such in a grid by category, instead of the list
+                    function showPalette(categories) {
+                        palette = categories;
+                        paletteCategory = 0;
+                        document.getElementById('candidate-list').hidden = true;
+                        document.getElementById('palette').hidden = false;
+                        document.getElementById('position').textContent = '';
+                        renderPalette();
+                    }
+
+                    function renderPalette() {
+                        const tabs = document.getElementById('palette-tabs');
+                        tabs.replaceChildren(...palette.map((category, index) => {
+                            const span = document.createElement('span');
+                            span.textContent = category.name;
+                            if (index === paletteCategory) {
+                                span.setAttribute('data-selected', '');
+                            }
+                            span.addEventListener('click', () => {
+                                paletteCategory = index;
+                                renderPalette();
+                            });
+                            return span;
+                        }));
+
+                        // the items are numbered through all the categories
+                        const first = palette
+                            .slice(0, paletteCategory)
+                            .reduce((sum, category) => sum + category.items.length, 0);
+                        const grid = document.getElementById('palette-grid');
+                        grid.replaceChildren(...(palette[paletteCategory]?.items ?? []).map((item, position) => {
+                            const span = document.createElement('span');
+                            span.textContent = item;
+                            span.title = item;
+                            span.addEventListener('click', () => {
+                                window.ipc.postMessage(JSON.stringify({
+                                    type: 'palette',
+                                    index: first + position
+                                }));
+                            });
+                            return span;
+                        }));
+                        grid.scrollTop = 0;
+                    }
+
+                    function updateLatency(value) {
+                        latency = value;
+                        showLatency();
+                    }
+
+                    function showLatency() {
+                        const ms = (us) => (us / 1000).toFixed(1);
+                        document.getElementById('latency').textContent =
+                            `queue ${ms(latency.queue)} / conv ${ms(latency.conversion)} / ` +
+                            `window ${ms(latency.window)} / render ${renderTime.toFixed(1)} ms`;
                     }
 
                     function updateSelection(index) {
                         const candidateList = document.getElementById('candidate-list');
+                        if (!candidateList.children[index]) {
+                            return;
+                        }
+
                         const selected = candidateList.querySelector('[data-selected]');
                         if (selected) {
                             selected.removeAttribute('data-selected');
                         }
                         
                         candidateList.children[index].setAttribute('data-selected', '');
+                        selectedIndex = index;
+                        showDetail();
+                        showPosition();
                         
-                        const itemHeight = candidateList.children[0].offsetHeight;
-                        const visibleItems = Math.floor(candidateList.clientHeight / itemHeight);
-                        
-                        const groupSize = 5;
-                        const groupIndex = Math.floor(index / groupSize);
-                        const scrollToIndex = groupIndex * groupSize;
+                        const groupIndex = Math.floor(index / pageSize);
+                        const scrollToIndex = groupIndex * pageSize;
                         
                         if (index === scrollToIndex || !isElementInView(candidateList.children[index], candidateList)) {
                             candidateList.children[scrollToIndex].scrollIntoView({ behavior: "instant", block: "start", inline: "start" });
                         }
                     }
                     
+                    function showDetail() {
+                        const item = items[selectedIndex];
+                        document.getElementById('detail').textContent =
+                            item?.annotation || '説明はありません';
+                    }
+
+                    // e.g. 3/27 for the third of all the candidates
+                    function showPosition() {
+                        document.getElementById('position').textContent = items.length && !compact
+                            ? `${Math.min(selectedIndex, items.length - 1) + 1}/${items.length}`
+                            : '';
+                    }
+
+                    function toggleDetail() {
+                        const detail = document.getElementById('detail');
+                        detail.hidden = !detail.hidden;
+                        showDetail();
+                        resizeWindow();
+                    }
+
+                    function resizeWindow() {
+                        const detail = document.getElementById('detail');
+                        const detailHeight = detail.hidden ? 0 : detail.offsetHeight;
+                        const predictions = document.getElementById('predictions');
+                        const predictionsHeight = predictions.hidden
+                            ? 0
+                            : predictions.offsetHeight + parseInt(window.getComputedStyle(predictions).marginBottom);
+
+                        const hiddenHeight = compact ? itemHeight * (PAGE_SIZE - COMPACT_PAGE_SIZE) : 0;
+
+                        window.ipc.postMessage(JSON.stringify({
+                            type: 'resize',
+                            height: baseHeight - hiddenHeight + detailHeight + predictionsHeight
+                        }));
+                    }
+
+                    function updatePageSize() {
+                        const candidateList = document.getElementById('candidate-list');
+                        // the smaller window is not a shorter page for the number keys
+                        if (!itemHeight || compact) {
+                            return;
+                        }
+
+                        const fits = Math.floor(candidateList.clientHeight / itemHeight);
+                        const size = Math.min(PAGE_SIZE, Math.max(1, fits));
+                        if (size === pageSize) {
+                            return;
+                        }
+
+                        pageSize = size;
+                        Array.from(candidateList.children).forEach((li, index) => {
+                            li.dataset.number = (index % pageSize) + 1;
+                        });
+                        window.ipc.postMessage(JSON.stringify({
+                            type: 'page_size',
+                            size: pageSize
+                        }));
+                    }
+
                     function isElementInView(element, container) {
                         const containerRect = container.getBoundingClientRect();
                         const elementRect = element.getBoundingClientRect();
@@ -212,8 +593,8 @@ pub fn create_candidate_webview<'a>() -> Result<WebViewBuilder<'a>> {
                         // Clear any existing items
                         candidateList.innerHTML = '';
                         
-                        // Add 5 test items to measure
-                        for (let i = 0; i < 5; i++) {
+                        // Add a page of test items to measure
+                        for (let i = 0; i < PAGE_SIZE; i++) {
                             const li = document.createElement('li');
                             li.textContent = `Item ${i+1}`;
                             candidateList.appendChild(li);
@@ -225,10 +606,10 @@ pub fn create_candidate_webview<'a>() -> Result<WebViewBuilder<'a>> {
                         const body = document.body;
                         
                         // Get the height of a single item
-                        const itemHeight = candidateList.children[0].offsetHeight;
+                        itemHeight = candidateList.children[0].offsetHeight;
                         
-                        // Calculate the height needed for exactly 5 items
-                        const candidateListHeight = itemHeight * 5;
+                        // Calculate the height needed for exactly one page
+                        const candidateListHeight = itemHeight * PAGE_SIZE;
                         const footerHeight = footer.offsetHeight;
                         const mainPadding = parseInt(window.getComputedStyle(main).paddingTop) + 
                                            parseInt(window.getComputedStyle(main).paddingBottom);
@@ -236,35 +617,60 @@ pub fn create_candidate_webview<'a>() -> Result<WebViewBuilder<'a>> {
                                           parseInt(window.getComputedStyle(body).paddingBottom);
                         
                         // Calculate total window height needed
-                        const totalHeight = candidateListHeight + footerHeight + mainPadding + bodyPadding;
+                        baseHeight = candidateListHeight + footerHeight + mainPadding + bodyPadding;
                         
                         // Clear the test items
                         candidateList.innerHTML = '';
                         
-                        window.ipc.postMessage(JSON.stringify({
-                            type: 'resize',
-                            height: totalHeight
-                        }));
+                        resizeWindow();
                     }
 
                     
                     window.addEventListener('DOMContentLoaded', () => {
+                        applyFont();
                         setTimeout(adjustWindowSize, 50); // Small delay to ensure rendering is complete
+
+                        // the list gets shorter or taller when the window or the font size changes
+                        window.addEventListener('resize', updatePageSize);
+
+                        // commit the clicked candidate
+                        document.getElementById('candidate-list').addEventListener('click', (event) => {
+                            const item = event.target.closest('li');
+                            if (!item) {
+                                return;
+                            }
+
+                            const position = Array.from(item.parentElement.children).indexOf(item);
+                            window.ipc.postMessage(JSON.stringify({
+                                type: 'select',
+                                index: items[position]?.index ?? position
+                            }));
+                        });
                     });
                 </script>
             </head>
             <body style="margin: 0;">
                 <main>
+                    <div id="predictions" hidden></div>
                     <ol id="candidate-list">
                     </ol>
+                    <div id="palette" hidden>
+                        <div id="palette-tabs"></div>
+                        <div id="palette-grid"></div>
+                    </div>
+                    <div id="detail" hidden></div>
                     <footer>
                         <svg width="20" height="14" viewBox="0 0 22 16" fill="none" xmlns="http://www.w3.org/2000/svg">
                             <path d="M3.5 8C4.59202 9.04403 7.54398 10.3978 13.5068 9.93754M1.25349 5.39919C2.77722 0.413397 8.08911 0.79692 10.9673 1.24436C14.2687 1.71311 20.8969 3.82675 20.9985 8.53129C21.1255 14.412 13.1894 15.3069 10.0784 14.9233C6.96748 14.5398 -0.46071 13.0696 1.25349 5.39919Z" stroke="#838384" stroke-width="1.5" stroke-linecap="round"/>
                         </svg>
+                        <span id="latency"></span>
+                        <span id="position"></span>
                     </footer>
                 </main>
             </body>
-        </html>"##,
+        </html>"##
+            .replace("{PAGE_SIZE}", &CANDIDATE_PAGE_SIZE.to_string())
+            .replace("{FONT}", &font),
     );
 
     Ok(webview_builder)
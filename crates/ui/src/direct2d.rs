@@ -0,0 +1,946 @@
+use anyhow::Result;
+use shared::{FontConfig, CANDIDATE_PAGE_SIZE};
+use tao::{dpi::LogicalSize, platform::windows::WindowExtWindows, window::Window};
+use windows::{
+    core::{w, Interface as _, PCWSTR},
+    Win32::{
+        Foundation::{HWND, RECT},
+        Graphics::{
+            Direct2D::{
+                Common::{
+                    D2D1_ALPHA_MODE_PREMULTIPLIED, D2D1_COLOR_F, D2D1_PIXEL_FORMAT, D2D_POINT_2F,
+                    D2D_RECT_F, D2D_SIZE_U,
+                },
+                D2D1CreateFactory, ID2D1Factory, ID2D1HwndRenderTarget, ID2D1RenderTarget,
+                ID2D1SolidColorBrush, D2D1_DRAW_TEXT_OPTIONS_CLIP,
+                D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT, D2D1_FACTORY_TYPE_SINGLE_THREADED,
+                D2D1_HWND_RENDER_TARGET_PROPERTIES, D2D1_PRESENT_OPTIONS_NONE,
+                D2D1_RENDER_TARGET_PROPERTIES, D2D1_ROUNDED_RECT,
+            },
+            DirectWrite::{
+                DWriteCreateFactory, IDWriteFactory2, IDWriteFontFallback, IDWriteTextFormat,
+                IDWriteTextFormat1, IDWriteTextLayout, DWRITE_FACTORY_TYPE_SHARED,
+                DWRITE_FLOW_DIRECTION_RIGHT_TO_LEFT, DWRITE_FONT_STRETCH_NORMAL,
+                DWRITE_FONT_STYLE_NORMAL, DWRITE_FONT_WEIGHT_BOLD, DWRITE_FONT_WEIGHT_NORMAL,
+                DWRITE_READING_DIRECTION_TOP_TO_BOTTOM, DWRITE_TEXT_METRICS, DWRITE_UNICODE_RANGE,
+                DWRITE_WORD_WRAPPING_NO_WRAP,
+            },
+            Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM,
+        },
+        UI::WindowsAndMessaging::GetClientRect,
+    },
+};
+
+use crate::ipc::{CandidateItem, PaletteCategory};
+
+// sizes in DIPs, close to the webview
+const FONT_FAMILY: PCWSTR = w!("Yu Gothic UI");
+const LOCALE: PCWSTR = w!("ja-JP");
+const SMALL_FONT_SIZE: f32 = 12.0;
+const WINDOW_PADDING: f32 = 15.0;
+const ITEM_PADDING: f32 = 8.0;
+const NUMBER_SIZE: f32 = 24.0;
+const MIN_TEXT_SIZE: f32 = 80.0;
+// the longer annotations are cut, the whole one is in the detail pane
+const ANNOTATION_SIZE: f32 = 160.0;
+const DETAIL_HEIGHT: f32 = 38.0;
+const FOOTER_HEIGHT: f32 = 24.0;
+const PREDICTION_HEIGHT: f32 = 30.0;
+const PALETTE_COLUMNS: usize = 4;
+// the candidates shown while typing, see `set_candidates`
+const COMPACT_PAGE_SIZE: usize = 3;
+
+// CJK Unified Ideographs Extension B and later, which most UI fonts don't have
+const RARE_KANJI: &[DWRITE_UNICODE_RANGE] = &[
+    DWRITE_UNICODE_RANGE {
+        first: 0x20000,
+        last: 0x2FA1F,
+    },
+    DWRITE_UNICODE_RANGE {
+        first: 0x30000,
+        last: 0x3134F,
+    },
+];
+const RARE_KANJI_FONTS: &[PCWSTR] = &[w!("Yu Gothic"), w!("SimSun-ExtB"), w!("MingLiU-ExtB")];
+
+// pictographs and symbols of the emoji candidates, used only when the font of the window
+// doesn't have them
+const EMOJI: &[DWRITE_UNICODE_RANGE] = &[
+    // ☀ to ➿
+    DWRITE_UNICODE_RANGE {
+        first: 0x2600,
+        last: 0x27BF,
+    },
+    // 🀄 to 🫸
+    DWRITE_UNICODE_RANGE {
+        first: 0x1F000,
+        last: 0x1FAFF,
+    },
+    // variation selector and zero width joiner of the sequences
+    DWRITE_UNICODE_RANGE {
+        first: 0xFE0F,
+        last: 0xFE0F,
+    },
+    DWRITE_UNICODE_RANGE {
+        first: 0x200D,
+        last: 0x200D,
+    },
+];
+const EMOJI_FONTS: &[PCWSTR] = &[w!("Segoe UI Emoji")];
+
+struct Palette {
+    background: D2D1_COLOR_F,
+    border: D2D1_COLOR_F,
+    text: D2D1_COLOR_F,
+    muted: D2D1_COLOR_F,
+    selected: D2D1_COLOR_F,
+    outline: D2D1_COLOR_F,
+}
+
+const fn rgb(hex: u32) -> D2D1_COLOR_F {
+    D2D1_COLOR_F {
+        r: ((hex >> 16) & 0xFF) as f32 / 255.0,
+        g: ((hex >> 8) & 0xFF) as f32 / 255.0,
+        b: (hex & 0xFF) as f32 / 255.0,
+        a: 1.0,
+    }
+}
+
+// same colors as the light theme of the webview
+const PALETTE: Palette = Palette {
+    background: rgb(0xFFFFFF),
+    border: rgb(0xE4E4E4),
+    text: rgb(0x000000),
+    muted: rgb(0x636363),
+    selected: rgb(0xD4F0FF),
+    outline: rgb(0x2CB5FF),
+};
+
+// candidate window drawn with Direct2D and DirectWrite instead of the webview
+// the text is rendered at the DPI of the monitor, rare kanji fall back to the fonts which have them,
+// and the candidates can be laid out in columns of vertical text
+pub struct CandidateRenderer {
+    hwnd: HWND,
+    factory: ID2D1Factory,
+    dwrite: IDWriteFactory2,
+    // created on the first paint, and again when the device is lost
+    target: Option<ID2D1HwndRenderTarget>,
+    fallback: IDWriteFontFallback,
+    // see `UiConfig.font`, only for the text of the candidates
+    font: FontConfig,
+    text_format: IDWriteTextFormat,
+    vertical_format: IDWriteTextFormat,
+    number_format: IDWriteTextFormat,
+    small_format: IDWriteTextFormat,
+    vertical: bool,
+    scale_factor: f64,
+    candidates: Vec<CandidateItem>,
+    selection: usize,
+    // the predictions while typing, shown in a smaller window until Space converts the reading
+    compact: bool,
+    // completions of the reading above the list, and the one chosen with Tab
+    predictions: Vec<CandidateItem>,
+    prediction: Option<usize>,
+    detail: bool,
+    latency: Option<String>,
+    // shown instead of the candidates until the next candidates come, with the chosen category
+    palette: Option<Vec<PaletteCategory>>,
+    palette_category: usize,
+    // the widest and the tallest item of all the categories, so that switching keeps the width
+    palette_cell: (f32, f32),
+    // rects of what can be clicked in DIPs, for clicks
+    hit_rects: Vec<(D2D_RECT_F, Hit)>,
+}
+
+// what is under the cursor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hit {
+    // the index in the candidate list
+    Candidate(i32),
+    // a tab of the palette, switched in the window
+    PaletteCategory(usize),
+    // numbered through all the categories
+    PaletteItem(i32),
+}
+
+impl CandidateRenderer {
+    pub fn new(window: &Window, vertical: bool, font: FontConfig) -> Result<Self> {
+        unsafe {
+            let factory: ID2D1Factory = D2D1CreateFactory(D2D1_FACTORY_TYPE_SINGLE_THREADED, None)?;
+            let dwrite: IDWriteFactory2 = DWriteCreateFactory(DWRITE_FACTORY_TYPE_SHARED)?;
+
+            let number_format = create_format(&dwrite, FONT_FAMILY, SMALL_FONT_SIZE, true)?;
+            let small_format = create_format(&dwrite, FONT_FAMILY, SMALL_FONT_SIZE, false)?;
+
+            // the system fallback picks a font per script, which may not have the rare kanji
+            // or the emoji in color
+            let builder = dwrite.CreateFontFallbackBuilder()?;
+            for (ranges, fonts) in [(RARE_KANJI, RARE_KANJI_FONTS), (EMOJI, EMOJI_FONTS)] {
+                let families: Vec<*const u16> = fonts.iter().map(|f| f.as_ptr()).collect();
+                builder.AddMapping(ranges, &families, None, PCWSTR::null(), PCWSTR::null(), 1.0)?;
+            }
+            builder.AddMappings(&dwrite.GetSystemFontFallback()?)?;
+            let fallback = builder.CreateFontFallback()?;
+            small_format
+                .cast::<IDWriteTextFormat1>()?
+                .SetFontFallback(&fallback)?;
+            let (text_format, vertical_format) = candidate_formats(&dwrite, &fallback, &font)?;
+
+            Ok(Self {
+                hwnd: HWND(window.hwnd() as *mut std::ffi::c_void),
+                factory,
+                dwrite,
+                target: None,
+                fallback,
+                font,
+                text_format,
+                vertical_format,
+                number_format,
+                small_format,
+                vertical,
+                scale_factor: window.scale_factor(),
+                candidates: vec![],
+                selection: 0,
+                compact: false,
+                predictions: vec![],
+                prediction: None,
+                detail: false,
+                latency: None,
+                palette: None,
+                palette_category: 0,
+                palette_cell: (0.0, 0.0),
+                hit_rects: vec![],
+            })
+        }
+    }
+
+    pub fn set_candidates(&mut self, candidates: Vec<CandidateItem>, compact: bool) {
+        self.candidates = candidates;
+        self.selection = 0;
+        self.compact = compact;
+        self.palette = None;
+    }
+
+    pub fn set_palette(&mut self, categories: Vec<PaletteCategory>) {
+        let mut width = MIN_TEXT_SIZE / 2.0;
+        let mut height = self.line_height();
+        for item in categories.iter().flat_map(|category| &category.items) {
+            if let Ok(metrics) = self.measure(item, &self.text_format) {
+                width = width.max(metrics.widthIncludingTrailingWhitespace);
+                height = height.max(metrics.height);
+            }
+        }
+        self.palette_cell = (width + ITEM_PADDING * 2.0, height + ITEM_PADDING * 2.0);
+        self.palette = Some(categories);
+        self.palette_category = 0;
+    }
+
+    pub fn set_palette_category(&mut self, index: usize) {
+        if let Some(categories) = &self.palette {
+            if index < categories.len() {
+                self.palette_category = index;
+            }
+        }
+    }
+
+    pub fn set_selection(&mut self, index: i32) {
+        if (index as usize) < self.candidates.len() {
+            self.selection = index as usize;
+        }
+    }
+
+    pub fn set_predictions(&mut self, predictions: Vec<CandidateItem>, selection: i32) {
+        self.prediction = usize::try_from(selection)
+            .ok()
+            .filter(|index| *index < predictions.len());
+        self.predictions = predictions;
+    }
+
+    // the window is resized for the new font by the caller, like the other changes
+    pub fn set_font(&mut self, font: FontConfig) -> Result<()> {
+        let (text_format, vertical_format) =
+            unsafe { candidate_formats(&self.dwrite, &self.fallback, &font)? };
+        self.text_format = text_format;
+        self.vertical_format = vertical_format;
+        self.font = font;
+        Ok(())
+    }
+
+    // the window is placed for the layout with the next position, see `get_candidate_window_position`
+    pub fn set_vertical(&mut self, vertical: bool) {
+        self.vertical = vertical;
+    }
+
+    pub fn toggle_detail(&mut self) {
+        self.detail = !self.detail;
+    }
+
+    pub fn set_latency(&mut self, queue_us: u32, conversion_us: u32, window_us: u32) {
+        let ms = |us: u32| us as f64 / 1000.0;
+        self.latency = Some(format!(
+            "queue {:.1} / conv {:.1} / window {:.1} ms",
+            ms(queue_us),
+            ms(conversion_us),
+            ms(window_us)
+        ));
+    }
+
+    // the render target follows the DPI of the monitor the window is on
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        if let Some(target) = &self.target {
+            let dpi = (96.0 * scale_factor) as f32;
+            unsafe { target.SetDpi(dpi, dpi) };
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if let Some(target) = &self.target {
+            let size = D2D_SIZE_U { width, height };
+            if unsafe { target.Resize(&size) }.is_err() {
+                self.target = None;
+            }
+        }
+    }
+
+    // size of the window for the current candidates
+    pub fn size(&self) -> LogicalSize<f64> {
+        if let Some((width, height)) = self.palette_size() {
+            return LogicalSize::new(
+                (width + WINDOW_PADDING * 2.0) as f64,
+                (height + WINDOW_PADDING * 2.0) as f64,
+            );
+        }
+
+        let (list_width, list_height) = self.list_size();
+        let mut width = list_width;
+        let mut height = list_height;
+
+        if !self.predictions.is_empty() {
+            width = width.max(self.predictions_width());
+            height += PREDICTION_HEIGHT;
+        }
+        if self.detail {
+            width = width.max(MIN_TEXT_SIZE * 2.0);
+            height += DETAIL_HEIGHT;
+        }
+        if self.latency.is_some() {
+            width = width.max(MIN_TEXT_SIZE * 3.0);
+        }
+        if self.latency.is_some() || self.position().is_some() {
+            height += FOOTER_HEIGHT;
+        }
+
+        LogicalSize::new(
+            (width + WINDOW_PADDING * 2.0) as f64,
+            (height + WINDOW_PADDING * 2.0) as f64,
+        )
+    }
+
+    // what is under the cursor, in physical pixels
+    pub fn hit_test(&self, x: f64, y: f64) -> Option<Hit> {
+        let x = (x / self.scale_factor) as f32;
+        let y = (y / self.scale_factor) as f32;
+
+        self.hit_rects
+            .iter()
+            .find(|(rect, _)| rect.left <= x && x < rect.right && rect.top <= y && y < rect.bottom)
+            .map(|(_, hit)| *hit)
+    }
+
+    pub fn render(&mut self) -> Result<()> {
+        let target = match &self.target {
+            Some(target) => target.clone(),
+            None => {
+                let target = self.create_target()?;
+                self.target = Some(target.clone());
+                target
+            }
+        };
+
+        let result = unsafe { self.draw(&target) };
+        if result.is_err() {
+            // e.g. the display driver was updated, the target is created again on the next paint
+            self.target = None;
+        }
+        result
+    }
+
+    fn create_target(&self) -> Result<ID2D1HwndRenderTarget> {
+        let dpi = (96.0 * self.scale_factor) as f32;
+        let mut rect = RECT::default();
+        unsafe { GetClientRect(self.hwnd, &mut rect)? };
+
+        let properties = D2D1_RENDER_TARGET_PROPERTIES {
+            pixelFormat: D2D1_PIXEL_FORMAT {
+                format: DXGI_FORMAT_B8G8R8A8_UNORM,
+                alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+            },
+            dpiX: dpi,
+            dpiY: dpi,
+            ..Default::default()
+        };
+        let hwnd_properties = D2D1_HWND_RENDER_TARGET_PROPERTIES {
+            hwnd: self.hwnd,
+            pixelSize: D2D_SIZE_U {
+                width: (rect.right - rect.left) as u32,
+                height: (rect.bottom - rect.top) as u32,
+            },
+            presentOptions: D2D1_PRESENT_OPTIONS_NONE,
+        };
+
+        Ok(unsafe {
+            self.factory
+                .CreateHwndRenderTarget(&properties, &hwnd_properties)?
+        })
+    }
+
+    // the page which contains the selection, like the number keys
+    // only the first few while typing
+    fn page(&self) -> std::ops::Range<usize> {
+        if self.compact {
+            return 0..COMPACT_PAGE_SIZE.min(self.candidates.len());
+        }
+        let start = self.selection / CANDIDATE_PAGE_SIZE * CANDIDATE_PAGE_SIZE;
+        start..(start + CANDIDATE_PAGE_SIZE).min(self.candidates.len())
+    }
+
+    // e.g. 3/27 for the third of all the candidates, None without candidates or while typing
+    fn position(&self) -> Option<String> {
+        (!self.candidates.is_empty() && !self.compact)
+            .then(|| format!("{}/{}", self.selection + 1, self.candidates.len()))
+    }
+
+    // the lowest a line of the candidates can be, taller with the fallback fonts
+    fn line_height(&self) -> f32 {
+        self.font.size * self.font.line_spacing
+    }
+
+    // line height of a candidate, and the longest text on the page
+    fn text_extent(&self) -> (f32, f32) {
+        let mut line = self.line_height();
+        let mut longest = MIN_TEXT_SIZE;
+
+        for candidate in &self.candidates[self.page()] {
+            if let Ok(metrics) = self.measure(&candidate.text, &self.text_format) {
+                line = line.max(metrics.height);
+                longest = longest.max(metrics.widthIncludingTrailingWhitespace);
+            }
+        }
+
+        (line, longest)
+    }
+
+    // the column of the annotations next to the candidates on the page, 0 if there are none
+    // the vertical columns have no room for them, they are only in the detail pane
+    fn annotation_width(&self) -> f32 {
+        if self.vertical {
+            return 0.0;
+        }
+
+        self.candidates[self.page()]
+            .iter()
+            .filter(|candidate| !candidate.annotation.is_empty())
+            .filter_map(|candidate| self.measure(&candidate.annotation, &self.small_format).ok())
+            .map(|metrics| {
+                metrics
+                    .widthIncludingTrailingWhitespace
+                    .min(ANNOTATION_SIZE)
+            })
+            .fold(0.0, f32::max)
+    }
+
+    // size of the candidate list without the window padding
+    fn list_size(&self) -> (f32, f32) {
+        let count = self.page().len().max(1) as f32;
+        let (line, longest) = self.text_extent();
+        let item = line + ITEM_PADDING * 2.0;
+        let text = longest + ITEM_PADDING * 2.0;
+
+        if self.vertical {
+            // the number above each column, the columns from right to left
+            (item * count, NUMBER_SIZE + text)
+        } else {
+            let mut width = NUMBER_SIZE + text;
+            let annotation = self.annotation_width();
+            if annotation > 0.0 {
+                width += annotation + ITEM_PADDING;
+            }
+            (width, item * count)
+        }
+    }
+
+    // the "Tab" label and the predictions side by side
+    fn predictions_width(&self) -> f32 {
+        let width = |text: &str| {
+            self.measure(text, &self.small_format)
+                .map(|metrics| metrics.widthIncludingTrailingWhitespace)
+                .unwrap_or(0.0)
+        };
+
+        width("Tab")
+            + ITEM_PADDING
+            + self
+                .predictions
+                .iter()
+                .map(|prediction| width(&prediction.text) + ITEM_PADDING * 2.0)
+                .sum::<f32>()
+    }
+
+    fn palette_tab_widths(&self, categories: &[PaletteCategory]) -> Vec<f32> {
+        categories
+            .iter()
+            .map(|category| {
+                self.measure(&category.name, &self.small_format)
+                    .map(|metrics| metrics.widthIncludingTrailingWhitespace)
+                    .unwrap_or(0.0)
+                    + ITEM_PADDING * 2.0
+            })
+            .collect()
+    }
+
+    // the tabs of the categories above a grid of the chosen one, None without the palette
+    fn palette_size(&self) -> Option<(f32, f32)> {
+        let categories = self.palette.as_ref()?;
+        let items = categories
+            .get(self.palette_category)
+            .map_or(0, |category| category.items.len());
+        let rows = items.div_ceil(PALETTE_COLUMNS).max(1) as f32;
+        let (cell_width, cell_height) = self.palette_cell;
+
+        let tabs: f32 = self.palette_tab_widths(categories).iter().sum();
+        let width = tabs.max(cell_width * PALETTE_COLUMNS as f32);
+        Some((width, PREDICTION_HEIGHT + cell_height * rows))
+    }
+
+    fn measure(&self, text: &str, format: &IDWriteTextFormat) -> Result<DWRITE_TEXT_METRICS> {
+        let layout = self.layout(text, format, f32::MAX, f32::MAX)?;
+        let mut metrics = DWRITE_TEXT_METRICS::default();
+        unsafe { layout.GetMetrics(&mut metrics)? };
+        Ok(metrics)
+    }
+
+    fn layout(
+        &self,
+        text: &str,
+        format: &IDWriteTextFormat,
+        width: f32,
+        height: f32,
+    ) -> Result<IDWriteTextLayout> {
+        let text: Vec<u16> = text.encode_utf16().collect();
+        Ok(unsafe { self.dwrite.CreateTextLayout(&text, format, width, height)? })
+    }
+
+    unsafe fn draw(&mut self, target: &ID2D1HwndRenderTarget) -> Result<()> {
+        let size = self.size();
+        let (width, height) = (size.width as f32, size.height as f32);
+        let (list_width, list_height) = self.list_size();
+        let (line, _) = self.text_extent();
+        let item = line + ITEM_PADDING * 2.0;
+        let annotation_width = self.annotation_width();
+
+        target.BeginDraw();
+        target.Clear(Some(&D2D1_COLOR_F::default()));
+
+        let brush = target.CreateSolidColorBrush(&PALETTE.border, None)?;
+        let panel = D2D_RECT_F {
+            left: WINDOW_PADDING / 2.0,
+            top: WINDOW_PADDING / 2.0,
+            right: width - WINDOW_PADDING / 2.0,
+            bottom: height - WINDOW_PADDING / 2.0,
+        };
+        target.FillRoundedRectangle(&rounded(panel, 10.0), &brush);
+        brush.SetColor(&PALETTE.background);
+        target.FillRoundedRectangle(&rounded(inset(panel, 1.0), 9.0), &brush);
+
+        if self.palette.is_some() {
+            self.draw_palette(target, &brush)?;
+            target.EndDraw(None, None)?;
+            return Ok(());
+        }
+
+        let origin_x = WINDOW_PADDING;
+        let mut origin_y = WINDOW_PADDING;
+        let mut hit_rects = vec![];
+
+        if !self.predictions.is_empty() {
+            brush.SetColor(&PALETTE.muted);
+            let label = self.layout("Tab", &self.small_format, f32::MAX, PREDICTION_HEIGHT)?;
+            target.DrawTextLayout(
+                point(origin_x + ITEM_PADDING, origin_y + 6.0),
+                &label,
+                &brush,
+                D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+            );
+
+            let mut x = origin_x
+                + self
+                    .measure("Tab", &self.small_format)?
+                    .widthIncludingTrailingWhitespace
+                + ITEM_PADDING;
+            for (index, prediction) in self.predictions.iter().enumerate() {
+                let layout = self.layout(
+                    &prediction.text,
+                    &self.small_format,
+                    f32::MAX,
+                    PREDICTION_HEIGHT,
+                )?;
+                let mut metrics = DWRITE_TEXT_METRICS::default();
+                layout.GetMetrics(&mut metrics)?;
+                let rect = D2D_RECT_F {
+                    left: x,
+                    top: origin_y + 2.0,
+                    right: x + metrics.widthIncludingTrailingWhitespace + ITEM_PADDING * 2.0,
+                    bottom: origin_y + PREDICTION_HEIGHT - 4.0,
+                };
+
+                if self.prediction == Some(index) {
+                    brush.SetColor(&PALETTE.outline);
+                    target.FillRoundedRectangle(&rounded(rect, 3.0), &brush);
+                    brush.SetColor(&PALETTE.selected);
+                    target.FillRoundedRectangle(&rounded(inset(rect, 1.0), 2.0), &brush);
+                }
+                brush.SetColor(&PALETTE.text);
+                target.DrawTextLayout(
+                    point(rect.left + ITEM_PADDING, origin_y + 6.0),
+                    &layout,
+                    &brush,
+                    D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+                );
+                x = rect.right;
+            }
+
+            origin_y += PREDICTION_HEIGHT;
+            self.draw_separator(target, &brush, origin_y - 1.0, width - WINDOW_PADDING * 2.0);
+        }
+
+        for (position, index) in self.page().enumerate() {
+            let candidate = &self.candidates[index];
+            let rect = if self.vertical {
+                let right = origin_x + list_width - item * position as f32;
+                D2D_RECT_F {
+                    left: right - item,
+                    top: origin_y,
+                    right,
+                    bottom: origin_y + list_height,
+                }
+            } else {
+                let top = origin_y + item * position as f32;
+                D2D_RECT_F {
+                    left: origin_x,
+                    top,
+                    right: origin_x + list_width,
+                    bottom: top + item,
+                }
+            };
+            hit_rects.push((rect, Hit::Candidate(candidate.index)));
+
+            // nothing is chosen until the reading is converted
+            if index == self.selection && !self.compact {
+                brush.SetColor(&PALETTE.outline);
+                target.FillRoundedRectangle(&rounded(rect, 3.0), &brush);
+                brush.SetColor(&PALETTE.selected);
+                target.FillRoundedRectangle(&rounded(inset(rect, 1.0), 2.0), &brush);
+            }
+
+            let number = (position + 1).to_string();
+            let (number_origin, text_origin, text_layout) = if self.vertical {
+                let number_origin = point(rect.left + ITEM_PADDING, rect.top + ITEM_PADDING);
+                let text_layout = self.layout(
+                    &candidate.text,
+                    &self.vertical_format,
+                    line,
+                    rect.bottom - rect.top - NUMBER_SIZE - ITEM_PADDING,
+                )?;
+                let text_origin = point(rect.left + ITEM_PADDING, rect.top + NUMBER_SIZE);
+                (number_origin, text_origin, text_layout)
+            } else {
+                let number_origin = point(rect.left + ITEM_PADDING, rect.top + ITEM_PADDING);
+                let text_layout = self.layout(
+                    &candidate.text,
+                    &self.text_format,
+                    rect.right - rect.left - NUMBER_SIZE - ITEM_PADDING,
+                    line,
+                )?;
+                let text_origin = point(rect.left + NUMBER_SIZE, rect.top + ITEM_PADDING);
+                (number_origin, text_origin, text_layout)
+            };
+
+            brush.SetColor(&PALETTE.muted);
+            let number_layout = self.layout(&number, &self.number_format, NUMBER_SIZE, line)?;
+            target.DrawTextLayout(
+                number_origin,
+                &number_layout,
+                &brush,
+                D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+            );
+            brush.SetColor(&PALETTE.text);
+            target.DrawTextLayout(
+                text_origin,
+                &text_layout,
+                &brush,
+                D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+            );
+
+            // dimmed at the right end of the row, e.g. 制作: 映画・番組など
+            if annotation_width > 0.0 && !candidate.annotation.is_empty() {
+                brush.SetColor(&PALETTE.muted);
+                let layout = self.layout(
+                    &candidate.annotation,
+                    &self.small_format,
+                    annotation_width,
+                    line,
+                )?;
+                target.DrawTextLayout(
+                    point(
+                        rect.right - ITEM_PADDING - annotation_width,
+                        rect.top + ITEM_PADDING + (self.font.size - SMALL_FONT_SIZE) / 2.0,
+                    ),
+                    &layout,
+                    &brush,
+                    D2D1_DRAW_TEXT_OPTIONS_CLIP | D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+                );
+            }
+        }
+
+        self.hit_rects = hit_rects;
+
+        let mut y = origin_y + list_height;
+        let inner_width = width - WINDOW_PADDING * 2.0;
+
+        if self.detail {
+            let annotation = self
+                .candidates
+                .get(self.selection)
+                .map(|c| c.annotation.as_str())
+                .filter(|a| !a.is_empty())
+                .unwrap_or("説明はありません");
+            self.draw_separator(target, &brush, y, inner_width);
+            brush.SetColor(&PALETTE.muted);
+            let layout = self.layout(
+                annotation,
+                &self.small_format,
+                inner_width - ITEM_PADDING * 2.0,
+                DETAIL_HEIGHT,
+            )?;
+            target.DrawTextLayout(
+                point(origin_x + ITEM_PADDING, y + 6.0),
+                &layout,
+                &brush,
+                D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+            );
+            y += DETAIL_HEIGHT;
+        }
+
+        let position = self.position();
+        if self.latency.is_some() || position.is_some() {
+            self.draw_separator(target, &brush, y, inner_width);
+            brush.SetColor(&PALETTE.muted);
+        }
+        if let Some(latency) = &self.latency {
+            let layout = self.layout(latency, &self.small_format, inner_width, FOOTER_HEIGHT)?;
+            target.DrawTextLayout(
+                point(origin_x + ITEM_PADDING, y + 5.0),
+                &layout,
+                &brush,
+                D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+            );
+        }
+        // at the right end, like the webview
+        if let Some(position) = position {
+            let layout = self.layout(&position, &self.small_format, f32::MAX, FOOTER_HEIGHT)?;
+            let mut metrics = DWRITE_TEXT_METRICS::default();
+            layout.GetMetrics(&mut metrics)?;
+            target.DrawTextLayout(
+                point(
+                    origin_x + inner_width
+                        - ITEM_PADDING
+                        - metrics.widthIncludingTrailingWhitespace,
+                    y + 5.0,
+                ),
+                &layout,
+                &brush,
+                D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+            );
+        }
+
+        target.EndDraw(None, None)?;
+        Ok(())
+    }
+
+    unsafe fn draw_palette(
+        &mut self,
+        target: &ID2D1HwndRenderTarget,
+        brush: &ID2D1SolidColorBrush,
+    ) -> Result<()> {
+        let Some(categories) = &self.palette else {
+            return Ok(());
+        };
+        let (width, _) = self.palette_size().unwrap_or_default();
+        let (cell_width, cell_height) = self.palette_cell;
+        let mut hit_rects = vec![];
+
+        let mut x = WINDOW_PADDING;
+        for (index, (category, tab_width)) in categories
+            .iter()
+            .zip(self.palette_tab_widths(categories))
+            .enumerate()
+        {
+            let rect = D2D_RECT_F {
+                left: x,
+                top: WINDOW_PADDING + 2.0,
+                right: x + tab_width,
+                bottom: WINDOW_PADDING + PREDICTION_HEIGHT - 4.0,
+            };
+            hit_rects.push((rect, Hit::PaletteCategory(index)));
+
+            if index == self.palette_category {
+                brush.SetColor(&PALETTE.outline);
+                target.FillRoundedRectangle(&rounded(rect, 3.0), brush);
+                brush.SetColor(&PALETTE.selected);
+                target.FillRoundedRectangle(&rounded(inset(rect, 1.0), 2.0), brush);
+            }
+            brush.SetColor(&PALETTE.text);
+            let layout = self.layout(
+                &category.name,
+                &self.small_format,
+                f32::MAX,
+                PREDICTION_HEIGHT,
+            )?;
+            target.DrawTextLayout(
+                point(x + ITEM_PADDING, WINDOW_PADDING + 6.0),
+                &layout,
+                brush,
+                D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+            );
+            x = rect.right;
+        }
+        let top = WINDOW_PADDING + PREDICTION_HEIGHT;
+        self.draw_separator(target, brush, top - 1.0, width);
+
+        // the items of the chosen category are numbered after the ones of the tabs before it
+        let first: usize = categories[..self.palette_category]
+            .iter()
+            .map(|category| category.items.len())
+            .sum();
+        let items = categories
+            .get(self.palette_category)
+            .map_or(&[][..], |category| &category.items);
+        brush.SetColor(&PALETTE.text);
+        for (position, item) in items.iter().enumerate() {
+            let left = WINDOW_PADDING + cell_width * (position % PALETTE_COLUMNS) as f32;
+            let top = top + cell_height * (position / PALETTE_COLUMNS) as f32;
+            let rect = D2D_RECT_F {
+                left,
+                top,
+                right: left + cell_width,
+                bottom: top + cell_height,
+            };
+            hit_rects.push((rect, Hit::PaletteItem((first + position) as i32)));
+
+            let layout = self.layout(
+                item,
+                &self.text_format,
+                cell_width - ITEM_PADDING * 2.0,
+                cell_height,
+            )?;
+            target.DrawTextLayout(
+                point(left + ITEM_PADDING, top + ITEM_PADDING),
+                &layout,
+                brush,
+                D2D1_DRAW_TEXT_OPTIONS_ENABLE_COLOR_FONT,
+            );
+        }
+
+        self.hit_rects = hit_rects;
+        Ok(())
+    }
+
+    unsafe fn draw_separator(
+        &self,
+        target: &ID2D1RenderTarget,
+        brush: &ID2D1SolidColorBrush,
+        y: f32,
+        width: f32,
+    ) {
+        brush.SetColor(&PALETTE.border);
+        let rect = D2D_RECT_F {
+            left: WINDOW_PADDING,
+            top: y,
+            right: WINDOW_PADDING + width,
+            bottom: y + 1.0,
+        };
+        target.FillRectangle(&rect, brush);
+    }
+}
+
+// the formats of the candidates in rows and in columns, see `UiConfig.font`
+unsafe fn candidate_formats(
+    dwrite: &IDWriteFactory2,
+    fallback: &IDWriteFontFallback,
+    font: &FontConfig,
+) -> Result<(IDWriteTextFormat, IDWriteTextFormat)> {
+    let family: Vec<u16> = font.family.encode_utf16().chain(Some(0)).collect();
+    let family = if font.family.is_empty() {
+        FONT_FAMILY
+    } else {
+        PCWSTR(family.as_ptr())
+    };
+
+    let text_format = create_format(dwrite, family, font.size, false)?;
+    let vertical_format = create_format(dwrite, family, font.size, false)?;
+    vertical_format.SetReadingDirection(DWRITE_READING_DIRECTION_TOP_TO_BOTTOM)?;
+    vertical_format.SetFlowDirection(DWRITE_FLOW_DIRECTION_RIGHT_TO_LEFT)?;
+    for format in [&text_format, &vertical_format] {
+        format
+            .cast::<IDWriteTextFormat1>()?
+            .SetFontFallback(fallback)?;
+    }
+
+    Ok((text_format, vertical_format))
+}
+
+unsafe fn create_format(
+    dwrite: &IDWriteFactory2,
+    family: PCWSTR,
+    size: f32,
+    bold: bool,
+) -> Result<IDWriteTextFormat> {
+    let weight = if bold {
+        DWRITE_FONT_WEIGHT_BOLD
+    } else {
+        DWRITE_FONT_WEIGHT_NORMAL
+    };
+    let format = dwrite.CreateTextFormat(
+        family,
+        None,
+        weight,
+        DWRITE_FONT_STYLE_NORMAL,
+        DWRITE_FONT_STRETCH_NORMAL,
+        size,
+        LOCALE,
+    )?;
+    format.SetWordWrapping(DWRITE_WORD_WRAPPING_NO_WRAP)?;
+    Ok(format)
+}
+
+fn rounded(rect: D2D_RECT_F, radius: f32) -> D2D1_ROUNDED_RECT {
+    D2D1_ROUNDED_RECT {
+        rect,
+        radiusX: radius,
+        radiusY: radius,
+    }
+}
+
+fn point(x: f32, y: f32) -> D2D_POINT_2F {
+    D2D_POINT_2F { x, y }
+}
+
+fn inset(rect: D2D_RECT_F, by: f32) -> D2D_RECT_F {
+    D2D_RECT_F {
+        left: rect.left + by,
+        top: rect.top + by,
+        right: rect.right - by,
+        bottom: rect.bottom - by,
+    }
+}
@@ -1,8 +1,16 @@
+use async_stream::stream;
+use futures_core::Stream;
 use shared::proto::{
-    window_service_server::WindowService as WindowServiceProto, EmptyResponse, SetCandidateRequest,
-    SetInputModeRequest, SetPositionRequest, SetSelectionRequest,
+    window_event, window_service_server::WindowService as WindowServiceProto, Candidate,
+    CandidateKind, CandidateSource, EmptyResponse, FlashModeIndicatorRequest, SetCandidateRequest,
+    SetFontRequest, SetInputModeRequest, SetLatencyRequest, SetLayoutRequest, SetPositionRequest,
+    SetPredictionsRequest, SetSelectionRequest, ShowPaletteRequest, WindowEvent, WindowPosition,
 };
-use tokio::sync::mpsc;
+use shared::FontConfig;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
 use tonic::{Request, Response, Status};
 
 #[derive(Debug, Clone)]
@@ -22,27 +30,127 @@ pub enum WindowAction {
     Show,
     Hide,
     SetPosition {
-        top: i32,
-        left: i32,
-        bottom: i32,
-        right: i32,
+        position: Rect,
+        caret_height: i32,
+        exclusion: Rect,
     },
     SetSelection {
         index: i32,
     },
+    // `compact` for the predictions while typing, see `CandidateKind`
     SetCandidate {
-        candidates: Vec<String>,
+        candidates: Vec<CandidateItem>,
+        compact: bool,
+    },
+    // -1 when no prediction is chosen
+    SetPredictions {
+        predictions: Vec<CandidateItem>,
+        selection: i32,
     },
     SetInputMode(String),
+    // the mode shown for a moment next to the caret
+    FlashModeIndicator {
+        position: Rect,
+        mode: String,
+    },
+    SetLatency {
+        queue_us: u32,
+        conversion_us: u32,
+        window_us: u32,
+    },
+    ToggleDetail,
+    // shown instead of the candidates until the next `SetCandidate`
+    ShowPalette {
+        categories: Vec<PaletteCategory>,
+    },
+    // see `UiConfig.vertical`
+    SetLayout {
+        vertical: bool,
+    },
+    // see `UiConfig.font`
+    SetFont(FontConfig),
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct Rect {
+    pub top: i32,
+    pub left: i32,
+    pub bottom: i32,
+    pub right: i32,
+}
+
+impl From<WindowPosition> for Rect {
+    fn from(position: WindowPosition) -> Self {
+        Rect {
+            top: position.top,
+            left: position.left,
+            bottom: position.bottom,
+            right: position.right,
+        }
+    }
+}
+
+// a candidate as passed to the webview
+#[derive(Debug, serde::Serialize)]
+pub struct CandidateItem {
+    pub text: String,
+    pub reading: String,
+    pub annotation: String,
+    pub source: &'static str,
+    pub index: i32,
+}
+
+impl From<Candidate> for CandidateItem {
+    fn from(candidate: Candidate) -> Self {
+        let source = match candidate.source() {
+            CandidateSource::Converter => "converter",
+            CandidateSource::UserDictionary => "user_dictionary",
+            CandidateSource::Date => "date",
+            CandidateSource::Generated => "generated",
+            CandidateSource::Emoji => "emoji",
+            CandidateSource::English => "english",
+        };
+
+        CandidateItem {
+            text: candidate.text,
+            reading: candidate.reading,
+            annotation: candidate.annotation,
+            source,
+            index: candidate.index,
+        }
+    }
+}
+
+// a category of the palette as passed to the webview
+// the items are numbered through all the categories, as the client knows them
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PaletteCategory {
+    pub name: String,
+    pub items: Vec<String>,
+}
+
+impl From<shared::proto::PaletteCategory> for PaletteCategory {
+    fn from(category: shared::proto::PaletteCategory) -> Self {
+        PaletteCategory {
+            name: category.name,
+            items: category.items,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct WindowService {
     pub controller: WindowController,
+    // events sent to the clients, e.g. clicks on candidates
+    pub events: broadcast::Sender<WindowEvent>,
+    // candidates which fit in the window, as last measured by the webview
+    pub page_size: Arc<AtomicI32>,
 }
 
 #[tonic::async_trait]
 impl WindowServiceProto for WindowService {
+    type SubscribeEventsStream = Pin<Box<dyn Stream<Item = Result<WindowEvent, Status>> + Send>>;
+
     async fn show_window(
         &self,
         _request: Request<EmptyResponse>,
@@ -70,18 +178,16 @@ impl WindowServiceProto for WindowService {
         &self,
         request: Request<SetPositionRequest>,
     ) -> Result<Response<EmptyResponse>, Status> {
-        let position = request.into_inner().position.unwrap();
-        let top = position.top;
-        let left = position.left;
-        let bottom = position.bottom;
-        let right = position.right;
+        let request = request.into_inner();
+        let position = Rect::from(request.position.unwrap_or_default());
+        // older clients don't send the exclusion, so keep the caret line visible at least
+        let exclusion = request.exclusion.map(Rect::from).unwrap_or(position);
         self.controller
             .sender
             .send(WindowAction::SetPosition {
-                top,
-                left,
-                bottom,
-                right,
+                position,
+                caret_height: request.caret_height,
+                exclusion,
             })
             .await
             .unwrap();
@@ -93,12 +199,40 @@ impl WindowServiceProto for WindowService {
         &self,
         request: Request<SetCandidateRequest>,
     ) -> Result<Response<EmptyResponse>, Status> {
-        let candidate = request.into_inner().candidates;
+        let request = request.into_inner();
+        let compact = request.kind() == CandidateKind::Prediction;
 
         self.controller
             .sender
             .send(WindowAction::SetCandidate {
-                candidates: candidate,
+                compact,
+                candidates: request
+                    .candidates
+                    .into_iter()
+                    .map(CandidateItem::from)
+                    .collect(),
+            })
+            .await
+            .unwrap();
+
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_predictions(
+        &self,
+        request: Request<SetPredictionsRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let request = request.into_inner();
+
+        self.controller
+            .sender
+            .send(WindowAction::SetPredictions {
+                predictions: request
+                    .predictions
+                    .into_iter()
+                    .map(CandidateItem::from)
+                    .collect(),
+                selection: request.selection,
             })
             .await
             .unwrap();
@@ -133,4 +267,129 @@ impl WindowServiceProto for WindowService {
 
         Ok(Response::new(EmptyResponse {}))
     }
+
+    async fn flash_mode_indicator(
+        &self,
+        request: Request<FlashModeIndicatorRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let request = request.into_inner();
+        self.controller
+            .sender
+            .send(WindowAction::FlashModeIndicator {
+                position: request.position.unwrap_or_default().into(),
+                mode: request.mode,
+            })
+            .await
+            .unwrap();
+
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_latency(
+        &self,
+        request: Request<SetLatencyRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let request = request.into_inner();
+        self.controller
+            .sender
+            .send(WindowAction::SetLatency {
+                queue_us: request.queue_us,
+                conversion_us: request.conversion_us,
+                window_us: request.window_us,
+            })
+            .await
+            .unwrap();
+
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn toggle_detail(
+        &self,
+        _request: Request<EmptyResponse>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        self.controller
+            .sender
+            .send(WindowAction::ToggleDetail)
+            .await
+            .unwrap();
+
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn show_palette(
+        &self,
+        request: Request<ShowPaletteRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let request = request.into_inner();
+        self.controller
+            .sender
+            .send(WindowAction::ShowPalette {
+                categories: request
+                    .categories
+                    .into_iter()
+                    .map(PaletteCategory::from)
+                    .collect(),
+            })
+            .await
+            .unwrap();
+
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_layout(
+        &self,
+        request: Request<SetLayoutRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let vertical = request.into_inner().vertical;
+        self.controller
+            .sender
+            .send(WindowAction::SetLayout { vertical })
+            .await
+            .unwrap();
+
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn set_font(
+        &self,
+        request: Request<SetFontRequest>,
+    ) -> Result<Response<EmptyResponse>, Status> {
+        let request = request.into_inner();
+        self.controller
+            .sender
+            .send(WindowAction::SetFont(FontConfig {
+                family: request.family,
+                size: request.size,
+                line_spacing: request.line_spacing,
+            }))
+            .await
+            .unwrap();
+
+        Ok(Response::new(EmptyResponse {}))
+    }
+
+    async fn subscribe_events(
+        &self,
+        _request: Request<EmptyResponse>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        let mut receiver = self.events.subscribe();
+        let page_size = self.page_size.load(Ordering::Relaxed);
+
+        let events = stream! {
+            // a new subscriber may have missed the last change
+            yield Ok(WindowEvent {
+                event: Some(window_event::Event::PageSize(page_size)),
+            });
+
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => yield Ok(event),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        };
+
+        Ok(Response::new(Box::pin(events)))
+    }
 }
@@ -1,19 +1,24 @@
 use std::cmp::max;
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::Arc;
 
 use anyhow::Context as _;
-use azookey_server::TonicNamedPipeServer;
-use ipc::{WindowAction, WindowController, WindowService};
-use shared::proto::window_service_server::WindowServiceServer;
+use azookey_server::TransportServer;
+use direct2d::{CandidateRenderer, Hit};
+use ipc::{Rect, WindowAction, WindowController, WindowService};
+use shared::proto::{self, window_event, window_service_server::WindowServiceServer};
+use shared::{AppConfig, FontConfig, UiRenderer, CANDIDATE_PAGE_SIZE};
 use tao::dpi::{LogicalSize, PhysicalPosition, PhysicalSize};
 use tao::platform::windows::{EventLoopBuilderExtWindows, WindowExtWindows};
 use tao::{
-    event::{Event, StartCause, WindowEvent},
-    event_loop::{ControlFlow, EventLoopBuilder},
+    event::{ElementState, Event, MouseButton, StartCause, WindowEvent},
+    event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy},
+    window::Window,
 };
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{broadcast, mpsc, Mutex};
 use tokio::task::JoinHandle;
 use tonic::transport::Server;
+use tray::{Tray, TrayAction};
 use uiaccess::prepare_uiaccess_token;
 use utils::get_candidate_window_position;
 use windows::Win32::UI::WindowsAndMessaging::{
@@ -23,20 +28,40 @@ use windows::Win32::{
     Foundation::HWND,
     UI::WindowsAndMessaging::{ShowWindow, SW_SHOWNOACTIVATE},
 };
+use wry::WebView;
 
 pub mod candidate;
+pub mod direct2d;
 pub mod indicator;
 pub mod ipc;
+pub mod tray;
 pub mod uiaccess;
 pub mod utils;
 
 #[derive(Debug)]
 pub enum UserEvent {
     UpdateHeight(i32),
-    UpdateCandidates(String),
+    // candidates as a JSON array of `CandidateItem`, and whether they are the predictions while
+    // typing
+    UpdateCandidates(String, bool),
+    // predictions as a JSON object with the `CandidateItem`s and the chosen index
+    UpdatePredictions(String),
+    // the categories of the palette as a JSON array of `PaletteCategory`
+    ShowPalette(String),
     UpdateSelection(i32),
     UpdateInputMethod(String),
+    UpdateLatency(String),
+    // the font as a JSON object of `FontConfig`
+    UpdateFont(String),
+    ToggleDetail,
     WindowAction(WindowAction),
+    Tray(TrayAction),
+}
+
+// what draws the candidate window, chosen by `ui.renderer` when the process starts
+enum CandidateView {
+    WebView(WebView),
+    Direct2D(CandidateRenderer),
 }
 
 #[tokio::main]
@@ -58,8 +83,12 @@ async fn main() -> anyhow::Result<()> {
     // initialize window controller
     let (tx, mut rx) = mpsc::channel(32);
     let window_controller = WindowController::new(tx.clone());
+    let (events, _) = broadcast::channel(16);
+    let page_size = Arc::new(AtomicI32::new(CANDIDATE_PAGE_SIZE as i32));
     let grpc_service = WindowService {
         controller: window_controller.clone(),
+        events: events.clone(),
+        page_size: page_size.clone(),
     };
 
     // start grpc server
@@ -68,7 +97,7 @@ async fn main() -> anyhow::Result<()> {
         println!("WindowServer creating named pipe azookey_ui...");
         Server::builder()
             .add_service(WindowServiceServer::new(grpc_service))
-            .serve_with_incoming(TonicNamedPipeServer::new("azookey_ui"))
+            .serve_with_incoming(TransportServer::new("azookey_ui"))
             .await
             .expect("gRPC server failed");
     });
@@ -80,29 +109,41 @@ async fn main() -> anyhow::Result<()> {
     let event_loop_proxy = event_loop.create_proxy();
     let task_guard: Arc<Mutex<Option<JoinHandle<()>>>> = Arc::new(Mutex::new(None));
 
-    let proxy_clone = event_loop_proxy.clone();
+    let ui_config = AppConfig::try_read().unwrap_or_default().ui;
+    // the width of the webview follows the size of the text
+    let mut font = ui_config.font.clamped();
     let candidate_window = candidate::create_candidate_window(&event_loop)?;
-    let candidate_webview_builder = candidate::create_candidate_webview()?;
-    let candidate_webview = candidate_webview_builder
-        .with_devtools(true)
-        .with_ipc_handler(move |message| {
-            if let Ok(message) = serde_json::from_str::<serde_json::Value>(message.body()) {
-                if let Some(type_value) = message.get("type") {
-                    if type_value == "resize" {
-                        if let Some(height) = message.get("height") {
-                            let height = height.as_f64().unwrap_or(0.0);
-                            proxy_clone
-                                .send_event(UserEvent::UpdateHeight(height as i32))
-                                .unwrap();
-                        }
-                    }
-                }
-            }
-        })
-        .build(&candidate_window)?;
+    let mut candidate_view = match ui_config.renderer {
+        UiRenderer::Webview => CandidateView::WebView(create_webview(
+            &candidate_window,
+            &event_loop_proxy,
+            &events,
+            &page_size,
+            &font,
+        )?),
+        UiRenderer::Direct2d => CandidateView::Direct2D(CandidateRenderer::new(
+            &candidate_window,
+            ui_config.vertical,
+            font.clone(),
+        )?),
+    };
+    // the webview has no vertical text, so its candidates are always in rows
+    let mut vertical = ui_config.vertical && ui_config.renderer == UiRenderer::Direct2d;
+    // last cursor position over the candidate window, for clicks with the direct2d renderer
+    let mut cursor = PhysicalPosition::new(0.0, 0.0);
+    // the last `SetPosition`, the window is placed again when the candidates change its size
+    let mut placement: Option<(Rect, i32, Rect)> = None;
 
     let indicator_window = indicator::create_indicator_window(&event_loop)?;
     let indicator_webview = indicator::create_indicator_webview(&indicator_window)?;
+    // the candidate window works without it, e.g. when the icons are missing
+    let mut tray = match Tray::new(event_loop_proxy.clone()) {
+        Ok(tray) => Some(tray),
+        Err(e) => {
+            eprintln!("Warning: Failed to create the tray icon: {:?}", e);
+            None
+        }
+    };
 
     // handle window actions
     let proxy_clone = event_loop_proxy.clone();
@@ -120,27 +161,64 @@ async fn main() -> anyhow::Result<()> {
                         .unwrap();
                 }
                 WindowAction::SetPosition {
-                    top,
-                    left,
-                    bottom,
-                    right,
+                    position,
+                    caret_height,
+                    exclusion,
                 } => {
                     proxy_clone
                         .send_event(UserEvent::WindowAction(WindowAction::SetPosition {
-                            top,
-                            left,
-                            bottom,
-                            right,
+                            position,
+                            caret_height,
+                            exclusion,
                         }))
                         .unwrap();
                 }
-                WindowAction::SetCandidate { candidates } => {
+                WindowAction::SetCandidate {
+                    candidates,
+                    compact,
+                } => {
                     proxy_clone
                         .send_event(UserEvent::WindowAction(WindowAction::SetCandidate {
                             candidates,
+                            compact,
+                        }))
+                        .unwrap();
+                }
+                WindowAction::SetPredictions {
+                    predictions,
+                    selection,
+                } => {
+                    proxy_clone
+                        .send_event(UserEvent::WindowAction(WindowAction::SetPredictions {
+                            predictions,
+                            selection,
                         }))
                         .unwrap();
                 }
+                WindowAction::ToggleDetail => {
+                    proxy_clone
+                        .send_event(UserEvent::WindowAction(WindowAction::ToggleDetail))
+                        .unwrap();
+                }
+                WindowAction::ShowPalette { categories } => {
+                    proxy_clone
+                        .send_event(UserEvent::WindowAction(WindowAction::ShowPalette {
+                            categories,
+                        }))
+                        .unwrap();
+                }
+                WindowAction::SetLayout { vertical } => {
+                    proxy_clone
+                        .send_event(UserEvent::WindowAction(WindowAction::SetLayout {
+                            vertical,
+                        }))
+                        .unwrap();
+                }
+                WindowAction::SetFont(font) => {
+                    proxy_clone
+                        .send_event(UserEvent::WindowAction(WindowAction::SetFont(font)))
+                        .unwrap();
+                }
                 WindowAction::SetSelection { index } => {
                     proxy_clone
                         .send_event(UserEvent::WindowAction(WindowAction::SetSelection {
@@ -155,6 +233,27 @@ async fn main() -> anyhow::Result<()> {
                         )))
                         .unwrap();
                 }
+                WindowAction::FlashModeIndicator { position, mode } => {
+                    proxy_clone
+                        .send_event(UserEvent::WindowAction(WindowAction::FlashModeIndicator {
+                            position,
+                            mode,
+                        }))
+                        .unwrap();
+                }
+                WindowAction::SetLatency {
+                    queue_us,
+                    conversion_us,
+                    window_us,
+                } => {
+                    proxy_clone
+                        .send_event(UserEvent::WindowAction(WindowAction::SetLatency {
+                            queue_us,
+                            conversion_us,
+                            window_us,
+                        }))
+                        .unwrap();
+                }
             }
         }
     });
@@ -170,22 +269,162 @@ async fn main() -> anyhow::Result<()> {
                 event: WindowEvent::CloseRequested,
                 ..
             } => *control_flow = ControlFlow::Exit,
+            Event::RedrawRequested(window_id) if window_id == candidate_window.id() => {
+                if let CandidateView::Direct2D(renderer) = &mut candidate_view {
+                    if let Err(e) = renderer.render() {
+                        eprintln!("Failed to render candidates: {:?}", e);
+                    }
+                }
+            }
+            Event::WindowEvent {
+                window_id, event, ..
+            } if window_id == candidate_window.id() => {
+                // e.g. more candidates than before, it may not fit where it is anymore
+                if let (WindowEvent::Resized(_), Some((position, caret_height, exclusion))) =
+                    (&event, placement)
+                {
+                    let (x, y) = get_candidate_window_position(
+                        position,
+                        caret_height,
+                        exclusion,
+                        vertical,
+                        &candidate_window,
+                    );
+                    candidate_window.set_outer_position(PhysicalPosition::new(x, y));
+                }
+                let CandidateView::Direct2D(renderer) = &mut candidate_view else {
+                    return;
+                };
+                match event {
+                    WindowEvent::Resized(size) => {
+                        renderer.resize(size.width, size.height);
+                    }
+                    WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                        renderer.set_scale_factor(scale_factor);
+                        candidate_window.set_inner_size(renderer.size());
+                    }
+                    WindowEvent::CursorMoved { position, .. } => {
+                        cursor = position;
+                    }
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button: MouseButton::Left,
+                        ..
+                    } => {
+                        let event = match renderer.hit_test(cursor.x, cursor.y) {
+                            Some(Hit::Candidate(index)) => {
+                                window_event::Event::CandidateClicked(index)
+                            }
+                            Some(Hit::PaletteItem(index)) => {
+                                window_event::Event::PaletteClicked(index)
+                            }
+                            Some(Hit::PaletteCategory(index)) => {
+                                renderer.set_palette_category(index);
+                                candidate_window.set_inner_size(renderer.size());
+                                candidate_window.request_redraw();
+                                return;
+                            }
+                            None => return,
+                        };
+                        // there may be no client subscribing, so ignore the error
+                        let _ = events.send(proto::WindowEvent { event: Some(event) });
+                    }
+                    _ => (),
+                }
+            }
             Event::UserEvent(script) => match script {
-                UserEvent::UpdateCandidates(candidates) => {
-                    candidate_webview
-                        .evaluate_script(&format!("updateCandidates({})", candidates))
-                        .unwrap();
+                UserEvent::UpdateCandidates(candidates, compact) => {
+                    if let CandidateView::WebView(webview) = &candidate_view {
+                        webview
+                            .evaluate_script(&format!(
+                                "updateCandidates({}, {})",
+                                candidates, compact
+                            ))
+                            .unwrap();
+                    }
                 }
-                UserEvent::UpdateSelection(index) => {
-                    candidate_webview
-                        .evaluate_script(&format!("updateSelection({})", index))
-                        .unwrap();
+                UserEvent::UpdatePredictions(predictions) => {
+                    if let CandidateView::WebView(webview) = &candidate_view {
+                        webview
+                            .evaluate_script(&format!("updatePredictions({})", predictions))
+                            .unwrap();
+                    }
+                }
+                UserEvent::ShowPalette(categories) => {
+                    if let CandidateView::WebView(webview) = &candidate_view {
+                        webview
+                            .evaluate_script(&format!("showPalette({})", categories))
+                            .unwrap();
+                    }
                 }
+                UserEvent::UpdateSelection(index) => match &mut candidate_view {
+                    CandidateView::WebView(webview) => {
+                        webview
+                            .evaluate_script(&format!("updateSelection({})", index))
+                            .unwrap();
+                    }
+                    CandidateView::Direct2D(renderer) => {
+                        // the window follows the size of the shown page
+                        renderer.set_selection(index);
+                        candidate_window.set_inner_size(renderer.size());
+                        candidate_window.request_redraw();
+                    }
+                },
                 UserEvent::UpdateInputMethod(input_method) => {
                     indicator_webview
                         .evaluate_script(&format!("updateInputMethod(\"{}\")", input_method))
                         .unwrap();
                 }
+                UserEvent::UpdateLatency(latency) => {
+                    if let CandidateView::WebView(webview) = &candidate_view {
+                        webview
+                            .evaluate_script(&format!("updateLatency({})", latency))
+                            .unwrap();
+                    }
+                }
+                UserEvent::UpdateFont(font) => {
+                    if let CandidateView::WebView(webview) = &candidate_view {
+                        webview
+                            .evaluate_script(&format!("updateFont({})", font))
+                            .unwrap();
+                    }
+                }
+                UserEvent::ToggleDetail => match &mut candidate_view {
+                    CandidateView::WebView(webview) => {
+                        webview.evaluate_script("toggleDetail()").unwrap();
+                    }
+                    CandidateView::Direct2D(renderer) => {
+                        renderer.toggle_detail();
+                        candidate_window.set_inner_size(renderer.size());
+                        candidate_window.request_redraw();
+                    }
+                },
+                UserEvent::Tray(action) => match action {
+                    TrayAction::ToggleInputMode => {
+                        // every text service takes the mode, so that they stay the same
+                        if let Some(tray) = &tray {
+                            let event = window_event::Event::InputModeSelected(tray.toggled_kana());
+                            let _ = events.send(proto::WindowEvent { event: Some(event) });
+                        }
+                    }
+                    TrayAction::OpenSettings => {
+                        if let Err(e) = tray::open_settings() {
+                            eprintln!("Failed to open the settings: {:?}", e);
+                        }
+                    }
+                    TrayAction::RestartEngine => {
+                        if let Err(e) = tray::restart_engine() {
+                            eprintln!("Failed to restart the server: {:?}", e);
+                        }
+                    }
+                    TrayAction::Quit => {
+                        if let Err(e) = tray::stop_engine() {
+                            eprintln!("Failed to stop the server: {:?}", e);
+                        }
+                        // exits with success, so the launcher doesn't start the ui again
+                        *control_flow = ControlFlow::Exit;
+                    }
+                },
                 UserEvent::UpdateHeight(height) => {
                     let width = candidate_window.inner_size().width as i32;
                     candidate_window.set_inner_size(LogicalSize::new(width, height));
@@ -229,16 +468,16 @@ async fn main() -> anyhow::Result<()> {
                             };
                         }
                         WindowAction::SetPosition {
-                            top,
-                            left,
-                            bottom,
-                            right,
+                            position,
+                            caret_height,
+                            exclusion,
                         } => {
+                            placement = Some((position, caret_height, exclusion));
                             let (x, y) = get_candidate_window_position(
-                                top,
-                                left,
-                                bottom,
-                                right,
+                                position,
+                                caret_height,
+                                exclusion,
+                                vertical,
                                 &candidate_window,
                             );
 
@@ -266,20 +505,30 @@ async fn main() -> anyhow::Result<()> {
                             candidate_window
                                 .set_outer_position(PhysicalPosition::new(x as f64, y as f64));
                             indicator_window.set_outer_position(PhysicalPosition::new(
-                                (left - 45) as f64,
-                                bottom as f64,
+                                (position.left - 45) as f64,
+                                position.bottom as f64,
                             ));
                         }
-                        WindowAction::SetCandidate { candidates } => {
+                        WindowAction::SetCandidate {
+                            candidates,
+                            compact,
+                        } => {
+                            if let CandidateView::Direct2D(renderer) = &mut candidate_view {
+                                renderer.set_candidates(candidates, compact);
+                                candidate_window.set_inner_size(renderer.size());
+                                candidate_window.request_redraw();
+                                return;
+                            }
+
                             let max_len = candidates
                                 .iter()
-                                .map(|s| s.chars().count())
+                                .map(|c| c.text.chars().count())
                                 .max()
                                 .unwrap_or(0) as u32;
 
                             let height = candidate_window.inner_size().height as i32;
                             candidate_window.set_inner_size(PhysicalSize::new(
-                                max(225, 120 + max_len * 18),
+                                max(225, 120 + max_len * (font.size * 1.25) as u32),
                                 height as u32,
                             ));
 
@@ -288,7 +537,56 @@ async fn main() -> anyhow::Result<()> {
                                 .unwrap();
 
                             event_loop_proxy
-                                .send_event(UserEvent::UpdateCandidates(candidates))
+                                .send_event(UserEvent::UpdateCandidates(candidates, compact))
+                                .unwrap();
+                        }
+                        WindowAction::SetPredictions {
+                            predictions,
+                            selection,
+                        } => {
+                            if let CandidateView::Direct2D(renderer) = &mut candidate_view {
+                                renderer.set_predictions(predictions, selection);
+                                candidate_window.set_inner_size(renderer.size());
+                                candidate_window.request_redraw();
+                                return;
+                            }
+
+                            let predictions = serde_json::json!({
+                                "predictions": predictions,
+                                "selection": selection,
+                            });
+
+                            event_loop_proxy
+                                .send_event(UserEvent::UpdatePredictions(predictions.to_string()))
+                                .unwrap();
+                        }
+                        WindowAction::SetLayout {
+                            vertical: new_vertical,
+                        } => {
+                            if let CandidateView::Direct2D(renderer) = &mut candidate_view {
+                                vertical = new_vertical;
+                                renderer.set_vertical(vertical);
+                                candidate_window.set_inner_size(renderer.size());
+                                candidate_window.request_redraw();
+                            }
+                        }
+                        // laid out again with the candidates on the window
+                        WindowAction::SetFont(new_font) => {
+                            font = new_font.clamped();
+                            if let CandidateView::Direct2D(renderer) = &mut candidate_view {
+                                if let Err(e) = renderer.set_font(font.clone()) {
+                                    eprintln!("Failed to set the font: {:?}", e);
+                                }
+                                candidate_window.set_inner_size(renderer.size());
+                                candidate_window.request_redraw();
+                                return;
+                            }
+
+                            let json = serde_json::to_string(&font)
+                                .context("Failed to serialize the font")
+                                .unwrap();
+                            event_loop_proxy
+                                .send_event(UserEvent::UpdateFont(json))
                                 .unwrap();
                         }
                         WindowAction::SetSelection { index } => {
@@ -296,9 +594,21 @@ async fn main() -> anyhow::Result<()> {
                                 .send_event(UserEvent::UpdateSelection(index))
                                 .unwrap();
                         }
-                        WindowAction::SetInputMode(input_method) => {
+                        WindowAction::SetInputMode(mode) => {
+                            if let Some(tray) = &mut tray {
+                                if let Err(e) = tray.set_mode(mode) {
+                                    eprintln!("Failed to update the tray icon: {:?}", e);
+                                }
+                            }
+                        }
+                        // below the caret, where the candidate window would be
+                        WindowAction::FlashModeIndicator { position, mode } => {
+                            indicator_window.set_outer_position(PhysicalPosition::new(
+                                (position.left - 45) as f64,
+                                position.bottom as f64,
+                            ));
                             event_loop_proxy
-                                .send_event(UserEvent::UpdateInputMethod(input_method))
+                                .send_event(UserEvent::UpdateInputMethod(mode))
                                 .unwrap();
 
                             let task_guard = task_guard.try_lock();
@@ -325,6 +635,53 @@ async fn main() -> anyhow::Result<()> {
                                 }));
                             }
                         }
+                        WindowAction::SetLatency {
+                            queue_us,
+                            conversion_us,
+                            window_us,
+                        } => {
+                            if let CandidateView::Direct2D(renderer) = &mut candidate_view {
+                                renderer.set_latency(queue_us, conversion_us, window_us);
+                                candidate_window.set_inner_size(renderer.size());
+                                candidate_window.request_redraw();
+                                return;
+                            }
+
+                            let latency = serde_json::json!({
+                                "queue": queue_us,
+                                "conversion": conversion_us,
+                                "window": window_us,
+                            });
+
+                            event_loop_proxy
+                                .send_event(UserEvent::UpdateLatency(latency.to_string()))
+                                .unwrap();
+                        }
+                        WindowAction::ToggleDetail => {
+                            event_loop_proxy
+                                .send_event(UserEvent::ToggleDetail)
+                                .unwrap();
+                        }
+                        WindowAction::ShowPalette { categories } => {
+                            if let CandidateView::Direct2D(renderer) = &mut candidate_view {
+                                renderer.set_palette(categories);
+                                candidate_window.set_inner_size(renderer.size());
+                                candidate_window.request_redraw();
+                                return;
+                            }
+
+                            // wide enough for the tabs, the height follows the grid
+                            let height = candidate_window.inner_size().height;
+                            candidate_window.set_inner_size(PhysicalSize::new(420, height));
+
+                            let categories = serde_json::to_string(&categories)
+                                .context("Failed to serialize the palette")
+                                .unwrap();
+
+                            event_loop_proxy
+                                .send_event(UserEvent::ShowPalette(categories))
+                                .unwrap();
+                        }
                     }
                 }
             },
@@ -332,3 +689,54 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 }
+
+fn create_webview(
+    window: &Window,
+    proxy: &EventLoopProxy<UserEvent>,
+    events: &broadcast::Sender<proto::WindowEvent>,
+    page_size: &Arc<AtomicI32>,
+    font: &FontConfig,
+) -> anyhow::Result<WebView> {
+    let proxy = proxy.clone();
+    let events = events.clone();
+    let page_size = page_size.clone();
+    let webview = candidate::create_candidate_webview(font)?
+        .with_devtools(true)
+        .with_ipc_handler(move |message| {
+            if let Ok(message) = serde_json::from_str::<serde_json::Value>(message.body()) {
+                if let Some(type_value) = message.get("type") {
+                    if type_value == "resize" {
+                        if let Some(height) = message.get("height") {
+                            let height = height.as_f64().unwrap_or(0.0);
+                            proxy
+                                .send_event(UserEvent::UpdateHeight(height as i32))
+                                .unwrap();
+                        }
+                    } else if type_value == "select" {
+                        if let Some(index) = message.get("index").and_then(|i| i.as_i64()) {
+                            // there may be no client subscribing, so ignore the error
+                            let _ = events.send(proto::WindowEvent {
+                                event: Some(window_event::Event::CandidateClicked(index as i32)),
+                            });
+                        }
+                    } else if type_value == "palette" {
+                        if let Some(index) = message.get("index").and_then(|i| i.as_i64()) {
+                            let _ = events.send(proto::WindowEvent {
+                                event: Some(window_event::Event::PaletteClicked(index as i32)),
+                            });
+                        }
+                    } else if type_value == "page_size" {
+                        if let Some(size) = message.get("size").and_then(|s| s.as_i64()) {
+                            page_size.store(size as i32, Ordering::Relaxed);
+                            let _ = events.send(proto::WindowEvent {
+                                event: Some(window_event::Event::PageSize(size as i32)),
+                            });
+                        }
+                    }
+                }
+            }
+        })
+        .build(window)?;
+
+    Ok(webview)
+}
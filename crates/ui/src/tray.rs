@@ -0,0 +1,198 @@
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context as _, Result};
+use tao::event_loop::EventLoopProxy;
+use tray_icon::{
+    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent,
+};
+use windows::{
+    core::w,
+    Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, REG_VALUE_TYPE, RRF_RT_REG_DWORD},
+};
+
+use crate::UserEvent;
+
+// what the menu of the tray icon asks for, handled in the event loop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrayAction {
+    ToggleInputMode,
+    OpenSettings,
+    RestartEngine,
+    Quit,
+}
+
+impl TrayAction {
+    const ALL: [TrayAction; 4] = [
+        TrayAction::ToggleInputMode,
+        TrayAction::OpenSettings,
+        TrayAction::RestartEngine,
+        TrayAction::Quit,
+    ];
+
+    fn id(&self) -> &'static str {
+        match self {
+            TrayAction::ToggleInputMode => "toggle_input_mode",
+            TrayAction::OpenSettings => "open_settings",
+            TrayAction::RestartEngine => "restart_engine",
+            TrayAction::Quit => "quit",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            TrayAction::ToggleInputMode => "ひらがな / 英数を切り替え",
+            TrayAction::OpenSettings => "設定を開く",
+            TrayAction::RestartEngine => "変換エンジンを再起動",
+            TrayAction::Quit => "終了",
+        }
+    }
+}
+
+// the icon in the notification area, with the mode of the text service which sent it last
+// the mode is switched by the text services, the tray only asks them with `WindowEvent`
+pub struct Tray {
+    icon: TrayIcon,
+    // `InputMode::indicator` of the client, e.g. あ or A
+    mode: String,
+}
+
+impl Tray {
+    pub fn new(proxy: EventLoopProxy<UserEvent>) -> Result<Self> {
+        let menu = Menu::new();
+        for (index, action) in TrayAction::ALL.iter().enumerate() {
+            if index == TrayAction::ALL.len() - 1 {
+                menu.append(&PredefinedMenuItem::separator())?;
+            }
+            menu.append(&MenuItem::with_id(action.id(), action.label(), true, None))?;
+        }
+
+        let mode = "あ".to_string();
+        let icon = TrayIconBuilder::new()
+            .with_menu(Box::new(menu))
+            .with_menu_on_left_click(false)
+            .with_tooltip(tooltip(&mode))
+            .with_icon(icon(&mode)?)
+            .build()
+            .context("Failed to create the tray icon")?;
+
+        // the handlers are called on the thread of the event loop, but they must be Sync
+        let proxy = Arc::new(Mutex::new(proxy));
+        let menu_proxy = proxy.clone();
+        MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+            if let Some(action) = TrayAction::ALL.iter().find(|a| event.id.0 == a.id()) {
+                send(&menu_proxy, *action);
+            }
+        }));
+        // a click toggles the mode like the language bar
+        TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                send(&proxy, TrayAction::ToggleInputMode);
+            }
+        }));
+
+        Ok(Self { icon, mode })
+    }
+
+    pub fn set_mode(&mut self, mode: String) -> Result<()> {
+        if self.mode == mode {
+            return Ok(());
+        }
+        self.icon.set_icon(Some(icon(&mode)?))?;
+        self.icon.set_tooltip(Some(tooltip(&mode)))?;
+        self.mode = mode;
+        Ok(())
+    }
+
+    // the mode to ask for with `ToggleInputMode`, true for ひらがな
+    pub fn toggled_kana(&self) -> bool {
+        is_latin(&self.mode)
+    }
+}
+
+fn send(proxy: &Mutex<EventLoopProxy<UserEvent>>, action: TrayAction) {
+    let _ = proxy
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .send_event(UserEvent::Tray(action));
+}
+
+// see `InputMode::indicator` of the client
+fn is_latin(mode: &str) -> bool {
+    matches!(mode, "A" | "Ａ")
+}
+
+fn tooltip(mode: &str) -> String {
+    format!("azooKey - {}", mode)
+}
+
+// the icons of the language bar, copied next to the executable on build
+fn icon(mode: &str) -> Result<Icon> {
+    let name = match (is_latin(mode), light_theme()) {
+        (false, true) => "kana_black.ico",
+        (true, true) => "latin_black.ico",
+        (false, false) => "kana_white.ico",
+        (true, false) => "latin_white.ico",
+    };
+    let path = exe_dir()?.join(name);
+    Icon::from_path(&path, None).with_context(|| format!("Failed to load {}", path.display()))
+}
+
+// same as `get_theme` of the client, the taskbar follows the theme of the system
+fn light_theme() -> bool {
+    let mut value_type = REG_VALUE_TYPE::default();
+    let mut data = [0u8; 4];
+    let mut data_size = data.len() as u32;
+
+    let _ = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!(r"Software\Microsoft\Windows\CurrentVersion\Themes\Personalize"),
+            w!("SystemUsesLightTheme"),
+            RRF_RT_REG_DWORD,
+            Some(&mut value_type),
+            Some(data.as_mut_ptr().cast()),
+            Some(&mut data_size),
+        )
+    };
+
+    data[0] != 0
+}
+
+fn exe_dir() -> Result<PathBuf> {
+    let exe = std::env::current_exe()?;
+    Ok(exe.parent().context("No parent directory")?.to_path_buf())
+}
+
+pub fn open_settings() -> Result<()> {
+    let path = shared::install::settings_app_path();
+    Command::new(&path)
+        .spawn()
+        .with_context(|| format!("Failed to start {}", path.display()))?;
+    Ok(())
+}
+
+// a new server replaces the running one, see the start of `main` of the server
+pub fn restart_engine() -> Result<()> {
+    let path = exe_dir()?.join("azookey-server.exe");
+    Command::new(&path)
+        .spawn()
+        .with_context(|| format!("Failed to start {}", path.display()))?;
+    Ok(())
+}
+
+// the launcher exits when both the server and the ui are gone
+pub fn stop_engine() -> Result<()> {
+    Command::new("taskkill")
+        .args(["/F", "/IM", "azookey-server.exe"])
+        .output()
+        .context("Failed to stop the server")?;
+    Ok(())
+}
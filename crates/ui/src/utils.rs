@@ -4,23 +4,35 @@ use windows::Win32::{
     Graphics::Gdi::{GetMonitorInfoW, MonitorFromRect, MONITORINFO, MONITOR_DEFAULTTONEAREST},
 };
 
+use crate::ipc::Rect;
+
+// `position` is the caret line, `exclusion` is the text being edited, which must stay visible
+// the vertical candidates go beside the text like the next column, the others below it
 pub fn get_candidate_window_position(
-    top: i32,
-    left: i32,
-    bottom: i32,
-    right: i32,
+    position: Rect,
+    caret_height: i32,
+    exclusion: Rect,
+    vertical: bool,
     window: &Window,
 ) -> (f64, f64) {
-    let mut x = left - 15;
-    let mut y = bottom;
+    // some applications report an empty rect for the composition, use the caret line instead
+    let exclusion = if exclusion.bottom > exclusion.top {
+        exclusion
+    } else {
+        Rect {
+            top: position.bottom - caret_height,
+            ..position
+        }
+    };
 
+    // the monitor of the caret, the composition may run over to the next one
     let monitor = unsafe {
         MonitorFromRect(
             &RECT {
-                left,
-                top,
-                right,
-                bottom,
+                left: position.left,
+                top: position.bottom - caret_height,
+                right: position.left + 1,
+                bottom: position.bottom,
             } as *const _,
             MONITOR_DEFAULTTONEAREST,
         )
@@ -33,26 +45,46 @@ pub fn get_candidate_window_position(
         let _ = GetMonitorInfoW(monitor, &mut monitor_info);
     }
 
-    // If the bottom of the candidate window is hidden, show it above
-    y = if y + window.inner_size().height as i32 > monitor_info.rcWork.bottom {
-        top - window.inner_size().height as i32
-    } else {
-        y
-    };
+    if vertical {
+        return vertical_position(exclusion, monitor_info.rcWork, window);
+    }
+    horizontal_position(position, exclusion, monitor_info.rcWork, window)
+}
 
-    // If the right of the candidate window is hidden, show it to the left
-    x = if x + window.inner_size().width as i32 > monitor_info.rcWork.right {
-        monitor_info.rcWork.right - window.inner_size().width as i32
-    } else {
-        x
-    };
+// below the text, or above it when there is no room, and along it from the caret
+fn horizontal_position(position: Rect, exclusion: Rect, work: RECT, window: &Window) -> (f64, f64) {
+    let width = window.inner_size().width as i32;
+    let height = window.inner_size().height as i32;
 
-    // If the left of the candidate window is hidden, show it to the right
-    x = if x < monitor_info.rcWork.left {
-        monitor_info.rcWork.left
+    // when it fits on neither side, the side with more room covers the least of the text
+    let below = work.bottom - exclusion.bottom;
+    let above = exclusion.top - work.top;
+    let y = if height <= below || below >= above {
+        exclusion.bottom
     } else {
-        x
+        exclusion.top - height
     };
+    let y = y.clamp(work.top, (work.bottom - height).max(work.top));
+
+    // near the right edge it is moved to the left, but stays below or above the text
+    let x = (position.left - 15).clamp(work.left, (work.right - width).max(work.left));
+
+    (x as f64, y as f64)
+}
+
+// on the left of the text, or on the right when there is no room, and along it from its top
+fn vertical_position(exclusion: Rect, work: RECT, window: &Window) -> (f64, f64) {
+    let width = window.inner_size().width as i32;
+    let height = window.inner_size().height as i32;
+
+    let mut x = exclusion.left - width;
+    if x < work.left {
+        x = exclusion.right;
+    }
+    // If it doesn't fit on the right either, keep it on the screen
+    x = x.clamp(work.left, (work.right - width).max(work.left));
+
+    let y = (exclusion.top - 15).clamp(work.top, (work.bottom - height).max(work.top));
 
     (x as f64, y as f64)
 }
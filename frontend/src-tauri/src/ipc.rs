@@ -1,17 +1,19 @@
 use anyhow::Result;
-use hyper_util::rt::TokioIo;
-use shared::proto::azookey_service_client::AzookeyServiceClient;
-use std::{sync::Arc, time::Duration};
-use tokio::{net::windows::named_pipe::ClientOptions, time};
-use tonic::transport::Endpoint;
-use tower::service_fn;
-use windows::Win32::Foundation::ERROR_PIPE_BUSY;
+use shared::proto::{
+    admin_service_client::AdminServiceClient, azookey_service_client::AzookeyServiceClient,
+};
+use shared::transport::{channel, NamedPipe};
+use shared::{AppConfig, TimeoutClass};
+use std::{future::Future, sync::Arc};
+use tokio::time;
 
 // connect to kkc server
 #[derive(Debug, Clone)]
 pub struct IPCService {
     // kkc server client
     azookey_client: AzookeyServiceClient<tonic::transport::channel::Channel>,
+    // admin client on the same connection, e.g. for the log viewer
+    admin_client: AdminServiceClient<tonic::transport::channel::Channel>,
     runtime: Arc<tokio::runtime::Runtime>,
 }
 
@@ -19,41 +21,114 @@ impl IPCService {
     pub fn new() -> Result<Self> {
         let runtime = tokio::runtime::Runtime::new()?;
 
-        let server_channel = runtime.block_on(
-            Endpoint::try_from("http://[::]:50051")?.connect_with_connector(service_fn(
-                |_| async {
-                    let client = loop {
-                        match ClientOptions::new().open(r"\\.\pipe\azookey_server") {
-                            Ok(client) => break client,
-                            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => (),
-                            Err(e) => return Err(e),
-                        }
+        let server_channel = runtime.block_on(channel(
+            "http://[::]:50051",
+            NamedPipe::new("azookey_server"),
+        ))?;
 
-                        time::sleep(Duration::from_millis(50)).await;
-                    };
-
-                    Ok::<_, std::io::Error>(TokioIo::new(client))
-                },
-            )),
-        )?;
-
-        let azookey_client = AzookeyServiceClient::new(server_channel);
+        let azookey_client = AzookeyServiceClient::new(server_channel.clone());
+        let admin_client = AdminServiceClient::new(server_channel);
 
         Ok(Self {
             azookey_client,
+            admin_client,
             runtime: Arc::new(runtime),
         })
     }
 }
 
+// the settings app never waits for the server longer than the admin timeout
+// read on every request, so that changes in the settings are applied at once
+fn admin_timeout() -> std::time::Duration {
+    AppConfig::try_read()
+        .unwrap_or_default()
+        .ipc
+        .timeout(TimeoutClass::Admin)
+}
+
 // implement methods to interact with kkc server
 impl IPCService {
+    fn call<T>(
+        &self,
+        request: impl Future<Output = Result<tonic::Response<T>, tonic::Status>>,
+    ) -> anyhow::Result<T> {
+        self.runtime.block_on(async {
+            match time::timeout(admin_timeout(), request).await {
+                Ok(Ok(response)) => Ok(response.into_inner()),
+                Ok(Err(status)) => Err(anyhow::anyhow!("gRPC error: {}", status)),
+                Err(_elapsed) => Err(anyhow::anyhow!("IPC timeout: server may be busy")),
+            }
+        })
+    }
+
     pub fn update_config(&mut self) -> anyhow::Result<()> {
         let request = tonic::Request::new(shared::proto::UpdateConfigRequest {});
-        self.runtime
-            .clone()
-            .block_on(self.azookey_client.update_config(request))?;
+        let mut client = self.azookey_client.clone();
+        self.call(client.update_config(request))?;
 
         Ok(())
     }
 }
+
+// implement methods for the settings app itself
+impl IPCService {
+    pub fn get_build_info(&mut self) -> anyhow::Result<shared::proto::BuildInfo> {
+        let request = tonic::Request::new(shared::proto::GetBuildInfoRequest {});
+        let mut client = self.admin_client.clone();
+
+        self.call(client.get_build_info(request))
+    }
+
+    // returns the number of user dictionary entries after reloading
+    pub fn reload_dictionaries(&mut self) -> anyhow::Result<u32> {
+        let request = tonic::Request::new(shared::proto::ReloadDictionariesRequest {});
+        let mut client = self.admin_client.clone();
+        let response = self.call(client.reload_dictionaries(request))?;
+
+        Ok(response.user_entries)
+    }
+
+    // the files of the export, see `ExportDataRequest` for the fields
+    pub fn export_data(
+        &mut self,
+        format: shared::proto::export_data_request::Format,
+        fields: Vec<String>,
+    ) -> anyhow::Result<Vec<shared::proto::ExportedFile>> {
+        let request = tonic::Request::new(shared::proto::ExportDataRequest {
+            format: format as i32,
+            fields,
+        });
+        let mut client = self.admin_client.clone();
+        let response = self.call(client.export_data(request))?;
+
+        Ok(response.files)
+    }
+
+    // send the server log lines at `level` or above to `on_line` until the task is aborted
+    pub fn tail_log(
+        &self,
+        level: String,
+        on_line: impl Fn(shared::proto::LogLine) + Send + 'static,
+    ) -> tokio::task::JoinHandle<()> {
+        let mut client = self.admin_client.clone();
+
+        self.runtime.spawn(async move {
+            let request = tonic::Request::new(shared::proto::TailLogRequest {
+                level,
+                backlog: 500,
+            });
+
+            match client.tail_log(request).await {
+                Ok(response) => {
+                    let mut lines = response.into_inner();
+                    while let Ok(Some(line)) = lines.message().await {
+                        on_line(line);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to tail the server log: {}", e);
+                }
+            }
+        })
+    }
+}
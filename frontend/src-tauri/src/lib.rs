@@ -1,13 +1,16 @@
 mod ipc;
 
 use serde::{Deserialize, Serialize};
-use shared::AppConfig;
-use std::{path::PathBuf, sync::Mutex};
+use shared::proto::export_data_request::Format as ExportFormat;
+use shared::{AppConfig, KeyPreset};
+use std::{collections::BTreeMap, path::PathBuf, sync::Mutex};
 
 #[derive(Debug)]
 pub struct AppState {
     settings: Mutex<AppConfig>,
     ipc: Mutex<Option<ipc::IPCService>>,
+    // the log viewer reading the server log, replaced when the viewer is opened again
+    log_tail: Mutex<Option<tokio::task::JoinHandle<()>>>,
 }
 
 impl AppState {
@@ -15,6 +18,7 @@ impl AppState {
         AppState {
             settings: Mutex::new(AppConfig::new()),
             ipc: Mutex::new(None), // Lazy initialization - connect only when needed
+            log_tail: Mutex::new(None),
         }
     }
 
@@ -64,6 +68,153 @@ fn update_config(state: tauri::State<AppState>, new_config: AppConfig) -> Result
     Ok(())
 }
 
+// strings of the configured language, the page is reloaded when it is changed
+#[tauri::command]
+fn get_strings(state: tauri::State<AppState>) -> BTreeMap<&'static str, &'static str> {
+    let locale = state.settings.lock().unwrap().locale;
+    shared::i18n::strings(locale)
+}
+
+// returns the new config, so that the page can show the new key settings
+#[tauri::command]
+fn apply_key_preset(state: tauri::State<AppState>, preset: KeyPreset) -> Result<AppConfig, String> {
+    let mut config = state.settings.lock().map_err(|e| e.to_string())?;
+    config.keys.apply_preset(preset);
+    config.write();
+
+    if let Some(mut ipc) = state.get_ipc() {
+        if let Err(e) = ipc.update_config() {
+            eprintln!("Failed to notify server of config update: {}", e);
+        }
+    }
+    Ok(config.clone())
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct BuildInfo {
+    version: String,
+    git_commit: String,
+    build_date: String,
+    kkc_revision: String,
+    zenzai_version: String,
+    backend: String,
+    zenzai_enabled: bool,
+}
+
+#[tauri::command]
+fn get_build_info(state: tauri::State<AppState>) -> Result<BuildInfo, String> {
+    let mut ipc = state
+        .get_ipc()
+        .ok_or("サーバーに接続できませんでした".to_string())?;
+    let info = ipc.get_build_info().map_err(|e| e.to_string())?;
+
+    Ok(BuildInfo {
+        version: info.version,
+        git_commit: info.git_commit,
+        build_date: info.build_date,
+        kkc_revision: info.kkc_revision,
+        zenzai_version: info.zenzai_version,
+        backend: info.backend,
+        zenzai_enabled: info.zenzai_enabled,
+    })
+}
+
+#[tauri::command]
+async fn reload_dictionaries(state: tauri::State<'_, AppState>) -> Result<u32, String> {
+    let mut ipc = state
+        .get_ipc()
+        .ok_or("サーバーに接続できませんでした".to_string())?;
+
+    // reloading takes a while, so don't block the main thread
+    tauri::async_runtime::spawn_blocking(move || ipc.reload_dictionaries())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+// writes the export to a new folder in the export directory and returns its path
+#[tauri::command]
+async fn export_data(
+    state: tauri::State<'_, AppState>,
+    format: String,
+    fields: Vec<String>,
+) -> Result<String, String> {
+    let format = match format.as_str() {
+        "json" => ExportFormat::Json,
+        "csv" => ExportFormat::Csv,
+        _ => return Err(format!("unknown format: {}", format)),
+    };
+    let mut ipc = state
+        .get_ipc()
+        .ok_or("サーバーに接続できませんでした".to_string())?;
+
+    let files = tauri::async_runtime::spawn_blocking(move || ipc.export_data(format, fields))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let dir = shared::get_export_dir().join(secs.to_string());
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    for file in files {
+        // the names come from the server, never let them leave the folder
+        let name = std::path::Path::new(&file.name)
+            .file_name()
+            .ok_or(format!("invalid file name: {}", file.name))?;
+        std::fs::write(dir.join(name), file.content).map_err(|e| e.to_string())?;
+    }
+
+    Ok(dir.to_string_lossy().into_owned())
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct LogLine {
+    timestamp: String,
+    level: String,
+    target: String,
+    message: String,
+}
+
+#[tauri::command]
+fn tail_log(
+    state: tauri::State<AppState>,
+    level: String,
+    on_line: tauri::ipc::Channel<LogLine>,
+) -> Result<(), String> {
+    let ipc = state
+        .get_ipc()
+        .ok_or("サーバーに接続できませんでした".to_string())?;
+
+    let handle = ipc.tail_log(level, move |line| {
+        let _ = on_line.send(LogLine {
+            timestamp: line.timestamp,
+            level: line.level,
+            target: line.target,
+            message: line.message,
+        });
+    });
+
+    let mut log_tail = state.log_tail.lock().map_err(|e| e.to_string())?;
+    if let Some(previous) = log_tail.replace(handle) {
+        previous.abort();
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+fn stop_tail_log(state: tauri::State<AppState>) -> Result<(), String> {
+    let mut log_tail = state.log_tail.lock().map_err(|e| e.to_string())?;
+    if let Some(handle) = log_tail.take() {
+        handle.abort();
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct Capability {
     cpu: bool,
@@ -123,7 +274,14 @@ pub fn run() {
             greet,
             get_config,
             update_config,
-            check_capability
+            apply_key_preset,
+            get_strings,
+            check_capability,
+            tail_log,
+            stop_tail_log,
+            reload_dictionaries,
+            get_build_info,
+            export_data
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,4 +1,4 @@
-import { Bot, Settings, Megaphone, Book } from "lucide-react"
+import { Bot, Settings, Megaphone, Book, ScrollText } from "lucide-react"
 
 import {
     Sidebar,
@@ -11,11 +11,12 @@ import {
     SidebarMenuButton,
     SidebarMenuItem,
 } from "@/components/ui/sidebar"
+import { useStrings } from "@/lib/i18n"
 
-// Menu items.
+// Menu items, the titles are keys of the strings
 const contents = [
     {
-        title: "全般",
+        title: "nav.general",
         url: "/",
         icon: Settings,
     },
@@ -25,12 +26,12 @@ const contents = [
     //     icon: Palette,
     // },
     {
-        title: "Zenzai",
+        title: "nav.zenzai",
         url: "/zenzai",
         icon: Bot,
     },
     {
-        title: "辞書",
+        title: "nav.dictionary",
         url: "/dictionary",
         icon: Book,
     },
@@ -39,7 +40,12 @@ const contents = [
 // Footer items.
 const footer = [
     {
-        title: "Azookeyについて",
+        title: "nav.logs",
+        url: "/logs",
+        icon: ScrollText,
+    },
+    {
+        title: "nav.about",
         url: "/about",
         icon: Megaphone,
     },
@@ -47,12 +53,13 @@ const footer = [
 
 export function AppSidebar() {
     let currentPath = window.location.pathname;
+    const t = useStrings();
 
     return (
         <Sidebar>
             <SidebarContent>
                 <SidebarGroup>
-                    <SidebarGroupLabel>設定</SidebarGroupLabel>
+                    <SidebarGroupLabel>{t("nav.settings")}</SidebarGroupLabel>
                     <SidebarGroupContent>
                         <SidebarMenu>
                             {contents.map((item) => (
@@ -60,7 +67,7 @@ export function AppSidebar() {
                                     <SidebarMenuButton asChild>
                                         <a href={item.url}>
                                             <item.icon />
-                                            <span>{item.title}</span>
+                                            <span>{t(item.title)}</span>
                                         </a>
                                     </SidebarMenuButton>
                                 </SidebarMenuItem>
@@ -78,7 +85,7 @@ export function AppSidebar() {
                                     <SidebarMenuButton asChild>
                                         <a href={item.url}>
                                             <item.icon />
-                                            <span>{item.title}</span>
+                                            <span>{t(item.title)}</span>
                                         </a>
                                     </SidebarMenuButton>
                                 </SidebarMenuItem>
@@ -0,0 +1,30 @@
+import { createContext, ReactNode, useContext, useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+
+type Strings = Record<string, string>;
+
+const StringsContext = createContext<Strings>({});
+
+// the strings live in the shared crate, so that the language bar uses the same ones
+export const StringsProvider = ({ children }: { children: ReactNode }) => {
+    const [strings, setStrings] = useState<Strings | null>(null);
+
+    useEffect(() => {
+        invoke<Strings>("get_strings")
+            .then(setStrings)
+            .catch(() => setStrings({}));
+    }, []);
+
+    // wait for the strings instead of showing the keys for a moment
+    if (!strings) {
+        return null;
+    }
+
+    return <StringsContext.Provider value={strings}>{children}</StringsContext.Provider>;
+};
+
+// unknown keys are shown as they are
+export const useStrings = () => {
+    const strings = useContext(StringsContext);
+    return (key: string) => strings[key] ?? key;
+};
@@ -11,26 +11,31 @@ import { Appearance } from "@/pages/appearance"
 import { Zenzai } from "@/pages/zenzai"
 import { Dictionary } from "@/pages/dictionary"
 import { About } from "@/pages/about"
+import { Logs } from "@/pages/logs"
 import { Toaster } from "@/components/ui/sonner"
+import { StringsProvider } from "@/lib/i18n"
 
 ReactDOM.createRoot(document.getElementById("root") as HTMLElement).render(
   <React.StrictMode>
-    <SidebarProvider>
-      <BrowserRouter>
-        <AppSidebar />
-        <main className="w-full p-6">
-          <ThemeProvider defaultTheme="system" storageKey="vite-ui-theme">
-            <Routes>
-              <Route path="/" element={<General />} />
-              <Route path="/appearance" element={<Appearance />} />
-              <Route path="/zenzai" element={<Zenzai />} />
-              <Route path="/dictionary" element={<Dictionary />} />
-              <Route path="/about" element={<About />} />
-            </Routes>
-            <Toaster />
-          </ThemeProvider>
-        </main>
-      </BrowserRouter>
-    </SidebarProvider>
+    <StringsProvider>
+      <SidebarProvider>
+        <BrowserRouter>
+          <AppSidebar />
+          <main className="w-full p-6">
+            <ThemeProvider defaultTheme="system" storageKey="vite-ui-theme">
+              <Routes>
+                <Route path="/" element={<General />} />
+                <Route path="/appearance" element={<Appearance />} />
+                <Route path="/zenzai" element={<Zenzai />} />
+                <Route path="/dictionary" element={<Dictionary />} />
+                <Route path="/logs" element={<Logs />} />
+                <Route path="/about" element={<About />} />
+              </Routes>
+              <Toaster />
+            </ThemeProvider>
+          </main>
+        </BrowserRouter>
+      </SidebarProvider>
+    </StringsProvider>
   </React.StrictMode>,
 );
@@ -1,11 +1,44 @@
-import { ExternalLink } from "lucide-react";
+import { ExternalLink, Info } from "lucide-react";
 import { Button } from "@/components/ui/button";
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { useStrings } from "@/lib/i18n";
+
+interface BuildInfo {
+    version: string;
+    git_commit: string;
+    build_date: string;
+    kkc_revision: string;
+    zenzai_version: string;
+    backend: string;
+    zenzai_enabled: boolean;
+}
 
 export const About = () => {
+    const t = useStrings();
+    const [buildInfo, setBuildInfo] = useState<BuildInfo | null>(null);
+
+    useEffect(() => {
+        invoke<BuildInfo>("get_build_info")
+            .then(setBuildInfo)
+            .catch(() => {
+                // The server may not be running
+            });
+    }, []);
+
+    const rows = buildInfo ? [
+        [t("about.build.version"), buildInfo.version],
+        [t("about.build.commit"), buildInfo.git_commit],
+        [t("about.build.date"), new Date(buildInfo.build_date).toLocaleString()],
+        [t("about.build.engine"), buildInfo.kkc_revision],
+        ["Zenzai", `${buildInfo.zenzai_version}（${buildInfo.zenzai_enabled ? t("about.build.enabled") : t("about.build.disabled")}）`],
+        [t("about.build.backend"), buildInfo.backend],
+    ] : [];
+
     return (
         <div className="space-y-8">
             <section className="space-y-2">
-                <h1 className="text-sm font-bold text-foreground">このソフトについて</h1>
+                <h1 className="text-sm font-bold text-foreground">{t("about.title")}</h1>
                 <div className="flex items-center space-x-4 rounded-md border p-4">
                     <ExternalLink />
                     <div className="flex-1 space-y-1">
@@ -13,16 +46,36 @@ export const About = () => {
                             Discord
                         </p>
                         <p className="text-xs text-muted-foreground">
-                            Azookey公式Discordサーバーに参加して、最新情報を入手する
+                            {t("about.discord.description")}
                         </p>
                     </div>
                     <Button variant="secondary">
                         <a href="https://discord.com/invite/dY9gHuyZN5" target="_blank" rel="noopener noreferrer">
-                            参加する
+                            {t("about.discord.join")}
                         </a>
                     </Button>
                 </div>
             </section>
+            <section className="space-y-2">
+                <h1 className="text-sm font-bold text-foreground">{t("about.build")}</h1>
+                <div className="flex items-start space-x-4 rounded-md border p-4">
+                    <Info />
+                    {buildInfo ? (
+                        <dl className="flex-1 grid grid-cols-[8rem_1fr] gap-y-1 text-sm">
+                            {rows.map(([label, value]) => (
+                                <div key={label} className="contents">
+                                    <dt className="text-muted-foreground">{label}</dt>
+                                    <dd className="font-mono select-text">{value}</dd>
+                                </div>
+                            ))}
+                        </dl>
+                    ) : (
+                        <p className="flex-1 text-sm text-muted-foreground">
+                            {t("about.build.unavailable")}
+                        </p>
+                    )}
+                </div>
+            </section>
         </div>
     )
-}
\ No newline at end of file
+}
@@ -1,6 +1,13 @@
 import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
-import { Book, Plus, Trash2 } from "lucide-react";
+import {
+    Select,
+    SelectContent,
+    SelectItem,
+    SelectTrigger,
+    SelectValue,
+} from "@/components/ui/select";
+import { Book, Download, Plus, RefreshCcw, Trash2 } from "lucide-react";
 import { useEffect, useState } from "react";
 import { toast } from "sonner";
 import { invoke } from "@tauri-apps/api/core";
@@ -8,12 +15,34 @@ import { invoke } from "@tauri-apps/api/core";
 interface DictionaryEntry {
     word: string;
     reading: string;
+    note?: string;
 }
 
+// the fields of `ExportData`, as "table.column"
+const EXPORT_TABLES = [
+    { name: "learning", label: "学習履歴", columns: [["learned_at", "日時"], ["reading", "読み"], ["word", "単語"]] },
+    { name: "dictionary", label: "ユーザー辞書", columns: [["reading", "読み"], ["word", "単語"], ["note", "注釈"]] },
+    {
+        name: "statistics",
+        label: "入力の統計",
+        columns: [
+            ["started_at", "集計の開始日時"],
+            ["typed_characters", "入力した文字数"],
+            ["removed_characters", "削除した文字数"],
+            ["committed_clauses", "先に確定した文節数"],
+            ["learned_candidates", "学習した候補数"],
+        ],
+    },
+];
+
 export const Dictionary = () => {
     const [entries, setEntries] = useState<DictionaryEntry[]>([]);
     const [newWord, setNewWord] = useState("");
     const [newReading, setNewReading] = useState("");
+    const [exportFormat, setExportFormat] = useState("json");
+    const [exportFields, setExportFields] = useState<string[]>(
+        EXPORT_TABLES.flatMap((table) => table.columns.map(([column]) => `${table.name}.${column}`))
+    );
 
     // Load dictionary on component mount
     useEffect(() => {
@@ -92,6 +121,35 @@ export const Dictionary = () => {
         }
     };
 
+    const handleReload = async () => {
+        try {
+            const count = await invoke<number>("reload_dictionaries");
+            toast(`辞書を再読み込みしました（ユーザー辞書 ${count} 語）`);
+        } catch (error) {
+            toast("辞書の再読み込みに失敗しました");
+        }
+    };
+
+    const toggleExportField = (field: string) => {
+        setExportFields((fields) =>
+            fields.includes(field) ? fields.filter((f) => f !== field) : [...fields, field]
+        );
+    };
+
+    const handleExport = async () => {
+        if (exportFields.length === 0) {
+            toast("書き出す項目を選択してください");
+            return;
+        }
+
+        try {
+            const dir = await invoke<string>("export_data", { format: exportFormat, fields: exportFields });
+            toast(`${dir} に書き出しました`);
+        } catch (error) {
+            toast("データの書き出しに失敗しました");
+        }
+    };
+
     const handleKeyDown = (e: React.KeyboardEvent) => {
         if (e.key === "Enter") {
             handleAddWord();
@@ -135,6 +193,20 @@ export const Dictionary = () => {
                         </Button>
                     </div>
                 </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <RefreshCcw />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            辞書を再読み込み
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            変換中の入力を止めずに、システム辞書とユーザー辞書を読み込み直します
+                        </p>
+                    </div>
+                    <Button onClick={handleReload} variant="secondary">
+                        再読み込み
+                    </Button>
+                </div>
             </section>
 
             <section className="space-y-2">
@@ -172,6 +244,53 @@ export const Dictionary = () => {
                     )}
                 </div>
             </section>
+
+            <section className="space-y-2">
+                <h1 className="text-sm font-bold text-foreground">データの書き出し</h1>
+                <div className="space-y-4 rounded-md border p-4">
+                    <div className="flex items-center space-x-4">
+                        <Download />
+                        <div className="flex-1 space-y-1">
+                            <p className="text-sm font-medium leading-none">
+                                学習履歴と統計を書き出す
+                            </p>
+                            <p className="text-xs text-muted-foreground">
+                                選んだ項目を JSON または CSV で設定フォルダーの export に書き出します
+                            </p>
+                        </div>
+                        <Select value={exportFormat} onValueChange={setExportFormat}>
+                            <SelectTrigger className="w-28">
+                                <SelectValue placeholder="形式" />
+                            </SelectTrigger>
+                            <SelectContent>
+                                <SelectItem value="json">JSON</SelectItem>
+                                <SelectItem value="csv">CSV</SelectItem>
+                            </SelectContent>
+                        </Select>
+                        <Button onClick={handleExport} variant="secondary">
+                            書き出す
+                        </Button>
+                    </div>
+                    {EXPORT_TABLES.map((table) => (
+                        <div key={table.name} className="flex flex-wrap items-center gap-2">
+                            <span className="w-24 text-sm text-muted-foreground">{table.label}</span>
+                            {table.columns.map(([column, label]) => {
+                                const field = `${table.name}.${column}`;
+                                return (
+                                    <Button
+                                        key={field}
+                                        size="sm"
+                                        variant={exportFields.includes(field) ? "secondary" : "outline"}
+                                        onClick={() => toggleExportField(field)}
+                                    >
+                                        {label}
+                                    </Button>
+                                );
+                            })}
+                        </div>
+                    ))}
+                </div>
+            </section>
         </div>
     );
 };
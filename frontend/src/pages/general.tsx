@@ -1,7 +1,490 @@
 import { Button } from "@/components/ui/button";
-import { RefreshCcw, ExternalLink } from "lucide-react";
+import { Input } from "@/components/ui/input";
+import { Switch } from "@/components/ui/switch";
+import {
+    Select,
+    SelectContent,
+    SelectItem,
+    SelectTrigger,
+    SelectValue,
+} from "@/components/ui/select"
+import { RefreshCcw, ExternalLink, Repeat, History, Keyboard, Delete, ArrowDownWideNarrow, MessageSquareText, Maximize, Settings, Layers, Languages, ShieldCheck, GraduationCap, Workflow, Zap, Sparkles, Type, Hand, Quote, Timer, ArrowBigUp, Space, ToggleRight, CaseUpper, Smile, Gamepad2, SpellCheck, CaseSensitive, Accessibility, Columns3, PanelTop, ALargeSmall, UnfoldVertical } from "lucide-react";
+import { useEffect, useState } from "react";
+import { toast } from "sonner"
+import { invoke } from '@tauri-apps/api/core';
+import { useStrings } from "@/lib/i18n";
+
+// an action of a shortcut as written in the config, e.g. "commit" or {"transform": "katakana"}
+const formatAction = (action: any): string =>
+    typeof action === "string"
+        ? action
+        : Object.entries(action)
+              .map(([name, value]) => `${name}(${value})`)
+              .join(", ");
 
 export const General = () => {
+    const t = useStrings();
+    const [wrap, setWrap] = useState(false);
+    const [order, setOrder] = useState("learning");
+    const [filterOffensive, setFilterOffensive] = useState(true);
+    const [vocabulary, setVocabulary] = useState("all");
+    const [predictions, setPredictions] = useState(true);
+    const [emoji, setEmoji] = useState(true);
+    const [english, setEnglish] = useState(true);
+    const [vertical, setVertical] = useState(false);
+    // the family is saved when the input loses the focus, the numbers as strings for the selects
+    const [fontFamily, setFontFamily] = useState("");
+    const [fontSize, setFontSize] = useState("14.4");
+    const [lineSpacing, setLineSpacing] = useState("1.5");
+    const [candidateWindow, setCandidateWindow] = useState("always");
+    const [learning, setLearning] = useState("learn");
+    const [liveConversion, setLiveConversion] = useState(true);
+    const [typoCorrection, setTypoCorrection] = useState(false);
+    // milliseconds as a string for the select, "0" is off
+    const [autoCommit, setAutoCommit] = useState("0");
+    const [escape, setEscape] = useState("cancel");
+    const [backspace, setBackspace] = useState("delete");
+    const [tab, setTab] = useState("detail");
+    const [fullscreen, setFullscreen] = useState("hide");
+    // executable names separated by commas, saved when the input loses the focus
+    const [excludedApps, setExcludedApps] = useState("");
+    // an empty shortcut is shown as "off", the select can't have an empty value
+    const [openSettings, setOpenSettings] = useState("off");
+    // the preset applied last, the keys can be changed one by one afterwards
+    const [preset, setPreset] = useState("");
+    // shortcuts of `keys.bindings`, replaced by the preset
+    const [bindings, setBindings] = useState<any[]>([]);
+    const [locale, setLocale] = useState("ja");
+    const [commitEvents, setCommitEvents] = useState(false);
+    const [romajiLayout, setRomajiLayout] = useState("standard");
+    const [punctuation, setPunctuation] = useState("japanese");
+    const [shiftKatakana, setShiftKatakana] = useState(false);
+    const [stickyModifiers, setStickyModifiers] = useState(false);
+    const [henkanMuhenkan, setHenkanMuhenkan] = useState(false);
+    const [capsLock, setCapsLock] = useState("caps");
+    const [spaceWidth, setSpaceWidth] = useState("half");
+    const [thumbShift, setThumbShift] = useState(false);
+    const [leftThumb, setLeftThumb] = useState("muhenkan");
+    const [rightThumb, setRightThumb] = useState("space");
+
+    // Load config on component mount
+    useEffect(() => {
+        invoke<any>("get_config")
+            .then((data) => {
+                setWrap(data.candidate?.boundary === "wrap");
+                setOrder(data.candidate?.order ?? "learning");
+                setFilterOffensive(data.candidate?.filter_offensive ?? true);
+                setVocabulary(data.candidate?.vocabulary ?? "all");
+                setPredictions(data.candidate?.predictions ?? true);
+                setEmoji(data.candidate?.emoji ?? true);
+                setEnglish(data.candidate?.english ?? true);
+                setVertical(data.ui?.vertical ?? false);
+                setFontFamily(data.ui?.font?.family ?? "");
+                setFontSize(String(data.ui?.font?.size ?? 14.4));
+                setLineSpacing(String(data.ui?.font?.line_spacing ?? 1.5));
+                setCandidateWindow(data.candidate?.window ?? "always");
+                setLearning(data.conversion?.learning ?? "learn");
+                setLiveConversion(data.conversion?.live_conversion ?? true);
+                setTypoCorrection(data.conversion?.typo_correction ?? false);
+                setAutoCommit(String(data.conversion?.auto_commit_ms ?? 0));
+                setEscape(data.keys?.escape ?? "cancel");
+                setBackspace(data.keys?.backspace ?? "delete");
+                setTab(data.keys?.tab ?? "detail");
+                setFullscreen(data.fullscreen?.behavior ?? "hide");
+                setExcludedApps((data.exclusion?.apps ?? []).join(", "));
+                setOpenSettings(data.keys?.open_settings || "off");
+                setBindings(data.keys?.bindings ?? []);
+                setLocale(data.locale ?? "ja");
+                setCommitEvents(data.automation?.commit_events ?? false);
+                setRomajiLayout(data.romaji_layout ?? "standard");
+                setPunctuation(data.punctuation_style ?? "japanese");
+                setShiftKatakana(data.keys?.shift_katakana ?? false);
+                setStickyModifiers(data.keys?.sticky_modifiers ?? false);
+                setHenkanMuhenkan(data.keys?.henkan_muhenkan ?? false);
+                setCapsLock(data.keys?.caps_lock ?? "caps");
+                setSpaceWidth(data.space_width ?? "half");
+                setThumbShift(data.thumb_shift?.enable ?? false);
+                setLeftThumb(data.thumb_shift?.left ?? "muhenkan");
+                setRightThumb(data.thumb_shift?.right ?? "space");
+            })
+            .catch(() => {
+                // Keep default values if config fetch fails
+            });
+    }, []);
+
+    const updateConfig = async (updater: (config: any) => void) => {
+        try {
+            const data = await invoke<any>("get_config");
+            updater(data);
+            await invoke("update_config", { newConfig: data });
+            return data;
+        } catch (error) {
+            toast(t("error.update_config"));
+            return null;
+        }
+    };
+
+    const handleWrapChange = async () => {
+        const data = await updateConfig((data) => {
+            data.candidate = { ...data.candidate, boundary: wrap ? "stop" : "wrap" };
+        });
+
+        if (data) {
+            setWrap(data.candidate.boundary === "wrap");
+        }
+    };
+
+    const handleOrderChange = async (value: string) => {
+        const data = await updateConfig((data) => {
+            data.candidate = { ...data.candidate, order: value };
+        });
+
+        if (data) {
+            setOrder(value);
+        }
+    };
+
+    const handleFilterOffensiveChange = async () => {
+        const data = await updateConfig((data) => {
+            data.candidate = { ...data.candidate, filter_offensive: !filterOffensive };
+        });
+
+        if (data) {
+            setFilterOffensive(data.candidate.filter_offensive);
+        }
+    };
+
+    const handleVocabularyChange = async (value: string) => {
+        const data = await updateConfig((data) => {
+            data.candidate = { ...data.candidate, vocabulary: value };
+        });
+
+        if (data) {
+            setVocabulary(value);
+        }
+    };
+
+    const handlePredictionsChange = async () => {
+        const data = await updateConfig((data) => {
+            data.candidate = { ...data.candidate, predictions: !predictions };
+        });
+
+        if (data) {
+            setPredictions(data.candidate.predictions);
+        }
+    };
+
+    const handleEmojiChange = async () => {
+        const data = await updateConfig((data) => {
+            data.candidate = { ...data.candidate, emoji: !emoji };
+        });
+
+        if (data) {
+            setEmoji(data.candidate.emoji);
+        }
+    };
+
+    const handleEnglishChange = async () => {
+        const data = await updateConfig((data) => {
+            data.candidate = { ...data.candidate, english: !english };
+        });
+
+        if (data) {
+            setEnglish(data.candidate.english);
+        }
+    };
+
+    const handleCandidateWindowChange = async (timing: string) => {
+        const data = await updateConfig((data) => {
+            data.candidate = { ...data.candidate, window: timing };
+        });
+
+        if (data) {
+            setCandidateWindow(timing);
+        }
+    };
+
+    const handleVerticalChange = async () => {
+        const data = await updateConfig((data) => {
+            data.ui = { ...data.ui, vertical: !vertical };
+        });
+
+        if (data) {
+            setVertical(data.ui.vertical);
+        }
+    };
+
+    const handleFontFamilyBlur = async () => {
+        const family = fontFamily.trim();
+        const data = await updateConfig((data) => {
+            data.ui = { ...data.ui, font: { ...data.ui?.font, family } };
+        });
+
+        if (data) {
+            setFontFamily(data.ui.font.family);
+        }
+    };
+
+    const handleFontSizeChange = async (value: string) => {
+        const data = await updateConfig((data) => {
+            data.ui = { ...data.ui, font: { ...data.ui?.font, size: Number(value) } };
+        });
+
+        if (data) {
+            setFontSize(String(data.ui.font.size));
+        }
+    };
+
+    const handleLineSpacingChange = async (value: string) => {
+        const data = await updateConfig((data) => {
+            data.ui = { ...data.ui, font: { ...data.ui?.font, line_spacing: Number(value) } };
+        });
+
+        if (data) {
+            setLineSpacing(String(data.ui.font.line_spacing));
+        }
+    };
+
+    const handleCommitEventsChange = async () => {
+        const data = await updateConfig((data) => {
+            data.automation = { ...data.automation, commit_events: !commitEvents };
+        });
+
+        if (data) {
+            setCommitEvents(data.automation.commit_events);
+        }
+    };
+
+    const handleRomajiLayoutChange = async (value: string) => {
+        const data = await updateConfig((data) => {
+            data.romaji_layout = value;
+        });
+
+        if (data) {
+            setRomajiLayout(value);
+        }
+    };
+
+    const handlePunctuationChange = async (value: string) => {
+        const data = await updateConfig((data) => {
+            data.punctuation_style = value;
+        });
+
+        if (data) {
+            setPunctuation(value);
+        }
+    };
+
+    const handleSpaceWidthChange = async (value: string) => {
+        const data = await updateConfig((data) => {
+            data.space_width = value;
+        });
+
+        if (data) {
+            setSpaceWidth(value);
+        }
+    };
+
+    const handleShiftKatakanaChange = async () => {
+        const data = await updateConfig((data) => {
+            data.keys = { ...data.keys, shift_katakana: !shiftKatakana };
+        });
+
+        if (data) {
+            setShiftKatakana(data.keys.shift_katakana);
+        }
+    };
+
+    const handleStickyModifiersChange = async () => {
+        const data = await updateConfig((data) => {
+            data.keys = { ...data.keys, sticky_modifiers: !stickyModifiers };
+        });
+
+        if (data) {
+            setStickyModifiers(data.keys.sticky_modifiers);
+        }
+    };
+
+    const handleHenkanMuhenkanChange = async () => {
+        const data = await updateConfig((data) => {
+            data.keys = { ...data.keys, henkan_muhenkan: !henkanMuhenkan };
+        });
+
+        if (data) {
+            setHenkanMuhenkan(data.keys.henkan_muhenkan);
+        }
+    };
+
+    const handleCapsLockChange = async (value: string) => {
+        const data = await updateConfig((data) => {
+            data.keys = { ...data.keys, caps_lock: value };
+        });
+
+        if (data) {
+            setCapsLock(value);
+        }
+    };
+
+    const handleThumbShiftChange = async () => {
+        const data = await updateConfig((data) => {
+            data.thumb_shift = { ...data.thumb_shift, enable: !thumbShift };
+        });
+
+        if (data) {
+            setThumbShift(data.thumb_shift.enable);
+        }
+    };
+
+    const handleThumbKeyChange = (side: "left" | "right") => async (key: string) => {
+        const data = await updateConfig((data) => {
+            data.thumb_shift = { ...data.thumb_shift, [side]: key };
+        });
+
+        if (data) {
+            (side === "left" ? setLeftThumb : setRightThumb)(key);
+        }
+    };
+
+    const handleLearningChange = async (mode: string) => {
+        const data = await updateConfig((data) => {
+            data.conversion = { ...data.conversion, learning: mode };
+        });
+
+        if (data) {
+            setLearning(mode);
+        }
+    };
+
+    const handleLiveConversionChange = async () => {
+        const data = await updateConfig((data) => {
+            data.conversion = { ...data.conversion, live_conversion: !liveConversion };
+        });
+
+        if (data) {
+            setLiveConversion(data.conversion.live_conversion);
+        }
+    };
+
+    const handleTypoCorrectionChange = async () => {
+        const data = await updateConfig((data) => {
+            data.conversion = { ...data.conversion, typo_correction: !typoCorrection };
+        });
+
+        if (data) {
+            setTypoCorrection(data.conversion.typo_correction);
+        }
+    };
+
+    const handleAutoCommitChange = async (value: string) => {
+        const data = await updateConfig((data) => {
+            data.conversion = { ...data.conversion, auto_commit_ms: Number(value) };
+        });
+
+        if (data) {
+            setAutoCommit(value);
+        }
+    };
+
+    const handleEscapeChange = async (behavior: string) => {
+        const data = await updateConfig((data) => {
+            data.keys = { ...data.keys, escape: behavior };
+        });
+
+        if (data) {
+            setEscape(behavior);
+        }
+    };
+
+    const handleBackspaceChange = async (behavior: string) => {
+        const data = await updateConfig((data) => {
+            data.keys = { ...data.keys, backspace: behavior };
+        });
+
+        if (data) {
+            setBackspace(behavior);
+        }
+    };
+
+    const handleTabChange = async (behavior: string) => {
+        const data = await updateConfig((data) => {
+            data.keys = { ...data.keys, tab: behavior };
+        });
+
+        if (data) {
+            setTab(behavior);
+        }
+    };
+
+    const handleFullscreenChange = async (behavior: string) => {
+        const data = await updateConfig((data) => {
+            data.fullscreen = { ...data.fullscreen, behavior };
+        });
+
+        if (data) {
+            setFullscreen(behavior);
+        }
+    };
+
+    const handleExcludedAppsBlur = async () => {
+        const apps = excludedApps
+            .split(",")
+            .map((app) => app.trim())
+            .filter((app) => app !== "");
+        const data = await updateConfig((data) => {
+            data.exclusion = { ...data.exclusion, apps };
+        });
+
+        if (data) {
+            setExcludedApps(apps.join(", "));
+        }
+    };
+
+    // the strings are loaded when the page is opened
+    const handleLocaleChange = async (value: string) => {
+        const data = await updateConfig((data) => {
+            data.locale = value;
+        });
+
+        if (data) {
+            setLocale(value);
+            window.location.reload();
+        }
+    };
+
+    const handlePresetChange = async (value: string) => {
+        try {
+            const data = await invoke<any>("apply_key_preset", { preset: value });
+            setEscape(data.keys.escape);
+            setBackspace(data.keys.backspace);
+            setTab(data.keys.tab);
+            setBindings(data.keys.bindings ?? []);
+            setPreset(value);
+        } catch (error) {
+            toast(t("error.update_config"));
+        }
+    };
+
+    const handleRemoveBinding = async (index: number) => {
+        const next = bindings.filter((_, i) => i !== index);
+        const data = await updateConfig((data) => {
+            data.keys = { ...data.keys, bindings: next };
+        });
+
+        if (data) {
+            setBindings(next);
+        }
+    };
+
+    const handleOpenSettingsChange = async (shortcut: string) => {
+        const data = await updateConfig((data) => {
+            data.keys = { ...data.keys, open_settings: shortcut === "off" ? "" : shortcut };
+        });
+
+        if (data) {
+            setOpenSettings(shortcut);
+        }
+    };
+
     return (
         <div className="space-y-8">
             <section className="space-y-2">
@@ -21,6 +504,633 @@ export const General = () => {
                     </Button>
                 </div>
             </section>
+            <section className="space-y-2">
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Languages />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            {t("general.locale")}
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            {t("general.locale.description")}
+                        </p>
+                    </div>
+                    <Select value={locale} onValueChange={handleLocaleChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="ja">日本語</SelectItem>
+                            <SelectItem value="en">English</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+            </section>
+            <section className="space-y-2">
+                <h1 className="text-sm font-bold text-foreground">入力</h1>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Type />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            ローマ字テーブル
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            ローマ字の拡張を選択します。設定フォルダの romaji.tsv の入力が優先されます
+                        </p>
+                    </div>
+                    <Select value={romajiLayout} onValueChange={handleRomajiLayoutChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="テーブルを選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="standard">標準</SelectItem>
+                            <SelectItem value="azik">AZIK</SelectItem>
+                            <SelectItem value="symbols">z + キーで記号</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Quote />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            句読点
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            「,」と「.」キーで入力する句読点を選択します
+                        </p>
+                    </div>
+                    <Select value={punctuation} onValueChange={handlePunctuationChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="句読点を選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="japanese">、。</SelectItem>
+                            <SelectItem value="western">，．</SelectItem>
+                            <SelectItem value="mixed">，。</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Space />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            スペース
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            ひらがな入力中、変換する文字がないときに入力するスペースを選択します
+                        </p>
+                    </div>
+                    <Select value={spaceWidth} onValueChange={handleSpaceWidthChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="スペースを選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="half">常に半角</SelectItem>
+                            <SelectItem value="full">常に全角</SelectItem>
+                            <SelectItem value="after_japanese">日本語の後だけ全角</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <ArrowBigUp />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            Shiftでカタカナ入力
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            Shiftを押しながら入力した文字を、変換前からカタカナにします
+                        </p>
+                    </div>
+                    <Switch checked={shiftKatakana} onCheckedChange={handleShiftKatakanaChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Accessibility />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            固定キー
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            ShiftやCtrlを押して離すと、次のキーと同時に押したことになります。もう一度押すと解除します
+                        </p>
+                    </div>
+                    <Switch checked={stickyModifiers} onCheckedChange={handleStickyModifiersChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <ToggleRight />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            変換/無変換キーでオン/オフ
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            変換キーでかな入力に、無変換キーで英数入力に切り替えます
+                        </p>
+                    </div>
+                    <Switch checked={henkanMuhenkan} onCheckedChange={handleHenkanMuhenkanChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <CaseUpper />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            Caps Lock / 英数キー
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            かな入力中にCaps Lockや英数キーを押したときの動作を選択します
+                        </p>
+                    </div>
+                    <Select value={capsLock} onValueChange={handleCapsLockChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="動作を選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="caps">Caps Lockのみ</SelectItem>
+                            <SelectItem value="latin">英数入力に切り替え</SelectItem>
+                            <SelectItem value="both">両方</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Hand />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            親指シフト (NICOLA)
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            文字キーと親指キーを同時に押して、かなを直接入力します
+                        </p>
+                    </div>
+                    <Switch checked={thumbShift} onCheckedChange={handleThumbShiftChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Hand />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            親指キー
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            親指シフトの左右の親指キーを選択します。単独で押したときは元のキーとして働きます
+                        </p>
+                    </div>
+                    <Select value={leftThumb} onValueChange={handleThumbKeyChange("left")} disabled={!thumbShift}>
+                        <SelectTrigger className="w-32">
+                            <SelectValue placeholder="左" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="muhenkan">無変換</SelectItem>
+                            <SelectItem value="space">スペース</SelectItem>
+                        </SelectContent>
+                    </Select>
+                    <Select value={rightThumb} onValueChange={handleThumbKeyChange("right")} disabled={!thumbShift}>
+                        <SelectTrigger className="w-32">
+                            <SelectValue placeholder="右" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="space">スペース</SelectItem>
+                            <SelectItem value="henkan">変換</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+            </section>
+            <section className="space-y-2">
+                <h1 className="text-sm font-bold text-foreground">変換</h1>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <History />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            学習
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            確定した候補を学習して、変換結果に反映します
+                        </p>
+                    </div>
+                    <Select value={learning} onValueChange={handleLearningChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="学習モードを選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="learn">学習する</SelectItem>
+                            <SelectItem value="read_only">学習を停止</SelectItem>
+                            <SelectItem value="off">学習結果を使わない</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Zap />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            ライブ変換
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            スペースキーを押さなくても、入力中の文字を変換して表示します
+                        </p>
+                    </div>
+                    <Switch checked={liveConversion} onCheckedChange={handleLiveConversionChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <SpellCheck />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            タイプミスの補正
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            余分に押したキーや、hs (sh) のような入れ替わったキーをローマ字入力中に直します
+                        </p>
+                    </div>
+                    <Switch checked={typoCorrection} onCheckedChange={handleTypoCorrectionChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Timer />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            自動確定
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            変換候補の表示中に、しばらくキーを押さないと選択中の候補を確定します
+                        </p>
+                    </div>
+                    <Select value={autoCommit} onValueChange={handleAutoCommitChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="時間を選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="0">しない</SelectItem>
+                            <SelectItem value="1000">1秒</SelectItem>
+                            <SelectItem value="2000">2秒</SelectItem>
+                            <SelectItem value="3000">3秒</SelectItem>
+                            <SelectItem value="5000">5秒</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+            </section>
+            <section className="space-y-2">
+                <h1 className="text-sm font-bold text-foreground">変換候補</h1>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Repeat />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            候補の選択をループする
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            最後の候補の次に最初の候補を選択します
+                        </p>
+                    </div>
+                    <Switch checked={wrap} onCheckedChange={handleWrapChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <ArrowDownWideNarrow />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            候補の並び順
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            変換候補を表示する順番を選択します
+                        </p>
+                    </div>
+                    <Select value={order} onValueChange={handleOrderChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="並び順を選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="learning">学習結果を優先</SelectItem>
+                            <SelectItem value="frequency">辞書の頻度順</SelectItem>
+                            <SelectItem value="shortest">短い候補を優先</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <ShieldCheck />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            不適切な語句を候補に出さない
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            読みを正確に入力したときだけ、不適切な語句を候補に表示します
+                        </p>
+                    </div>
+                    <Switch checked={filterOffensive} onCheckedChange={handleFilterOffensiveChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Sparkles />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            予測候補
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            入力中に読みの続きを補った候補を表示し、Tabキーで選択します
+                        </p>
+                    </div>
+                    <Switch checked={predictions} onCheckedChange={handlePredictionsChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Smile />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            絵文字変換
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            「ねこ」や「わらい」などの読みで、変換候補の後に絵文字を表示します
+                        </p>
+                    </div>
+                    <Switch checked={emoji} onCheckedChange={handleEmojiChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <CaseSensitive />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            英単語の候補
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            かなモードのまま「apple」などと入力したとき、英単語とそのカタカナを変換候補に表示します
+                        </p>
+                    </div>
+                    <Switch checked={english} onCheckedChange={handleEnglishChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <PanelTop />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            候補ウィンドウの表示
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            入力中から候補ウィンドウを表示するか、最初の候補は文中にだけ表示して2回目の変換で候補ウィンドウを開くかを選択します
+                        </p>
+                    </div>
+                    <Select value={candidateWindow} onValueChange={handleCandidateWindowChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="表示を選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="always">入力中から表示</SelectItem>
+                            <SelectItem value="second_space">2回目の変換で表示</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Columns3 />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            縦書きの候補一覧
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            変換候補を縦書きの列で右から並べます。Direct2Dで描画しているときのみ有効です
+                        </p>
+                    </div>
+                    <Switch checked={vertical} onCheckedChange={handleVerticalChange} />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Type />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            候補のフォント
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            候補一覧の文字のフォント名を指定します。空欄では Yu Gothic UI を使います
+                        </p>
+                    </div>
+                    <Input
+                        placeholder="例: BIZ UDPゴシック"
+                        value={fontFamily}
+                        onChange={(e) => setFontFamily(e.target.value)}
+                        onBlur={handleFontFamilyBlur}
+                        className="w-48"
+                    />
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <ALargeSmall />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            候補の文字の大きさ
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            候補一覧の文字の大きさを選択します。次に文字を入力するときから反映されます
+                        </p>
+                    </div>
+                    <Select value={fontSize} onValueChange={handleFontSizeChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="大きさを選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="12">小</SelectItem>
+                            <SelectItem value="14.4">標準</SelectItem>
+                            <SelectItem value="18">大</SelectItem>
+                            <SelectItem value="24">特大</SelectItem>
+                            <SelectItem value="32">最大</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <UnfoldVertical />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            候補の行の高さ
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            文字の大きさに対する候補一覧の行の高さを選択します
+                        </p>
+                    </div>
+                    <Select value={lineSpacing} onValueChange={handleLineSpacingChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="行の高さを選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="1.2">狭い</SelectItem>
+                            <SelectItem value="1.5">標準</SelectItem>
+                            <SelectItem value="2">広い</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <GraduationCap />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            使う語彙
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            学習用では、学校で習う漢字だけを候補に出し、読みを注釈に表示します（管理者のポリシーが優先されます）
+                        </p>
+                    </div>
+                    <Select value={vocabulary} onValueChange={handleVocabularyChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="語彙を選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="all">すべて</SelectItem>
+                            <SelectItem value="restricted">学習用</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Maximize />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            全画面のアプリ
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            ゲームや動画などを全画面で表示しているときの候補ウィンドウを選択します
+                        </p>
+                    </div>
+                    <Select value={fullscreen} onValueChange={handleFullscreenChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="動作を選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="hide">表示しない</SelectItem>
+                            <SelectItem value="overlay">重ねて表示する</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Gamepad2 />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            IMEを使わないアプリ
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            キー入力をすべてそのまま渡すアプリの実行ファイル名をカンマ区切りで指定します。アプリを開き直すと反映されます
+                        </p>
+                    </div>
+                    <Input
+                        placeholder="例: game.exe"
+                        value={excludedApps}
+                        onChange={(e) => setExcludedApps(e.target.value)}
+                        onBlur={handleExcludedAppsBlur}
+                        className="w-48"
+                    />
+                </div>
+            </section>
+            <section className="space-y-2">
+                <h1 className="text-sm font-bold text-foreground">キー操作</h1>
+                <div className="space-y-4 rounded-md border p-4">
+                    <div className="flex items-center space-x-4">
+                        <Layers />
+                        <div className="flex-1 space-y-1">
+                            <p className="text-sm font-medium leading-none">
+                                プリセットとショートカット
+                            </p>
+                            <p className="text-xs text-muted-foreground">
+                                使い慣れたIMEに近いキー操作をまとめて設定します。追加したショートカットも置き換わります
+                            </p>
+                        </div>
+                        <Select value={preset} onValueChange={handlePresetChange}>
+                            <SelectTrigger className="w-48">
+                                <SelectValue placeholder="プリセットを選択" />
+                            </SelectTrigger>
+                            <SelectContent>
+                                <SelectItem value="ms_ime">MS-IME</SelectItem>
+                                <SelectItem value="atok">ATOK</SelectItem>
+                                <SelectItem value="kotoeri">ことえり (macOS)</SelectItem>
+                            </SelectContent>
+                        </Select>
+                    </div>
+                    {bindings.map((binding, index) => (
+                        <div key={index} className="flex items-center space-x-4 text-sm">
+                            <span className="w-32 font-mono">{binding.key}</span>
+                            <span className="flex-1 text-xs text-muted-foreground">
+                                {binding.actions.map(formatAction).join(" → ")}
+                            </span>
+                            <Button variant="ghost" onClick={() => handleRemoveBinding(index)}>
+                                削除
+                            </Button>
+                        </div>
+                    ))}
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Keyboard />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            Escキー
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            変換中にEscキーを押したときの動作を選択します
+                        </p>
+                    </div>
+                    <Select value={escape} onValueChange={handleEscapeChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="動作を選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="cancel">入力を取り消す</SelectItem>
+                            <SelectItem value="revert">読みに戻してから取り消す</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Delete />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            BackSpaceキー
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            変換候補の表示中にBackSpaceキーを押したときの動作を選択します
+                        </p>
+                    </div>
+                    <Select value={backspace} onValueChange={handleBackspaceChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="動作を選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="delete">1文字削除する</SelectItem>
+                            <SelectItem value="revert">読みに戻す</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <MessageSquareText />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            Tabキー
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            変換候補の表示中にTabキーを押したときの動作を選択します
+                        </p>
+                    </div>
+                    <Select value={tab} onValueChange={handleTabChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="動作を選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="detail">候補の説明を表示する</SelectItem>
+                            <SelectItem value="next">次の候補を選択する</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Settings />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            設定を開くショートカット
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            この設定画面を開くキーを選択します。次に入力を切り替えたときに反映されます
+                        </p>
+                    </div>
+                    <Select value={openSettings} onValueChange={handleOpenSettingsChange}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder="キーを選択" />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="off">なし</SelectItem>
+                            <SelectItem value="Ctrl+Shift+F12">Ctrl+Shift+F12</SelectItem>
+                            <SelectItem value="Ctrl+Alt+F12">Ctrl+Alt+F12</SelectItem>
+                            <SelectItem value="Ctrl+Shift+O">Ctrl+Shift+O</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+            </section>
+            <section className="space-y-2">
+                <h1 className="text-sm font-bold text-foreground">自動化</h1>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <Workflow />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            確定した文字列を通知する
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            確定した文字列と読みを、サーバーの SubscribeCommits を購読しているツールに送ります。プライバシーモード中は送りません
+                        </p>
+                    </div>
+                    <Switch checked={commitEvents} onCheckedChange={handleCommitEventsChange} />
+                </div>
+            </section>
             {/* <section className="space-y-2">
                 <h1 className="text-sm font-bold text-foreground">診断とフィードバック</h1>
                 <div className="flex items-center space-x-4 rounded-md border p-4">
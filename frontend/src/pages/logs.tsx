@@ -0,0 +1,104 @@
+import {
+    Select,
+    SelectContent,
+    SelectItem,
+    SelectTrigger,
+    SelectValue,
+} from "@/components/ui/select"
+import { ScrollText } from "lucide-react";
+import { useEffect, useRef, useState } from "react";
+import { toast } from "sonner";
+import { Channel, invoke } from "@tauri-apps/api/core";
+import { useStrings } from "@/lib/i18n";
+
+interface LogLine {
+    timestamp: string;
+    level: string;
+    target: string;
+    message: string;
+}
+
+// lines kept in the viewer
+const MAX_LINES = 1000;
+
+const levelColor = (level: string) => {
+    switch (level) {
+        case "ERROR":
+            return "text-red-500";
+        case "WARN":
+            return "text-yellow-500";
+        default:
+            return "text-muted-foreground";
+    }
+};
+
+export const Logs = () => {
+    const t = useStrings();
+    const [level, setLevel] = useState("info");
+    const [lines, setLines] = useState<LogLine[]>([]);
+    const bottom = useRef<HTMLDivElement>(null);
+
+    // the server filters the lines, so subscribe again when the level changes
+    useEffect(() => {
+        setLines([]);
+
+        const onLine = new Channel<LogLine>();
+        onLine.onmessage = (line) => {
+            setLines((lines) => [...lines.slice(-(MAX_LINES - 1)), line]);
+        };
+
+        invoke("tail_log", { level, onLine }).catch((error) => {
+            toast(String(error));
+        });
+
+        return () => {
+            invoke("stop_tail_log").catch(() => {});
+        };
+    }, [level]);
+
+    useEffect(() => {
+        bottom.current?.scrollIntoView({ block: "end" });
+    }, [lines]);
+
+    return (
+        <div className="space-y-8">
+            <section className="space-y-2">
+                <h1 className="text-sm font-bold text-foreground">{t("logs.title")}</h1>
+                <div className="flex items-center space-x-4 rounded-md border p-4">
+                    <ScrollText />
+                    <div className="flex-1 space-y-1">
+                        <p className="text-sm font-medium leading-none">
+                            {t("logs.level")}
+                        </p>
+                        <p className="text-xs text-muted-foreground">
+                            {t("logs.level.description")}
+                        </p>
+                    </div>
+                    <Select value={level} onValueChange={setLevel}>
+                        <SelectTrigger className="w-48">
+                            <SelectValue placeholder={t("logs.level.placeholder")} />
+                        </SelectTrigger>
+                        <SelectContent>
+                            <SelectItem value="error">{t("logs.level.error")}</SelectItem>
+                            <SelectItem value="warn">{t("logs.level.warn")}</SelectItem>
+                            <SelectItem value="info">{t("logs.level.info")}</SelectItem>
+                            <SelectItem value="debug">{t("logs.level.debug")}</SelectItem>
+                            <SelectItem value="trace">{t("logs.level.trace")}</SelectItem>
+                        </SelectContent>
+                    </Select>
+                </div>
+                <div className="h-96 overflow-y-auto rounded-md border p-4 font-mono text-xs">
+                    {lines.map((line, index) => (
+                        <div key={index} className="whitespace-pre-wrap break-all">
+                            <span className="text-muted-foreground">{line.timestamp.slice(11, 23)}</span>{" "}
+                            <span className={levelColor(line.level)}>{line.level}</span>{" "}
+                            <span className="text-muted-foreground">{line.target}</span>{" "}
+                            {line.message}
+                        </div>
+                    ))}
+                    <div ref={bottom} />
+                </div>
+            </section>
+        </div>
+    )
+}
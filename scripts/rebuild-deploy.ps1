@@ -39,7 +39,7 @@ Start-Sleep -Seconds 2
 Write-Host "`n[3/4] Deploying DLLs..." -ForegroundColor Yellow
 
 # x64 DLL
-$x64Src = "$ProjectRoot\target\release\azookey_windows.dll"
+$x64Src = "$ProjectRoot\target\client-release\azookey_windows.dll"
 $x64Dst = "$ProjectRoot\build\azookey_windows.dll"
 if (Test-Path $x64Src) {
     Move-Item -Path $x64Dst -Destination "$x64Dst.old" -Force -ErrorAction SilentlyContinue
@@ -50,7 +50,7 @@ if (Test-Path $x64Src) {
 }
 
 # x86 DLL
-$x86Src = "$ProjectRoot\target\i686-pc-windows-msvc\release\azookey_windows.dll"
+$x86Src = "$ProjectRoot\target\i686-pc-windows-msvc\client-release\azookey_windows.dll"
 $x86Dst = "$ProjectRoot\build\x86\azookey_windows.dll"
 if (Test-Path $x86Src) {
     Move-Item -Path $x86Dst -Destination "$x86Dst.old" -Force -ErrorAction SilentlyContinue
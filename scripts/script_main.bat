@@ -1,3 +1,3 @@
-regsvr32.exe "D:\azookey-windows\target\release\azookey_windows.dll" /u /s
+regsvr32.exe "D:\azookey-windows\target\client-release\azookey_windows.dll" /u /s
 start D:\azookey-windows\target\release\azookey-server.exe
-regsvr32.exe "D:\azookey-windows\target\release\azookey_windows.dll" /s
\ No newline at end of file
+regsvr32.exe "D:\azookey-windows\target\client-release\azookey_windows.dll" /s
\ No newline at end of file
@@ -15,6 +15,14 @@ import ffi
 // Store last conversion result for learning
 @MainActor var lastConversionResult: [Candidate] = []
 
+// Completions of the whole reading in the last conversion, returned by PredictText
+@MainActor var lastPredictions: [(text: String, reading: String)] = []
+let maxPredictions = 5
+
+// Length of the first clause in characters of convertTarget
+// nil lets the converter decide the clause boundary
+@MainActor var segmentLength: Int? = nil
+
 // MARK: - Reusable FFI Buffers (to prevent memory leaks)
 // These buffers are reused across calls instead of allocating new memory each time
 // Using raw UnsafeMutablePointer for stable memory addresses (Swift Arrays can move)
@@ -23,6 +31,10 @@ import ffi
 let stringBufferSize = 1024
 @MainActor var stringBuffer: UnsafeMutablePointer<CChar> = .allocate(capacity: stringBufferSize)
 
+// Buffer for the predictions, one "text<TAB>reading" line each
+let predictionBufferSize = 4096
+@MainActor var predictionBuffer: UnsafeMutablePointer<CChar> = .allocate(capacity: predictionBufferSize)
+
 // Buffers for candidate list (max 100 candidates, each with 3 strings of max 256 chars)
 let maxCandidates = 100
 let maxStringLen = 256
@@ -31,10 +43,18 @@ let maxStringLen = 256
 @MainActor var candidateTextBuffers: [UnsafeMutablePointer<CChar>] = []
 @MainActor var candidateSubtextBuffers: [UnsafeMutablePointer<CChar>] = []
 @MainActor var candidateHiraganaBuffers: [UnsafeMutablePointer<CChar>] = []
+@MainActor var candidateAnnotationBuffers: [UnsafeMutablePointer<CChar>] = []
 @MainActor var candidatePtrs: [UnsafeMutablePointer<FFICandidate>] = []
 @MainActor var candidatePtrArray: UnsafeMutablePointer<UnsafeMutablePointer<FFICandidate>?>? = nil
 @MainActor var buffersInitialized = false
 
+// Where a candidate comes from, the values match Suggestion.Source in service.proto
+enum CandidateSource: Int32 {
+    case converter = 0
+    case userDictionary = 1
+    case date = 2
+}
+
 // Helper to copy string to buffer safely
 @MainActor func copyToBuffer(_ str: String, buffer: UnsafeMutablePointer<CChar>, maxLen: Int) {
     let cString = str.utf8CString
@@ -57,19 +77,22 @@ let maxStringLen = 256
         let textBuf = UnsafeMutablePointer<CChar>.allocate(capacity: maxStringLen)
         let subtextBuf = UnsafeMutablePointer<CChar>.allocate(capacity: maxStringLen)
         let hiraganaBuf = UnsafeMutablePointer<CChar>.allocate(capacity: maxStringLen)
+        let annotationBuf = UnsafeMutablePointer<CChar>.allocate(capacity: maxStringLen)
 
         // Initialize to empty strings
         textBuf[0] = 0
         subtextBuf[0] = 0
         hiraganaBuf[0] = 0
+        annotationBuf[0] = 0
 
         candidateTextBuffers.append(textBuf)
         candidateSubtextBuffers.append(subtextBuf)
         candidateHiraganaBuffers.append(hiraganaBuf)
+        candidateAnnotationBuffers.append(annotationBuf)
 
         // Allocate candidate struct
         let candidatePtr = UnsafeMutablePointer<FFICandidate>.allocate(capacity: 1)
-        candidatePtr.pointee = FFICandidate(text: textBuf, subtext: subtextBuf, hiragana: hiraganaBuf, correspondingCount: 0)
+        candidatePtr.pointee = FFICandidate(text: textBuf, subtext: subtextBuf, hiragana: hiraganaBuf, correspondingCount: 0, annotation: annotationBuf, source: 0)
         candidatePtrs.append(candidatePtr)
         candidatePtrArray![i] = candidatePtr
     }
@@ -100,10 +123,116 @@ let PERF_LOGGING_ENABLED = false
 @MainActor var config: [String : Any] = [
     "enable": false,
     "profile": "",
+    "learning": "learn",
+    "order": "learning",
+    "filter_offensive": true,
+    "vocabulary": "all",
 ]
 
+// RestrictedVocabulary policy from the registry, nil when the settings decide
+@MainActor var vocabularyPolicy: Bool? = nil
+
+@_silgen_name("SetVocabularyPolicy")
+@MainActor public func set_vocabulary_policy(policy: Int32) {
+    vocabularyPolicy = policy < 0 ? nil : policy != 0
+}
+
+@MainActor func isVocabularyRestricted() -> Bool {
+    vocabularyPolicy ?? (config["vocabulary"] as! String == "restricted")
+}
+
+@MainActor func getLearningType() -> LearningType {
+    switch config["learning"] as! String {
+    case "read_only":
+        return .onlyOutput
+    case "off":
+        return .nothing
+    default:
+        return .inputAndOutput
+    }
+}
+
+// Reorder the converter results by the candidate order in the config
+// The index stays stable for candidates with the same key
+@MainActor func sortCandidates(_ candidates: [Candidate]) -> [Candidate] {
+    let indexed = Array(candidates.enumerated())
+    let sorted: [(offset: Int, element: Candidate)]
+    switch config["order"] as! String {
+    case "frequency":
+        sorted = indexed.sorted { a, b in
+            a.element.value != b.element.value ? a.element.value > b.element.value : a.offset < b.offset
+        }
+    case "shortest":
+        sorted = indexed.sorted { a, b in
+            a.element.text.count != b.element.text.count ? a.element.text.count < b.element.text.count : a.offset < b.offset
+        }
+    default:
+        return candidates
+    }
+    return sorted.map { $0.element }
+}
+
+// Offensive words and their readings, from Filter/offensive_words.tsv next to the executable
+@MainActor var offensiveWords: [(word: String, reading: String)] = []
+
+// Lines are "reading<TAB>word", empty lines and lines starting with # are skipped
+func loadOffensiveWords(_ url: URL) -> [(word: String, reading: String)] {
+    guard let content = try? String(contentsOf: url, encoding: .utf8) else {
+        print("Failed to read the offensive word list: \(url.path)")
+        return []
+    }
+    return content.split(whereSeparator: \.isNewline).compactMap { line in
+        if line.hasPrefix("#") {
+            return nil
+        }
+        let fields = line.split(separator: "\t")
+        guard fields.count == 2 else { return nil }
+        return (word: String(fields[1]), reading: String(fields[0]))
+    }
+}
+
+// Drop the candidates containing an offensive word, unless the whole input is its reading
+// This runs before the candidates are stored for learning, so the indices stay the same
+@MainActor func filterCandidates(_ candidates: [Candidate], hiragana: String) -> [Candidate] {
+    guard config["filter_offensive"] as! Bool else {
+        return candidates
+    }
+    return candidates.filter { candidate in
+        !offensiveWords.contains { $0.reading != hiragana && candidate.text.contains($0.word) }
+    }
+}
+
+// Kanji allowed in the restricted vocabulary, from Filter/restricted_kanji.txt next to the executable
+@MainActor var restrictedKanji: Set<Character> = []
+
+// Every character outside comment lines is a kanji, so the list can be pasted as is
+func loadRestrictedKanji(_ url: URL) -> Set<Character> {
+    guard let content = try? String(contentsOf: url, encoding: .utf8) else {
+        print("Failed to read the restricted kanji list: \(url.path)")
+        return []
+    }
+    let lines = content.split(whereSeparator: \.isNewline).filter { !$0.hasPrefix("#") }
+    return Set(lines.joined().filter { !$0.isWhitespace })
+}
+
+func isKanji(_ character: Character) -> Bool {
+    character.unicodeScalars.contains { $0.properties.isIdeographic }
+}
+
+// Keep the candidates written only with kana, symbols and the allowed kanji
+@MainActor func restrictVocabulary(_ candidates: [Candidate]) -> [Candidate] {
+    guard isVocabularyRestricted() else {
+        return candidates
+    }
+    return candidates.filter { candidate in
+        candidate.text.allSatisfy { !isKanji($0) || restrictedKanji.contains($0) }
+    }
+}
+
 // User dictionary entries: [reading: [words]]
 @MainActor var userDictionary: [String: [String]] = [:]
+// Notes of the user dictionary entries, keyed by the word
+@MainActor var userDictionaryNotes: [String: String] = [:]
 
 // Cache for ConvertRequestOptions to avoid recreating on every keystroke
 @MainActor var cachedOptions: ConvertRequestOptions?
@@ -121,7 +250,7 @@ let PERF_LOGGING_ENABLED = false
         requireJapanesePrediction: true,
         requireEnglishPrediction: false,
         keyboardLanguage: .ja_JP,
-        learningType: .inputAndOutput,
+        learningType: getLearningType(),
         maxMemoryCount: 65536,
         memoryDirectoryURL: memoryURL,
         sharedContainerURL: memoryURL,
@@ -133,6 +262,7 @@ let PERF_LOGGING_ENABLED = false
             inferenceLimit: 1,
             requestRichCandidates: true,
             personalizationMode: nil,
+            // Reported as ZENZAI_VERSION in crates/server/src/admin.rs
             versionDependentMode: .v3(
                 .init(
                     profile: config["profile"] as! String,
@@ -188,44 +318,102 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
     return result
 }
 
+// Read settings.json written by the settings app
+func readSettings() -> [String: Any]? {
+    guard let appDataPath = ProcessInfo.processInfo.environment["APPDATA"] else { return nil }
+    let settingsPath = URL(filePath: appDataPath).appendingPathComponent("Azookey/settings.json")
+
+    do {
+        let data = try Data(contentsOf: settingsPath)
+        return try JSONSerialization.jsonObject(with: data) as? [String: Any]
+    } catch {
+        print("Failed to read settings: \(error)")
+        return nil
+    }
+}
+
+// Build the user dictionary from the settings, returns nil if the settings have no dictionary
+func parseUserDictionary(_ json: [String: Any]) -> (entries: [String: [String]], notes: [String: String], count: Int)? {
+    guard let dictConfig = json["dictionary"] as? [String: Any],
+          let entries = dictConfig["entries"] as? [[String: String]] else {
+        return nil
+    }
+
+    var dictionary: [String: [String]] = [:]
+    var notes: [String: String] = [:]
+    for entry in entries {
+        if let word = entry["word"], let reading = entry["reading"] {
+            dictionary[reading, default: []].append(word)
+            if let note = entry["note"], !note.isEmpty {
+                notes[word] = note
+            }
+        }
+    }
+    return (dictionary, notes, entries.count)
+}
+
 @_silgen_name("LoadConfig")
 @MainActor public func load_config() {
-    if let appDataPath = ProcessInfo.processInfo.environment["APPDATA"] {
-        let settingsPath = URL(filePath: appDataPath).appendingPathComponent("Azookey/settings.json")
-
-        do {
-            let data = try Data(contentsOf: settingsPath)
-            if let json = try JSONSerialization.jsonObject(with: data) as? [String: Any] {
-                // Load Zenzai config
-                if let zenzaiDict = json["zenzai"] as? [String: Any] {
-                    if let enableValue = zenzaiDict["enable"] as? Bool {
-                        config["enable"] = enableValue
-                    }
-
-                    if let profileValue = zenzaiDict["profile"] as? String {
-                        config["profile"] = profileValue
-                    }
-                }
-
-                // Load user dictionary
-                if let dictConfig = json["dictionary"] as? [String: Any],
-                   let entries = dictConfig["entries"] as? [[String: String]] {
-                    userDictionary = [:]
-                    for entry in entries {
-                        if let word = entry["word"], let reading = entry["reading"] {
-                            if userDictionary[reading] == nil {
-                                userDictionary[reading] = []
-                            }
-                            userDictionary[reading]?.append(word)
-                        }
-                    }
-                    print("Loaded \(entries.count) user dictionary entries")
-                }
-            }
-        } catch {
-            print("Failed to read settings: \(error)")
+    guard let json = readSettings() else { return }
+
+    // Load Zenzai config
+    if let zenzaiDict = json["zenzai"] as? [String: Any] {
+        if let enableValue = zenzaiDict["enable"] as? Bool {
+            config["enable"] = enableValue
+        }
+
+        if let profileValue = zenzaiDict["profile"] as? String {
+            config["profile"] = profileValue
         }
     }
+
+    // Load learning mode
+    if let conversionDict = json["conversion"] as? [String: Any],
+       let learningValue = conversionDict["learning"] as? String {
+        config["learning"] = learningValue
+    }
+
+    // Load candidate order and the offensive word filter
+    if let candidateDict = json["candidate"] as? [String: Any] {
+        if let orderValue = candidateDict["order"] as? String {
+            config["order"] = orderValue
+        }
+        if let filterValue = candidateDict["filter_offensive"] as? Bool {
+            config["filter_offensive"] = filterValue
+        }
+        if let vocabularyValue = candidateDict["vocabulary"] as? String {
+            config["vocabulary"] = vocabularyValue
+        }
+    }
+
+    // Load user dictionary
+    if let dictionary = parseUserDictionary(json) {
+        userDictionary = dictionary.entries
+        userDictionaryNotes = dictionary.notes
+        print("Loaded \(dictionary.count) user dictionary entries")
+    }
+}
+
+// Read the user and system dictionaries again without restarting the server
+// The new dictionaries are built next to the current ones and swapped in at the end,
+// so a composition in progress keeps converting with the old ones until then
+@_silgen_name("ReloadDictionaries")
+@MainActor public func reload_dictionaries() -> Int32 {
+    let dictionaryURL = execURL.appendingPathComponent("Dictionary")
+    let newDicdataStore = DicdataStore(dictionaryURL: dictionaryURL, preloadDictionary: true)
+    let newConverter = KanaKanjiConverter(dicdataStore: newDicdataStore)
+    let newUserDictionary = readSettings().flatMap(parseUserDictionary)
+
+    dicdataStore = newDicdataStore
+    converter = newConverter
+    if let dictionary = newUserDictionary {
+        userDictionary = dictionary.entries
+        userDictionaryNotes = dictionary.notes
+    }
+
+    let count = newUserDictionary?.count ?? 0
+    print("Reloaded dictionaries with \(count) user dictionary entries")
+    return Int32(count)
 }
 
 @_silgen_name("Initialize")
@@ -261,6 +449,8 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
     }
 
     load_config()
+    offensiveWords = loadOffensiveWords(execURL.appendingPathComponent("Filter").appendingPathComponent("offensive_words.tsv"))
+    restrictedKanji = loadRestrictedKanji(execURL.appendingPathComponent("Filter").appendingPathComponent("restricted_kanji.txt"))
 
     // Initialize DicdataStore and KanaKanjiConverter with new API
     let dictionaryURL = execURL.appendingPathComponent("Dictionary")
@@ -338,6 +528,7 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
 ) -> UnsafeMutablePointer<CChar> {
     let inputString = String(cString: input)
     composingText.insertAtCursorPosition(inputString, inputStyle: .roman2kana)
+    segmentLength = nil
 
     cursorPtr.pointee = composingText.convertTargetCursorPosition
     copyToBuffer(composingText.convertTarget, buffer: stringBuffer, maxLen: stringBufferSize)
@@ -349,6 +540,7 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
     cursorPtr: UnsafeMutablePointer<Int>
 ) -> UnsafeMutablePointer<CChar> {
     composingText.deleteBackwardFromCursorPosition(count: 1)
+    segmentLength = nil
 
     cursorPtr.pointee = composingText.convertTargetCursorPosition
     copyToBuffer(composingText.convertTarget, buffer: stringBuffer, maxLen: stringBufferSize)
@@ -372,6 +564,8 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
 @MainActor public func clear_text() {
     print("[CLEAR] Clearing all text and calling stopComposition()")
     composingText = ComposingText()
+    segmentLength = nil
+    lastPredictions = []
     // Reset converter internal state to prevent slowdown from accumulated caches
     converter.stopComposition()
 }
@@ -385,14 +579,18 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
     // Initialize buffers on first call
     initCandidateBuffers()
 
-    let hiragana = composingText.convertTarget
+    // Only the first clause is converted when its length is fixed by the user
+    let target = segmentLength.map { composingTextPrefix(length: $0) } ?? composingText
+    let hiragana = target.convertTarget
+    // Reading after the first clause, appended to the subtext of the extra candidates
+    let rest = String(composingText.convertTarget.dropFirst(hiragana.count))
     debugLog("GetComposedText called, hiragana: '\(hiragana)'")
     let contextString = (config["context"] as? String) ?? ""
     let options = getOptions(context: contextString)
 
     // Time the conversion for performance debugging
     let startTime = Date()
-    let converted = converter.requestCandidates(composingText, options: options)
+    let converted = converter.requestCandidates(target, options: options)
     let elapsed = Date().timeIntervalSince(startTime) * 1000  // ms
 
     conversionCount += 1
@@ -415,22 +613,25 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
 
     debugLog("mainResults count: \(converted.mainResults.count)")
 
-    // Store conversion result for learning
-    lastConversionResult = converted.mainResults
+    // Store conversion result for learning, in the order shown to the user
+    lastConversionResult = restrictVocabulary(filterCandidates(sortCandidates(converted.mainResults), hiragana: hiragana))
+    lastPredictions = predictions(lastConversionResult, hiragana: hiragana)
 
     var candidateIndex = 0
 
     // Helper to add a candidate using reusable buffers (stable raw pointers)
-    func addCandidate(text: String, subtext: String, reading: String, count: Int32) {
+    func addCandidate(text: String, subtext: String, reading: String, count: Int32, annotation: String? = nil, source: CandidateSource = .converter) {
         guard candidateIndex < maxCandidates else { return }
 
         // Copy strings to pre-allocated stable buffers
         copyToBuffer(text, buffer: candidateTextBuffers[candidateIndex], maxLen: maxStringLen)
         copyToBuffer(subtext, buffer: candidateSubtextBuffers[candidateIndex], maxLen: maxStringLen)
         copyToBuffer(reading, buffer: candidateHiraganaBuffers[candidateIndex], maxLen: maxStringLen)
+        copyToBuffer(annotation ?? annotateCandidate(text), buffer: candidateAnnotationBuffers[candidateIndex], maxLen: maxStringLen)
 
-        // Update only the correspondingCount (pointers are already set during init)
+        // Update only the correspondingCount and source (pointers are already set during init)
         candidatePtrs[candidateIndex].pointee.correspondingCount = count
+        candidatePtrs[candidateIndex].pointee.source = source.rawValue
 
         candidateIndex += 1
     }
@@ -438,7 +639,7 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
     // Add user dictionary entries first (if hiragana matches a reading)
     if let userWords = userDictionary[hiragana] {
         for word in userWords {
-            addCandidate(text: word, subtext: "", reading: hiragana, count: Int32(hiragana.count))
+            addCandidate(text: word, subtext: rest, reading: hiragana, count: Int32(hiragana.count), annotation: userDictionaryNotes[word] ?? "ユーザー辞書", source: .userDictionary)
         }
     }
 
@@ -447,7 +648,7 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
         if hiragana.hasPrefix(reading) && reading != hiragana {
             for word in words {
                 let remaining = String(hiragana.dropFirst(reading.count))
-                addCandidate(text: word, subtext: remaining, reading: reading, count: Int32(reading.count))
+                addCandidate(text: word, subtext: remaining + rest, reading: reading, count: Int32(reading.count), annotation: userDictionaryNotes[word] ?? "ユーザー辞書", source: .userDictionary)
             }
         }
     }
@@ -462,43 +663,32 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
         // Japanese weekday names (Sunday=1 in Calendar)
         let weekdays = ["", "日", "月", "火", "水", "木", "金", "土"]
 
+        // the formats of a day
+        func dayStrings(_ date: Date) -> [String] {
+            let year = cal.component(.year, from: date)
+            let month = cal.component(.month, from: date)
+            let day = cal.component(.day, from: date)
+            let weekday = weekdays[cal.component(.weekday, from: date)]
+            return [
+                "\(year)\(String(format: "%02d", month))\(String(format: "%02d", day))",
+                "\(month)月\(day)日(\(weekday))",
+                "\(year)/\(String(format: "%02d", month))/\(String(format: "%02d", day))",
+                "\(year)年\(month)月\(day)日",
+                "\(year)年\(month)月\(day)日(\(weekday))",
+                "\(month)月\(day)日",
+            ] + (japaneseEra(year: year, month: month).map { ["\($0)\(month)月\(day)日"] } ?? [])
+        }
+
         switch hiragana {
         case "きょう":
-            let year = cal.component(.year, from: now)
-            let month = cal.component(.month, from: now)
-            let day = cal.component(.day, from: now)
-            let weekday = weekdays[cal.component(.weekday, from: now)]
-            dateStrings.append("\(year)\(String(format: "%02d", month))\(String(format: "%02d", day))")
-            dateStrings.append("\(month)月\(day)日(\(weekday))")
-            dateStrings.append("\(year)/\(String(format: "%02d", month))/\(String(format: "%02d", day))")
-            dateStrings.append("\(year)年\(month)月\(day)日")
-            dateStrings.append("\(year)年\(month)月\(day)日(\(weekday))")
-            dateStrings.append("\(month)月\(day)日")
+            dateStrings.append(contentsOf: dayStrings(now))
         case "あした":
             if let tomorrow = cal.date(byAdding: .day, value: 1, to: now) {
-                let year = cal.component(.year, from: tomorrow)
-                let month = cal.component(.month, from: tomorrow)
-                let day = cal.component(.day, from: tomorrow)
-                let weekday = weekdays[cal.component(.weekday, from: tomorrow)]
-                dateStrings.append("\(year)\(String(format: "%02d", month))\(String(format: "%02d", day))")
-                dateStrings.append("\(month)月\(day)日(\(weekday))")
-                dateStrings.append("\(year)/\(String(format: "%02d", month))/\(String(format: "%02d", day))")
-                dateStrings.append("\(year)年\(month)月\(day)日")
-                dateStrings.append("\(year)年\(month)月\(day)日(\(weekday))")
-                dateStrings.append("\(month)月\(day)日")
+                dateStrings.append(contentsOf: dayStrings(tomorrow))
             }
         case "きのう":
             if let yesterday = cal.date(byAdding: .day, value: -1, to: now) {
-                let year = cal.component(.year, from: yesterday)
-                let month = cal.component(.month, from: yesterday)
-                let day = cal.component(.day, from: yesterday)
-                let weekday = weekdays[cal.component(.weekday, from: yesterday)]
-                dateStrings.append("\(year)\(String(format: "%02d", month))\(String(format: "%02d", day))")
-                dateStrings.append("\(month)月\(day)日(\(weekday))")
-                dateStrings.append("\(year)/\(String(format: "%02d", month))/\(String(format: "%02d", day))")
-                dateStrings.append("\(year)年\(month)月\(day)日")
-                dateStrings.append("\(year)年\(month)月\(day)日(\(weekday))")
-                dateStrings.append("\(month)月\(day)日")
+                dateStrings.append(contentsOf: dayStrings(yesterday))
             }
         case "いま":
             let hour = cal.component(.hour, from: now)
@@ -517,12 +707,12 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
         }
 
         for dateStr in dateStrings {
-            addCandidate(text: dateStr, subtext: "", reading: hiragana, count: Int32(hiragana.count))
+            addCandidate(text: dateStr, subtext: rest, reading: hiragana, count: Int32(hiragana.count), annotation: "日付", source: .date)
         }
     }
 
-    for i in 0..<converted.mainResults.count {
-        let candidate = converted.mainResults[i]
+    for i in 0..<lastConversionResult.count {
+        let candidate = lastConversionResult[i]
         let text = constructCandidateString(candidate: candidate, hiragana: hiragana)
         let composingCount = candidate.composingCount
 
@@ -530,7 +720,14 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
         afterComposingText.prefixComplete(composingCount: composingCount)
         let subtext = afterComposingText.convertTarget
 
-        addCandidate(text: text, subtext: subtext, reading: hiragana, count: Int32(getInputCount(composingCount)))
+        // Furigana for the kanji in the restricted vocabulary, the reading of the converted part
+        var annotation: String? = nil
+        if isVocabularyRestricted() && text.contains(where: isKanji) {
+            let fullReading = composingText.convertTarget
+            annotation = fullReading.hasSuffix(subtext) ? String(fullReading.dropLast(subtext.count)) : hiragana
+        }
+
+        addCandidate(text: text, subtext: subtext, reading: hiragana, count: Int32(getInputCount(composingCount)), annotation: annotation)
     }
 
     lengthPtr.pointee = candidateIndex
@@ -538,6 +735,101 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
     return candidatePtrArray!
 }
 
+// The year in the Japanese era like 令和6年, nil before Reiwa
+func japaneseEra(year: Int, month: Int) -> String? {
+    guard year > 2019 || (year == 2019 && month >= 5) else { return nil }
+    let reiwa = year - 2018
+    return reiwa == 1 ? "令和元年" : "令和\(reiwa)年"
+}
+
+// The ruby of the converter is in katakana
+func toKatakana(_ text: String) -> String {
+    String(String.UnicodeScalarView(text.unicodeScalars.map { scalar in
+        (0x3041...0x3096).contains(scalar.value) ? Unicode.Scalar(scalar.value + 0x60)! : scalar
+    }))
+}
+
+func toHiragana(_ text: String) -> String {
+    String(String.UnicodeScalarView(text.unicodeScalars.map { scalar in
+        (0x30A1...0x30F6).contains(scalar.value) ? Unicode.Scalar(scalar.value - 0x60)! : scalar
+    }))
+}
+
+// Candidates whose reading continues the typed one, e.g. 変換候補 for へんかん
+// The candidate list cuts them at the end of the reading, so they are kept whole here
+@MainActor func predictions(_ candidates: [Candidate], hiragana: String) -> [(text: String, reading: String)] {
+    let typed = toKatakana(hiragana)
+    var result: [(text: String, reading: String)] = []
+
+    for candidate in candidates {
+        let ruby = candidate.data.map { $0.ruby }.joined()
+        guard ruby.count > typed.count && ruby.hasPrefix(typed) else { continue }
+        guard !result.contains(where: { $0.text == candidate.text }) else { continue }
+
+        result.append((text: candidate.text, reading: toHiragana(ruby)))
+        if result.count == maxPredictions { break }
+    }
+
+    return result
+}
+
+@_silgen_name("PredictText")
+@MainActor public func predict_text() -> UnsafeMutablePointer<CChar> {
+    let lines = lastPredictions.map { "\($0.text)\t\($0.reading)" }.joined(separator: "\n")
+    copyToBuffer(lines, buffer: predictionBuffer, maxLen: predictionBufferSize)
+    return predictionBuffer
+}
+
+// Note on the kind of characters, shown when there is nothing better to say about the candidate
+func annotateCandidate(_ text: String) -> String {
+    if text.isEmpty {
+        return ""
+    }
+    if text.unicodeScalars.allSatisfy({ $0.properties.isEmojiPresentation }) {
+        return "絵文字"
+    }
+    if text.allSatisfy({ ("ァ"..."ヶ").contains($0) || $0 == "ー" }) {
+        return "カタカナ"
+    }
+    if text.allSatisfy({ ("ｦ"..."ﾟ").contains($0) }) {
+        return "半角カタカナ"
+    }
+    if text.allSatisfy({ ("！"..."～").contains($0) }) {
+        return "全角英数"
+    }
+    if text.allSatisfy({ $0.isASCII }) {
+        return "半角英数"
+    }
+    return ""
+}
+
+// ComposingText which contains only the first `length` characters of convertTarget
+@MainActor func composingTextPrefix(length: Int) -> ComposingText {
+    var prefix = composingText
+    _ = prefix.moveCursorFromCursorPosition(count: length - prefix.convertTargetCursorPosition)
+    return prefix.prefixToCursorPosition()
+}
+
+@_silgen_name("ResizeSegment")
+@MainActor public func resize_segment(
+    offset: Int32
+) -> UnsafeMutablePointer<CChar> {
+    let total = composingText.convertTarget.count
+
+    // Start from the clause chosen by the converter
+    let current = segmentLength ?? lastConversionResult.first.map { candidate in
+        var afterComposingText = composingText
+        afterComposingText.prefixComplete(composingCount: candidate.composingCount)
+        return total - afterComposingText.convertTarget.count
+    } ?? total
+
+    segmentLength = max(1, min(total, current + Int(offset)))
+    print("[SEGMENT] offset=\(offset), length=\(segmentLength!)/\(total)")
+
+    copyToBuffer(composingText.convertTarget, buffer: stringBuffer, maxLen: stringBufferSize)
+    return stringBuffer
+}
+
 @_silgen_name("ShrinkText")
 @MainActor public func shrink_text(
     offset: Int32
@@ -545,6 +837,7 @@ func constructCandidateString(candidate: Candidate, hiragana: String) -> String
     var afterComposingText = composingText
     afterComposingText.prefixComplete(composingCount: .inputCount(Int(offset)))
     composingText = afterComposingText
+    segmentLength = nil
 
     // Reset converter state when text is confirmed
     print("[SHRINK] offset=\(offset), remaining='\(composingText.convertTarget)', calling stopComposition()")
@@ -584,6 +877,7 @@ public func free_composed_text_result(
             free(candidatePtr.pointee.text)
             free(candidatePtr.pointee.subtext)
             free(candidatePtr.pointee.hiragana)
+            free(candidatePtr.pointee.annotation)
             // Free the FFICandidate pointer
             candidatePtr.deallocate()
         }
@@ -610,12 +904,31 @@ public func free_composed_text_result(
     converter.setCompletedData(candidate)
     converter.updateLearningData(candidate)
     converter.commitUpdateLearningData()
+    appendLearningLog(candidate)
 
     print("[LEARN] Learning committed successfully")
 }
 
+// The converter keeps its history in a binary format, so the learned candidates are also
+// logged as "time<TAB>ruby<TAB>word" lines for the export of the settings app
+@MainActor func appendLearningLog(_ candidate: Candidate) {
+    let ruby = candidate.data.map { $0.ruby }.joined()
+    let line = "\(Int(Date().timeIntervalSince1970))\t\(ruby)\t\(candidate.text)\n"
+    guard let data = line.data(using: .utf8) else { return }
+
+    let logURL = memoryURL.appendingPathComponent("learning_log.tsv")
+    if let handle = try? FileHandle(forWritingTo: logURL) {
+        handle.seekToEndOfFile()
+        handle.write(data)
+        handle.closeFile()
+    } else {
+        FileManager.default.createFile(atPath: logURL.path, contents: data)
+    }
+}
+
 @_silgen_name("ResetLearningMemory")
 @MainActor public func reset_learning_memory() {
     print("[LEARN] Resetting all learning memory")
     converter.resetMemory()
+    try? FileManager.default.removeItem(at: memoryURL.appendingPathComponent("learning_log.tsv"))
 }
\ No newline at end of file
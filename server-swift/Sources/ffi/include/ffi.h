@@ -10,8 +10,13 @@ struct FFICandidate {
     char *subtext;
     char *hiragana;
     int correspondingCount;
+    char *annotation;
+    int source; // 0: converter, 1: user dictionary, 2: date
 };
 
 // History learning functions
 void LearnCandidate(int candidateIndex);
-void ResetLearningMemory(void);
\ No newline at end of file
+void ResetLearningMemory(void);
+
+// Dictionary functions
+int ReloadDictionaries(void);